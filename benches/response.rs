@@ -0,0 +1,131 @@
+use std::io::{self, Cursor, Read, Write};
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use irrc::{Connection, Query};
+
+const ITEMS: usize = 100_000;
+const PIPELINES: usize = 1_000;
+
+/// In-memory stream replaying a canned server response, and discarding
+/// anything written to it.
+#[derive(Debug)]
+struct Replay(Cursor<Vec<u8>>);
+
+impl Read for Replay {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl Write for Replay {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// In-memory stream answering each query written to it with a short
+/// response.
+#[derive(Debug, Default)]
+struct Responder(Cursor<Vec<u8>>);
+
+impl Read for Responder {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl Write for Responder {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let queries = buf.iter().filter(|&&b| b == b'\n').count();
+        let mut pending = self.0.get_ref()[self.0.position() as usize..].to_vec();
+        (0..queries).for_each(|_| pending.extend_from_slice(b"A5\nIRRd\nC\n"));
+        self.0 = Cursor::new(pending);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn response() -> Vec<u8> {
+    let data = (0..ITEMS)
+        .map(|i| format!("10.{}.{}.0/24", (i >> 8) & 0xff, i & 0xff))
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!("A{}\n{data}\nC\n", data.len() + 1).into_bytes()
+}
+
+fn connection(response: &[u8]) -> Connection {
+    Connection::from_stream(Replay(Cursor::new(response.to_vec())))
+}
+
+fn read_response(c: &mut Criterion) {
+    let response = response();
+    let query = Query::Ipv4Routes("AS65000".parse().unwrap());
+    let mut group = c.benchmark_group("read_response");
+    _ = group.bench_function("iterator", |b| {
+        b.iter_batched(
+            || connection(&response),
+            |mut conn| {
+                conn.pipeline()
+                    .push(query.clone())
+                    .unwrap()
+                    .pop::<String>()
+                    .unwrap()
+                    .unwrap()
+                    .for_each(|item| _ = black_box(item.unwrap()));
+            },
+            BatchSize::SmallInput,
+        );
+    });
+    _ = group.bench_function("for_each_raw", |b| {
+        b.iter_batched(
+            || connection(&response),
+            |mut conn| {
+                conn.pipeline()
+                    .push(query.clone())
+                    .unwrap()
+                    .pop::<String>()
+                    .unwrap()
+                    .unwrap()
+                    .for_each_raw(|raw| _ = black_box(raw))
+                    .unwrap();
+            },
+            BatchSize::SmallInput,
+        );
+    });
+    group.finish();
+}
+
+fn short_pipelines(c: &mut Criterion) {
+    let mut group = c.benchmark_group("short_pipelines");
+    for (name, pooled) in [("fresh", false), ("pooled", true)] {
+        _ = group.bench_function(name, |b| {
+            b.iter_batched(
+                || {
+                    let conn = Connection::from_stream(Responder::default());
+                    if pooled {
+                        conn.with_buffer_pool()
+                    } else {
+                        conn
+                    }
+                },
+                |mut conn| {
+                    for _ in 0..PIPELINES {
+                        _ = black_box(conn.version().unwrap());
+                    }
+                },
+                BatchSize::SmallInput,
+            );
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, read_response, short_pipelines);
+criterion_main!(benches);
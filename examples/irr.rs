@@ -16,15 +16,15 @@ fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
         .try_init()?;
     let mut irr = IrrClient::new("whois.radb.net:43").connect()?;
     irr.pipeline()
-        .push(Query::AsSetMembers("AS37271:AS-CUSTOMERS".parse()?))?
+        .push(Query::as_set_members("AS37271:AS-CUSTOMERS")?)?
         .responses::<AsSetMember>()
         .for_each(hanndle_item_result);
     irr.pipeline()
-        .push(Query::Ipv4Routes("AS37271".parse()?))?
+        .push(Query::ipv4_routes(37271)?)?
         .responses::<Prefix<Ipv4>>()
         .for_each(hanndle_item_result);
     irr.pipeline()
-        .push(Query::Ipv6Routes("AS37271".parse()?))?
+        .push(Query::ipv6_routes(37271)?)?
         .responses::<Prefix<Ipv6>>()
         .for_each(hanndle_item_result);
     Ok(())
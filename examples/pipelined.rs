@@ -1,7 +1,7 @@
 use std::{env::args, io::stderr, sync::mpsc, thread};
 
 use ip::{traits::PrefixSet as _, Any, Prefix, PrefixSet};
-use irrc::{Error, IrrClient, Query, ResponseItem};
+use irrc::{AddressFamily, Error, IrrClient, Query, ResponseItem};
 use rpsl::names::{AsSet, AutNum};
 
 struct Collector {
@@ -59,15 +59,15 @@ impl QueryThread {
             .sender()
             .expect("failed to take collector send handle");
         let join_handle = thread::spawn(move || -> Result<(), Error> {
-            IrrClient::new(host)
-                .connect()?
-                .pipeline_from_initial(Query::AsSetMembersRecursive(object), |item| {
-                    item.map(into_routes_queries).map_err(log_warning).ok()
-                })?
-                .responses()
-                .filter_map(|item| item.map_err(log_warning).ok())
-                .for_each(|item| sender.collect(item));
-            Ok(())
+            IrrClient::new(host).connect()?.expand_into(
+                Query::AsSetMembersRecursive(object),
+                |item| item.map(into_routes_queries).map_err(log_warning).ok(),
+                |item| {
+                    if let Ok(item) = item.map_err(log_warning) {
+                        sender.collect(item);
+                    }
+                },
+            )
         });
         Self(join_handle)
     }
@@ -84,7 +84,7 @@ fn log_warning<E: std::error::Error>(err: E) -> E {
 
 fn into_routes_queries(item: ResponseItem<AutNum>) -> [Query; 2] {
     let autnum = item.into_content();
-    [Query::Ipv4Routes(autnum), Query::Ipv6Routes(autnum)]
+    AddressFamily::ALL.map(|family| Query::routes(family, autnum))
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
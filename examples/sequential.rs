@@ -1,7 +1,8 @@
 use std::error::Error;
 
-use ip::{traits::PrefixSet as _, Any, Prefix, PrefixSet};
-use irrc::{IrrClient, Query};
+use ip::{traits::PrefixSet as _, Any, Prefix};
+use irrc::{AddressFamily, IrrClient, Query};
+use rpsl::names::AutNum;
 
 fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
     tracing_subscriber::fmt()
@@ -13,12 +14,11 @@ fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
         .push(Query::AsSetMembersRecursive(
             "AS37271:AS-CUSTOMERS".parse()?,
         ))?
-        .responses()
+        .responses::<AutNum>()
         .filter_map(|result| match result {
-            Ok(item) => Some([
-                Query::Ipv4Routes(*item.content()),
-                Query::Ipv6Routes(*item.content()),
-            ]),
+            Ok(item) => {
+                Some(AddressFamily::ALL.map(|family| Query::routes(family, *item.content())))
+            }
             Err(err) => {
                 tracing::error!("{err}");
                 None
@@ -28,14 +28,7 @@ fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
         .collect();
     irr.pipeline_from_iter(route_queries)
         .responses::<Prefix<Any>>()
-        .filter_map(|result| match result {
-            Ok(item) => Some(item.into_content()),
-            Err(err) => {
-                tracing::error!("{err}");
-                None
-            }
-        })
-        .collect::<PrefixSet<Any>>()
+        .into_prefix_set()
         .ranges()
         .for_each(|range| println!("{range}"));
     Ok(())
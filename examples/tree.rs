@@ -1,7 +1,7 @@
 use std::{collections::HashMap, env::args, error::Error, io::stderr};
 
-use ip::{traits::PrefixSet as _, Any, Ipv4, Ipv6, Prefix, PrefixSet};
-use irrc::{IrrClient, Query};
+use ip::{traits::PrefixSet as _, Ipv4, Ipv6, PrefixSet};
+use irrc::{AddressFamily, IrrClient, Query};
 use rpsl::names::AutNum;
 
 fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
@@ -35,30 +35,20 @@ fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
     pipeline.extend(
         autnums
             .keys()
-            .flat_map(|k| [Query::Ipv4Routes(*k), Query::Ipv6Routes(*k)]),
+            .flat_map(|k| AddressFamily::ALL.map(|family| Query::routes(family, *k))),
     );
-    while let Some(response_result) = pipeline.pop::<Prefix<Any>>() {
+    while let Some(response_result) = pipeline.pop::<String>() {
         match response_result {
-            Ok(response) => match response.query() {
+            Ok(response) => match *response.query() {
                 Query::Ipv4Routes(autnum) => {
-                    autnums.entry(*autnum).and_modify(|(set, _)| {
-                        set.extend(response.filter_map::<Prefix<Ipv4>, _>(|item_result| {
-                            item_result
-                                .map(|item| item.into_content().try_into().unwrap())
-                                .map_err(|err| tracing::warn!(%err))
-                                .ok()
-                        }))
-                    });
+                    autnums
+                        .entry(autnum)
+                        .and_modify(|(set, _)| *set = response.into_prefix_set_v4());
                 }
                 Query::Ipv6Routes(autnum) => {
-                    autnums.entry(*autnum).and_modify(|(_, set)| {
-                        set.extend(response.filter_map::<Prefix<Ipv6>, _>(|item_result| {
-                            item_result
-                                .map(|item| item.into_content().try_into().unwrap())
-                                .map_err(|err| tracing::warn!(%err))
-                                .ok()
-                        }))
-                    });
+                    autnums
+                        .entry(autnum)
+                        .and_modify(|(_, set)| *set = response.into_prefix_set_v6());
                 }
                 _ => unreachable!(),
             },
@@ -0,0 +1,130 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::Error;
+
+/// A range of AS numbers, as used for the primary key of an RPSL `as-block`
+/// object.
+///
+/// The range is written as `ASx-ASy`, where `x` is not greater than `y`.
+/// Whitespace around the `-` is permitted, and the `AS` prefix is matched
+/// case-insensitively.
+///
+/// # Example
+///
+/// ```
+/// use irrc::AsBlock;
+///
+/// let block: AsBlock = "AS64512 - AS65534".parse()?;
+/// assert_eq!((block.start(), block.end()), (64512, 65534));
+/// assert!(block.contains(65000));
+/// assert_eq!(block.to_string(), "AS64512-AS65534");
+/// assert!("AS65534-AS64512".parse::<AsBlock>().is_err());
+/// # Ok::<(), irrc::Error>(())
+/// ```
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct AsBlock {
+    start: u32,
+    end: u32,
+}
+
+impl AsBlock {
+    /// The first AS number in the range.
+    #[must_use]
+    pub const fn start(&self) -> u32 {
+        self.start
+    }
+
+    /// The last AS number in the range.
+    #[must_use]
+    pub const fn end(&self) -> u32 {
+        self.end
+    }
+
+    /// Whether `asn` falls within the range.
+    #[must_use]
+    pub const fn contains(&self, asn: u32) -> bool {
+        self.start <= asn && asn <= self.end
+    }
+}
+
+impl FromStr for AsBlock {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidAsBlock(s.to_owned());
+        let asn = |n: &str| {
+            let n = n.trim();
+            match (n.get(..2), n.get(2..)) {
+                (Some(prefix), Some(digits))
+                    if prefix.eq_ignore_ascii_case("as")
+                        && !digits.is_empty()
+                        && digits.bytes().all(|b| b.is_ascii_digit()) =>
+                {
+                    digits.parse::<u32>().map_err(|_| invalid())
+                }
+                _ => Err(invalid()),
+            }
+        };
+        let (start, end) = s.split_once('-').ok_or_else(invalid)?;
+        let (start, end) = (asn(start)?, asn(end)?);
+        if start > end {
+            return Err(invalid());
+        }
+        Ok(Self { start, end })
+    }
+}
+
+impl fmt::Display for AsBlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "AS{}-AS{}", self.start, self.end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn valid_ranges_are_parsed() {
+        for (input, start, end) in [
+            ("AS1000-AS2000", 1000, 2000),
+            ("as1000 - as2000", 1000, 2000),
+            ("AS65000-AS65000", 65000, 65000),
+            ("AS0-AS4294967295", 0, u32::MAX),
+        ] {
+            let block: AsBlock = input.parse().unwrap();
+            assert_eq!((block.start(), block.end()), (start, end), "{input}");
+        }
+    }
+
+    #[test]
+    fn invalid_ranges_are_rejected() {
+        for input in [
+            "",
+            "AS1000",
+            "AS2000-AS1000",
+            "1000-2000",
+            "AS1000-AS",
+            "AS+1000-AS2000",
+            "AS1000-AS4294967296",
+            "AS1000-AS2000-AS3000",
+        ] {
+            assert!(
+                matches!(input.parse::<AsBlock>(), Err(Error::InvalidAsBlock(_))),
+                "{input:?}"
+            );
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn display_round_trips(start in any::<u32>(), end in any::<u32>()) {
+            let (start, end) = (start.min(end), start.max(end));
+            let block = AsBlock { start, end };
+            assert_eq!(block.to_string().parse::<AsBlock>().unwrap(), block);
+        }
+    }
+}
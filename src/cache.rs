@@ -0,0 +1,277 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::num::NonZeroUsize;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::{client::Connection, error::Error, pipeline::ResponseItem, query::Query};
+
+/// A [`Connection`] wrapper that caches the responses to queries for a fixed
+/// time-to-live.
+///
+/// Repeating a query within the TTL of an earlier, successful, response to
+/// it returns the cached items without a round-trip to the server. Queries
+/// are matched using their [`canonical()`][Query::canonical] form, so that
+/// semantically equivalent queries share a cache entry.
+///
+/// Each response is cached as the unparsed text of its items, and parsed
+/// again as the requested type on each hit. The same cache entry can
+/// therefore be read as different types by different callers.
+///
+/// # Invalidation
+///
+/// Entries expire once they are older than the TTL given to
+/// [`new()`][Self::new], and can be removed explicitly using
+/// [`invalidate()`][Self::invalidate] or [`clear()`][Self::clear].
+///
+/// The cache is not aware of changes to the data held by the server, and
+/// may return stale results for up to the TTL. Error responses, including
+/// [`KeyNotFound`][crate::error::Response::KeyNotFound], are never cached.
+///
+/// Responses depend on server-side connection state, such as the selected
+/// sources. Queries that return no data, such as [`Query::SetSources`],
+/// are issued directly and clear the cache. After modifying the state of
+/// the underlying connection by other means (via
+/// [`connection()`][Self::connection]), call [`clear()`][Self::clear].
+///
+/// # Memory usage
+///
+/// Each entry holds the complete response to a query, which may be large
+/// (for example, a recursive `as-set` expansion). By default, the number of
+/// entries is unbounded, and expired entries are only removed when a new
+/// response is inserted. Use [`max_entries()`][Self::max_entries] to bound
+/// the number of entries retained, in which case the oldest entry is
+/// evicted to make room for a new one.
+///
+/// # Example
+///
+/// ``` no_run
+/// # use std::time::Duration;
+/// # use irrc::{IrrClient, Query, Error};
+/// # fn main() -> Result<(), Error> {
+/// use irrc::CachedConnection;
+///
+/// let conn = IrrClient::new("whois.radb.net:43").connect()?;
+/// let mut cached = CachedConnection::new(conn, Duration::from_secs(30));
+/// let query = Query::Ipv4Routes("AS65000".parse().unwrap());
+/// let first = cached.query::<String>(query.clone())?;
+/// // answered from the cache, without a round-trip
+/// let second = cached.query::<String>(query)?;
+/// assert_eq!(first.len(), second.len());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct CachedConnection {
+    conn: Connection,
+    ttl: Duration,
+    max_entries: Option<NonZeroUsize>,
+    entries: HashMap<String, Entry>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    inserted: Instant,
+    items: Vec<ResponseItem<String>>,
+}
+
+impl CachedConnection {
+    /// Wrap `conn`, caching responses for `ttl`.
+    #[must_use]
+    pub fn new(conn: Connection, ttl: Duration) -> Self {
+        Self {
+            conn,
+            ttl,
+            max_entries: None,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Limit the number of responses retained in the cache.
+    ///
+    /// When the cache is full, the oldest entry is evicted before a new
+    /// response is inserted.
+    pub const fn max_entries(&mut self, max: NonZeroUsize) -> &mut Self {
+        self.max_entries = Some(max);
+        self
+    }
+
+    /// Issue `query`, or return the items of a cached response to it, parsed
+    /// as `T`.
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if a failure occurs on the underlying connection,
+    /// if the server returns an error response, or if an item cannot be parsed
+    /// as `T`. In the last case, the response remains cached.
+    pub fn query<T>(&mut self, query: Query) -> Result<Vec<ResponseItem<T>>, Error>
+    where
+        T: FromStr + fmt::Debug,
+        T::Err: std::error::Error + Send + Sync + 'static,
+    {
+        if !query.expect_data() {
+            tracing::debug!("clearing cache before {query}");
+            self.clear();
+            _ = self
+                .conn
+                .pipeline()
+                .push(query)?
+                .pop::<String>()
+                .unwrap_or_else(|| Err(Error::Dequeue))?;
+            return Ok(Vec::new());
+        }
+        let key = query.canonical().cmd();
+        let items = match self.entries.get(&key) {
+            Some(entry) if entry.inserted.elapsed() < self.ttl => {
+                tracing::debug!("cache hit for {query}");
+                &entry.items
+            }
+            _ => {
+                tracing::debug!("cache miss for {query}");
+                let items = self
+                    .conn
+                    .pipeline()
+                    .push(query)?
+                    .pop::<String>()
+                    .unwrap_or_else(|| Err(Error::Dequeue))?
+                    .into_vec()?;
+                self.insert(key.clone(), items);
+                &self.entries[&key].items
+            }
+        };
+        items.iter().cloned().map(ResponseItem::parse_as).collect()
+    }
+
+    /// Insert a response, first removing expired entries and, if the cache
+    /// is full, the oldest entry.
+    fn insert(&mut self, key: String, items: Vec<ResponseItem<String>>) {
+        let ttl = self.ttl;
+        self.entries
+            .retain(|_, entry| entry.inserted.elapsed() < ttl);
+        if let Some(max) = self.max_entries {
+            if self.entries.len() >= max.get() && !self.entries.contains_key(&key) {
+                let oldest = self
+                    .entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.inserted)
+                    .map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    _ = self.entries.remove(&oldest);
+                }
+            }
+        }
+        _ = self.entries.insert(
+            key,
+            Entry {
+                inserted: Instant::now(),
+                items,
+            },
+        );
+    }
+
+    /// Remove any cached response to `query`, returning whether one was
+    /// present.
+    pub fn invalidate(&mut self, query: &Query) -> bool {
+        self.entries.remove(&query.canonical().cmd()).is_some()
+    }
+
+    /// Remove all cached responses.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// The number of responses currently cached, including any that have
+    /// expired but not yet been removed.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether no responses are currently cached.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Borrow the underlying [`Connection`], for issuing queries that bypass
+    /// the cache.
+    pub const fn connection(&mut self) -> &mut Connection {
+        &mut self.conn
+    }
+
+    /// Discard the cache, and return the underlying [`Connection`].
+    #[must_use]
+    pub fn into_inner(self) -> Connection {
+        self.conn
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::transport::mock::Mock;
+
+    use super::*;
+
+    fn routes() -> Query {
+        Query::Ipv4Routes("AS65000".parse().unwrap())
+    }
+
+    #[test]
+    fn repeated_query_is_answered_from_cache() {
+        let stream = Mock::new(b"A13\n192.0.2.0/24\nC\n");
+        let output = stream.output();
+        let conn = Connection::from_stream(stream);
+        let mut cached = CachedConnection::new(conn, Duration::from_secs(30));
+        for _ in 0..3 {
+            let items = cached.query::<String>(routes()).unwrap();
+            assert_eq!(items.len(), 1);
+            assert_eq!(items[0].content(), "192.0.2.0/24");
+        }
+        assert_eq!(output.lock().unwrap().as_slice(), b"!gAS65000\n");
+        assert!(cached.invalidate(&routes()));
+        assert!(cached.is_empty());
+    }
+
+    #[test]
+    fn expired_entries_are_refreshed() {
+        let stream = Mock::responding(&[b"A13\n192.0.2.0/24\nC\n", b"A16\n198.51.100.0/24\nC\n"]);
+        let conn = Connection::from_stream(stream);
+        let mut cached = CachedConnection::new(conn, Duration::ZERO);
+        assert_eq!(
+            cached.query::<String>(routes()).unwrap()[0].content(),
+            "192.0.2.0/24"
+        );
+        assert_eq!(
+            cached.query::<String>(routes()).unwrap()[0].content(),
+            "198.51.100.0/24"
+        );
+        assert_eq!(cached.len(), 1);
+    }
+
+    #[test]
+    fn oldest_entry_is_evicted() {
+        let stream = Mock::responding(&[b"A13\n192.0.2.0/24\nC\n", b"A14\n2001:db8::/32\nC\n"]);
+        let conn = Connection::from_stream(stream);
+        let mut cached = CachedConnection::new(conn, Duration::from_secs(30));
+        _ = cached.max_entries(NonZeroUsize::new(1).unwrap());
+        _ = cached.query::<String>(routes()).unwrap();
+        _ = cached
+            .query::<String>(Query::Ipv6Routes("AS65000".parse().unwrap()))
+            .unwrap();
+        assert_eq!(cached.len(), 1);
+        assert!(!cached.invalidate(&routes()));
+    }
+
+    #[test]
+    fn state_changing_query_clears_cache() {
+        let stream = Mock::responding(&[b"A13\n192.0.2.0/24\nC\n", b"C\n"]);
+        let conn = Connection::from_stream(stream);
+        let mut cached = CachedConnection::new(conn, Duration::from_secs(30));
+        _ = cached.query::<String>(routes()).unwrap();
+        assert!(cached
+            .query::<String>(Query::SetSources(vec!["RADB".to_string()]))
+            .unwrap()
+            .is_empty());
+        assert!(cached.is_empty());
+    }
+}
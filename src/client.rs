@@ -1,13 +1,31 @@
+use std::collections::{HashMap, HashSet};
 use std::fmt;
-use std::io::{Read, Write};
-use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::io::{self, Read, Write};
+use std::iter;
+#[cfg(feature = "std-net")]
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::num::NonZeroUsize;
 use std::str::FromStr;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+use circular::Buffer;
+
+#[cfg(feature = "ip")]
+use ip::{Any, Prefix, PrefixSet};
+use rpsl::names::{AsSet, AutNum, Mntner};
+#[cfg(feature = "std-net")]
+use socket2::{SockRef, TcpKeepalive};
+
+#[cfg(feature = "std-net")]
+use crate::transport;
 use crate::{
-    error::Error,
-    pipeline::{Pipeline, ResponseItem},
-    query::Query,
+    error::{self, Error},
+    journal::Journal,
+    pipeline::{Expand, Pipeline, Response, ResponseItem},
+    query::{self, Query, QueryKind, RpslObjectClass},
+    transport::{Record, Replay, Stream, Transport},
+    tree::AsSetTree,
+    version::ServerVersion,
 };
 
 /// Builder for IRR query protocol connections.
@@ -28,14 +46,21 @@ use crate::{
 /// ```
 ///
 /// [IRRd]: https://irrd.readthedocs.io/en/stable/
+#[cfg(feature = "std-net")]
 #[allow(clippy::module_name_repetitions)]
 #[derive(Debug)]
 pub struct IrrClient<A> {
     addr: A,
     client_id: Option<String>,
     server_timeout: Option<Duration>,
+    tcp_keepalive: Option<Duration>,
+    persistent_mode: bool,
+    handshake_timeout: Option<Duration>,
+    max_queries_per_connection: Option<NonZeroUsize>,
+    pin_address: bool,
 }
 
+#[cfg(feature = "std-net")]
 impl<A> IrrClient<A>
 where
     A: ToSocketAddrs + fmt::Display,
@@ -67,12 +92,26 @@ where
             addr,
             client_id: None,
             server_timeout: None,
+            tcp_keepalive: None,
+            persistent_mode: true,
+            handshake_timeout: None,
+            max_queries_per_connection: None,
+            pin_address: false,
         }
     }
 
     /// Set a client identification string to send to the server upon
     /// connection.
     ///
+    /// This may be used to include run-specific metadata, such as a trace
+    /// identifier or ticket number, allowing the server operator to
+    /// correlate queries with their origin.
+    ///
+    /// Any control characters (including newlines) in `id` are replaced with
+    /// spaces before it is sent, so that the identification string cannot be
+    /// used to inject additional queries. The identification string actually
+    /// sent can be retrieved using [`Connection::client_id()`].
+    ///
     /// Default if not set is [`DEFAULT_CLIENT_ID`][Self::DEFAULT_CLIENT_ID].
     pub fn client_id<S: AsRef<str>>(&mut self, id: Option<S>) {
         self.client_id = id.map(|id| id.as_ref().to_string());
@@ -85,11 +124,139 @@ where
         self.server_timeout = duration;
     }
 
+    /// Enable TCP keepalive probes on the connection, after the connection
+    /// has been idle for `idle`.
+    ///
+    /// This prevents idle connections from being silently dropped by
+    /// stateful firewalls and NAT devices. If the server stops responding to
+    /// keepalive probes, subsequent reads or writes on the connection will
+    /// fail with an [`Error::Io`] or [`Error::ConnectionClosed`], rather than
+    /// hanging indefinitely.
+    ///
+    /// Only the idle time before the first probe is set. The interval between
+    /// probes, and the number of unacknowledged probes before the connection
+    /// is considered dead, are left at the operating system defaults, since
+    /// support for configuring these varies between platforms.
+    ///
+    /// TCP keepalive is disabled by default.
+    pub const fn tcp_keepalive(&mut self, idle: Option<Duration>) {
+        self.tcp_keepalive = idle;
+    }
+
+    /// Enable or disable multiple command mode (`!!`) on connection.
+    ///
+    /// By default, the connection is placed into multiple command mode
+    /// immediately after it is established, allowing any number of queries
+    /// to be pipelined over it. Some IRRd compatible servers do not implement
+    /// multiple command mode, and instead answer a single query before
+    /// closing the connection.
+    ///
+    /// If persistent mode is disabled:
+    ///
+    /// - The `!!` command is not sent, and neither are the client
+    ///   identification string or server-side timeout, since each of these
+    ///   would use up the single query allowed.
+    /// - [`Pipeline`]s created from the [`Connection`] write only a single
+    ///   query at a time, rather than batching queries. Only the first query
+    ///   sent can be answered: attempting to send any further query returns
+    ///   [`Error::ConnectionClosed`].
+    /// - The `!q` command is not sent when the [`Connection`] is dropped.
+    ///
+    /// Persistent mode is enabled by default.
+    pub const fn persistent_mode(&mut self, enabled: bool) {
+        self.persistent_mode = enabled;
+    }
+
+    /// Wait up to `timeout` for the server to acknowledge the multiple
+    /// command mode (`!!`) request.
+    ///
+    /// IRRd sends nothing in response to `!!`, but some IRRd compatible
+    /// servers acknowledge it with an empty success response (`C`). If not
+    /// read, this would be taken as the response to the first query sent
+    /// during connection setup, and the responses to all subsequent queries
+    /// would be misattributed.
+    ///
+    /// If a timeout is set, the client waits for up to `timeout` after
+    /// sending `!!` for the acknowledgement, and discards it if received. If
+    /// nothing is received, the connection proceeds as normal. Anything other
+    /// than an acknowledgement causes [`connect()`][Self::connect] to fail
+    /// with an [`Error::Handshake`].
+    ///
+    /// Since the full timeout elapses when connecting to a server that sends
+    /// no acknowledgement, this should be kept short. It has no effect if
+    /// [`persistent_mode()`][Self::persistent_mode] is disabled.
+    ///
+    /// By default, no acknowledgement is expected.
+    pub const fn handshake_timeout(&mut self, timeout: Option<Duration>) {
+        self.handshake_timeout = timeout;
+    }
+
+    /// Limit the number of queries sent over each connection.
+    ///
+    /// Some IRR mirrors close the connection once a per-connection query
+    /// limit is exceeded. If a limit is set, a [`Pipeline`] stops writing
+    /// queries when the limit is reached. Once the responses to all of the
+    /// queries already sent have been read, it transparently
+    /// [`reconnect()`][Connection::reconnect]s, and sends the remaining
+    /// queries over the new connection.
+    ///
+    /// The queries sent during connection setup are not counted towards the
+    /// limit. The number of times that the connection has been re-established
+    /// in this way is available from [`Connection::rotations()`].
+    ///
+    /// The latest [`Query::SetSources`] (or [`Query::UnsetSources`]),
+    /// [`Query::SetClientId`] and [`Query::SetTimeout`] accepted by the
+    /// server are re-sent on each new connection, so that the remaining
+    /// queries are answered as they would have been on the original one. If
+    /// a [`Query::Raw`] command has been sent, its effect cannot be restored,
+    /// and an [`Error::StateNotRestorable`] is returned instead of
+    /// re-establishing the connection.
+    ///
+    /// No limit is applied by default.
+    pub const fn max_queries_per_connection(&mut self, limit: Option<NonZeroUsize>) {
+        self.max_queries_per_connection = limit;
+    }
+
+    /// Pin the IP address of the server for the lifetime of the
+    /// [`Connection`].
+    ///
+    /// By default, the server address is resolved again each time the
+    /// connection is re-established, whether explicitly using
+    /// [`reconnect()`][Connection::reconnect], or after reaching the limit
+    /// set using
+    /// [`max_queries_per_connection()`][Self::max_queries_per_connection].
+    /// If the server name resolves to several instances, for example using
+    /// round-robin DNS, each new connection may reach a different instance,
+    /// which may be at a different serial.
+    ///
+    /// If enabled, the address that the first connection is established to
+    /// is recorded, and used for all subsequent re-connections, so that every
+    /// query is answered by the same instance. The pinned address is
+    /// available from [`Connection::pinned_addr()`], and can be discarded
+    /// using [`Connection::re_resolve()`].
+    ///
+    /// Address pinning is disabled by default.
+    pub const fn pin_address(&mut self, enabled: bool) {
+        self.pin_address = enabled;
+    }
+
     /// Initiate a new connection to an IRRd server.
     ///
+    /// If the server address resolves to multiple IP addresses, connection
+    /// attempts are raced in the manner of "Happy Eyeballs" ([RFC 8305]),
+    /// preferring IPv6, and the first connection to succeed is used.
+    ///
+    /// The client identification string and server-side timeout (if set) are
+    /// sent to the server during connection setup, and the server's
+    /// acknowledgement of each is checked before returning.
+    ///
     /// # Errors
     ///
-    /// Returns an error if the TCP connection to the IRRd server cannot be established.
+    /// Returns an error if the TCP connection to the IRRd server cannot be
+    /// established, or if the server rejects the client identification string
+    /// or server-side timeout.
+    ///
+    /// [RFC 8305]: https://www.rfc-editor.org/rfc/rfc8305
     #[tracing::instrument(skip(self), fields(addr = %self.addr), level = "debug")]
     pub fn connect(&self) -> Result<Connection, Error> {
         Connection::connect(self)
@@ -100,8 +267,153 @@ where
             .as_ref()
             .map_or(Self::DEFAULT_CLIENT_ID, String::as_ref)
     }
+
+    fn endpoint(&self) -> Endpoint {
+        Endpoint {
+            addr: self.addr.to_string(),
+            client_id: self.effective_client_id().to_string(),
+            server_timeout: self.server_timeout,
+            tcp_keepalive: self.tcp_keepalive,
+            persistent_mode: self.persistent_mode,
+            handshake_timeout: self.handshake_timeout,
+            pin_address: self.pin_address,
+            pinned: None,
+        }
+    }
+}
+
+#[cfg(feature = "std-net")]
+impl IrrClient<String> {
+    /// The default port for the IRRd query protocol.
+    pub const DEFAULT_PORT: u16 = 43;
+
+    /// Initialize a new [`IrrClient`], with the server address taken from
+    /// `url`.
+    ///
+    /// This allows the server to be configured using a single string, for
+    /// example in a configuration file or on the command line. `url` takes
+    /// the form `scheme://host[:port]`, where the scheme is one of:
+    ///
+    /// - `whois` or `irr`, for the IRRd query protocol over plain TCP. The
+    ///   port defaults to [`DEFAULT_PORT`][Self::DEFAULT_PORT].
+    /// - `irr+tls`, for the query protocol over TLS. This is recognised, but
+    ///   not currently supported.
+    ///
+    /// IPv6 addresses must be enclosed in square brackets, as in
+    /// `whois://[2001:db8::1]:43`. A trailing `/` is permitted, but no other
+    /// path, query or fragment.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::UnsupportedScheme`] is returned if the scheme is not
+    /// supported, and an [`Error::InvalidUrl`] if `url` is not of the form
+    /// described above.
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # use irrc::{IrrClient, Error};
+    /// # fn main() -> Result<(), Error> {
+    /// let mut irr = IrrClient::from_url("whois://whois.radb.net")?.connect()?;
+    /// println!("{}", irr.version()?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_url(url: &str) -> Result<Self, Error> {
+        parse_url(url).map(Self::new)
+    }
+}
+
+/// Parse an IRRd server URL, returning the address to connect to.
+#[cfg(feature = "std-net")]
+fn parse_url(url: &str) -> Result<String, Error> {
+    let invalid = || Error::InvalidUrl(url.to_owned());
+    let (scheme, rest) = url.split_once("://").ok_or_else(invalid)?;
+    match scheme.to_ascii_lowercase().as_str() {
+        "whois" | "irr" => {}
+        _ => return Err(Error::UnsupportedScheme(scheme.to_owned())),
+    }
+    let authority = rest.strip_suffix('/').unwrap_or(rest);
+    if authority.contains(['/', '?', '#', '@']) {
+        return Err(invalid());
+    }
+    let (host, port) = match authority.rsplit_once(':') {
+        // a bracketed IPv6 address, with no port
+        Some((_, port)) if port.ends_with(']') => (authority, None),
+        Some((host, port)) => (host, Some(port)),
+        None => (authority, None),
+    };
+    if host.is_empty() || (host.contains(':') && !(host.starts_with('[') && host.ends_with(']'))) {
+        return Err(invalid());
+    }
+    let port = match port {
+        Some(port) if port.bytes().all(|b| b.is_ascii_digit()) => {
+            port.parse::<u16>().map_err(|_| invalid())?
+        }
+        Some(_) => return Err(invalid()),
+        None => IrrClient::<String>::DEFAULT_PORT,
+    };
+    Ok(format!("{host}:{port}"))
+}
+
+/// The acknowledgement of the multiple command mode request sent by some
+/// servers.
+#[cfg(feature = "std-net")]
+const HANDSHAKE_ACK: &[u8] = b"C\n";
+
+/// Read and discard the acknowledgement of the multiple command mode request
+/// from `conn`, if one is received within `timeout`.
+#[cfg(feature = "std-net")]
+fn read_handshake_ack<T: Transport>(conn: &mut T, timeout: Duration) -> Result<(), Error> {
+    conn.set_read_timeout(Some(timeout))?;
+    let mut ack = Vec::with_capacity(HANDSHAKE_ACK.len());
+    let mut buf = [0; HANDSHAKE_ACK.len()];
+    while ack.len() < HANDSHAKE_ACK.len() {
+        match conn.read(&mut buf[..HANDSHAKE_ACK.len() - ack.len()]) {
+            Ok(0) => return Err(Error::ConnectionClosed),
+            Ok(read) => ack.extend_from_slice(&buf[..read]),
+            Err(err)
+                if ack.is_empty()
+                    && matches!(
+                        err.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+            {
+                tracing::debug!("no multiple command mode acknowledgement received");
+                break;
+            }
+            Err(err) => return Err(Error::from_transport(err, true)),
+        }
+        if !HANDSHAKE_ACK.starts_with(&ack) {
+            let received = String::from_utf8_lossy(&ack).into_owned();
+            tracing::error!("unexpected response to multiple command mode request: {received:?}");
+            return Err(Error::Handshake(received));
+        }
+    }
+    conn.set_read_timeout(None)?;
+    Ok(())
 }
 
+/// The settings required to (re-)establish a [`Connection`].
+#[cfg(feature = "std-net")]
+#[derive(Debug, Clone)]
+struct Endpoint {
+    addr: String,
+    client_id: String,
+    server_timeout: Option<Duration>,
+    tcp_keepalive: Option<Duration>,
+    persistent_mode: bool,
+    handshake_timeout: Option<Duration>,
+    pin_address: bool,
+    pinned: Option<SocketAddr>,
+}
+
+/// Without the `std-net` feature, connections can only be constructed over
+/// a caller-supplied stream, and so can never be re-established.
+#[cfg(not(feature = "std-net"))]
+#[derive(Debug, Clone)]
+enum Endpoint {}
+
 /// A connection to an [IRRd] server.
 ///
 /// Constructed by [`connect()`][IrrClient::connect()]. See the method
@@ -109,36 +421,512 @@ where
 ///
 /// [IRRd]: https://irrd.readthedocs.io/en/stable/
 #[derive(Debug)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Connection {
-    conn: TcpStream,
+    conn: Box<dyn Transport>,
+    persistent: bool,
+    used: bool,
+    client_id: Option<String>,
+    closed: bool,
+    endpoint: Option<Endpoint>,
+    max_queries: Option<NonZeroUsize>,
+    queries_sent: usize,
+    rotations: usize,
+    buffer_pool: Option<BufferPool>,
+    pipeline_active: bool,
+    sources: Option<Vec<String>>,
+    session: Vec<Query>,
+    normalize_sources: bool,
+    received: bool,
 }
 
 impl Connection {
     /// Default read buffer size allocated for new [`Pipeline`]s.
     pub const DEFAULT_CAPACITY: usize = 1 << 20;
 
-    #[allow(clippy::cognitive_complexity)]
+    /// Read timeout applied by [`ping()`][Self::ping].
+    pub const PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[cfg(feature = "std-net")]
     fn connect<A>(builder: &IrrClient<A>) -> Result<Self, Error>
     where
         A: ToSocketAddrs + fmt::Display,
     {
-        tracing::info!("trying to connect to {}", builder.addr);
-        let mut conn = TcpStream::connect(&builder.addr)?;
+        let mut endpoint = builder.endpoint();
+        let mut this = Self::establish(&mut endpoint)?;
+        this.endpoint = Some(endpoint);
+        this.max_queries = builder.max_queries_per_connection;
+        Ok(this)
+    }
+
+    #[cfg(feature = "std-net")]
+    #[allow(clippy::cognitive_complexity)]
+    fn establish(endpoint: &mut Endpoint) -> Result<Self, Error> {
+        let mut conn = if let Some(pinned) = endpoint.pinned {
+            tracing::info!(
+                "trying to connect to {} at pinned address {pinned}",
+                endpoint.addr
+            );
+            transport::connect_tcp(&pinned)?
+        } else {
+            tracing::info!("trying to connect to {}", endpoint.addr);
+            transport::connect_tcp(&endpoint.addr)?
+        };
+        if endpoint.pin_address && endpoint.pinned.is_none() {
+            let addr = conn.peer_addr()?;
+            tracing::debug!("pinning server address {addr}");
+            endpoint.pinned = Some(addr);
+        }
         tracing::debug!("disabling Nagle's algorithm");
         conn.set_nodelay(true)?;
+        if let Some(idle) = endpoint.tcp_keepalive {
+            tracing::debug!("enabling TCP keepalive after {idle:?} idle");
+            SockRef::from(&conn).set_tcp_keepalive(&TcpKeepalive::new().with_time(idle))?;
+        }
+        if !endpoint.persistent_mode {
+            tracing::info!("connected to {} in single query mode", endpoint.addr);
+            return Ok(Self::single_query(Box::new(conn)));
+        }
         tracing::debug!("requesting multiple command mode");
-        conn.write_all(b"!!\n")?;
+        conn.write_all(b"!!\n")
+            .map_err(|err| Error::from_transport(err, true))?;
         conn.flush()?;
-        tracing::info!("connected to {}", builder.addr);
-        let mut this = Self { conn };
+        if let Some(timeout) = endpoint.handshake_timeout {
+            read_handshake_ack(&mut conn, timeout)?;
+        }
+        tracing::info!("connected to {}", endpoint.addr);
+        let mut this = Self::from_transport(Box::new(conn));
+        this.initialize(&endpoint.client_id, endpoint.server_timeout)?;
+        this.queries_sent = 0;
+        Ok(this)
+    }
+
+    #[cfg(not(feature = "std-net"))]
+    const fn establish(_: &mut Endpoint) -> Result<Self, Error> {
+        Err(Error::NotReconnectable)
+    }
+
+    /// Close this [`Connection`], and establish a new one to the same server,
+    /// with the same settings.
+    ///
+    /// The server address is resolved again, unless it was pinned using
+    /// [`IrrClient::pin_address()`], and the connection setup queries are
+    /// re-sent. Any state set on the server during the previous
+    /// connection, such as the selected sources, is lost, and the sources
+    /// cached by [`selected_sources()`][Self::selected_sources] are
+    /// discarded.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::NotReconnectable`] is returned if this [`Connection`] was
+    /// not established using [`IrrClient::connect()`]. Otherwise, an error is
+    /// returned if the new connection cannot be established, in which case
+    /// the existing connection is left unchanged.
+    #[tracing::instrument(skip(self), level = "debug")]
+    pub fn reconnect(&mut self) -> Result<(), Error> {
+        let endpoint = self.endpoint.clone().ok_or(Error::NotReconnectable)?;
+        self.reconnect_to(endpoint, &[])
+    }
+
+    /// Close this [`Connection`], and establish a new one to the same server,
+    /// after discarding any address pinned using
+    /// [`IrrClient::pin_address()`].
+    ///
+    /// The server address is resolved again, and if address pinning is
+    /// enabled, the address of the new connection is pinned in its place.
+    /// Otherwise, this is equivalent to [`reconnect()`][Self::reconnect].
+    ///
+    /// # Errors
+    ///
+    /// As for [`reconnect()`][Self::reconnect]. If an error is returned, the
+    /// existing connection and pinned address are left unchanged.
+    #[cfg(feature = "std-net")]
+    #[tracing::instrument(skip(self), level = "debug")]
+    pub fn re_resolve(&mut self) -> Result<(), Error> {
+        let endpoint = self.endpoint.clone().ok_or(Error::NotReconnectable)?;
+        self.reconnect_to(
+            Endpoint {
+                pinned: None,
+                ..endpoint
+            },
+            &[],
+        )
+    }
+
+    /// The server IP address pinned for re-connections, if address pinning
+    /// was enabled using [`IrrClient::pin_address()`].
+    #[cfg(feature = "std-net")]
+    #[must_use]
+    pub fn pinned_addr(&self) -> Option<SocketAddr> {
+        self.endpoint.as_ref().and_then(|endpoint| endpoint.pinned)
+    }
+
+    /// Establish a new connection to `endpoint`, restore the server-side
+    /// `session` state on it, and replace this [`Connection`] with it.
+    fn reconnect_to(&mut self, mut endpoint: Endpoint, session: &[Query]) -> Result<(), Error> {
+        let mut fresh = Self::establish(&mut endpoint)?;
+        fresh.restore_session(session)?;
+        fresh.endpoint = Some(endpoint);
+        fresh.max_queries = self.max_queries;
+        fresh.rotations = self.rotations;
+        fresh.buffer_pool = self.buffer_pool.take();
+        fresh.pipeline_active = self.pipeline_active;
+        fresh.normalize_sources = self.normalize_sources;
+        drop(std::mem::replace(self, fresh));
+        Ok(())
+    }
+
+    /// Re-establish the connection after its query limit has been reached,
+    /// restoring the server-side state set by the queries sent so far.
+    pub(crate) fn rotate(&mut self) -> Result<(), Error> {
+        tracing::info!("query limit reached, rotating connection");
+        if let Some(query) = self
+            .session
+            .iter()
+            .find(|query| matches!(query, Query::Raw(_)))
         {
-            let mut init_pipeline = this.pipeline_with_capacity(8);
-            _ = init_pipeline.push(Query::SetClientId(builder.effective_client_id().to_owned()))?;
-            if let Some(server_timeout) = builder.server_timeout {
-                _ = init_pipeline.push(Query::SetTimeout(server_timeout))?;
+            tracing::error!("cannot restore state set by {query} on a new connection");
+            return Err(Error::StateNotRestorable(query.clone()));
+        }
+        let endpoint = self.endpoint.clone().ok_or(Error::NotReconnectable)?;
+        let session = self.session.clone();
+        self.reconnect_to(endpoint, &session)?;
+        self.rotations += 1;
+        Ok(())
+    }
+
+    /// Re-send the queries in `session` on this newly established
+    /// connection, checking that each is accepted.
+    fn restore_session(&mut self, session: &[Query]) -> Result<(), Error> {
+        if session.is_empty() {
+            return Ok(());
+        }
+        tracing::debug!("restoring connection state");
+        let mut pipeline = self.pipeline_with_capacity(1 << 12);
+        for query in session {
+            _ = pipeline.push(query.clone())?;
+        }
+        while let Some(response) = pipeline.pop::<String>() {
+            if let Err(err) = response {
+                tracing::error!("failed to restore connection state: {err}");
+                return Err(err);
             }
         }
-        Ok(this)
+        drop(pipeline);
+        self.queries_sent = 0;
+        Ok(())
+    }
+
+    /// The number of times that this [`Connection`] has been automatically
+    /// re-established, after reaching the limit set using
+    /// [`IrrClient::max_queries_per_connection()`].
+    ///
+    /// The state set on the server by the queries sent previously, such as
+    /// the selected sources, the client identification, and the server-side
+    /// timeout, is restored on each new connection.
+    #[must_use]
+    pub const fn rotations(&self) -> usize {
+        self.rotations
+    }
+
+    /// The number of further queries that may be sent before the
+    /// connection must be re-established.
+    pub(crate) fn query_budget(&self) -> usize {
+        self.max_queries.map_or(usize::MAX, |max| {
+            max.get().saturating_sub(self.queries_sent)
+        })
+    }
+
+    /// Send the connection setup queries, and check that each is
+    /// acknowledged by the server.
+    #[cfg_attr(not(feature = "std-net"), allow(dead_code))]
+    #[tracing::instrument(skip(self), level = "debug")]
+    fn initialize(
+        &mut self,
+        client_id: &str,
+        server_timeout: Option<Duration>,
+    ) -> Result<(), Error> {
+        let mut init_pipeline = self.pipeline_with_capacity(1 << 12);
+        let client_id = query::single_line(client_id);
+        _ = init_pipeline.push(Query::SetClientId(client_id.clone()))?;
+        if let Some(server_timeout) = server_timeout {
+            _ = init_pipeline.push(Query::SetTimeout(server_timeout))?;
+        }
+        while let Some(response_result) = init_pipeline.pop::<String>() {
+            if let Err(err) = response_result {
+                tracing::error!("connection setup failed: {err}");
+                return Err(err);
+            }
+        }
+        drop(init_pipeline);
+        self.client_id = Some(client_id);
+        Ok(())
+    }
+
+    /// Create a new [`Connection`] over an existing byte stream.
+    ///
+    /// This allows queries to be issued over transports other than TCP, or
+    /// over a mock stream for testing purposes.
+    ///
+    /// Unlike [`IrrClient::connect()`], no connection setup is performed:
+    /// the caller is responsible for ensuring that the server at the other end
+    /// of `stream` is already in multiple command mode.
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # fn main() -> Result<(), irrc::Error> {
+    /// use std::net::TcpStream;
+    /// use std::io::Write;
+    ///
+    /// use irrc::Connection;
+    ///
+    /// let mut stream = TcpStream::connect("whois.radb.net:43")?;
+    /// stream.write_all(b"!!\n")?;
+    /// let mut conn = Connection::from_stream(stream);
+    /// println!("{}", conn.version()?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_stream<S>(stream: S) -> Self
+    where
+        S: Read + Write + fmt::Debug + Send + 'static,
+    {
+        Self::from_transport(Box::new(Stream(stream)))
+    }
+
+    /// Create a new [`Connection`] that replays server output previously
+    /// captured using [`record_to()`][Self::record_to].
+    ///
+    /// Queries written to the connection are discarded, and responses are
+    /// read from `recording` instead. Provided that the same sequence of
+    /// queries is issued as when the recording was made, the responses will
+    /// be identical, allowing code using this crate to be tested offline and
+    /// deterministically, against the exact framing emitted by the server.
+    ///
+    /// As with [`from_stream()`][Self::from_stream], no connection setup is
+    /// performed.
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use std::fs::File;
+    ///
+    /// use irrc::Connection;
+    ///
+    /// let mut conn = Connection::from_recording(File::open("version.irr")?);
+    /// assert_eq!(conn.version()?, "IRRd -- version 4.4.0");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_recording<R>(recording: R) -> Self
+    where
+        R: Read + fmt::Debug + Send + 'static,
+    {
+        Self::from_transport(Box::new(Replay(recording)))
+    }
+
+    /// Copy all server output subsequently read from this [`Connection`] to
+    /// `sink`.
+    ///
+    /// The recording can be replayed using
+    /// [`from_recording()`][Self::from_recording]. Since responses to the
+    /// connection setup queries have already been read when this method is
+    /// called, they are not included in the recording.
+    ///
+    /// If writing to `sink` fails, an error is logged and the recording is
+    /// stopped. The [`Connection`] itself is unaffected.
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use std::fs::File;
+    ///
+    /// use irrc::IrrClient;
+    ///
+    /// let mut conn = IrrClient::new("whois.radb.net:43").connect()?;
+    /// conn.record_to(File::create("version.irr")?);
+    /// println!("{}", conn.version()?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn record_to<W>(&mut self, sink: W)
+    where
+        W: Write + fmt::Debug + Send + 'static,
+    {
+        let placeholder = Box::new(Replay(io::empty()));
+        let inner = std::mem::replace(&mut self.conn, placeholder);
+        self.conn = Box::new(Record::new(inner, sink));
+    }
+
+    pub(crate) fn from_transport(conn: Box<dyn Transport>) -> Self {
+        Self {
+            conn,
+            persistent: true,
+            used: false,
+            client_id: None,
+            closed: false,
+            endpoint: None,
+            max_queries: None,
+            queries_sent: 0,
+            rotations: 0,
+            buffer_pool: None,
+            pipeline_active: false,
+            sources: None,
+            session: Vec::new(),
+            normalize_sources: true,
+            received: false,
+        }
+    }
+
+    #[cfg_attr(not(feature = "std-net"), allow(dead_code))]
+    fn single_query(conn: Box<dyn Transport>) -> Self {
+        Self {
+            conn,
+            persistent: false,
+            used: false,
+            client_id: None,
+            closed: false,
+            endpoint: None,
+            max_queries: None,
+            queries_sent: 0,
+            rotations: 0,
+            buffer_pool: None,
+            pipeline_active: false,
+            sources: None,
+            session: Vec::new(),
+            normalize_sources: true,
+            received: false,
+        }
+    }
+
+    /// The client identification string sent to the server during connection
+    /// setup.
+    ///
+    /// This is the value set using [`IrrClient::client_id()`] (or the default)
+    /// after any control characters have been replaced. `None` is returned
+    /// for connections on which no identification string was sent, such as
+    /// those constructed using [`from_stream()`][Self::from_stream], or in
+    /// single query mode.
+    #[must_use]
+    pub fn client_id(&self) -> Option<&str> {
+        self.client_id.as_deref()
+    }
+
+    /// Send and report source names exactly as given, rather than in upper
+    /// case.
+    ///
+    /// IRRd treats source names case-insensitively, but reports them in
+    /// upper case. By default, source names are therefore upper-cased when
+    /// sent in a [`Query::SetSources`] or [`Query::JournalStatus`], when read
+    /// using [`get_sources()`][Self::get_sources], and when extracted from
+    /// the `source:` attribute of an RPSL object for
+    /// [`ResponseItem::source()`], so that they can be compared directly.
+    ///
+    /// This disables that normalization, for use with servers whose source
+    /// names are case-sensitive.
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # use irrc::{IrrClient, Error};
+    /// # fn main() -> Result<(), Error> {
+    /// let mut conn = IrrClient::new("irr.example.net:43")
+    ///     .connect()?
+    ///     .with_case_sensitive_sources();
+    /// conn.set_sources(&["MixedCase"])?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub const fn with_case_sensitive_sources(mut self) -> Self {
+        self.normalize_sources = false;
+        self
+    }
+
+    /// Whether source names are normalized to upper case.
+    pub(crate) const fn normalizes_sources(&self) -> bool {
+        self.normalize_sources
+    }
+
+    /// Re-use the read buffer of each [`Pipeline`] created on this
+    /// [`Connection`] once it is dropped, rather than allocating a new one
+    /// for the next.
+    ///
+    /// This avoids repeatedly allocating a buffer of
+    /// [`DEFAULT_CAPACITY`][Self::DEFAULT_CAPACITY] bytes when many
+    /// short-lived [`Pipeline`]s are created on a single connection, at the
+    /// cost of retaining the buffer for the lifetime of the [`Connection`].
+    ///
+    /// A retained buffer is only re-used if it is at least as large as the
+    /// capacity requested for the new [`Pipeline`].
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # use irrc::{IrrClient, Error};
+    /// # fn main() -> Result<(), Error> {
+    /// let mut conn = IrrClient::new("whois.radb.net:43")
+    ///     .connect()?
+    ///     .with_buffer_pool();
+    /// for _ in 0..10 {
+    ///     println!("{}", conn.version()?);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn with_buffer_pool(mut self) -> Self {
+        self.buffer_pool = Some(BufferPool::default());
+        self
+    }
+
+    /// Get a read buffer of at least `capacity` bytes for a new [`Pipeline`].
+    pub(crate) fn take_buffer(&mut self, capacity: usize) -> Buffer {
+        self.buffer_pool
+            .as_mut()
+            .and_then(|pool| pool.take(capacity))
+            .unwrap_or_else(|| Buffer::with_capacity(capacity))
+    }
+
+    /// Return the read buffer of a dropped [`Pipeline`] for re-use, if
+    /// buffer pooling is enabled.
+    pub(crate) fn return_buffer(&mut self, buf: Buffer) {
+        if let Some(pool) = self.buffer_pool.as_mut() {
+            pool.put(buf);
+        }
+    }
+
+    /// Record that a [`Pipeline`] has been created on this connection.
+    ///
+    /// The `&mut self` borrow taken by [`pipeline()`][Self::pipeline]
+    /// ordinarily ensures that at most one [`Pipeline`] exists for a
+    /// [`Connection`] at a time. [`pipeline_from_initial()`] and [`expand()`]
+    /// push queries through a raw pointer to their [`Pipeline`], and their
+    /// soundness depends on nothing else writing to the connection in the
+    /// meantime. In debug builds, a violation of this invariant causes a
+    /// panic here, rather than interleaved queries and responses.
+    ///
+    /// [`pipeline_from_initial()`]: Self::pipeline_from_initial
+    /// [`expand()`]: Self::expand
+    pub(crate) fn enter_pipeline(&mut self) {
+        debug_assert!(
+            !self.pipeline_active,
+            "a Pipeline was created while another Pipeline on the same Connection is still alive"
+        );
+        self.pipeline_active = true;
+    }
+
+    /// Record that the [`Pipeline`] using this connection has been dropped.
+    pub(crate) const fn exit_pipeline(&mut self) {
+        self.pipeline_active = false;
+    }
+
+    /// Whether the server is in multiple command mode.
+    pub(crate) const fn is_persistent(&self) -> bool {
+        self.persistent
     }
 
     /// Create a new query [`Pipeline`] using this connection.
@@ -191,21 +979,153 @@ impl Connection {
         Pipeline::from_initial(self, initial, f)
     }
 
-    /// Create a new query [`Pipeline`] from an iterator of [`Query`] items.
-    pub fn pipeline_from_iter<I>(&mut self, iter: I) -> Pipeline<'_>
+    /// Execute an `initial` [`Query`], enqueue the follow-up queries created
+    /// from its response items by `make_followups`, and pass each item of the
+    /// follow-up responses to `on_result`.
+    ///
+    /// This combines [`pipeline_from_initial()`][Self::pipeline_from_initial]
+    /// with reading the follow-up responses, so that an expansion such as
+    /// `as-set` to routes can be streamed into a consumer in a single call.
+    /// The follow-up items are passed to `on_result` as they are read, and
+    /// are never collected.
+    ///
+    /// However, the follow-up responses can only be read once the initial
+    /// response has been read in full. Until then, the follow-up queries that
+    /// cannot yet be written to the server (see
+    /// [`Pipeline::max_in_flight_bytes()`]) are held in memory, which
+    /// therefore grows with the number of items in the initial response.
+    ///
+    /// The items of the initial response are parsed as `T`, and the items of
+    /// the follow-up responses as `U`. Errors encountered while reading the
+    /// follow-up responses are passed to `on_result` in place of the affected
+    /// items, as for [`Pipeline::responses()`].
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # use irrc::{IrrClient, Query, Error};
+    /// # fn main() -> Result<(), Error> {
+    /// use irrc::{AddressFamily, ResponseItem};
+    /// use rpsl::names::AutNum;
+    ///
+    /// let mut prefixes = Vec::new();
+    /// IrrClient::new("whois.radb.net:43").connect()?.expand_into(
+    ///     Query::AsSetMembersRecursive("AS-FOO".parse().unwrap()),
+    ///     |item: Result<ResponseItem<AutNum>, Error>| {
+    ///         let autnum = item.ok()?.into_content();
+    ///         Some(AddressFamily::ALL.map(|family| Query::routes(family, autnum)))
+    ///     },
+    ///     |item: Result<ResponseItem<String>, Error>| {
+    ///         if let Ok(item) = item {
+    ///             prefixes.push(item.into_content());
+    ///         }
+    ///     },
+    /// )?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// An [`Error`] is returned if a connection error is encountered during
+    /// the processing of the `initial` query.
+    pub fn expand_into<T, U, F, I, G>(
+        &mut self,
+        initial: Query,
+        make_followups: F,
+        on_result: G,
+    ) -> Result<(), Error>
     where
+        T: FromStr + fmt::Debug,
+        T::Err: std::error::Error + Send + Sync + 'static,
+        U: FromStr + fmt::Debug,
+        U::Err: std::error::Error + Send + Sync + 'static,
+        F: FnMut(Result<ResponseItem<T>, Error>) -> Option<I>,
         I: IntoIterator<Item = Query>,
+        G: FnMut(Result<ResponseItem<U>, Error>),
     {
-        let mut pipeline = self.pipeline();
-        pipeline.extend(iter);
-        pipeline
-    }
-
-    /// Create a new query [`Pipeline`] with a non-default read buffer size.
-    #[tracing::instrument(skip(self), level = "debug")]
-    pub fn pipeline_with_capacity(&mut self, capacity: usize) -> Pipeline<'_> {
-        tracing::debug!("constructing new query pipeline");
-        Pipeline::new(self, capacity)
+        Pipeline::from_initial(self, initial, make_followups)?
+            .responses()
+            .for_each(on_result);
+        Ok(())
+    }
+
+    /// Execute an `initial` [`Query`], and recursively expand the response
+    /// items into follow-up queries, returning an iterator over the items of
+    /// every response received.
+    ///
+    /// Each successfully parsed [`ResponseItem`] is passed to `f` before
+    /// being yielded, and the [`Query`]s that `f` returns are added to the
+    /// end of the underlying [`Pipeline`]. As with
+    /// [`pipeline_from_initial()`][Self::pipeline_from_initial], follow-up
+    /// queries are enqueued as soon as the item they are constructed from
+    /// has been read, but the expansion is driven entirely by pulling from
+    /// the returned iterator.
+    ///
+    /// The returned [`Expand`] iterator owns the [`Pipeline`], and so
+    /// exclusively borrows the [`Connection`] until it is dropped. Since the
+    /// iterator reads responses from the same [`Pipeline`] that `f` adds
+    /// queries to, items cannot borrow from the iterator, and `f` receives
+    /// each item only by reference.
+    ///
+    /// If a follow-up query returned by `f` cannot be added, for example
+    /// because it is invalid or the connection has failed, any remaining
+    /// queries returned by that call are not added, and the error is yielded
+    /// by the iterator immediately after the item they were constructed from.
+    ///
+    /// Any responses not read before the iterator is dropped are consumed
+    /// and discarded, without being passed to `f`.
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # use irrc::{IrrClient, Query, Error, ResponseItem};
+    /// # fn main() -> Result<(), Error> {
+    /// let mut conn = IrrClient::new("whois.radb.net:43").connect()?;
+    /// conn.expand(
+    ///     Query::as_set_members("AS-FOO")?,
+    ///     |item: &ResponseItem<String>| {
+    ///         item.content()
+    ///             .starts_with("AS-")
+    ///             .then(|| Query::as_set_members(item.content()).ok())
+    ///             .flatten()
+    ///     },
+    /// )?
+    /// .filter_map(Result::ok)
+    /// .for_each(|item| println!("{}", item.content()));
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// An [`Error`] is returned if the `initial` query cannot be added to the
+    /// [`Pipeline`].
+    pub fn expand<T, F, I>(&mut self, initial: Query, f: F) -> Result<Expand<'_, T, F>, Error>
+    where
+        T: FromStr + fmt::Debug,
+        T::Err: std::error::Error + Send + Sync + 'static,
+        F: FnMut(&ResponseItem<T>) -> I,
+        I: IntoIterator<Item = Query>,
+    {
+        Expand::new(self, initial, f)
+    }
+
+    /// Create a new query [`Pipeline`] from an iterator of [`Query`] items.
+    pub fn pipeline_from_iter<I>(&mut self, iter: I) -> Pipeline<'_>
+    where
+        I: IntoIterator<Item = Query>,
+    {
+        let mut pipeline = self.pipeline();
+        pipeline.extend(iter);
+        pipeline
+    }
+
+    /// Create a new query [`Pipeline`] with a non-default read buffer size.
+    #[tracing::instrument(skip(self), level = "debug")]
+    pub fn pipeline_with_capacity(&mut self, capacity: usize) -> Pipeline<'_> {
+        tracing::debug!("constructing new query pipeline");
+        Pipeline::new(self, capacity)
     }
 
     /// Get the server's version identification string.
@@ -227,26 +1147,1691 @@ impl Connection {
             .clone())
     }
 
+    /// Get the server's version, parsed to determine which optional features
+    /// are available.
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # use irrc::{IrrClient, Query, Error};
+    /// # fn main() -> Result<(), Error> {
+    /// use irrc::Capability;
+    ///
+    /// let mut conn = IrrClient::new("whois.radb.net:43").connect()?;
+    /// if conn.server_version()?.supports(Capability::AsSetPrefixes) {
+    ///     conn.pipeline()
+    ///         .push(Query::as_set_prefixes("AS-FOO", None)?)?
+    ///         .responses::<String>()
+    ///         .filter_map(Result::ok)
+    ///         .for_each(|prefix| println!("{}", prefix.content()));
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// An error is returned under the same conditions as for
+    /// [`version()`][Self::version].
+    pub fn server_version(&mut self) -> Result<ServerVersion, Error> {
+        self.query_one(Query::Version)
+    }
+
+    /// Issue `query`, and parse the single item expected in the response as
+    /// `T`.
+    ///
+    /// This is intended for queries that should return exactly one data
+    /// element, such as a [`Query::Origins`] lookup of a prefix with a single
+    /// origin, or a [`Query::RpslObject`] lookup of a unique primary key.
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # use irrc::{IrrClient, Query, Error, RpslObjectClass};
+    /// # fn main() -> Result<(), Error> {
+    /// let mut conn = IrrClient::new("whois.radb.net:43").connect()?;
+    /// let object: String = conn.query_one(Query::RpslObject(
+    ///     RpslObjectClass::AutNum,
+    ///     "AS65000".to_string(),
+    /// ))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::ExpectedSingle`] is returned if the response contains no
+    /// items, or more than one. Any further items are read and discarded
+    /// before returning, so that the connection remains usable.
+    ///
+    /// Error responses from the server are returned unchanged. In particular,
+    /// if the key queried for is present more than once in a single source,
+    /// the server returns an error response rather than multiple items, and
+    /// an [`Error::ResponseErr`] wrapping
+    /// [`KeyNotUnique`][error::Response::KeyNotUnique] is returned.
+    ///
+    /// An error is also returned if the single item cannot be parsed as `T`,
+    /// or if a failure occurs on the underlying connection.
+    pub fn query_one<T>(&mut self, query: Query) -> Result<T, Error>
+    where
+        T: FromStr + fmt::Debug,
+        T::Err: std::error::Error + Send + Sync + 'static,
+    {
+        self.pipeline()
+            .push(query)?
+            .pop::<T>()
+            .unwrap_or_else(|| Err(Error::Dequeue))?
+            .single()
+            .next()
+            .unwrap_or(Err(Error::Dequeue))
+            .map(ResponseItem::into_content)
+    }
+
+    /// Set the sources used to resolve subsequent queries on this
+    /// connection, in order of preference.
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if any of `sources` is not a valid query input,
+    /// if a failure occurs on the underlying TCP connection, or if the server
+    /// rejects the request, for example because a source is unknown.
+    pub fn set_sources<S: AsRef<str>>(&mut self, sources: &[S]) -> Result<(), Error> {
+        let query = Query::SetSources(
+            sources
+                .iter()
+                .map(|source| source.as_ref().to_owned())
+                .collect(),
+        );
+        _ = self
+            .pipeline()
+            .push(query)?
+            .pop::<String>()
+            .unwrap_or_else(|| Err(Error::Dequeue))?;
+        Ok(())
+    }
+
+    /// Get the sources currently used to resolve queries on this connection.
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if a failure occurs on the underlying TCP
+    /// connection, or if the response bytes cannot be parsed as UTF-8.
+    pub fn get_sources(&mut self) -> Result<Vec<String>, Error> {
+        let mut sources = Self::read_sources(&mut self.pipeline())?;
+        if self.normalize_sources {
+            for source in &mut sources {
+                source.make_ascii_uppercase();
+            }
+        }
+        self.sources = Some(sources.clone());
+        Ok(sources)
+    }
+
+    /// Get the sources currently used to resolve queries on this connection,
+    /// without a round-trip to the server if they are already known.
+    ///
+    /// The selected sources are recorded whenever the server accepts a
+    /// [`Query::SetSources`], on this [`Connection`] or a [`Pipeline`]
+    /// created from it, and whenever they are read using
+    /// [`get_sources()`][Self::get_sources]. Otherwise, for example after a
+    /// [`Query::UnsetSources`] or a [`reconnect()`][Self::reconnect], a
+    /// [`Query::GetSources`] is issued and its result recorded.
+    ///
+    /// Unless [`with_case_sensitive_sources()`][Self::with_case_sensitive_sources]
+    /// is used, source names are upper-cased.
+    ///
+    /// # Errors
+    ///
+    /// As for [`get_sources()`][Self::get_sources], if the sources are not
+    /// already known.
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # use irrc::{IrrClient, Error};
+    /// # fn main() -> Result<(), Error> {
+    /// let mut conn = IrrClient::new("whois.radb.net:43").connect()?;
+    /// conn.set_sources(&["RADB", "RIPE"])?;
+    /// // answered without a round-trip
+    /// assert_eq!(conn.selected_sources()?, ["RADB", "RIPE"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn selected_sources(&mut self) -> Result<&[String], Error> {
+        let sources = match self.sources.take() {
+            Some(sources) => sources,
+            None => self.get_sources()?,
+        };
+        Ok(self.sources.insert(sources))
+    }
+
+    /// Update the recorded server-side state following the response to
+    /// `query`, which was `accepted` by the server or not.
+    pub(crate) fn track_state(&mut self, query: &Query, accepted: bool) {
+        match query {
+            Query::SetSources(sources) if accepted => self.sources = Some(sources.clone()),
+            Query::SetSources(_) | Query::UnsetSources => self.sources = None,
+            Query::Raw(cmd) if cmd.starts_with("!s") => self.sources = None,
+            _ => {}
+        }
+        if accepted && query.changes_state() {
+            // only the latest query setting each kind of state is retained
+            let slot = |query: &Query| match query {
+                Query::UnsetSources => QueryKind::SetSources,
+                query => QueryKind::from(query),
+            };
+            self.session.retain(|prev| slot(prev) != slot(query));
+            self.session.push(query.clone());
+        }
+    }
+
+    fn read_sources(pipeline: &mut Pipeline<'_>) -> Result<Vec<String>, Error> {
+        let response = pipeline
+            .push(Query::GetSources)?
+            .pop::<String>()
+            .unwrap_or_else(|| Err(Error::Dequeue))?;
+        let mut sources = Vec::new();
+        for item in response {
+            sources.extend(
+                item?
+                    .content()
+                    .split(',')
+                    .filter(|source| !source.is_empty())
+                    .map(str::to_owned),
+            );
+        }
+        Ok(sources)
+    }
+
+    /// Issue `query` against each of the currently selected sources in turn,
+    /// and tag each item returned with the source that answered it.
+    ///
+    /// In multi-source mode, the responses to queries such as
+    /// [`Query::Ipv4Routes`], [`Query::Ipv6Routes`] and
+    /// [`Query::AsSetMembersRecursive`] merge the data from all selected
+    /// sources, without indicating which source each item was found in. This
+    /// method recovers that information by scoping the connection to a single
+    /// source with [`Query::SetSources`] before each repetition of `query`.
+    ///
+    /// The original source selection is read using
+    /// [`selected_sources()`][Self::selected_sources] beforehand and restored
+    /// afterwards, so up to `2 * n + 2` queries are issued for `n` selected
+    /// sources, and the server resolves `query` once per
+    /// source rather than once in total. The queries are pipelined, but this
+    /// is still considerably more expensive than issuing `query` directly,
+    /// and should only be used when the provenance of each item is required.
+    ///
+    /// Sources for which the server returns
+    /// [`KeyNotFound`][error::Response::KeyNotFound] contribute no items.
+    /// Items that fail to parse as `T` are logged and skipped.
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # use irrc::{IrrClient, Query, Error};
+    /// # fn main() -> Result<(), Error> {
+    /// let mut conn = IrrClient::new("whois.radb.net:43").connect()?;
+    /// conn.set_sources(&["RADB", "RIPE"])?;
+    /// let routes = conn.query_per_source::<String>(Query::Ipv4Routes("AS65000".parse().unwrap()))?;
+    /// for (source, route) in routes {
+    ///     println!("{route} (from {source})");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if a failure occurs on the underlying TCP
+    /// connection, or if the server returns any other error response.
+    ///
+    /// If an error is returned, the connection may remain scoped to a single
+    /// source.
+    pub fn query_per_source<T>(&mut self, query: Query) -> Result<Vec<(String, T)>, Error>
+    where
+        T: FromStr + fmt::Debug,
+        T::Err: std::error::Error + Send + Sync + 'static,
+    {
+        let sources = self.selected_sources()?.to_vec();
+        let mut pipeline = self.pipeline();
+        for (source, query) in sources.iter().zip(iter::repeat_n(query, sources.len())) {
+            _ = pipeline
+                .push(Query::SetSources(vec![source.clone()]))?
+                .push(query)?;
+        }
+        _ = pipeline.push(if sources.is_empty() {
+            Query::UnsetSources
+        } else {
+            Query::SetSources(sources)
+        })?;
+        let mut tagged = Vec::new();
+        let mut current = None;
+        while let Some(result) = pipeline.pop::<T>() {
+            let response = match result {
+                Ok(response) => response,
+                Err(Error::ResponseErr(query, error::Response::KeyNotFound)) => {
+                    tracing::debug!("no {query} found in source {current:?}");
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            if let Query::SetSources(selected) = response.query() {
+                current = selected.first().cloned();
+                continue;
+            }
+            let Some(source) = current.as_ref() else {
+                continue;
+            };
+            for result in response {
+                match result {
+                    Ok(item) => tagged.push((source.clone(), item.into_content())),
+                    Err(err) if err.is_fatal() => return Err(err),
+                    Err(err) => tracing::warn!("skipping item from source {source}: {err}"),
+                }
+            }
+        }
+        Ok(tagged)
+    }
+
+    /// Check that the server is still responsive.
+    ///
+    /// A [`Query::Version`] is issued, and the response checked for validity.
+    /// This is intended to be cheap enough to call before each re-use of a
+    /// long-lived or pooled [`Connection`].
+    ///
+    /// Reads are subject to a timeout of
+    /// [`PING_TIMEOUT`][Self::PING_TIMEOUT] for the duration of the check.
+    /// For connections constructed using [`from_stream()`][Self::from_stream],
+    /// no timeout is applied.
+    ///
+    /// Since the server only answers a single query when not in persistent
+    /// mode (see [`IrrClient::persistent_mode()`]), calling this method uses
+    /// up that query.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ConnectionClosed`] if the server has closed the
+    /// connection, an [`Error::Io`] of kind [`TimedOut`][io::ErrorKind::TimedOut]
+    /// if no response is received within the timeout, or another error if the
+    /// response is invalid.
+    ///
+    /// In any case, the [`Connection`] should be discarded if an error is
+    /// returned.
     #[tracing::instrument(skip(self), level = "debug")]
-    pub(crate) fn send(&mut self, query: &str) -> Result<(), Error> {
+    pub fn ping(&mut self) -> Result<(), Error> {
+        self.conn.set_read_timeout(Some(Self::PING_TIMEOUT))?;
+        let result = (|| {
+            self.pipeline_with_capacity(1 << 12)
+                .push(Query::Version)?
+                .pop::<String>()
+                .unwrap_or_else(|| Err(Error::Dequeue))
+                .and_then(Response::into_vec)
+                .and_then(|items| {
+                    if items.is_empty() {
+                        Err(Error::EmptyResponse(Query::Version))
+                    } else {
+                        Ok(())
+                    }
+                })
+        })()
+        .map_err(|err| match err {
+            Error::Io(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                Error::Io(io::ErrorKind::TimedOut.into())
+            }
+            err => err,
+        });
+        if let Err(err) = &result {
+            tracing::warn!("ping failed: {err}");
+        }
+        // restore the timeout even if the ping failed, preferring to report
+        // the failure of the ping itself
+        let restored = self.conn.set_read_timeout(None).map_err(Error::from);
+        result.and(restored)
+    }
+
+    /// Get all RPSL objects maintained by any of the provided maintainers.
+    ///
+    /// IRRd's `!o` query accepts only a single maintainer name, so a
+    /// [`Query::MntBy`] is issued for each of `mntners` using a single
+    /// [`Pipeline`], and the responses are merged.
+    ///
+    /// Objects maintained by more than one of `mntners` are returned only
+    /// once, de-duplicated on the primary key and `source:` of the object.
+    /// Maintainers for which the server returns
+    /// [`KeyNotFound`][error::Response::KeyNotFound] are skipped.
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if a failure occurs on the underlying TCP
+    /// connection, or if the server returns any other error response.
+    pub fn mnt_by_any<I>(&mut self, mntners: I) -> Result<Vec<String>, Error>
+    where
+        I: IntoIterator<Item = Mntner>,
+    {
+        let mut pipeline = self.pipeline();
+        _ = pipeline.push_mnt_by_any(mntners)?;
+        let mut objects = Vec::new();
+        for result in pipeline.responses::<String>().dedup_objects() {
+            match result {
+                Ok(item) => objects.push(item.into_content()),
+                Err(Error::ResponseErr(query, error::Response::KeyNotFound)) => {
+                    tracing::debug!("no objects found for {query}");
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(objects)
+    }
+
+    /// Get the RPSL objects of class `class` with each of the provided keys.
+    ///
+    /// IRRd has no command to fetch multiple objects by key in a single
+    /// query, so a [`Query::RpslObject`] is issued for each of `keys` using a
+    /// single [`Pipeline`], and the responses are collected in order. More
+    /// than one object may be returned for a key, if it exists in more than
+    /// one source.
+    ///
+    /// Keys for which the server returns
+    /// [`KeyNotFound`][error::Response::KeyNotFound] are skipped.
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # use irrc::{IrrClient, Error, RpslObjectClass};
+    /// # fn main() -> Result<(), Error> {
+    /// let mut conn = IrrClient::new("whois.radb.net:43").connect()?;
+    /// for object in conn.rpsl_objects(RpslObjectClass::AutNum, ["AS65000", "AS65001"])? {
+    ///     println!("{object}");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if any of `keys` is not a valid query input, if a
+    /// failure occurs on the underlying TCP connection, or if the server
+    /// returns any other error response.
+    pub fn rpsl_objects<I, K>(
+        &mut self,
+        class: RpslObjectClass,
+        keys: I,
+    ) -> Result<Vec<String>, Error>
+    where
+        I: IntoIterator<Item = K>,
+        K: AsRef<str>,
+    {
+        let mut pipeline = self.pipeline();
+        _ = pipeline.push_rpsl_objects(class, keys)?;
+        let mut objects = Vec::new();
+        for result in pipeline.responses::<String>() {
+            match result {
+                Ok(item) => objects.push(item.into_content()),
+                Err(Error::ResponseErr(query, error::Response::KeyNotFound)) => {
+                    tracing::debug!("no objects found for {query}");
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(objects)
+    }
+
+    /// Expand each of the provided `as-set`s into the `aut-num`s that are
+    /// members of it, directly or indirectly.
+    ///
+    /// A [`Query::AsSetMembersRecursive`] is issued for each of `as_sets`
+    /// using a single [`Pipeline`], and the members returned are attributed to
+    /// the `as-set` named in the query that they were returned in response
+    /// to. An `aut-num` that is a member of more than one of `as_sets` is
+    /// therefore included in the members of each. An `as-set` that appears
+    /// more than once in `as_sets` is queried only once.
+    ///
+    /// `as-set`s for which the server returns
+    /// [`KeyNotFound`][error::Response::KeyNotFound] are omitted from the
+    /// result. Members that fail to parse as an [`AutNum`] are logged and
+    /// skipped.
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # use irrc::{IrrClient, Error};
+    /// # fn main() -> Result<(), Error> {
+    /// let mut conn = IrrClient::new("whois.radb.net:43").connect()?;
+    /// let sets = ["AS-FOO", "AS-BAR"].map(|set| set.parse().unwrap());
+    /// for (set, members) in conn.as_set_members_by_set(sets)? {
+    ///     println!("{set}: {} members", members.len());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if a failure occurs on the underlying TCP
+    /// connection, or if the server returns any other error response.
+    pub fn as_set_members_by_set<I>(
+        &mut self,
+        as_sets: I,
+    ) -> Result<HashMap<AsSet, Vec<AutNum>>, Error>
+    where
+        I: IntoIterator<Item = AsSet>,
+    {
+        let mut seen = HashSet::new();
+        let as_sets = as_sets
+            .into_iter()
+            .filter(|as_set| seen.insert(as_set.clone()));
+        let mut pipeline = self.pipeline();
+        _ = pipeline.push_as_set_members_recursive(as_sets)?;
+        let mut members: HashMap<_, Vec<_>> = HashMap::new();
+        for result in pipeline.responses::<AutNum>() {
+            match result {
+                Ok(item) => {
+                    if let Query::AsSetMembersRecursive(as_set) = item.query() {
+                        members
+                            .entry(as_set.clone())
+                            .or_default()
+                            .push(item.into_content());
+                    }
+                }
+                Err(Error::ResponseErr(query, error::Response::KeyNotFound)) => {
+                    tracing::debug!("no {query} found");
+                }
+                Err(err) if err.is_fatal() => return Err(err),
+                Err(err @ Error::ResponseErr(..)) => return Err(err),
+                Err(err) => tracing::warn!("skipping as-set member: {err}"),
+            }
+        }
+        Ok(members)
+    }
+
+    /// Read the changes to the objects of `source` recorded in the journal of
+    /// the server, starting at the change with serial number `serial`.
+    ///
+    /// This is used to keep a local copy of a source up-to-date
+    /// incrementally, after an initial full copy has been loaded. The
+    /// available range of serials can be found using
+    /// [`Query::JournalStatus`].
+    ///
+    /// The journal is requested using the NRTM version 3 command
+    /// `-g SOURCE:3:SERIAL-LAST`, and each `ADD` or `DEL` operation in the
+    /// response is yielded as a [`JournalEntry`], in order of serial number.
+    /// The server must permit NRTM access from the client address.
+    ///
+    /// Since the NRTM response is not framed like the responses to the
+    /// queries in a [`Pipeline`], the connection cannot be re-used
+    /// afterwards, and is consumed by this method. The returned [`Journal`]
+    /// reads from the connection as it is iterated, and closes it when
+    /// dropped.
+    ///
+    /// The server may also report that no newer changes are available, in
+    /// which case the [`Journal`] is empty.
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # use irrc::{IrrClient, Error};
+    /// # fn main() -> Result<(), Error> {
+    /// let journal = IrrClient::new("whois.radb.net:43")
+    ///     .connect()?
+    ///     .journal_since("RADB", 2_351_000)?;
+    /// for entry in journal {
+    ///     let entry = entry?;
+    ///     println!("{} {} {}", entry.serial(), entry.operation(), entry.object().key());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::InvalidQueryInput`] is returned if `source` is not a valid
+    /// source name, and an error is returned if the request cannot be sent.
+    ///
+    /// An [`Error::Journal`] is yielded by the [`Journal`] if the server
+    /// rejects the request, or sends a response that cannot be parsed.
+    pub fn journal_since(self, source: &str, serial: u32) -> Result<Journal, Error> {
+        Journal::request(self, source, serial)
+    }
+
+    /// Expand an `as-set` into the hierarchy of `as-set`s that it references.
+    ///
+    /// This is a convenience wrapper around [`Pipeline::as_set_tree()`],
+    /// using a new [`Pipeline`].
+    ///
+    /// # Errors
+    ///
+    /// See [`Pipeline::as_set_tree()`].
+    pub fn as_set_tree(&mut self, as_set: AsSet) -> Result<AsSetTree, Error> {
+        self.pipeline().as_set_tree(as_set)
+    }
+
+    /// Expand an `as-set` into the set of IPv4 and IPv6 prefixes originated
+    /// by its members.
+    ///
+    /// A [`Query::AsSetMembersRecursive`] is issued for `as-set`, and
+    /// [`Query::Ipv4Routes`] and [`Query::Ipv6Routes`] queries are enqueued
+    /// for each `aut-num` as soon as it is read, using
+    /// [`pipeline_from_initial()`][Self::pipeline_from_initial]. The
+    /// resulting prefixes are aggregated into a single [`PrefixSet`].
+    ///
+    /// Response items that fail to parse, and error responses from the
+    /// server, are logged and skipped.
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # use irrc::{IrrClient, Error};
+    /// # fn main() -> Result<(), Error> {
+    /// let prefixes = IrrClient::new("whois.radb.net:43")
+    ///     .connect()?
+    ///     .as_set_prefixes("AS-FOO".parse().unwrap())?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if an error occurs after which the underlying
+    /// connection is no longer usable. See [`Error::is_fatal()`].
+    #[cfg(feature = "ip")]
+    pub fn as_set_prefixes(&mut self, as_set: AsSet) -> Result<PrefixSet<Any>, Error> {
+        let mut pipeline = self.pipeline_from_initial(
+            Query::AsSetMembersRecursive(as_set),
+            |result: Result<ResponseItem<AutNum>, Error>| match result {
+                Ok(item) => {
+                    let autnum = item.into_content();
+                    Some([Query::Ipv4Routes(autnum), Query::Ipv6Routes(autnum)])
+                }
+                Err(err) => {
+                    tracing::warn!("failed to parse as-set member: {err}");
+                    None
+                }
+            },
+        )?;
+        let mut prefixes = PrefixSet::<Any>::default();
+        for result in pipeline.responses::<Prefix<Any>>() {
+            match result {
+                Ok(item) => prefixes.extend([item.into_content()]),
+                Err(err) if err.is_fatal() => return Err(err),
+                Err(err) => tracing::warn!("skipping route: {err}"),
+            }
+        }
+        Ok(prefixes)
+    }
+
+    /// Whether this [`Connection`] has been closed by
+    /// [`Pipeline::abort_remaining()`].
+    ///
+    /// A closed [`Connection`] cannot be used to issue further queries, and
+    /// should be discarded and replaced with a new one.
+    #[must_use]
+    pub const fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Close this [`Connection`], sending the quit command to the server and
+    /// shutting down the underlying transport.
+    ///
+    /// Dropping a [`Connection`] does the same, but can only log any failure.
+    /// This method should be used instead where the caller needs to know
+    /// whether the connection was shut down cleanly.
+    ///
+    /// Any outstanding responses are discarded. Closing a connection that has
+    /// already been closed by [`Pipeline::abort_remaining()`] does nothing.
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if writing the quit command to, or shutting down,
+    /// the underlying transport fails. The transport is shut down in either
+    /// case.
+    pub fn close(mut self) -> Result<(), Error> {
+        self.quit(None)
+    }
+
+    /// Close this [`Connection`] as with [`close()`][Self::close], waiting
+    /// up to `timeout` for the server to acknowledge the quit command by
+    /// closing its end of the connection.
+    ///
+    /// # Errors
+    ///
+    /// As for [`close()`][Self::close]. Additionally, an [`Error::Io`] is
+    /// returned if the server has not closed the connection within `timeout`.
+    pub fn close_and_wait(mut self, timeout: Duration) -> Result<(), Error> {
+        self.quit(Some(timeout))
+    }
+
+    /// Send the quit command if the connection is persistent, optionally
+    /// wait for the server to close the connection, and shut down the
+    /// underlying transport.
+    #[tracing::instrument(skip(self), level = "debug")]
+    fn quit(&mut self, wait: Option<Duration>) -> Result<(), Error> {
+        if self.closed {
+            return Ok(());
+        }
+        tracing::info!("closing connection");
+        self.closed = true;
+        let result = self
+            .send_quit()
+            .and_then(|()| wait.map_or(Ok(()), |timeout| self.wait_for_eof(timeout)));
+        let shutdown = self.conn.shutdown().map_err(Error::from);
+        result.and(shutdown)
+    }
+
+    fn send_quit(&mut self) -> Result<(), Error> {
+        if self.persistent {
+            tracing::trace!(target: WIRE_TARGET, sent = %b"!q\n".escape_ascii());
+            self.conn.write_all(b"!q\n")?;
+            self.conn.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Read and discard any data from the server until it closes the
+    /// connection, giving up once `timeout` has elapsed in total.
+    fn wait_for_eof(&mut self, timeout: Duration) -> Result<(), Error> {
+        let deadline = Instant::now() + timeout;
+        let mut buf = [0; 512];
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "server did not close the connection before the timeout",
+                )
+                .into());
+            }
+            self.conn.set_read_timeout(Some(remaining))?;
+            match self.conn.read(&mut buf)? {
+                0 => break Ok(()),
+                read => tracing::debug!("discarding {read} bytes received after quit"),
+            }
+        }
+    }
+
+    /// Shut down the underlying transport without reading any outstanding
+    /// responses, and prevent any further use.
+    #[tracing::instrument(skip(self), level = "debug")]
+    pub(crate) fn abort(&mut self) {
+        if !self.closed {
+            tracing::info!("closing connection with responses outstanding");
+            self.closed = true;
+            if let Err(err) = self.conn.shutdown() {
+                tracing::error!("failed to close connection: {err}");
+            }
+        }
+    }
+
+    /// Write `query` to the server.
+    ///
+    /// If a write times out, because the server has stopped reading queries
+    /// until the responses already sent are read, `on_stall` is called to
+    /// read them before the write is retried.
+    #[tracing::instrument(skip(self, on_stall), level = "debug")]
+    pub(crate) fn send<F>(&mut self, query: &str, mut on_stall: F) -> Result<(), Error>
+    where
+        F: FnMut(&mut Self) -> Result<(), Error>,
+    {
+        if self.closed {
+            return Err(Error::ConnectionClosed);
+        }
+        if !self.persistent && self.used {
+            tracing::error!("server has closed connection after previous query");
+            return Err(Error::ConnectionClosed);
+        }
+        self.used = true;
+        self.queries_sent += 1;
         tracing::debug!("sending query");
-        self.conn.write_all(query.as_bytes())?;
-        self.conn.flush().map_err(Error::from)
+        tracing::trace!(target: WIRE_TARGET, sent = %query.as_bytes().escape_ascii());
+        let mut remaining = query.as_bytes();
+        while !remaining.is_empty() {
+            match self.conn.write(remaining) {
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::WriteZero).into()),
+                Ok(written) => remaining = &remaining[written..],
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    tracing::debug!("write stalled, reading pending responses");
+                    on_stall(self)?;
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(Error::from_transport(err, !self.received)),
+            }
+        }
+        let fresh = !self.received;
+        self.conn
+            .flush()
+            .map_err(|err| Error::from_transport(err, fresh))
+    }
+
+    /// Set the timeout for blocking reads from the underlying transport,
+    /// where supported.
+    pub(crate) fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<(), Error> {
+        self.conn.set_read_timeout(timeout).map_err(Error::from)
+    }
+
+    /// Set the timeout for blocking writes to the underlying transport,
+    /// where supported.
+    pub(crate) fn set_write_timeout(&mut self, timeout: Option<Duration>) -> Result<(), Error> {
+        self.conn.set_write_timeout(timeout).map_err(Error::from)
     }
 
     pub(crate) fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
-        self.conn.read(buf).map_err(Error::from)
+        if self.closed {
+            return Err(Error::ConnectionClosed);
+        }
+        let fresh = !self.received;
+        let read = self
+            .conn
+            .read(buf)
+            .map_err(|err| Error::from_transport(err, fresh))?;
+        self.received |= read > 0;
+        tracing::trace!(target: WIRE_TARGET, received = %buf[..read].escape_ascii());
+        Ok(read)
     }
 }
 
+/// The `tracing` target used to log the raw bytes written to and read from a
+/// [`Connection`].
+///
+/// Events are emitted at [`TRACE`][tracing::Level::TRACE] level, with the
+/// bytes escaped as ASCII, within the spans of the query or response being
+/// processed. Enable the target to debug protocol framing issues, for
+/// example by setting `RUST_LOG=irrc::wire=trace` when using
+/// `tracing_subscriber::EnvFilter`. Since the bytes are only formatted if the
+/// target is enabled, the events have negligible cost otherwise.
+pub const WIRE_TARGET: &str = "irrc::wire";
+
 impl Drop for Connection {
     fn drop(&mut self) {
-        tracing::info!("closing connection");
-        if let Err(err) = self.conn.write(b"!q\n") {
-            tracing::error!("failed to send quit command: {err}");
-        }
-        if let Err(err) = self.conn.shutdown(Shutdown::Both) {
+        if let Err(err) = self.quit(None) {
             tracing::error!("failed to close connection: {err}");
         }
     }
 }
+
+/// A read buffer retained by a [`Connection`] for re-use by the next
+/// [`Pipeline`].
+#[derive(Default)]
+struct BufferPool(Option<Buffer>);
+
+impl BufferPool {
+    fn take(&mut self, capacity: usize) -> Option<Buffer> {
+        self.0.take_if(|buf| buf.capacity() >= capacity)
+    }
+
+    fn put(&mut self, mut buf: Buffer) {
+        // Bytes beyond the end of the available data are never exposed, so
+        // resetting the read and write positions is sufficient to prevent
+        // stale data reaching the next pipeline.
+        buf.reset();
+        self.0 = Some(buf);
+    }
+}
+
+impl fmt::Debug for BufferPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BufferPool")
+            .field("capacity", &self.0.as_ref().map(Buffer::capacity))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    #[cfg(feature = "std-net")]
+    use std::io::{BufRead, BufReader};
+    #[cfg(feature = "std-net")]
+    use std::net::{SocketAddr, TcpListener};
+    use std::sync::{Arc, Mutex};
+    #[cfg(feature = "std-net")]
+    use std::thread;
+
+    use crate::transport::mock::Mock;
+
+    use super::*;
+
+    /// Accept `connections` connections, answering connection setup queries
+    /// with an empty response, and `!v` with the index of the connection.
+    ///
+    /// Returns the number of `!v` queries received on each connection.
+    #[cfg(feature = "std-net")]
+    fn fake_server(connections: usize) -> (SocketAddr, thread::JoinHandle<Vec<usize>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            (0..connections)
+                .map(|i| {
+                    let (stream, _) = listener.accept().unwrap();
+                    thread::spawn(move || {
+                        let mut writer = stream.try_clone().unwrap();
+                        let mut count = 0;
+                        let mut sources = String::new();
+                        for line in BufReader::new(stream).lines() {
+                            match line.unwrap().as_str() {
+                                "!q" => break,
+                                "!v" => {
+                                    count += 1;
+                                    let data = format!("conn {i}{sources}\n");
+                                    write!(writer, "A{}\n{data}C\n", data.len()).unwrap();
+                                }
+                                set if set.starts_with("!s") => {
+                                    sources = format!(" {}", &set[2..]);
+                                    writer.write_all(b"C\n").unwrap();
+                                }
+                                setup if setup.starts_with("!n") || setup.starts_with("!t") => {
+                                    writer.write_all(b"C\n").unwrap();
+                                }
+                                _ => {}
+                            }
+                        }
+                        count
+                    })
+                })
+                .collect::<Vec<_>>()
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect()
+        });
+        (addr, handle)
+    }
+
+    /// Start a server on a local port that sends `ack` in response to `!!`,
+    /// and answers the connection setup and a single `!v` query.
+    #[cfg(feature = "std-net")]
+    fn acknowledging_server(ack: &'static [u8]) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        _ = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            for line in BufReader::new(stream).lines().map_while(Result::ok) {
+                let response: &[u8] = match line.as_str() {
+                    "!!" => ack,
+                    "!v" => b"A5\nIRRd\nC\n",
+                    "!q" => break,
+                    _ => b"C\n",
+                };
+                if writer.write_all(response).is_err() {
+                    break;
+                }
+            }
+        });
+        addr
+    }
+
+    #[cfg(feature = "std-net")]
+    #[test]
+    fn pinned_address_is_reused() {
+        let (addr, server) = fake_server(3);
+        let mut client = IrrClient::new(format!("localhost:{}", addr.port()));
+        client.pin_address(true);
+        let mut conn = client.connect().unwrap();
+        let pinned = conn.pinned_addr().unwrap();
+        assert!(pinned.ip().is_loopback());
+        assert_eq!(pinned.port(), addr.port());
+        conn.reconnect().unwrap();
+        assert_eq!(conn.pinned_addr(), Some(pinned));
+        conn.re_resolve().unwrap();
+        assert_eq!(
+            conn.pinned_addr().map(|addr| addr.port()),
+            Some(pinned.port())
+        );
+        drop(conn);
+        assert_eq!(server.join().unwrap(), vec![0, 0, 0]);
+        let conn = IrrClient::new(acknowledging_server(b"")).connect().unwrap();
+        assert!(conn.pinned_addr().is_none());
+    }
+
+    #[cfg(feature = "std-net")]
+    #[test]
+    fn handshake_acknowledgement_is_discarded() {
+        for ack in [&b"C\n"[..], b""] {
+            let mut client = IrrClient::new(acknowledging_server(ack));
+            client.handshake_timeout(Some(Duration::from_millis(100)));
+            let mut conn = client.connect().unwrap();
+            assert_eq!(conn.version().unwrap(), "IRRd");
+        }
+    }
+
+    #[cfg(feature = "std-net")]
+    #[test]
+    fn unexpected_handshake_response_is_rejected() {
+        let mut client = IrrClient::new(acknowledging_server(b"F unknown command\n"));
+        client.handshake_timeout(Some(Duration::from_millis(100)));
+        assert!(
+            matches!(client.connect(), Err(Error::Handshake(received)) if received.starts_with('F'))
+        );
+    }
+
+    #[test]
+    fn pipeline_buffers_are_reused() {
+        let stream = Mock::new(b"A5\nIRRd\nC\n");
+        let mut conn = Connection::from_stream(stream).with_buffer_pool();
+        assert_eq!(conn.version().unwrap(), "IRRd");
+        let pooled = |conn: &Connection| {
+            conn.buffer_pool.as_ref().and_then(|pool| {
+                pool.0
+                    .as_ref()
+                    .map(|buf| (buf.capacity(), buf.available_data()))
+            })
+        };
+        assert_eq!(pooled(&conn), Some((Connection::DEFAULT_CAPACITY, 0)));
+        drop(conn.pipeline_with_capacity(Connection::DEFAULT_CAPACITY * 2));
+        assert_eq!(pooled(&conn), Some((Connection::DEFAULT_CAPACITY * 2, 0)));
+        drop(conn.pipeline());
+        assert_eq!(pooled(&conn), Some((Connection::DEFAULT_CAPACITY * 2, 0)));
+    }
+
+    #[test]
+    fn query_one_returns_single_item() {
+        let query = || Query::Origins("192.0.2.0/24".to_string());
+        let mut conn = Connection::from_stream(Mock::new(b"A8\nAS65000\nC\n"));
+        let origin: AutNum = conn.query_one(query()).unwrap();
+        assert_eq!(origin, "AS65000".parse().unwrap());
+        for (data, count) in [(&b"C\n"[..], 0), (b"A16\nAS65000 AS65001\nC\n", 2)] {
+            let mut conn = Connection::from_stream(Mock::new(data));
+            assert!(matches!(
+                conn.query_one::<AutNum>(query()),
+                Err(Error::ExpectedSingle(_, n)) if n == count
+            ));
+        }
+        let mut conn = Connection::from_stream(Mock::new(b"E\n"));
+        assert!(matches!(
+            conn.query_one::<AutNum>(query()),
+            Err(Error::ResponseErr(_, error::Response::KeyNotUnique))
+        ));
+    }
+
+    #[test]
+    fn pipeline_buffers_are_not_pooled_by_default() {
+        let mut conn = Connection::from_stream(Mock::new(b"A5\nIRRd\nC\n"));
+        assert_eq!(conn.version().unwrap(), "IRRd");
+        assert!(conn.buffer_pool.is_none());
+    }
+
+    #[cfg(feature = "std-net")]
+    #[test]
+    fn connection_is_rotated_at_query_limit() {
+        let (addr, server) = fake_server(3);
+        let mut client = IrrClient::new(addr);
+        client.max_queries_per_connection(NonZeroUsize::new(2));
+        let mut conn = client.connect().unwrap();
+        let responses = conn
+            .pipeline_from_iter((0..5).map(|_| Query::Version))
+            .responses::<String>()
+            .map(|item| item.unwrap().into_content())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            responses,
+            vec!["conn 0", "conn 0", "conn 1", "conn 1", "conn 2"]
+        );
+        assert_eq!(conn.rotations(), 2);
+        drop(conn);
+        assert_eq!(server.join().unwrap(), vec![2, 2, 1]);
+    }
+
+    #[cfg(feature = "std-net")]
+    #[test]
+    fn session_state_is_restored_on_rotation() {
+        let (addr, server) = fake_server(2);
+        let mut client = IrrClient::new(addr);
+        client.max_queries_per_connection(NonZeroUsize::new(2));
+        let mut conn = client.connect().unwrap();
+        let responses = conn
+            .pipeline_from_iter([
+                Query::SetSources(vec!["RADB".to_string()]),
+                Query::Version,
+                Query::Version,
+                Query::Version,
+            ])
+            .responses::<String>()
+            .map(|item| item.unwrap().into_content())
+            .collect::<Vec<_>>();
+        assert_eq!(responses, vec!["conn 0 RADB", "conn 1 RADB", "conn 1 RADB"]);
+        assert_eq!(conn.rotations(), 1);
+        assert_eq!(conn.selected_sources().unwrap(), ["RADB"]);
+        drop(conn);
+        assert_eq!(server.join().unwrap(), vec![1, 2]);
+    }
+
+    #[cfg(feature = "std-net")]
+    #[test]
+    fn raw_state_prevents_rotation() {
+        let (addr, server) = fake_server(1);
+        let mut client = IrrClient::new(addr);
+        client.max_queries_per_connection(NonZeroUsize::new(2));
+        let mut conn = client.connect().unwrap();
+        let mut pipeline = conn.pipeline();
+        _ = pipeline
+            .push_raw("!sRADB\n")
+            .unwrap()
+            .push(Query::Version)
+            .unwrap()
+            .push(Query::Version)
+            .unwrap();
+        assert!(pipeline.pop_raw::<String>().unwrap().is_ok());
+        assert_eq!(
+            pipeline
+                .pop::<String>()
+                .unwrap()
+                .unwrap()
+                .into_vec()
+                .unwrap()[0]
+                .content(),
+            "conn 0 RADB"
+        );
+        assert!(matches!(
+            pipeline.pop::<String>().unwrap(),
+            Err(Error::StateNotRestorable(Query::Raw(_)))
+        ));
+        drop(pipeline);
+        drop(conn);
+        assert_eq!(server.join().unwrap(), vec![1]);
+    }
+
+    #[cfg(feature = "std-net")]
+    #[test]
+    fn server_urls_are_parsed() {
+        for (url, addr) in [
+            ("whois://whois.radb.net", "whois.radb.net:43"),
+            ("whois://whois.radb.net:4343/", "whois.radb.net:4343"),
+            ("IRR://192.0.2.1", "192.0.2.1:43"),
+            ("irr://[2001:db8::1]", "[2001:db8::1]:43"),
+            ("irr://[2001:db8::1]:43", "[2001:db8::1]:43"),
+        ] {
+            assert_eq!(parse_url(url).unwrap(), addr, "{url}");
+        }
+    }
+
+    #[cfg(feature = "std-net")]
+    #[test]
+    fn invalid_server_urls_are_rejected() {
+        for url in [
+            "whois.radb.net:43",
+            "whois://",
+            "whois://:43",
+            "whois://whois.radb.net:",
+            "whois://whois.radb.net:65536",
+            "whois://whois.radb.net:foo",
+            "whois://whois.radb.net/foo",
+            "whois://user@whois.radb.net",
+            "irr://2001:db8::1",
+        ] {
+            assert!(matches!(parse_url(url), Err(Error::InvalidUrl(_))), "{url}");
+        }
+        for url in ["irr+tls://whois.radb.net:443", "http://whois.radb.net"] {
+            assert!(
+                matches!(parse_url(url), Err(Error::UnsupportedScheme(_))),
+                "{url}"
+            );
+        }
+    }
+
+    #[test]
+    fn stream_connection_cannot_reconnect() {
+        let mut conn = Connection::from_stream(Mock::new(b""));
+        assert!(matches!(conn.reconnect(), Err(Error::NotReconnectable)));
+    }
+
+    #[test]
+    fn initialization_is_pipelined() {
+        let stream = Mock::new(b"C\nC\n");
+        let output = stream.output();
+        let mut conn = Connection::from_stream(stream);
+        conn.initialize("foo", Some(Duration::from_secs(30)))
+            .unwrap();
+        assert!(output.lock().unwrap().starts_with(b"!nfoo\n!t30\n"));
+        assert_eq!(conn.client_id(), Some("foo"));
+    }
+
+    #[test]
+    fn client_id_cannot_inject_queries() {
+        let stream = Mock::new(b"C\n");
+        let output = stream.output();
+        let mut conn = Connection::from_stream(stream);
+        assert_eq!(conn.client_id(), None);
+        conn.initialize("foo\n!q\r", None).unwrap();
+        assert_eq!(output.lock().unwrap().as_slice(), b"!nfoo !q \n");
+        assert_eq!(conn.client_id(), Some("foo !q "));
+    }
+
+    #[test]
+    fn initialization_checks_acknowledgements() {
+        let stream = Mock::new(b"C\nF Invalid value for timeout: 30\n");
+        let mut conn = Connection::from_stream(stream);
+        let err = conn
+            .initialize("foo", Some(Duration::from_secs(30)))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::ResponseErr(Query::SetTimeout(_), error::Response::MalformedQuery(_))
+        ));
+    }
+
+    #[test]
+    fn query_per_source_tags_items_with_source() {
+        let stream = Mock::responding(&[
+            b"A10\nRADB,RIPE\nC\n",
+            b"C\nA29\n192.0.2.0/24 198.51.100.0/24\nC\nC\nD\nC\n",
+        ]);
+        let output = stream.output();
+        let mut conn = Connection::from_stream(stream);
+        let routes = conn
+            .query_per_source::<String>(Query::Ipv4Routes("AS65000".parse().unwrap()))
+            .unwrap();
+        assert_eq!(
+            routes,
+            vec![
+                ("RADB".to_string(), "192.0.2.0/24".to_string()),
+                ("RADB".to_string(), "198.51.100.0/24".to_string()),
+            ]
+        );
+        assert_eq!(
+            output.lock().unwrap().as_slice(),
+            b"!s-lc\n!sRADB\n!gAS65000\n!sRIPE\n!gAS65000\n!sRADB,RIPE\n"
+        );
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "another Pipeline on the same Connection is still alive")]
+    fn concurrent_pipelines_are_detected() {
+        let mut conn = Connection::from_stream(Mock::new(b""));
+        conn.enter_pipeline();
+        conn.enter_pipeline();
+    }
+
+    #[test]
+    fn sequential_pipelines_are_allowed() {
+        let mut conn = Connection::from_stream(Mock::new(b""));
+        drop(conn.pipeline());
+        drop(conn.pipeline());
+        assert!(!conn.pipeline_active);
+    }
+
+    #[test]
+    fn server_version_is_parsed() {
+        let stream = Mock::new(b"A22\nIRRd -- version 4.4.2\nC\n");
+        let mut conn = Connection::from_stream(stream);
+        let version = conn.server_version().unwrap();
+        assert_eq!(version.version(), Some((4, 4, 2)));
+        assert!(version.supports(crate::Capability::AsSetPrefixes));
+    }
+
+    #[test]
+    fn ping_checks_response() {
+        let stream = Mock::new(b"A9\nIRRd 4.4\nC\n");
+        let output = stream.output();
+        let mut conn = Connection::from_stream(stream);
+        conn.ping().unwrap();
+        assert_eq!(output.lock().unwrap().as_slice(), b"!v\n");
+    }
+
+    #[test]
+    fn ping_detects_closed_connection() {
+        let mut conn = Connection::from_stream(Mock::new(b""));
+        assert!(matches!(conn.ping(), Err(Error::ConnectionClosed)));
+    }
+
+    /// A transport that records its read timeout, and fails all writes.
+    #[derive(Debug, Clone, Default)]
+    struct Unwritable(Arc<Mutex<Option<Duration>>>);
+
+    impl Read for Unwritable {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Ok(0)
+        }
+    }
+
+    impl Write for Unwritable {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Transport for Unwritable {
+        fn shutdown(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+            *self.0.lock().unwrap() = timeout;
+            Ok(())
+        }
+
+        fn set_write_timeout(&mut self, _: Option<Duration>) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn ping_restores_read_timeout_on_failure() {
+        let transport = Unwritable::default();
+        let timeout = Arc::clone(&transport.0);
+        let mut conn = Connection::from_transport(Box::new(transport));
+        assert!(matches!(conn.ping(), Err(Error::Io(_))));
+        assert_eq!(*timeout.lock().unwrap(), None);
+    }
+
+    #[derive(Debug, Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn wire_bytes_are_traced() {
+        let log = Shared::default();
+        let writer = log.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::TRACE)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        tracing::subscriber::with_default(subscriber, || {
+            let mut conn = Connection::from_stream(Mock::new(b"A9\nIRRd 4.4\nC\n"));
+            assert_eq!(conn.version().unwrap(), "IRRd 4.4");
+        });
+        let log = String::from_utf8(log.0.lock().unwrap().clone()).unwrap();
+        let wire = log
+            .lines()
+            .filter(|line| line.contains(WIRE_TARGET))
+            .collect::<Vec<_>>();
+        assert!(wire[0].ends_with(r"sent=!v\n"), "{}", wire[0]);
+        assert!(
+            wire[1].ends_with(r"received=A9\nIRRd 4.4\nC\n"),
+            "{}",
+            wire[1]
+        );
+    }
+
+    #[test]
+    fn recorded_session_is_replayed() {
+        let live = b"A9\nIRRd 4.4\nC\nA8\nAS65000\nC\n";
+        let recording = Shared::default();
+        let queries = || {
+            [
+                Query::Version,
+                Query::AsSetMembers("AS-FOO".parse().unwrap()),
+            ]
+        };
+        let mut conn = Connection::from_stream(Mock::new(live));
+        conn.record_to(recording.clone());
+        let recorded: Vec<String> = conn
+            .pipeline_from_iter(queries())
+            .responses()
+            .map(|item| item.unwrap().into_content())
+            .collect();
+        drop(conn);
+        assert_eq!(recording.0.lock().unwrap().as_slice(), live);
+
+        let replay = Cursor::new(recording.0.lock().unwrap().clone());
+        let mut conn = Connection::from_recording(replay);
+        let replayed: Vec<String> = conn
+            .pipeline_from_iter(queries())
+            .responses()
+            .map(|item| item.unwrap().into_content())
+            .collect();
+        assert_eq!(recorded, replayed);
+    }
+
+    #[derive(Debug)]
+    struct Full;
+
+    impl Write for Full {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::StorageFull.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Err(io::ErrorKind::StorageFull.into())
+        }
+    }
+
+    #[test]
+    fn recording_failure_does_not_affect_connection() {
+        let mut conn = Connection::from_stream(Mock::new(b"A9\nIRRd 4.4\nC\nA8\nAS65000\nC\n"));
+        conn.record_to(Full);
+        let items: Vec<String> = conn
+            .pipeline_from_iter([
+                Query::Version,
+                Query::AsSetMembers("AS-FOO".parse().unwrap()),
+            ])
+            .responses()
+            .map(|item| item.unwrap().into_content())
+            .collect();
+        assert_eq!(items, ["IRRd 4.4", "AS65000"]);
+    }
+
+    #[test]
+    fn single_query_mode() {
+        let stream = Mock::new(b"A8\nAS65000\nC\n");
+        let output = stream.output();
+        let mut conn = Connection::single_query(Box::new(Stream(stream)));
+        {
+            let mut pipeline = conn.pipeline();
+            _ = pipeline
+                .push(Query::AsSetMembers("AS-FOO".parse().unwrap()))
+                .unwrap()
+                .push(Query::AsSetMembers("AS-BAR".parse().unwrap()))
+                .unwrap();
+            let items = pipeline.pop::<String>().unwrap().unwrap().into_vec();
+            assert_eq!(items.unwrap()[0].content(), "AS65000");
+            assert!(matches!(
+                pipeline.pop::<String>(),
+                Some(Err(Error::ConnectionClosed))
+            ));
+            assert!(pipeline.pop::<String>().is_none());
+        }
+        drop(conn);
+        assert_eq!(output.lock().unwrap().as_slice(), b"!iAS-FOO\n");
+    }
+
+    #[test]
+    fn expand_into_streams_follow_up_items() {
+        let stream = Mock::new(b"A8\nAS1 AS2\nC\nA13\n192.0.2.0/24\nC\nD\n");
+        let output = stream.output();
+        let mut conn = Connection::from_stream(stream);
+        let mut routes = Vec::new();
+        let mut errors = 0;
+        conn.expand_into(
+            Query::AsSetMembersRecursive("AS-FOO".parse().unwrap()),
+            |item: Result<ResponseItem<AutNum>, Error>| {
+                item.ok().map(|item| Query::Ipv4Routes(item.into_content()))
+            },
+            |item: Result<ResponseItem<String>, Error>| match item {
+                Ok(item) => routes.push(item.into_content()),
+                Err(_) => errors += 1,
+            },
+        )
+        .unwrap();
+        assert_eq!(routes, ["192.0.2.0/24"]);
+        assert_eq!(errors, 1);
+        assert_eq!(
+            output.lock().unwrap().as_slice(),
+            b"!iAS-FOO,1\n!gAS1\n!gAS2\n"
+        );
+    }
+
+    #[test]
+    fn mnt_by_any_deduplicates_objects() {
+        let stream = Mock::new(
+            b"A45\nmntner: FOO-MNT\nsource: RADB\n\nas-set: AS-FOO\nC\n\
+              D\n\
+              A29\nmntner: FOO-MNT\nsource: RADB\nC\n",
+        );
+        let mut conn = Connection::from_stream(stream);
+        let objects = conn
+            .mnt_by_any(["FOO-MNT", "BAR-MNT", "BAZ-MNT"].map(|m| m.parse().unwrap()))
+            .unwrap();
+        assert_eq!(
+            objects,
+            vec!["mntner: FOO-MNT\nsource: RADB", "as-set: AS-FOO"]
+        );
+    }
+
+    #[test]
+    fn as_set_members_are_attributed_to_each_set() {
+        let stream = Mock::new(
+            b"A16\nAS65000 AS65001\nC\n\
+              D\n\
+              A20\nAS65001 foo AS65002\nC\n",
+        );
+        let output = stream.output();
+        let mut conn = Connection::from_stream(stream);
+        let sets: Vec<AsSet> = ["AS-FOO", "AS-BAR", "AS-BAZ"]
+            .iter()
+            .map(|set| set.parse().unwrap())
+            .collect();
+        let mut pipeline = conn.pipeline();
+        _ = pipeline
+            .push_as_set_members_recursive(sets.clone())
+            .unwrap();
+        let tagged = pipeline
+            .responses::<String>()
+            .filter_map(Result::ok)
+            .map(|item| (item.query().clone(), item.into_content()))
+            .collect::<Vec<_>>();
+        assert_eq!(tagged.len(), 5);
+        assert_eq!(
+            tagged[4],
+            (
+                Query::AsSetMembersRecursive(sets[2].clone()),
+                "AS65002".to_owned()
+            )
+        );
+        drop(pipeline);
+        assert_eq!(
+            output.lock().unwrap().as_slice(),
+            b"!iAS-FOO,1\n!iAS-BAR,1\n!iAS-BAZ,1\n"
+        );
+
+        let stream = Mock::new(
+            b"A16\nAS65000 AS65001\nC\n\
+              D\n\
+              A20\nAS65001 foo AS65002\nC\n",
+        );
+        let mut conn = Connection::from_stream(stream);
+        let members = conn.as_set_members_by_set(sets.clone()).unwrap();
+        assert_eq!(members.len(), 2);
+        assert!(!members.contains_key(&sets[1]));
+        let autnums = |autnums: &[&str]| -> Vec<AutNum> {
+            autnums
+                .iter()
+                .map(|autnum| autnum.parse().unwrap())
+                .collect()
+        };
+        assert_eq!(members[&sets[0]], autnums(&["AS65000", "AS65001"]));
+        assert_eq!(members[&sets[2]], autnums(&["AS65001", "AS65002"]));
+    }
+
+    #[test]
+    fn rpsl_objects_are_fetched_for_each_key() {
+        let stream = Mock::new(
+            b"A27\naut-num: AS65000\nsource: A\nC\n\
+              D\n\
+              A55\naut-num: AS65002\nsource: A\n\naut-num: AS65002\nsource: B\nC\n",
+        );
+        let output = stream.output();
+        let mut conn = Connection::from_stream(stream);
+        let objects = conn
+            .rpsl_objects(RpslObjectClass::AutNum, ["AS65000", "AS65001", "AS65002"])
+            .unwrap();
+        assert_eq!(
+            objects,
+            vec![
+                "aut-num: AS65000\nsource: A",
+                "aut-num: AS65002\nsource: A",
+                "aut-num: AS65002\nsource: B",
+            ]
+        );
+        assert_eq!(
+            output.lock().unwrap().as_slice(),
+            b"!maut-num,AS65000\n!maut-num,AS65001\n!maut-num,AS65002\n"
+        );
+    }
+
+    #[cfg(feature = "ip")]
+    #[test]
+    fn as_set_prefixes_are_collected() {
+        let stream = Mock::new(
+            b"A16\nAS65000 AS65001\nC\n\
+              A24\n192.0.2.0/24 10.0.0.0/8\nC\n\
+              A14\n2001:db8::/32\nC\n\
+              D\n\
+              A20\n2001:db8:1::/48 foo\nC\n",
+        );
+        let output = stream.output();
+        let mut conn = Connection::from_stream(stream);
+        let prefixes = conn.as_set_prefixes("AS-FOO".parse().unwrap()).unwrap();
+        assert_eq!(
+            prefixes,
+            [
+                "192.0.2.0/24",
+                "10.0.0.0/8",
+                "2001:db8::/32",
+                "2001:db8:1::/48"
+            ]
+            .into_iter()
+            .map(|prefix| prefix.parse::<Prefix<Any>>().unwrap())
+            .collect()
+        );
+        assert_eq!(
+            output.lock().unwrap().as_slice(),
+            b"!iAS-FOO,1\n!gAS65000\n!6AS65000\n!gAS65001\n!6AS65001\n"
+        );
+    }
+
+    #[test]
+    fn close_sends_quit_command() {
+        let stream = Mock::new(b"");
+        let output = stream.output();
+        let conn = Connection::from_stream(stream);
+        conn.close_and_wait(Duration::from_secs(1)).unwrap();
+        assert_eq!(output.lock().unwrap().as_slice(), b"!q\n");
+    }
+
+    /// A transport that trickles data, never closing the connection.
+    #[derive(Debug)]
+    struct Trickle;
+
+    impl Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            buf[0] = b'%';
+            Ok(1)
+        }
+    }
+
+    impl Write for Trickle {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Transport for Trickle {
+        fn shutdown(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn set_read_timeout(&mut self, _: Option<Duration>) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn set_write_timeout(&mut self, _: Option<Duration>) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn close_wait_is_bounded_by_total_timeout() {
+        let conn = Connection::from_transport(Box::new(Trickle));
+        let start = Instant::now();
+        match conn.close_and_wait(Duration::from_millis(100)) {
+            Err(Error::Io(err)) => assert_eq!(err.kind(), io::ErrorKind::TimedOut),
+            result => panic!("expected timeout, got {result:?}"),
+        }
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn close_after_abort_does_nothing() {
+        let stream = Mock::new(b"");
+        let output = stream.output();
+        let mut conn = Connection::from_stream(stream);
+        _ = conn.pipeline().abort_remaining();
+        conn.close().unwrap();
+        assert!(output.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn sources_are_set() {
+        let stream = Mock::new(b"C\n");
+        let output = stream.output();
+        let mut conn = Connection::from_stream(stream);
+        conn.set_sources(&["RADB", "RIPE"]).unwrap();
+        assert_eq!(output.lock().unwrap().as_slice(), b"!sRADB,RIPE\n");
+    }
+
+    #[test]
+    fn unknown_sources_are_rejected() {
+        let stream = Mock::new(b"F Unknown source(s): FOO\n");
+        let mut conn = Connection::from_stream(stream);
+        assert!(matches!(
+            conn.set_sources(&["FOO"]),
+            Err(Error::ResponseErr(Query::SetSources(_), _))
+        ));
+    }
+
+    #[test]
+    fn selected_sources_are_cached() {
+        let stream = Mock::responding(&[b"C\n", b"C\n", b"A10\nRADB,RIPE\nC\n"]);
+        let output = stream.output();
+        let mut conn = Connection::from_stream(stream);
+        conn.set_sources(&["NTTCOM"]).unwrap();
+        assert_eq!(conn.selected_sources().unwrap(), ["NTTCOM"]);
+        _ = conn
+            .pipeline()
+            .push(Query::UnsetSources)
+            .unwrap()
+            .pop::<String>()
+            .unwrap()
+            .unwrap();
+        for _ in 0..2 {
+            assert_eq!(conn.selected_sources().unwrap(), ["RADB", "RIPE"]);
+        }
+        assert_eq!(
+            output.lock().unwrap().as_slice(),
+            b"!sNTTCOM\n!s-*\n!s-lc\n"
+        );
+    }
+
+    #[test]
+    fn source_names_are_uppercased() {
+        let stream = Mock::responding(&[b"C\n", b"A10\nradb,Ripe\nC\n"]);
+        let output = stream.output();
+        let mut conn = Connection::from_stream(stream);
+        conn.set_sources(&["radb", "Ripe"]).unwrap();
+        assert_eq!(conn.selected_sources().unwrap(), ["RADB", "RIPE"]);
+        assert_eq!(conn.get_sources().unwrap(), ["RADB", "RIPE"]);
+        assert_eq!(output.lock().unwrap().as_slice(), b"!sRADB,RIPE\n!s-lc\n");
+    }
+
+    #[test]
+    fn case_sensitive_sources_are_preserved() {
+        let stream = Mock::responding(&[b"C\n", b"A10\nradb,Ripe\nC\n"]);
+        let output = stream.output();
+        let mut conn = Connection::from_stream(stream).with_case_sensitive_sources();
+        conn.set_sources(&["radb", "Ripe"]).unwrap();
+        assert_eq!(conn.selected_sources().unwrap(), ["radb", "Ripe"]);
+        assert_eq!(conn.get_sources().unwrap(), ["radb", "Ripe"]);
+        assert_eq!(output.lock().unwrap().as_slice(), b"!sradb,Ripe\n!s-lc\n");
+    }
+
+    #[test]
+    fn sources_are_listed() {
+        let stream = Mock::new(b"A15\nRADB,RIPE,ARIN\nC\n");
+        let output = stream.output();
+        let mut conn = Connection::from_stream(stream);
+        assert_eq!(conn.get_sources().unwrap(), vec!["RADB", "RIPE", "ARIN"]);
+        assert_eq!(output.lock().unwrap().as_slice(), b"!s-lc\n");
+    }
+}
@@ -1,9 +1,16 @@
 #![allow(single_use_lifetimes)]
 
+use std::fmt;
 use std::io;
+use std::net::SocketAddr;
 use std::num::ParseIntError;
+use std::time::Duration;
 
-use crate::{pipeline::Pipeline, query::Query};
+use crate::{
+    parse,
+    pipeline::Pipeline,
+    query::{AddressFamily, MatchMode, Query, RpslObjectClass},
+};
 
 /// Error responses returned by [IRRd].
 ///
@@ -18,11 +25,126 @@ pub enum Response {
     /// database.
     #[error("the query was valid, but there are multiple copies of the key in one database")]
     KeyNotUnique,
+    /// The server is limiting the rate at which queries are accepted.
+    ///
+    /// IRRd itself does not limit query rates, but some mirrors and the
+    /// proxies in front of them reject queries with a notice such as "rate
+    /// limit exceeded, try again later".
+    ///
+    /// Responses of this kind are reported as an [`Error::RateLimited`],
+    /// rather than an [`Error::ResponseErr`].
+    #[error("the query was rejected due to rate limiting: {0}")]
+    RateLimited(String),
+    /// The query referenced an RPSL object class that is invalid or not
+    /// supported by the server.
+    #[error("the query referenced an invalid object class: {0}")]
+    InvalidObjectClass(String),
+    /// The query was not understood by the server, or had missing or invalid
+    /// parameters.
+    #[error("the query was malformed: {0}")]
+    MalformedQuery(String),
     /// The query was invalid.
     #[error("the query was invalid: {0}")]
     Other(String),
 }
 
+impl Response {
+    /// Whether the same query might succeed if it is retried later.
+    ///
+    /// Only [`RateLimited`][Self::RateLimited] responses are considered
+    /// transient.
+    #[must_use]
+    pub const fn is_transient(&self) -> bool {
+        match self {
+            Self::RateLimited(_) => true,
+            Self::KeyNotFound
+            | Self::KeyNotUnique
+            | Self::InvalidObjectClass(_)
+            | Self::MalformedQuery(_)
+            | Self::Other(_) => false,
+        }
+    }
+}
+
+/// A data element returned in response to an address family specific
+/// [`Query`] belongs to a different address family.
+///
+/// For queries that look up `route` or `route6` objects by IP prefix, such
+/// as [`Query::RoutesExact`], this is returned if an object of the wrong
+/// class for the address family of the prefix is received.
+///
+/// Returned as the source of an [`Error::ParseItem`].
+#[derive(Debug, thiserror::Error)]
+#[error("expected an {expected} prefix, got '{item}'")]
+pub struct AddressFamilyMismatch {
+    pub(crate) expected: AddressFamily,
+    pub(crate) item: String,
+}
+
+impl AddressFamilyMismatch {
+    /// The address family expected for the [`Query`].
+    #[must_use]
+    pub const fn expected(&self) -> AddressFamily {
+        self.expected
+    }
+
+    /// The data element returned by the server.
+    ///
+    /// For queries that return whole RPSL objects, this is the first line of
+    /// the object.
+    #[must_use]
+    pub fn item(&self) -> &str {
+        &self.item
+    }
+}
+
+/// The position of a data element within the response to a [`Query`].
+///
+/// Attached to an [`Error::ParseItem`] returned while reading a response,
+/// to help locate malformed data. See
+/// [`item_position()`][Error::item_position].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ItemPosition {
+    pub(crate) index: usize,
+    pub(crate) offset: usize,
+    pub(crate) line: usize,
+}
+
+impl ItemPosition {
+    /// The zero-based index of the data element within the response,
+    /// including any elements skipped or rejected before it.
+    #[must_use]
+    pub const fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The byte offset of the start of the data element within the response
+    /// data.
+    #[must_use]
+    pub const fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// The one-based line number of the first line of the data element
+    /// within the response data.
+    #[must_use]
+    pub const fn line(&self) -> usize {
+        self.line
+    }
+}
+
+impl fmt::Display for ItemPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "item {} (line {}, byte offset {})",
+            self.index + 1,
+            self.line,
+            self.offset
+        )
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 #[error("{inner}")]
 pub(crate) struct Wrapper<'a, 'b> {
@@ -55,6 +177,67 @@ pub enum Error {
     /// IO errors on the underlying transport.
     #[error("an I/O error occurred: {0}")]
     Io(#[from] io::Error),
+    /// A connection could not be established to any of the addresses to which
+    /// the server address resolved.
+    #[error(
+        "failed to connect to server: [{}]",
+        .0.iter()
+            .map(|(addr, err)| format!("{addr}: {err}"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    )]
+    Connect(Vec<(SocketAddr, io::Error)>),
+    /// The server address did not resolve to any socket addresses.
+    #[error("the server address {0} did not resolve to any socket addresses")]
+    NoAddresses(String),
+    /// The connection was closed by the server.
+    #[error("the connection was closed by the server")]
+    ConnectionClosed,
+    /// The server is limiting the rate at which it accepts queries or
+    /// connections from this client.
+    ///
+    /// This is returned for an error response classified as
+    /// [`Response::RateLimited`], and when the connection is reset by the
+    /// server while connecting, or before any response data has been
+    /// received, as some servers do to shed load instead of sending an error
+    /// response. A reset at any other time is reported as an [`Error::Io`].
+    #[error(
+        "rate limited by the server{}",
+        .retry_after
+            .map(|delay| format!(", retry after {delay:?}"))
+            .unwrap_or_default()
+    )]
+    RateLimited {
+        /// The delay before retrying suggested by the server, if any.
+        retry_after: Option<Duration>,
+        /// The reset of the connection taken to indicate rate limiting, if
+        /// any.
+        #[source]
+        source: Option<io::Error>,
+    },
+    /// A [`Connection`][crate::Connection] that reached the limit set using
+    /// [`IrrClient::max_queries_per_connection()`][crate::IrrClient::max_queries_per_connection]
+    /// could not be re-established, because the server-side state set by a
+    /// query sent over it cannot be restored on a new connection.
+    ///
+    /// This is the case for [`Query::Raw`] commands, whose effect is unknown.
+    #[error("the connection state set by query {0:?} cannot be restored on a new connection")]
+    StateNotRestorable(Query),
+    /// An attempt was made to re-establish a [`Connection`][crate::Connection]
+    /// that was not established using
+    /// [`IrrClient::connect()`][crate::IrrClient::connect].
+    #[error("the connection cannot be re-established")]
+    NotReconnectable,
+    /// A server URL could not be parsed.
+    ///
+    /// See [`IrrClient::from_url()`][crate::IrrClient::from_url].
+    #[error("invalid server URL '{0}'")]
+    InvalidUrl(String),
+    /// A server URL has a scheme that is not supported.
+    ///
+    /// See [`IrrClient::from_url()`][crate::IrrClient::from_url].
+    #[error("unsupported URL scheme '{0}'")]
+    UnsupportedScheme(String),
     /// Failure parsing the "expected length" of a response.
     #[error("failed to decode response length: {0}")]
     BadLength(#[from] ParseIntError),
@@ -68,8 +251,23 @@ pub enum Error {
     #[error("fatal parsing erroring while trying to parse response: {0}")]
     ParseFailure(nom::Err<nom::error::Error<Vec<u8>>>),
     /// An error occurred while parsing a response item.
-    #[error("failed to parse item from response data: {0}")]
-    ParseItem(#[source] Box<dyn std::error::Error + Send + Sync>, usize),
+    ///
+    /// Contains the underlying error, the number of bytes occupied by the
+    /// item, and its position within the response, if known.
+    ///
+    /// Use [`item_error()`][Self::item_error] or
+    /// [`downcast_item_error()`][Self::downcast_item_error] to inspect the
+    /// underlying error, and [`item_position()`][Self::item_position] to
+    /// locate the item.
+    #[error(
+        "failed to parse {} from response data: {0}",
+        .2.map_or_else(|| "item".to_string(), |pos| pos.to_string())
+    )]
+    ParseItem(
+        #[source] Box<dyn std::error::Error + Send + Sync>,
+        usize,
+        Option<ItemPosition>,
+    ),
     /// Failed to de-queue a query response.
     #[error("failed to dequeue a query response from the pipeline")]
     Dequeue,
@@ -82,14 +280,313 @@ pub enum Error {
     #[error("attempted to extract items after EOR was reached")]
     ConsumedResponse,
     /// End of response marker was received before the expected data length had been reached.
-    #[error("premature end of response after {0} bytes: expected {1} bytes")]
-    ResponseDataUnderrun(usize, usize),
+    ///
+    /// Contains the [`Query`] to which the response was received, the number of
+    /// bytes of response data received, and the number expected.
+    #[error("premature end of response to query {0:?} after {1} bytes: expected {2} bytes")]
+    ResponseDataUnderrun(Query, usize, usize),
+    /// The connection was closed by the server before the expected data
+    /// length had been received.
+    ///
+    /// Contains the [`Query`] to which the response was received, the number of
+    /// bytes of response data received, and the number expected.
+    ///
+    /// This is distinct from a [`ConnectionClosed`][Self::ConnectionClosed]
+    /// error between responses, and typically indicates a server that is
+    /// failing part way through sending large responses.
+    #[error(
+        "connection closed during response to query {0:?} after {1} bytes: expected {2} bytes"
+    )]
+    ResponseTruncated(Query, usize, usize),
     /// Received all expected data without reaching end of response marker.
-    #[error("response data has over run the length indicated in the response preamble")]
-    ResponseDataOverrun(usize, usize),
+    ///
+    /// Contains the [`Query`] to which the response was received, the number of
+    /// bytes of response data received, and the number expected.
+    #[error(
+        "response data for query {0:?} has over run the length indicated in the response \
+         preamble: received {1} bytes, expected {2} bytes"
+    )]
+    ResponseDataOverrun(Query, usize, usize),
+    /// The response data length indicated by the server exceeds the maximum
+    /// configured on the [`Pipeline`].
+    #[error("response length of {0} bytes exceeds the configured maximum")]
+    ResponseTooLarge(usize),
+    /// A single response item does not fit in the read buffer, even at the
+    /// maximum response data length configured on the [`Pipeline`].
+    #[error("response item does not fit in read buffer of {0} bytes")]
+    ItemTooLarge(usize),
+    /// A response contained more items than the limit set using
+    /// [`Pipeline::max_items_per_response()`][crate::Pipeline::max_items_per_response].
+    ///
+    /// Contains the [`Query`] to which the response was received, and the
+    /// limit.
+    #[error("response to query {0:?} contains more than {1} items")]
+    TooManyItems(Query, usize),
+    /// No response item was received within the timeout set using
+    /// [`Response::item_timeout()`][crate::Response::item_timeout].
+    ///
+    /// The rest of the response may still be in flight: see the method
+    /// documentation for how to re-synchronize.
+    #[error("timed out waiting for response item")]
+    ItemTimeout,
+    /// An invalid IP prefix was provided when constructing a [`Query`].
+    #[error("invalid IP prefix '{0}'")]
+    InvalidPrefix(String),
+    /// An invalid RPSL range operator was encountered, or a range operator
+    /// selects prefix lengths that are not valid for the prefix it follows.
+    #[error("invalid range operator in '{0}'")]
+    InvalidRangeOperator(String),
+    /// A prefix length bound provided when constructing a [`Query`] is not
+    /// valid for the prefix it applies to.
+    #[error("invalid prefix length bound /{1} for prefix '{0}'")]
+    InvalidLengthBound(String, u8),
+    /// The server sent an unexpected response to the multiple command mode
+    /// request during connection setup.
+    ///
+    /// See [`IrrClient::handshake_timeout()`][crate::IrrClient::handshake_timeout].
+    #[error("unexpected response to multiple command mode request: {0:?}")]
+    Handshake(String),
+    /// An AS number range could not be parsed, or its first AS number is
+    /// greater than its last.
+    ///
+    /// See [`AsBlock`][crate::AsBlock].
+    #[error("invalid as-block range '{0}'")]
+    InvalidAsBlock(String),
+    /// An RPSL object could not be parsed.
+    ///
+    /// Contains the offending line, or the whole input if it contains no
+    /// attributes.
+    ///
+    /// See [`RpslObject`][crate::RpslObject].
+    #[error("invalid RPSL object: {0:?}")]
+    InvalidRpslObject(String),
+    /// The server rejected a request for the journal of a source, or sent a
+    /// journal that could not be parsed.
+    ///
+    /// See [`Connection::journal_since()`][crate::Connection::journal_since].
+    #[error("failed to read journal: {0}")]
+    Journal(String),
+    /// A value provided when constructing a [`Query`] is invalid, or
+    /// contains characters that would alter the framing of the command sent
+    /// to the server.
+    #[error("invalid query input {0:?}")]
+    InvalidQueryInput(String),
+    /// A [`MatchMode`] was provided that is not supported for the RPSL object
+    /// class being queried.
+    #[error("{1} key matching is not supported for {0} objects")]
+    UnsupportedMatchMode(RpslObjectClass, MatchMode),
+    /// A command string could not be converted into a [`Query`], because it
+    /// is not a query command supported by this crate.
+    ///
+    /// See the [`TryFrom`] implementation for [`Query`].
+    #[error("unrecognized query command {0:?}")]
+    UnrecognizedCommand(String),
+    /// The options set on a [`RouteQuery`][crate::RouteQuery] cannot be
+    /// combined into a single [`Query`].
+    #[error("invalid route query for {0}")]
+    InvalidRouteQuery(String),
     /// Received a zero-length response for a [`Query`] that should always return data.
+    ///
+    /// See [`Pipeline::allow_empty_response()`][crate::Pipeline::allow_empty_response].
     #[error("unexpectedly empty response received for query {0:?}")]
     EmptyResponse(Query),
+    /// A response to a [`Query`] expected to return a single item contained
+    /// some other number of items.
+    ///
+    /// See [`Connection::query_one()`][crate::Connection::query_one].
+    #[error("expected a single item in response to query {0:?}, received {1}")]
+    ExpectedSingle(Query, usize),
+}
+
+impl Error {
+    /// Construct the [`Error`] for the error `response` of the server to
+    /// `query`.
+    pub(crate) fn from_response(query: Query, response: Response) -> Self {
+        match response {
+            Response::RateLimited(msg) => {
+                tracing::warn!("rate limited by the server: {msg}");
+                Self::RateLimited {
+                    retry_after: parse::retry_after(&msg),
+                    source: None,
+                }
+            }
+            response => Self::ResponseErr(query, response),
+        }
+    }
+
+    /// Construct the [`Error`] for a failed read from, or write to, the
+    /// underlying transport.
+    ///
+    /// A reset of the connection by the server is taken to indicate rate
+    /// limiting only if `fresh`, i.e. while connecting or before any
+    /// response data has been received.
+    pub(crate) fn from_transport(err: io::Error, fresh: bool) -> Self {
+        if fresh && err.kind() == io::ErrorKind::ConnectionReset {
+            tracing::warn!("connection reset by the server, assuming rate limiting");
+            Self::RateLimited {
+                retry_after: None,
+                source: Some(err),
+            }
+        } else {
+            Self::Io(err)
+        }
+    }
+
+    /// The underlying error, if this is an [`Error::ParseItem`].
+    ///
+    /// This is one of:
+    ///
+    /// - the [`FromStr::Err`][std::str::FromStr::Err] of the type that the
+    ///   item was being parsed as;
+    /// - a [`Utf8Error`][std::str::Utf8Error], if the item data is not valid
+    ///   UTF-8 (invalid sequences in the response to a
+    ///   [`Query::Version`] are instead replaced); or
+    /// - an [`AddressFamilyMismatch`], if the item is a prefix of the wrong
+    ///   address family.
+    ///
+    /// Use [`downcast_ref()`][std::error::Error#method.downcast_ref] to
+    /// recover the concrete error type, for example when the items of
+    /// responses to different queries are parsed as different types.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::net::AddrParseError;
+    /// use std::str::Utf8Error;
+    ///
+    /// # fn handle(err: irrc::Error) {
+    /// if let Some(err) = err.item_error() {
+    ///     if let Some(err) = err.downcast_ref::<AddrParseError>() {
+    ///         println!("invalid address: {err}");
+    ///     } else if err.is::<Utf8Error>() {
+    ///         println!("item is not valid UTF-8");
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn item_error(&self) -> Option<&(dyn std::error::Error + Send + Sync + 'static)> {
+        match self {
+            Self::ParseItem(err, ..) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+
+    /// The position within the response of the item that could not be
+    /// parsed, if this is an [`Error::ParseItem`] returned while reading a
+    /// [`Response`][crate::Response].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn handle(err: irrc::Error) {
+    /// if let Some(pos) = err.item_position() {
+    ///     println!("malformed object {} at line {}", pos.index() + 1, pos.line());
+    /// }
+    /// # }
+    /// ```
+    #[must_use]
+    pub const fn item_position(&self) -> Option<ItemPosition> {
+        match self {
+            Self::ParseItem(_, _, pos) => *pos,
+            _ => None,
+        }
+    }
+
+    /// The underlying error, if this is an [`Error::ParseItem`] caused by an
+    /// error of type `E`.
+    ///
+    /// This is a shorthand for calling
+    /// [`downcast_ref()`][std::error::Error#method.downcast_ref] on the
+    /// result of [`item_error()`][Self::item_error].
+    #[must_use]
+    pub fn downcast_item_error<E>(&self) -> Option<&E>
+    where
+        E: std::error::Error + 'static,
+    {
+        self.item_error().and_then(|err| err.downcast_ref())
+    }
+
+    /// Whether the operation that caused this error might succeed if retried,
+    /// possibly using a new [`Connection`][crate::Connection].
+    ///
+    /// The following errors are considered transient:
+    ///
+    /// - [`Error::Connect`], [`Error::ConnectionClosed`],
+    ///   [`Error::RateLimited`] and [`Error::ResponseTruncated`];
+    /// - [`Error::ItemTimeout`];
+    /// - [`Error::Io`], if the [`io::ErrorKind`] indicates a timeout,
+    ///   interruption or loss of the connection; and
+    /// - [`Error::ResponseErr`], if the server
+    ///   [`Response`] [`is_transient()`][Response::is_transient].
+    ///
+    /// All other errors, such as parsing and protocol errors, are not
+    /// transient: retrying the same query will produce the same error.
+    #[must_use]
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::Connect(_)
+            | Self::ConnectionClosed
+            | Self::RateLimited { .. }
+            | Self::ResponseTruncated(..)
+            | Self::ItemTimeout => true,
+            Self::Io(err) => matches!(
+                err.kind(),
+                io::ErrorKind::TimedOut
+                    | io::ErrorKind::WouldBlock
+                    | io::ErrorKind::Interrupted
+                    | io::ErrorKind::ConnectionRefused
+                    | io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::NotConnected
+                    | io::ErrorKind::BrokenPipe
+                    | io::ErrorKind::UnexpectedEof
+            ),
+            Self::ResponseErr(_, response) => response.is_transient(),
+            _ => false,
+        }
+    }
+
+    /// Whether the [`Connection`][crate::Connection] on which this error
+    /// occurred is no longer usable.
+    ///
+    /// This is the case for errors on the underlying transport, and for
+    /// errors that leave the client unable to determine where the next query
+    /// response begins:
+    ///
+    /// - [`Error::Io`], [`Error::ConnectionClosed`] and
+    ///   [`Error::ResponseTruncated`];
+    /// - [`Error::BadLength`], [`Error::ParseErr`] and
+    ///   [`Error::ParseFailure`];
+    /// - [`Error::UnexpectedData`], [`Error::ResponseTooLarge`] and
+    ///   [`Error::ItemTooLarge`]; and
+    /// - [`Error::ResponseDataUnderrun`] and [`Error::ResponseDataOverrun`].
+    ///
+    /// [`Error::RateLimited`] is also fatal: the connection may have been
+    /// reset, and otherwise further queries on it are likely to be rejected
+    /// until the server is ready to accept them.
+    ///
+    /// Other errors, such as server error responses and failures to parse an
+    /// individual [`ResponseItem`][crate::ResponseItem], affect only a single
+    /// query or item, and the connection may continue to be used.
+    #[must_use]
+    pub const fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            Self::Io(_)
+                | Self::ConnectionClosed
+                | Self::RateLimited { .. }
+                | Self::ResponseTruncated(..)
+                | Self::BadLength(_)
+                | Self::ParseErr
+                | Self::ParseFailure(_)
+                | Self::UnexpectedData(..)
+                | Self::ResponseTooLarge(_)
+                | Self::ItemTooLarge(_)
+                | Self::ResponseDataUnderrun(..)
+                | Self::ResponseDataOverrun(..)
+        )
+    }
 }
 
 impl From<Wrapper<'_, '_>> for Error {
@@ -107,3 +604,103 @@ impl From<nom::Err<nom::error::Error<&[u8]>>> for Error {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn connection_errors_are_transient_and_fatal() {
+        for err in [
+            Error::ConnectionClosed,
+            Error::ResponseTruncated(Query::Version, 1, 2),
+            Error::Io(io::ErrorKind::TimedOut.into()),
+            Error::Io(io::ErrorKind::ConnectionReset.into()),
+        ] {
+            assert!(err.is_transient(), "{err:?}");
+            assert!(err.is_fatal(), "{err:?}");
+        }
+    }
+
+    #[test]
+    fn protocol_errors_are_not_transient() {
+        for err in [
+            Error::Io(io::ErrorKind::InvalidData.into()),
+            Error::ParseErr,
+            Error::ResponseTooLarge(1),
+            Error::ResponseDataUnderrun(Query::Version, 1, 2),
+        ] {
+            assert!(!err.is_transient(), "{err:?}");
+            assert!(err.is_fatal(), "{err:?}");
+        }
+    }
+
+    #[test]
+    fn rate_limited_responses_are_transient() {
+        let err = Error::ResponseErr(Query::Version, Response::RateLimited("foo".to_string()));
+        assert!(err.is_transient());
+        assert!(!err.is_fatal());
+    }
+
+    #[test]
+    fn rate_limiting_is_detected() {
+        let err = Error::from_response(
+            Query::Version,
+            Response::RateLimited("rate limit exceeded, retry after 30s".to_string()),
+        );
+        assert!(matches!(
+            err,
+            Error::RateLimited {
+                retry_after: Some(delay),
+                source: None,
+            } if delay == Duration::from_secs(30)
+        ));
+        assert!(err.is_transient());
+        assert!(err.is_fatal());
+        let err = Error::from_transport(io::ErrorKind::ConnectionReset.into(), true);
+        assert!(matches!(
+            err,
+            Error::RateLimited {
+                retry_after: None,
+                source: Some(ref err),
+            } if err.kind() == io::ErrorKind::ConnectionReset
+        ));
+        assert!(err.is_transient());
+        assert!(err.is_fatal());
+        assert!(matches!(
+            Error::from_transport(io::ErrorKind::ConnectionReset.into(), false),
+            Error::Io(err) if err.kind() == io::ErrorKind::ConnectionReset
+        ));
+        assert!(matches!(
+            Error::from_response(Query::Version, Response::KeyNotFound),
+            Error::ResponseErr(Query::Version, Response::KeyNotFound)
+        ));
+    }
+
+    #[test]
+    fn query_errors_are_neither_transient_nor_fatal() {
+        for err in [
+            Error::ResponseErr(Query::Version, Response::KeyNotFound),
+            Error::ResponseErr(Query::Version, Response::Other("foo".to_string())),
+            Error::ParseItem("foo".into(), 3, None),
+            Error::EmptyResponse(Query::Version),
+        ] {
+            assert!(!err.is_transient(), "{err:?}");
+            assert!(!err.is_fatal(), "{err:?}");
+        }
+    }
+
+    #[test]
+    fn item_errors_can_be_downcast() {
+        use std::net::{AddrParseError, Ipv4Addr};
+        use std::str::Utf8Error;
+
+        let q = Query::Origins("192.0.2.0/24".to_string());
+        let err = q.parse_item::<Ipv4Addr>(b"foo bar", false).unwrap_err();
+        assert!(err.downcast_item_error::<AddrParseError>().is_some());
+        assert!(err.downcast_item_error::<Utf8Error>().is_none());
+        let err = q.parse_item::<Ipv4Addr>(b"\xff bar", false).unwrap_err();
+        assert!(err.item_error().unwrap().is::<Utf8Error>());
+        assert!(Error::ParseErr.item_error().is_none());
+    }
+}
@@ -0,0 +1,309 @@
+use std::fmt;
+use std::iter::FusedIterator;
+
+use crate::{client::Connection, error::Error, object::RpslObject};
+
+/// The change to an RPSL object recorded by a [`JournalEntry`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Operation {
+    /// The object was created or updated.
+    Add,
+    /// The object was deleted.
+    Delete,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Add => write!(f, "ADD"),
+            Self::Delete => write!(f, "DEL"),
+        }
+    }
+}
+
+/// A single change to an IRR source, read from the journal of the server.
+///
+/// Yielded by [`Journal`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JournalEntry {
+    operation: Operation,
+    serial: u32,
+    object: RpslObject,
+}
+
+impl JournalEntry {
+    /// Whether the object was added or deleted.
+    #[must_use]
+    pub const fn operation(&self) -> Operation {
+        self.operation
+    }
+
+    /// The serial number of the change.
+    #[must_use]
+    pub const fn serial(&self) -> u32 {
+        self.serial
+    }
+
+    /// The object, as added or as it was before deletion.
+    #[must_use]
+    pub const fn object(&self) -> &RpslObject {
+        &self.object
+    }
+
+    /// Consume the [`JournalEntry`], returning the object.
+    #[must_use]
+    #[allow(clippy::missing_const_for_fn)]
+    pub fn into_object(self) -> RpslObject {
+        self.object
+    }
+}
+
+/// The state of the NRTM stream being read by a [`Journal`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    Header,
+    Entries,
+    Finished,
+}
+
+/// Iterator returned by [`journal_since()`][Connection::journal_since]
+/// method.
+///
+/// See [`Connection::journal_since`] for details.
+#[derive(Debug)]
+pub struct Journal {
+    conn: Connection,
+    source: String,
+    buf: Vec<u8>,
+    pos: usize,
+    state: State,
+}
+
+impl Journal {
+    /// The command used to request the journal of `source` from `serial`
+    /// onwards.
+    fn cmd(source: &str, serial: u32) -> String {
+        format!("-g {source}:3:{serial}-LAST\n")
+    }
+
+    pub(crate) fn request(mut conn: Connection, source: &str, serial: u32) -> Result<Self, Error> {
+        if source.is_empty()
+            || source
+                .chars()
+                .any(|c| c.is_control() || c.is_whitespace() || c == ':')
+        {
+            return Err(Error::InvalidQueryInput(source.to_owned()));
+        }
+        let source = if conn.normalizes_sources() {
+            source.to_ascii_uppercase()
+        } else {
+            source.to_owned()
+        };
+        conn.send(&Self::cmd(&source, serial), |_| Ok(()))?;
+        Ok(Self {
+            conn,
+            source,
+            buf: Vec::new(),
+            pos: 0,
+            state: State::Header,
+        })
+    }
+
+    /// The name of the source whose journal is being read.
+    #[must_use]
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Read the next line from the connection, without the terminating
+    /// newline, or `None` if the connection was closed by the server.
+    fn read_line(&mut self) -> Result<Option<String>, Error> {
+        loop {
+            if let Some(end) = self.buf[self.pos..].iter().position(|&b| b == b'\n') {
+                let line = &self.buf[self.pos..self.pos + end];
+                let line = line.strip_suffix(b"\r").unwrap_or(line);
+                let line = String::from_utf8(line.to_vec())
+                    .map_err(|err| Error::Journal(format!("invalid line in journal: {err}")))?;
+                self.pos += end + 1;
+                return Ok(Some(line));
+            }
+            _ = self.buf.drain(..self.pos);
+            self.pos = 0;
+            let mut chunk = [0; 4096];
+            let read = self.conn.read(&mut chunk)?;
+            if read == 0 {
+                return Ok(None);
+            }
+            self.buf.extend_from_slice(&chunk[..read]);
+        }
+    }
+
+    /// Read lines until a non-blank line is found.
+    fn read_non_blank(&mut self) -> Result<String, Error> {
+        loop {
+            match self.read_line()? {
+                Some(line) if line.trim().is_empty() => {}
+                Some(line) => return Ok(line),
+                None => return Err(Error::ConnectionClosed),
+            }
+        }
+    }
+
+    /// Read the `%START` line, returning `false` if the server indicated
+    /// that there are no changes to return.
+    fn read_header(&mut self) -> Result<bool, Error> {
+        loop {
+            let line = self.read_non_blank()?;
+            if let Some(version) = line.strip_prefix("%START Version: ") {
+                tracing::debug!("reading journal: {line}");
+                return if version.starts_with("3 ") {
+                    Ok(true)
+                } else {
+                    Err(Error::Journal(format!("unsupported NRTM version: {line}")))
+                };
+            } else if line.starts_with("%ERROR") || line.starts_with("%% ERROR") {
+                tracing::error!("journal request rejected: {line}");
+                return Err(Error::Journal(line));
+            } else if line.contains("no newer updates available") {
+                tracing::debug!("journal is up-to-date: {line}");
+                return Ok(false);
+            } else if line.starts_with('%') {
+                tracing::debug!("skipping comment in journal: {line}");
+            } else {
+                return Err(Error::Journal(format!("expected %START, got {line:?}")));
+            }
+        }
+    }
+
+    /// Read the next entry, or `None` at the `%END` marker.
+    fn read_entry(&mut self) -> Result<Option<JournalEntry>, Error> {
+        let line = self.read_non_blank()?;
+        if line.starts_with("%END") {
+            return Ok(None);
+        }
+        let (operation, serial) = match line.split_once(' ') {
+            Some(("ADD", serial)) => (Operation::Add, serial),
+            Some(("DEL", serial)) => (Operation::Delete, serial),
+            _ => return Err(Error::Journal(format!("expected ADD or DEL, got {line:?}"))),
+        };
+        let serial = serial
+            .trim()
+            .parse()
+            .map_err(|_| Error::Journal(format!("invalid serial in {line:?}")))?;
+        let mut text = self.read_non_blank()?;
+        loop {
+            match self.read_line()? {
+                Some(line) if line.trim().is_empty() => break,
+                Some(line) => {
+                    text.push('\n');
+                    text.push_str(&line);
+                }
+                None => return Err(Error::ConnectionClosed),
+            }
+        }
+        Ok(Some(JournalEntry {
+            operation,
+            serial,
+            object: text.parse()?,
+        }))
+    }
+}
+
+impl Iterator for Journal {
+    type Item = Result<JournalEntry, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let result = match self.state {
+            State::Finished => return None,
+            State::Header => match self.read_header() {
+                Ok(true) => {
+                    self.state = State::Entries;
+                    self.read_entry()
+                }
+                Ok(false) => Ok(None),
+                Err(err) => Err(err),
+            },
+            State::Entries => self.read_entry(),
+        };
+        match result {
+            Ok(Some(entry)) => Some(Ok(entry)),
+            Ok(None) => {
+                self.state = State::Finished;
+                None
+            }
+            Err(err) => {
+                // the position of the next entry is unknown
+                self.state = State::Finished;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+impl FusedIterator for Journal {}
+
+#[cfg(test)]
+mod tests {
+    use crate::transport::mock::Mock;
+
+    use super::*;
+
+    #[test]
+    fn journal_entries_are_parsed() {
+        let stream = Mock::new(
+            b"% Comment\n\
+            %START Version: 3 RADB 10-11\n\
+            \n\
+            ADD 10\n\
+            \n\
+            route: 192.0.2.0/24\n\
+            origin: AS65000\n\
+            source: RADB\n\
+            \n\
+            DEL 11\n\
+            \n\
+            aut-num: AS65000\n\
+            source: RADB\n\
+            \n\
+            %END RADB\n\
+            \n",
+        );
+        let output = stream.output();
+        let conn = Connection::from_stream(stream);
+        let entries = conn
+            .journal_since("radb", 10)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(&output.lock().unwrap()[..], b"-g RADB:3:10-LAST\n!q\n");
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].operation(), Operation::Add);
+        assert_eq!(entries[0].serial(), 10);
+        assert_eq!(entries[0].object().key(), "192.0.2.0/24");
+        assert_eq!(entries[1].operation(), Operation::Delete);
+        assert_eq!(entries[1].serial(), 11);
+        assert_eq!(entries[1].object().class(), "aut-num");
+    }
+
+    #[test]
+    fn journal_errors_are_reported() {
+        let conn = Connection::from_stream(Mock::new(b"%% ERROR: Invalid range\n\n"));
+        let mut journal = conn.journal_since("RADB", 10).unwrap();
+        assert!(matches!(journal.next(), Some(Err(Error::Journal(_)))));
+        assert!(journal.next().is_none());
+        let conn = Connection::from_stream(Mock::new(
+            b"% Warning: there are no newer updates available\n\n",
+        ));
+        assert_eq!(conn.journal_since("RADB", 10).unwrap().count(), 0);
+        let conn = Connection::from_stream(Mock::new(b"%START Version: 3 RADB 10-11\n\nADD 10\n"));
+        assert!(matches!(
+            conn.journal_since("RADB", 10).unwrap().last(),
+            Some(Err(Error::ConnectionClosed))
+        ));
+        let conn = Connection::from_stream(Mock::new(b""));
+        assert!(matches!(
+            conn.journal_since("RADB:3", 10),
+            Err(Error::InvalidQueryInput(_))
+        ));
+    }
+}
@@ -16,10 +16,9 @@
 //!
 //!     println!("connected to {}", irr.version()?);
 //!
-//!     let as_set = "AS-FOO".parse().unwrap();
-//!     println!("getting members of {}", as_set);
+//!     println!("getting members of AS-FOO");
 //!     irr.pipeline()
-//!         .push(Query::AsSetMembersRecursive(as_set))?
+//!         .push(Query::as_set_members_recursive("AS-FOO")?)?
 //!         .responses::<AutNum>()
 //!         .filter_map(|result| {
 //!             result.map_err(|err| {
@@ -34,6 +33,24 @@
 //! }
 //! ```
 //!
+//! # Feature flags
+//!
+//! - `std-net` (enabled by default): establish connections over TCP using
+//!   [`IrrClient`]. Without it, the query formatting, response parsing and
+//!   [`Pipeline`] machinery remain available over a caller-supplied byte
+//!   stream, using [`Connection::from_stream()`]. This allows them to be used
+//!   on targets without TCP sockets, such as WebAssembly.
+//! - `ip`: parse `route-set` members, including range operators, using the
+//!   [`generic-ip`](https://docs.rs/generic-ip) crate, and expand `as-set`s
+//!   directly into a prefix set. See `PrefixRange` and
+//!   `Connection::as_set_prefixes()`.
+//! - `parallel`: parse response items on a thread pool, using the
+//!   [`rayon`](https://docs.rs/rayon) crate, while preserving their order.
+//!   See `Pipeline::responses_parallel()`.
+//! - `spill`: write large responses to a temporary file, using the
+//!   [`tempfile`](https://docs.rs/tempfile) crate, so that they can be
+//!   processed with bounded memory usage. See `Response::spill()`.
+//!
 //! [irrd]: https://irrd.readthedocs.io/en/stable/users/queries/#irrd-style-queries
 #![doc(html_root_url = "https://docs.rs/irrc/0.1.0")]
 // clippy lints
@@ -82,21 +99,59 @@
 // silence unused dev-dependency warnings
 #[cfg(test)]
 mod deps {
+    use criterion as _;
     use ip as _;
     use tracing_subscriber as _;
     use version_sync as _;
 }
 
+mod as_block;
+pub use self::as_block::AsBlock;
+
+mod cache;
+pub use self::cache::CachedConnection;
+
 mod client;
-pub use self::client::{Connection, IrrClient};
+#[cfg(feature = "std-net")]
+pub use self::client::IrrClient;
+pub use self::client::{Connection, WIRE_TARGET};
+
+mod journal;
+pub use self::journal::{Journal, JournalEntry, Operation};
+
+mod object;
+pub use self::object::RpslObject;
 
 mod parse;
 
 mod pipeline;
-pub use self::pipeline::{Pipeline, Response, ResponseItem, Responses};
+#[cfg(feature = "parallel")]
+pub use self::pipeline::ParallelResponses;
+pub use self::pipeline::{
+    Checkpoint, CollectErrors, DedupObjects, Expand, ExtendReport, Pipeline, Response,
+    ResponseChunks, ResponseItem, ResponseOutcome, Responses, ResponsesWithQuery, SingleResponse,
+};
+#[cfg(feature = "spill")]
+pub use self::pipeline::{SpillOptions, SpilledResponse};
+
+mod prefix;
+#[cfg(feature = "ip")]
+pub use self::prefix::PrefixRange;
+pub use self::prefix::RangeOperator;
 
 mod query;
-pub use self::query::{Query, RpslObjectClass};
+pub use self::query::{AddressFamily, IntoName, MatchMode, Query, QueryKind, RpslObjectClass};
+
+mod route_query;
+pub use self::route_query::RouteQuery;
+
+mod transport;
+
+mod tree;
+pub use self::tree::{AsSetMembership, AsSetTree};
+
+mod version;
+pub use self::version::{Capability, Implementation, ServerVersion};
 
 /// Error types returned during query execution
 pub mod error;
@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::error::Error;
+
+/// An RPSL object, parsed into an ordered list of attributes.
+///
+/// Parse the items of a response to a [`Query::RpslObject`] as
+/// [`RpslObject`] to obtain structured objects rather than the raw object
+/// text.
+///
+/// Attribute names are converted to lower case. The order of attributes is
+/// preserved, as are repeated attributes. Continuation lines (beginning with
+/// whitespace or `+`) are joined to the value of the attribute they follow,
+/// separated by `\n`. Comments, from `#` to the end of a line, are removed, as
+/// described in [RFC 2622].
+///
+/// # Example
+///
+/// ``` no_run
+/// # use irrc::{IrrClient, Query, Error, RpslObjectClass};
+/// # fn main() -> Result<(), Error> {
+/// use irrc::RpslObject;
+///
+/// IrrClient::new("whois.radb.net:43")
+///     .connect()?
+///     .pipeline()
+///     .push(Query::RpslObject(RpslObjectClass::AutNum, "AS65000".to_string()))?
+///     .responses::<RpslObject>()
+///     .filter_map(Result::ok)
+///     .for_each(|item| {
+///         let object = item.content();
+///         println!("{} {}: {:?}", object.class(), object.key(), object.source());
+///     });
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`Query::RpslObject`]: crate::Query::RpslObject
+/// [RFC 2622]: https://www.rfc-editor.org/rfc/rfc2622#section-2
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RpslObject {
+    attrs: Vec<(String, String)>,
+}
+
+impl RpslObject {
+    /// The object class, given by the name of the first attribute.
+    #[must_use]
+    pub fn class(&self) -> &str {
+        &self.attrs[0].0
+    }
+
+    /// The value of the first (class) attribute.
+    ///
+    /// This is the primary key of the object for all classes except `route`
+    /// and `route6`, for which the primary key is the combination of the
+    /// prefix and the value of the `origin:` attribute.
+    #[must_use]
+    pub fn key(&self) -> &str {
+        &self.attrs[0].1
+    }
+
+    /// The value of the first attribute called `name`, if any.
+    ///
+    /// `name` is matched case-insensitively.
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.attributes()
+            .find(|(attr, _)| attr.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value)
+    }
+
+    /// Iterate over the values of each attribute called `name`, in order.
+    ///
+    /// `name` is matched case-insensitively.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.attributes()
+            .filter(move |(attr, _)| attr.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value)
+    }
+
+    /// The value of the `source:` attribute, if any.
+    ///
+    /// If more than one `source:` attribute is present, the last is returned.
+    #[must_use]
+    pub fn source(&self) -> Option<&str> {
+        self.get_all("source").last()
+    }
+
+    /// Iterate over the name and value of each attribute, in order.
+    pub fn attributes(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.attrs
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// Collect the attributes into a map from attribute name to the values of
+    /// each attribute with that name, in order.
+    #[must_use]
+    pub fn to_map(&self) -> HashMap<String, Vec<String>> {
+        let mut map: HashMap<_, Vec<_>> = HashMap::new();
+        for (name, value) in &self.attrs {
+            map.entry(name.clone()).or_default().push(value.clone());
+        }
+        map
+    }
+}
+
+impl FromStr for RpslObject {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut attrs: Vec<(String, String)> = Vec::new();
+        for line in s.lines() {
+            let content = line.split_once('#').map_or(line, |(content, _)| content);
+            if content.trim().is_empty() {
+                continue;
+            }
+            if let Some(continuation) =
+                content.strip_prefix(|c: char| c.is_whitespace() || c == '+')
+            {
+                let (_, value) = attrs
+                    .last_mut()
+                    .ok_or_else(|| Error::InvalidRpslObject(line.to_owned()))?;
+                value.push('\n');
+                value.push_str(continuation.trim());
+            } else {
+                let (name, value) = content
+                    .split_once(':')
+                    .filter(|(name, _)| !name.trim().is_empty())
+                    .ok_or_else(|| Error::InvalidRpslObject(line.to_owned()))?;
+                attrs.push((name.trim().to_ascii_lowercase(), value.trim().to_owned()));
+            }
+        }
+        if attrs.is_empty() {
+            Err(Error::InvalidRpslObject(s.to_owned()))
+        } else {
+            Ok(Self { attrs })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AUT_NUM: &str = "\
+aut-num:        AS37271
+as-name:        Workonline
+descr:          Workonline Communications
+                Johannesburg, South Africa
+import:         from AS-ANY accept ANY
+export:         to AS-ANY announce AS37271:AS-CUSTOMERS # customers only
+# filtering is applied by upstreams
+admin-c:        BG10-AFRINIC
+tech-c:         BG10-AFRINIC
++               NOC-AFRINIC
+mnt-by:         WOLCOMM-MNT
+mnt-by:         RADB-MNT
+changed:        noc@workonline.africa 20230101
+source:         RADB";
+
+    const ROUTE: &str = "\
+route:          192.0.2.0/24
+descr:          Example route
+origin:         AS65000
+mnt-by:         MAINT-AS65000
+source:         RADB";
+
+    #[test]
+    fn aut_num_object_is_parsed() {
+        let object: RpslObject = AUT_NUM.parse().unwrap();
+        assert_eq!(object.class(), "aut-num");
+        assert_eq!(object.key(), "AS37271");
+        assert_eq!(object.source(), Some("RADB"));
+        assert_eq!(
+            object.get("descr"),
+            Some("Workonline Communications\nJohannesburg, South Africa")
+        );
+        assert_eq!(
+            object.get("export"),
+            Some("to AS-ANY announce AS37271:AS-CUSTOMERS")
+        );
+        assert_eq!(object.get("TECH-C"), Some("BG10-AFRINIC\nNOC-AFRINIC"));
+        assert_eq!(
+            object.get_all("mnt-by").collect::<Vec<_>>(),
+            vec!["WOLCOMM-MNT", "RADB-MNT"]
+        );
+        assert_eq!(object.attributes().count(), 11);
+        assert_eq!(object.to_map()["mnt-by"], vec!["WOLCOMM-MNT", "RADB-MNT"]);
+    }
+
+    #[test]
+    fn route_object_is_parsed() {
+        let object: RpslObject = ROUTE.parse().unwrap();
+        assert_eq!(object.class(), "route");
+        assert_eq!(object.key(), "192.0.2.0/24");
+        assert_eq!(object.get("origin"), Some("AS65000"));
+        assert_eq!(object.source(), Some("RADB"));
+        assert_eq!(object.get("members"), None);
+    }
+
+    #[test]
+    fn invalid_objects_are_rejected() {
+        for input in [
+            "",
+            "# comment only",
+            "  leading continuation",
+            "no colon here",
+        ] {
+            assert!(
+                matches!(
+                    input.parse::<RpslObject>(),
+                    Err(Error::InvalidRpslObject(_))
+                ),
+                "{input:?}"
+            );
+        }
+    }
+}
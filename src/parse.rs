@@ -1,4 +1,5 @@
 use std::str::from_utf8;
+use std::time::Duration;
 
 use nom::{
     branch::alt,
@@ -16,6 +17,8 @@ use crate::error;
 
 type ResponseResult = Result<Option<usize>, error::Response>;
 
+type ErrVariant = fn(String) -> error::Response;
+
 const EOR: &[u8] = b"\nC\n";
 
 fn resp_ok_data(input: &[u8]) -> IResult<&[u8], ResponseResult> {
@@ -45,7 +48,105 @@ fn resp_err_other(input: &[u8]) -> IResult<&[u8], ResponseResult> {
         delimited(char(' '), take_till(is_newline), newline),
         from_utf8,
     )(rem)?;
-    Ok((rem, Err(error::Response::Other(msg.to_owned()))))
+    Ok((rem, Err(classify_err(msg))))
+}
+
+/// Prefixes of the messages of the `F` responses returned by IRRd for
+/// malformed queries, with the classification of each.
+const ERR_PREFIXES: &[(&str, ErrVariant)] = &[
+    ("Unrecognised command", error::Response::MalformedQuery),
+    ("Missing parameter", error::Response::MalformedQuery),
+    ("Invalid AS number", error::Response::MalformedQuery),
+    (
+        "Invalid argument for object lookup",
+        error::Response::MalformedQuery,
+    ),
+    (
+        "Invalid input for route search",
+        error::Response::MalformedQuery,
+    ),
+    (
+        "Invalid route search option",
+        error::Response::MalformedQuery,
+    ),
+    ("Invalid value for timeout", error::Response::MalformedQuery),
+    ("Invalid object class", error::Response::InvalidObjectClass),
+];
+
+/// Phrases found in the rate limiting notices sent by IRR mirrors, and the
+/// proxies in front of them, in place of a response.
+///
+/// IRRd itself does not send such notices, and their wording is not
+/// standardised, so these are matched anywhere in the message, ignoring
+/// case.
+const RATE_LIMIT_PHRASES: [&str; 5] = [
+    "rate limit",
+    "query limit",
+    "too many queries",
+    "too many connections",
+    "try again later",
+];
+
+/// Classify the message of an `F` response.
+///
+/// The messages returned by IRRd are recognised by their exact prefixes.
+/// Messages containing one of the [`RATE_LIMIT_PHRASES`] are classified as
+/// [`RateLimited`][error::Response::RateLimited]. All others are classified
+/// as [`Other`][error::Response::Other].
+fn classify_err(msg: &str) -> error::Response {
+    for (prefix, variant) in ERR_PREFIXES {
+        if msg.starts_with(prefix) {
+            return variant(msg.to_owned());
+        }
+    }
+    let lower = msg.to_ascii_lowercase();
+    if RATE_LIMIT_PHRASES
+        .iter()
+        .any(|phrase| lower.contains(phrase))
+    {
+        error::Response::RateLimited(msg.to_owned())
+    } else {
+        error::Response::Other(msg.to_owned())
+    }
+}
+
+/// Extract the delay before retrying suggested in the message of a rate
+/// limiting `F` response, such as "retry after 30 seconds" or "try again in
+/// 2 minutes".
+///
+/// A delay given without a unit is taken to be in seconds.
+pub(crate) fn retry_after(msg: &str) -> Option<Duration> {
+    let lower = msg.to_ascii_lowercase();
+    let hint = [
+        "retry-after",
+        "retry after",
+        "retry in",
+        "try again in",
+        "try again after",
+        "wait",
+    ]
+    .iter()
+    .find_map(|pattern| lower.find(pattern).map(|pos| &lower[pos + pattern.len()..]))?
+    .trim_start_matches(|c: char| c.is_whitespace() || c == ':' || c == '=');
+    let (value, rest) = hint.split_at(
+        hint.find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(hint.len()),
+    );
+    let value = value.parse::<u64>().ok()?;
+    let unit = rest
+        .trim_start()
+        .split(|c: char| !c.is_ascii_alphabetic())
+        .next()
+        .unwrap_or_default();
+    let secs = match unit {
+        "ms" | "msec" | "millisecond" | "milliseconds" => {
+            return Some(Duration::from_millis(value));
+        }
+        "m" | "min" | "mins" | "minute" | "minutes" => value.checked_mul(60)?,
+        "h" | "hr" | "hrs" | "hour" | "hours" => value.checked_mul(3600)?,
+        _ => value,
+    };
+    Some(Duration::from_secs(secs))
 }
 
 pub(crate) fn response_status(input: &[u8]) -> IResult<&[u8], (usize, ResponseResult)> {
@@ -67,6 +168,14 @@ pub(crate) fn end_of_response(input: &[u8]) -> IResult<&[u8], usize> {
     })(input)
 }
 
+/// Match an end-of-response marker that is not preceded by a newline, as sent
+/// by some non-canonical servers.
+pub(crate) fn bare_end_of_response(input: &[u8]) -> IResult<&[u8], usize> {
+    map(consumed(tag(&EOR[1..])), |(consumed, _): (&[u8], &[u8])| {
+        consumed.len()
+    })(input)
+}
+
 fn till_word_end(input: &[u8]) -> IResult<&[u8], &[u8]> {
     take_till1(|b| b == b' ' || b == b'\n')(input)
 }
@@ -104,17 +213,57 @@ pub(crate) fn paragraph(input: &[u8]) -> IResult<&[u8], (usize, &[u8])> {
 
 fn take_paragraph(input: &[u8]) -> IResult<&[u8], &[u8]> {
     let (remaining, _) = opt(newline)(input)?;
-    let (remaining, result) = match take_until("\n\n")(remaining) {
-        Ok((mut remaining, result)) => {
-            (remaining, _) = newline(remaining)?;
-            (remaining, result)
+    // the paragraph ends at whichever of a blank line or the end of the
+    // response comes first, so that it cannot extend into the next response
+    let paragraph: IResult<&[u8], &[u8]> = take_until("\n\n")(remaining);
+    let response: IResult<&[u8], &[u8]> = take_until(EOR)(remaining);
+    match (paragraph, response) {
+        (Ok((_, paragraph)), Ok((remaining, result))) if result.len() < paragraph.len() => {
+            Ok((remaining, result))
         }
-        err @ Err(_) => match take_until::<_, _, (&[u8], _)>(EOR)(remaining) {
-            Ok((remaining, result)) => (remaining, result),
-            Err(_) => return err,
-        },
-    };
-    Ok((remaining, result))
+        (Ok((remaining, result)), _) => {
+            let (remaining, _) = newline(remaining)?;
+            Ok((remaining, result))
+        }
+        (Err(_), Ok((remaining, result))) => Ok((remaining, result)),
+        (Err(err), Err(_)) => Err(err),
+    }
+}
+
+fn attributes(object: &str) -> impl Iterator<Item = (String, &str)> {
+    object
+        .lines()
+        .filter(|line| !line.starts_with(|c: char| c.is_whitespace() || c == '+'))
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim()))
+}
+
+/// Extract the primary key of an RPSL object, qualified by its `source:`.
+///
+/// The primary key of `route` and `route6` objects is the combination of the
+/// prefix and the `origin:`. For all other classes it is the value of the
+/// first (class) attribute.
+pub(crate) fn primary_key(object: &str) -> Option<String> {
+    let mut attrs = attributes(object);
+    let (class, key) = attrs.next()?;
+    let is_route = class == "route" || class == "route6";
+    let (mut origin, mut source) = ("", "");
+    for (name, value) in attrs {
+        match name.as_str() {
+            "origin" if is_route => origin = value,
+            "source" => source = value,
+            _ => {}
+        }
+    }
+    Some(format!("{class}:{key}:{origin}:{source}").to_ascii_uppercase())
+}
+
+/// Extract the value of the `source:` attribute of an RPSL object.
+pub(crate) fn source(object: &str) -> Option<&str> {
+    attributes(object)
+        .filter(|(name, _)| name == "source")
+        .map(|(_, value)| value)
+        .last()
 }
 
 #[cfg(test)]
@@ -227,6 +376,73 @@ mod tests {
         );
     }
 
+    mod classify_err {
+        use super::*;
+
+        macro_rules! assert_classified {
+            ( $( $desc:ident: $msg:literal => $variant:ident ),* $(,)? ) => {
+                paste! {
+                    $(
+                        #[test]
+                        fn [<$desc _is_classified>]() {
+                            assert!(matches!(
+                                classify_err($msg),
+                                error::Response::$variant { .. }
+                            ), "{:?}", classify_err($msg));
+                        }
+                    )*
+                }
+            }
+        }
+
+        assert_classified! {
+            unrecognised_command: "Unrecognised command: z" => MalformedQuery,
+            missing_parameter: "Missing parameter for set members query" => MalformedQuery,
+            invalid_asn: "Invalid AS number ASFOO: number part is not numeric" => MalformedQuery,
+            invalid_object_lookup: "Invalid argument for object lookup: foo" => MalformedQuery,
+            invalid_route_search: "Invalid input for route search: foo" => MalformedQuery,
+            invalid_route_option: "Invalid route search option: x" => MalformedQuery,
+            invalid_timeout: "Invalid value for timeout: foo" => MalformedQuery,
+            invalid_object_class: "Invalid object class: foo" => InvalidObjectClass,
+            lower_case: "unrecognised command: z" => Other,
+            not_prefix: "Query failed: Invalid AS number" => Other,
+            rate_limited: "Query rate limit exceeded, try again later" => RateLimited,
+            too_many_connections: "Too many connections from your address" => RateLimited,
+            retry_hint: "Rate limit exceeded, retry after 30 seconds" => RateLimited,
+            too_many_results: "Too many results, please refine the query" => Other,
+            unknown: "Internal server error" => Other,
+        }
+    }
+
+    mod retry_after {
+        use super::*;
+
+        proptest! {
+            #[test]
+            fn does_not_panic(input in any::<String>()) {
+                _ = retry_after(&input);
+            }
+        }
+
+        #[test]
+        fn hints_are_parsed() {
+            for (msg, expected) in [
+                ("Rate limit exceeded, retry after 30 seconds", Some(30)),
+                ("Too many connections. Retry-After: 120", Some(120)),
+                ("Query limit reached, try again in 2 minutes", Some(120)),
+                ("Please wait 1h before reconnecting", Some(3600)),
+                ("Query rate limit exceeded, try again later", None),
+                ("Too many connections from your address", None),
+            ] {
+                assert_eq!(retry_after(msg), expected.map(Duration::from_secs), "{msg}");
+            }
+            assert_eq!(
+                retry_after("retry in 500ms"),
+                Some(Duration::from_millis(500))
+            );
+        }
+    }
+
     mod end_of_response {
         use super::*;
 
@@ -270,6 +486,33 @@ mod tests {
         }
     }
 
+    mod bare_end_of_response {
+        use super::*;
+
+        does_not_panic!(bare_end_of_response);
+
+        assert_incomplete_parse!(bare_end_of_response {
+            empty: b"",
+            partial: b"C",
+        });
+
+        assert_error_kind!(
+            bare_end_of_response {
+                leading_newline: b"\nC\n" => Tag,
+                missing_newline: b"CC" => Tag,
+            }
+        );
+
+        proptest! {
+            #[test]
+            fn parses_with_arbitary_trailing_input(
+                input in proptest::string::bytes_regex("C\n.*").unwrap(),
+            ) {
+                assert_eq!(bare_end_of_response(&input), Ok((&input[2..], 2)));
+            }
+        }
+    }
+
     mod word {
         use super::*;
 
@@ -300,10 +543,19 @@ mod tests {
 
         does_not_panic!(paragraph);
 
-        assert_incomplete_parse!(all {
+        assert_incomplete_parse!(paragraph {
             empty: b"",
             unterminated: b"foo",
         });
+
+        assert_parse_result!(
+            paragraph {
+                terminated: b"foo: bar\nC\n" => (8, b"foo: bar"),
+                followed_by_paragraph: b"foo: bar\n\nbaz: qux\nC\n" => (9, b"foo: bar"),
+                leading_newline: b"\nbaz: qux\nC\n" => (9, b"baz: qux"),
+                followed_by_response: b"foo: bar\nC\nA14\nbaz: qux\n\nq: x\nC\n" => (8, b"foo: bar"),
+            }
+        );
     }
 
     mod all {
@@ -314,15 +566,75 @@ mod tests {
         assert_incomplete_parse!(all {
             empty: b"",
             unterminated: b"foo",
+            partial_terminator: b"foo\nC",
         });
 
         assert_parse_result!(
             all {
                 terminated: b"foo bar baz\nC\n" => (11, b"foo bar baz"),
+                multi_line: b"foo\nbar\nC\n" => (7, b"foo\nbar"),
+                followed_by_response: b"foo\nC\nA4\nbar\nC\n" => (3, b"foo"),
             }
         );
     }
 
+    mod primary_key {
+        use super::*;
+
+        proptest! {
+            #[test]
+            fn does_not_panic(input in any::<String>()) {
+                _ = primary_key(&input);
+            }
+        }
+
+        #[test]
+        fn empty_object_has_no_key() {
+            assert_eq!(primary_key(""), None);
+        }
+
+        #[test]
+        fn mntner_key() {
+            let object = "mntner: FOO-MNT\ndescr: foo\nsource: RADB";
+            assert_eq!(
+                primary_key(object),
+                Some("MNTNER:FOO-MNT::RADB".to_string())
+            );
+        }
+
+        #[test]
+        fn route_key_includes_origin() {
+            let object =
+                "route: 192.0.2.0/24\ndescr: foo\n  origin: AS1\norigin: as65000\nsource: RADB";
+            assert_eq!(
+                primary_key(object),
+                Some("ROUTE:192.0.2.0/24:AS65000:RADB".to_string())
+            );
+        }
+    }
+
+    mod source {
+        use super::*;
+
+        proptest! {
+            #[test]
+            fn does_not_panic(input in any::<String>()) {
+                _ = source(&input);
+            }
+        }
+
+        #[test]
+        fn missing_source() {
+            assert_eq!(source("mntner: FOO-MNT\ndescr: foo"), None);
+        }
+
+        #[test]
+        fn trailing_source() {
+            let object = "route: 192.0.2.0/24\nremarks: foo\n source: BAR\nsource: RADB  ";
+            assert_eq!(source(object), Some("RADB"));
+        }
+    }
+
     mod noop {
         use super::*;
 
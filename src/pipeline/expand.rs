@@ -0,0 +1,210 @@
+use std::fmt;
+use std::iter::FusedIterator;
+use std::mem::ManuallyDrop;
+use std::str::FromStr;
+
+use crate::{
+    client::Connection,
+    error::Error,
+    pipeline::{Pipeline, ResponseItem, Responses},
+    query::Query,
+};
+
+/// Iterator returned by [`Connection::expand()`].
+///
+/// See [`Connection::expand`] for details.
+pub struct Expand<'a, T, F>
+where
+    T: FromStr + fmt::Debug,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    // `responses` mutably borrows the `Pipeline` behind `pipeline`, so it
+    // must be dropped before the `Pipeline` is freed. See `Drop` below.
+    responses: ManuallyDrop<Responses<'a, 'a, T>>,
+    pipeline: *mut Pipeline<'a>,
+    f: F,
+    // an error adding a follow-up query, to be yielded next
+    push_err: Option<Error>,
+}
+
+impl<'a, T, F, I> Expand<'a, T, F>
+where
+    T: FromStr + fmt::Debug,
+    T::Err: std::error::Error + Send + Sync + 'static,
+    F: FnMut(&ResponseItem<T>) -> I,
+    I: IntoIterator<Item = Query>,
+{
+    #[tracing::instrument(skip(conn, f), fields(initial = initial.cmd()), level = "debug")]
+    pub(crate) fn new(conn: &'a mut Connection, initial: Query, f: F) -> Result<Self, Error> {
+        let mut pipeline = Box::new(conn.pipeline());
+        _ = pipeline.push(initial)?;
+        let pipeline = Box::into_raw(pipeline);
+        #[allow(unsafe_code)]
+        // SAFETY:
+        // `pipeline` was just created from a valid `Box`, and is not freed
+        // until `responses` has been dropped.
+        let responses = ManuallyDrop::new(unsafe { (*pipeline).responses() });
+        Ok(Self {
+            responses,
+            pipeline,
+            f,
+            push_err: None,
+        })
+    }
+}
+
+impl<T, F, I> Iterator for Expand<'_, T, F>
+where
+    T: FromStr + fmt::Debug,
+    T::Err: std::error::Error + Send + Sync + 'static,
+    F: FnMut(&ResponseItem<T>) -> I,
+    I: IntoIterator<Item = Query>,
+{
+    type Item = Result<ResponseItem<T>, Error>;
+
+    #[tracing::instrument(skip(self), level = "trace")]
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(err) = self.push_err.take() {
+            return Some(Err(err));
+        }
+        let item = self.responses.next()?;
+        if let Ok(ref item) = item {
+            let result = (self.f)(item).into_iter().try_for_each(|query| {
+                #[allow(unsafe_code)]
+                // SAFETY:
+                // This is safe here, as nothing is concurrently popping the
+                // `Pipeline` queue or writing to its `Connection`: `responses`
+                // is only advanced between calls to `f`, and this is the only
+                // `Pipeline` on the `Connection` (see
+                // `Connection::enter_pipeline()`).
+                //
+                // A stalled write may also read pending responses into the
+                // buffer that `responses` is reading, which is sound for the
+                // reasons given in `Pipeline::from_initial()`.
+                let result = unsafe { (*self.pipeline).push(query) };
+                if let Err(err) = result {
+                    tracing::error!("error enqueing query: {}", err);
+                    Err(err)
+                } else {
+                    Ok(())
+                }
+            });
+            self.push_err = result.err();
+        }
+        Some(item)
+    }
+}
+
+impl<T, F, I> FusedIterator for Expand<'_, T, F>
+where
+    T: FromStr + fmt::Debug,
+    T::Err: std::error::Error + Send + Sync + 'static,
+    F: FnMut(&ResponseItem<T>) -> I,
+    I: IntoIterator<Item = Query>,
+{
+}
+
+impl<T, F> Drop for Expand<'_, T, F>
+where
+    T: FromStr + fmt::Debug,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    fn drop(&mut self) {
+        #[allow(unsafe_code)]
+        // SAFETY:
+        // `responses` is never used again after being dropped here, and the
+        // `Pipeline` it borrows is freed only afterwards. `pipeline` was
+        // created by `Box::into_raw` in `new()` and is freed exactly once.
+        unsafe {
+            ManuallyDrop::drop(&mut self.responses);
+            drop(Box::from_raw(self.pipeline));
+        }
+    }
+}
+
+impl<T, F> fmt::Debug for Expand<'_, T, F>
+where
+    T: FromStr + fmt::Debug,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Expand")
+            .field("responses", &self.responses)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::transport::mock::Mock;
+
+    #[test]
+    fn follow_up_queries_are_expanded() {
+        let stream = Mock::new(b"A7\nAS-BAR\nC\nA8\nAS1 AS2\nC\n");
+        let output = stream.output();
+        let mut conn = Connection::from_stream(stream);
+        let items = conn
+            .expand(
+                Query::AsSetMembers("AS-FOO".parse().unwrap()),
+                |item: &ResponseItem<String>| {
+                    item.content()
+                        .starts_with("AS-")
+                        .then(|| Query::AsSetMembers(item.content().parse().unwrap()))
+                },
+            )
+            .unwrap()
+            .map(|item| item.unwrap().into_content())
+            .collect::<Vec<_>>();
+        assert_eq!(items, vec!["AS-BAR", "AS1", "AS2"]);
+        assert_eq!(output.lock().unwrap().as_slice(), b"!iAS-FOO\n!iAS-BAR\n");
+    }
+
+    #[test]
+    fn unread_responses_are_not_expanded() {
+        let stream = Mock::new(b"A7\nAS-BAR\nC\nA8\nAS1 AS2\nC\n");
+        let output = stream.output();
+        let mut conn = Connection::from_stream(stream);
+        let mut calls = 0;
+        let mut expand = conn
+            .expand(
+                Query::AsSetMembers("AS-FOO".parse().unwrap()),
+                |item: &ResponseItem<String>| {
+                    calls += 1;
+                    Some(Query::AsSetMembers(item.content().parse().unwrap()))
+                },
+            )
+            .unwrap();
+        assert_eq!(expand.next().unwrap().unwrap().content(), "AS-BAR");
+        drop(expand);
+        assert_eq!(calls, 1);
+        assert_eq!(output.lock().unwrap().as_slice(), b"!iAS-FOO\n!iAS-BAR\n");
+    }
+
+    #[test]
+    fn follow_up_query_errors_are_yielded() {
+        let stream = Mock::new(b"A7\nAS-BAR\nC\nA3\nAS1\nC\n");
+        let output = stream.output();
+        let mut conn = Connection::from_stream(stream);
+        let mut expand = conn
+            .expand(
+                Query::AsSetMembers("AS-FOO".parse().unwrap()),
+                |item: &ResponseItem<String>| {
+                    [
+                        Query::SetClientId(format!("{}\n!v", item.content())),
+                        Query::Version,
+                    ]
+                },
+            )
+            .unwrap();
+        assert_eq!(expand.next().unwrap().unwrap().content(), "AS-BAR");
+        assert!(matches!(
+            expand.next(),
+            Some(Err(Error::InvalidQueryInput(_)))
+        ));
+        assert!(expand.next().is_none());
+        drop(expand);
+        assert_eq!(output.lock().unwrap().as_slice(), b"!iAS-FOO\n");
+    }
+}
@@ -1,21 +1,42 @@
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io;
 use std::iter::FusedIterator;
 use std::marker::PhantomData;
 use std::str::{from_utf8, FromStr};
+use std::time::{Duration, Instant};
 
 use circular::Buffer;
+#[cfg(feature = "ip")]
+use ip::{AfiClass, Any, Ipv4, Ipv6, Prefix, PrefixSet};
+use rpsl::names::{AsSet, Mntner};
 
 use crate::{
     client::Connection,
-    error::{self, Error},
+    error::{self, Error, ItemPosition},
     parse,
-    query::Query,
+    query::{Query, QueryKind, RpslObjectClass},
+    tree::{self, AsSetTree},
 };
 
+mod expand;
+pub use self::expand::Expand;
+
+#[cfg(feature = "parallel")]
+mod parallel;
+#[cfg(feature = "parallel")]
+pub use self::parallel::ParallelResponses;
+
 mod queue;
 use self::queue::Queue;
 
+#[cfg(feature = "spill")]
+mod spill;
+#[cfg(feature = "spill")]
+pub use self::spill::{SpillOptions, SpilledResponse};
+
 /// A sequence of queries to be executed sequentially using pipelining.
 ///
 /// See [`Connection::pipeline()`] for details.
@@ -23,14 +44,121 @@ pub struct Pipeline<'a> {
     conn: &'a mut Connection,
     buf: Buffer,
     queue: Queue,
+    max_response_len: usize,
+    max_items_per_response: usize,
+    relaxed_terminator: bool,
+    allow_empty: HashMap<QueryKind, bool>,
+    batch: HashSet<String>,
+    drain_timeout: Option<Duration>,
+    drain_deadline: Option<Instant>,
+    drain_expired: bool,
 }
 
 impl<'a> Pipeline<'a> {
+    /// Default maximum response data length accepted by a [`Pipeline`].
+    pub const DEFAULT_MAX_RESPONSE_LEN: usize = 1 << 28;
+
+    /// Default limit on the total length of queries sent but not yet
+    /// answered.
+    pub const DEFAULT_MAX_IN_FLIGHT_BYTES: usize = 1 << 14;
+
     #[tracing::instrument(level = "debug")]
     pub(crate) fn new(conn: &'a mut Connection, capacity: usize) -> Self {
-        let buf = Buffer::with_capacity(capacity);
-        let queue = Queue::default();
-        Self { conn, buf, queue }
+        conn.enter_pipeline();
+        let buf = conn.take_buffer(capacity);
+        let queue = if conn.is_persistent() {
+            Queue::default()
+        } else {
+            Queue::single_query()
+        };
+        Self {
+            conn,
+            buf,
+            queue,
+            max_response_len: Self::DEFAULT_MAX_RESPONSE_LEN,
+            max_items_per_response: usize::MAX,
+            relaxed_terminator: false,
+            allow_empty: HashMap::new(),
+            batch: HashSet::new(),
+            drain_timeout: None,
+            drain_deadline: None,
+            drain_expired: false,
+        }
+    }
+
+    /// Set the maximum response data length, in bytes, that will be accepted.
+    ///
+    /// This also limits the size to which the read buffer is grown if a
+    /// single response item does not fit in it. An item that cannot fit in a
+    /// buffer of this size causes [`Error::ItemTooLarge`] to be returned.
+    ///
+    /// If the length indicated in a response preamble exceeds this value,
+    /// [`pop()`][Self::pop] returns [`Error::ResponseTooLarge`] without
+    /// attempting to read the response data. This protects against corrupt or
+    /// malicious servers indicating absurd response lengths.
+    ///
+    /// The default is [`DEFAULT_MAX_RESPONSE_LEN`][Self::DEFAULT_MAX_RESPONSE_LEN]
+    /// bytes.
+    ///
+    /// Since the response data is not read, the underlying [`Connection`]
+    /// should not be re-used after this error is encountered.
+    pub const fn max_response_len(&mut self, len: usize) -> &mut Self {
+        self.max_response_len = len;
+        self
+    }
+
+    /// Set the maximum number of items that will be read from a single
+    /// response.
+    ///
+    /// A response within the limit set by
+    /// [`max_response_len()`][Self::max_response_len] may still contain a
+    /// very large number of very short items, and consumers that collect the
+    /// items of a response can be made to use an unbounded amount of memory.
+    ///
+    /// Once `limit` items have been read from a response, the next attempt to
+    /// read an item returns [`Error::TooManyItems`], and the remainder of the
+    /// response data is discarded without being parsed. Subsequent responses
+    /// are unaffected, and the [`Connection`] remains usable.
+    ///
+    /// There is no limit by default.
+    pub const fn max_items_per_response(&mut self, limit: usize) -> &mut Self {
+        self.max_items_per_response = limit;
+        self
+    }
+
+    /// Accept non-canonical response terminators.
+    ///
+    /// By default, response data must be followed by `\nC\n`, with the
+    /// leading newline counted in the length given in the response preamble.
+    /// Some IRRd-compatible servers deviate from this, either by excluding
+    /// the newline from the response length, or by omitting it altogether.
+    ///
+    /// When enabled, the end of the response is also recognised if the full
+    /// response length has been consumed, and is followed by either `\nC\n`
+    /// or `C\n`.
+    ///
+    /// Relaxed mode is disabled by default, since it weakens the detection of
+    /// response data underruns.
+    pub const fn relaxed_terminator(&mut self, enabled: bool) -> &mut Self {
+        self.relaxed_terminator = enabled;
+        self
+    }
+
+    /// Set whether a zero-length response is accepted for queries of kind
+    /// `kind` that are expected to return data.
+    ///
+    /// For some queries, such as [`Query::Ipv4Routes`], an empty response is
+    /// a normal result. For others, such as [`Query::Version`], it indicates a
+    /// problem with the server.
+    ///
+    /// If accepted, the [`Response`] returned by [`pop()`][Self::pop] yields
+    /// no items. Otherwise, [`Error::EmptyResponse`] is returned in its place.
+    ///
+    /// By default, empty responses are accepted for every kind of query
+    /// except [`Query::Version`].
+    pub fn allow_empty_response(&mut self, kind: QueryKind, allowed: bool) -> &mut Self {
+        _ = self.allow_empty.insert(kind, allowed);
+        self
     }
 
     #[tracing::instrument(skip(conn, f), fields(initial = initial.cmd()), level = "debug")]
@@ -57,7 +185,19 @@ impl<'a> Pipeline<'a> {
                 #[allow(unsafe_code)]
                 // SAFETY:
                 // This is safe here, as nothing is concurrently popping `self.queue`
-                // or writing to `self.conn`.
+                // or writing to `self.conn`: this is the only `Pipeline` on
+                // `conn` (see `Connection::enter_pipeline()`).
+                //
+                // If a write stalls, `push()` also reads pending responses into
+                // `self.buf`, which may shift, grow or reallocate it while the
+                // `Response` returned by `pop()` above is still being read. That
+                // `Response` holds no references into the buffer between items:
+                // it re-borrows `self.buf` on each call to `next()`, and its
+                // state is kept as counts relative to the start of
+                // `Buffer::data()`. `read_stalled()` only appends to the data,
+                // and never consumes it, so those counts remain valid. This is
+                // the same mutation that `Response` makes itself when it calls
+                // `fetch()`.
                 let result = unsafe { (*raw_self).push(query) };
                 if let Err(err) = result {
                     tracing::error!("error enqueing query: {}", err);
@@ -69,6 +209,86 @@ impl<'a> Pipeline<'a> {
         Ok(pipeline)
     }
 
+    /// Set the limit, in bytes, on the total length of queries that have been
+    /// written to the server but whose responses have not yet been read.
+    ///
+    /// Queries are read from the connection by the server sequentially, and
+    /// the server will typically stop reading further queries while it is
+    /// unable to write the response to the current one. If the client in
+    /// turn blocks while writing queries, without reading responses, neither
+    /// side can make progress.
+    ///
+    /// To avoid this, queries [`push()`][Self::push]ed to the [`Pipeline`]
+    /// are only written while the length of unanswered queries is below this
+    /// limit. The remaining queries are held until responses are read using
+    /// [`pop()`][Self::pop] (or one of the methods built on it), at which
+    /// point more queries are written.
+    ///
+    /// Provided that this limit is smaller than the socket buffer space
+    /// available between the client and server, writing queries will never
+    /// block for long. The default of
+    /// [`DEFAULT_MAX_IN_FLIGHT_BYTES`][Self::DEFAULT_MAX_IN_FLIGHT_BYTES] is
+    /// chosen to be well within typical socket buffer sizes.
+    ///
+    /// If a write does stall, for example because this limit has been
+    /// raised, any responses already sent by the server are read into the
+    /// [`Pipeline`]'s buffer before the write is retried, so that both sides
+    /// can make progress. Responses read in this way are held in memory until
+    /// they are [`pop()`][Self::pop]ped, and count towards the
+    /// [`max_response_len()`][Self::max_response_len] limit on the size of
+    /// the buffer.
+    pub const fn max_in_flight_bytes(&mut self, bytes: usize) -> &mut Self {
+        self.queue.set_max_in_flight_bytes(bytes);
+        self
+    }
+
+    /// Limit the time spent reading and discarding outstanding responses
+    /// when this [`Pipeline`] is [`clear()`][Self::clear]ed or dropped.
+    ///
+    /// By default, clearing the [`Pipeline`] blocks until every outstanding
+    /// response has been received from the server, however long that takes.
+    /// If a large response is pending, this can stall a caller that simply
+    /// wants to give up.
+    ///
+    /// If the outstanding responses have not been read by the time `timeout`
+    /// has elapsed, the remaining responses are abandoned as if by
+    /// [`abort_remaining()`][Self::abort_remaining]. Since the client can no
+    /// longer determine where the next response begins, this closes the
+    /// underlying [`Connection`]: [`Connection::is_closed()`] returns `true`,
+    /// and the connection must be re-established, for example using
+    /// [`Connection::reconnect()`], before it can be used again.
+    ///
+    /// The timeout does not apply to dropping an individual [`Response`].
+    ///
+    /// For connections constructed using [`Connection::from_stream()`],
+    /// reads cannot be interrupted, and the timeout is only checked between
+    /// reads.
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # use irrc::{IrrClient, Query, Error};
+    /// # fn main() -> Result<(), Error> {
+    /// use std::time::Duration;
+    ///
+    /// let mut conn = IrrClient::new("whois.radb.net:43").connect()?;
+    /// let mut pipeline = conn.pipeline();
+    /// pipeline
+    ///     .drain_timeout(Duration::from_secs(1))
+    ///     .push(Query::AsSetMembersRecursive("AS-HUGE".parse().unwrap()))?;
+    /// // decide that the results are no longer needed
+    /// drop(pipeline);
+    /// if conn.is_closed() {
+    ///     conn.reconnect()?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub const fn drain_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.drain_timeout = Some(timeout);
+        self
+    }
+
     /// Add a query to be executed in order using this [`Pipeline`].
     ///
     /// This method will block until the query is written to the underlying
@@ -76,9 +296,16 @@ impl<'a> Pipeline<'a> {
     ///
     /// # Errors
     ///
+    /// An [`Error::InvalidQueryInput`] is returned, and the query is not
+    /// added, if a value contained in the query includes characters that
+    /// would alter the framing of the command, such as newlines.
+    ///
     /// An [`Error`] is returned if the query cannot be written to the
     /// underlying TCP socket.
     ///
+    /// Source names are converted to upper case, unless disabled using
+    /// [`Connection::with_case_sensitive_sources()`].
+    ///
     /// # Example
     ///
     /// ``` no_run
@@ -96,14 +323,383 @@ impl<'a> Pipeline<'a> {
     #[tracing::instrument(skip(self), level = "debug")]
     pub fn push(&mut self, query: Query) -> Result<&mut Self, Error> {
         tracing::debug!("pushing new query");
+        query.validate()?;
+        let query = if self.conn.normalizes_sources() {
+            query.with_uppercase_sources()
+        } else {
+            query
+        };
+        if query.changes_state() {
+            self.batch.clear();
+        }
         self.queue.push(query);
         self.flush()?;
         Ok(self)
     }
 
+    /// Add an arbitrary `!` command to this [`Pipeline`], as a
+    /// [`Query::Raw`].
+    ///
+    /// **Unstable:** this is an escape hatch for experimenting with server
+    /// features that are not yet modelled as [`Query`] variants, and may
+    /// change or be removed in any release. Use
+    /// [`pop_raw()`][Self::pop_raw] to read the response.
+    ///
+    /// `cmd` must begin with `!` and end with a single newline, which is the
+    /// only line break it may contain.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::InvalidQueryInput`] is returned, and the command is not
+    /// added, if `cmd` is not a single `!` command terminated by a newline.
+    ///
+    /// Otherwise, as for [`push()`][Self::push].
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # use irrc::{IrrClient, Error};
+    /// # fn main() -> Result<(), Error> {
+    /// # let mut conn = IrrClient::new("whois.radb.net:43").connect()?;
+    /// let mut pipeline = conn.pipeline();
+    /// pipeline.push_raw("!jRADB\n")?;
+    /// if let Some(response) = pipeline.pop_raw::<String>() {
+    ///     for item in response? {
+    ///         println!("{:?}", item?.raw());
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn push_raw(&mut self, cmd: &str) -> Result<&mut Self, Error> {
+        let line = cmd
+            .strip_suffix('\n')
+            .ok_or_else(|| Error::InvalidQueryInput(cmd.to_owned()))?;
+        self.push(Query::Raw(line.to_owned()))
+    }
+
+    /// Add a query to this [`Pipeline`], unless an identical query is
+    /// already part of the current batch.
+    ///
+    /// Queries are compared using their [`canonical()`][Query::canonical]
+    /// forms. This avoids issuing the same query more than once when, for
+    /// example, a batch of [`Query::Ipv4Routes`] queries is built from
+    /// overlapping `as-set` memberships.
+    ///
+    /// The current batch consists of the queries added using this method
+    /// since the responses to all previously added queries were read. Queries
+    /// are not de-duplicated across batches, or against queries added using
+    /// [`push()`][Self::push]. Note that no response is returned for a query
+    /// that is dropped as a duplicate.
+    ///
+    /// Queries that change the state of the connection, such as
+    /// [`Query::SetSources`], are never dropped, and begin a new batch, since
+    /// the responses to the queries that follow them may differ.
+    ///
+    /// # Errors
+    ///
+    /// As for [`push()`][Self::push].
+    #[tracing::instrument(skip(self), level = "debug")]
+    pub fn push_deduplicated(&mut self, query: Query) -> Result<&mut Self, Error> {
+        if self.queue.pushed() == self.queue.popped() {
+            self.batch.clear();
+        }
+        if query.changes_state() || self.batch.insert(query.canonical().cmd()) {
+            self.push(query)
+        } else {
+            tracing::debug!("dropping duplicate query");
+            Ok(self)
+        }
+    }
+
+    /// Add a [`Query::MntBy`] to this [`Pipeline`] for each of the provided
+    /// maintainers.
+    ///
+    /// IRRd's `!o` query accepts only a single maintainer name, so a separate
+    /// query is issued for each of `mntners`. See
+    /// [`Connection::mnt_by_any()`] for a method that also merges and
+    /// de-duplicates the responses.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error`] is returned if any of the queries cannot be written to
+    /// the underlying TCP socket.
+    #[tracing::instrument(skip(self, mntners), level = "debug")]
+    pub fn push_mnt_by_any<I>(&mut self, mntners: I) -> Result<&mut Self, Error>
+    where
+        I: IntoIterator<Item = Mntner>,
+    {
+        self.try_extend(mntners.into_iter().map(Query::MntBy))
+    }
+
+    /// Add a [`Query::AsSetMembersRecursive`] to this [`Pipeline`] for each
+    /// of the provided `as-set`s.
+    ///
+    /// The queries are pipelined together, and each [`ResponseItem`] can be
+    /// attributed to the `as-set` that it is a member of using
+    /// [`ResponseItem::query()`]. See [`Connection::as_set_members_by_set()`]
+    /// for a method that also collects the members of each `as-set`.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error`] is returned if any of the queries cannot be written to
+    /// the underlying TCP socket.
+    #[tracing::instrument(skip(self, as_sets), level = "debug")]
+    pub fn push_as_set_members_recursive<I>(&mut self, as_sets: I) -> Result<&mut Self, Error>
+    where
+        I: IntoIterator<Item = AsSet>,
+    {
+        self.try_extend(as_sets.into_iter().map(Query::AsSetMembersRecursive))
+    }
+
+    /// Expand an `as-set` into the hierarchy of `as-set`s that it references.
+    ///
+    /// Whereas [`Query::AsSetMembersRecursive`] returns only the flattened
+    /// list of `aut-num`s, this method expands the hierarchy client-side,
+    /// using [`Query::AsSetMembers`] for each `as-set` encountered, so that the
+    /// membership tree can be inspected.
+    ///
+    /// The queries for each level of the hierarchy are pipelined together.
+    /// Each `as-set` is expanded only once, so cyclic references are handled
+    /// without infinite recursion. `as-set`s that do not exist on the server
+    /// are recorded in [`AsSetTree::unresolved()`].
+    ///
+    /// Any responses still outstanding in this [`Pipeline`] are first
+    /// discarded, as by [`clear()`][Self::clear].
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # use irrc::{IrrClient, Error};
+    /// # fn main() -> Result<(), Error> {
+    /// # let mut irr = IrrClient::new("whois.radb.net:43").connect()?;
+    /// let tree = irr.pipeline().as_set_tree("AS-FOO".parse().unwrap())?;
+    /// for (set, members) in tree.sets() {
+    ///     println!("{set}: {} nested as-sets", members.sets().len());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if a failure occurs on the underlying TCP
+    /// connection, or if the server returns an error response other than
+    /// [`KeyNotFound`][error::Response::KeyNotFound].
+    pub fn as_set_tree(&mut self, as_set: AsSet) -> Result<AsSetTree, Error> {
+        _ = self.clear();
+        tree::expand(self, as_set)
+    }
+
+    /// Add a [`Query::RpslObject`] to this [`Pipeline`] for each of the
+    /// provided keys, of the RPSL object class `class`.
+    ///
+    /// IRRd has no command to fetch multiple objects by key in a single
+    /// query, so a separate query is issued for each of `keys`. See
+    /// [`Connection::rpsl_objects()`] for a method that also collects the
+    /// responses.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error`] is returned if any of `keys` is not a valid query input,
+    /// or if any of the queries cannot be written to the underlying TCP
+    /// socket.
+    #[tracing::instrument(skip(self, keys), level = "debug")]
+    pub fn push_rpsl_objects<I, K>(
+        &mut self,
+        class: RpslObjectClass,
+        keys: I,
+    ) -> Result<&mut Self, Error>
+    where
+        I: IntoIterator<Item = K>,
+        K: AsRef<str>,
+    {
+        self.try_extend(
+            keys.into_iter()
+                .map(|key| Query::RpslObject(class, key.as_ref().to_owned())),
+        )
+    }
+
+    /// Add each of the queries in `iter` to this [`Pipeline`], stopping at
+    /// the first error.
+    ///
+    /// This is the fallible equivalent of the [`Extend<Query>`] implementation
+    /// for [`Pipeline`], which logs and ignores errors.
+    ///
+    /// # Errors
+    ///
+    /// The first [`Error`] returned by [`push()`][Self::push] is returned.
+    /// The query that caused the error will have been added to the
+    /// [`Pipeline`], and any remaining queries in `iter` are not added.
+    #[tracing::instrument(skip(self, iter), level = "debug")]
+    pub fn try_extend<I>(&mut self, iter: I) -> Result<&mut Self, Error>
+    where
+        I: IntoIterator<Item = Query>,
+    {
+        iter.into_iter()
+            .try_for_each(|query| self.push(query).map(|_| ()))?;
+        Ok(self)
+    }
+
+    /// Add each of the queries in `iter` to this [`Pipeline`], reporting how
+    /// many were added, and how many queries were written to the server in
+    /// the process.
+    ///
+    /// As with the [`Extend<Query>`] implementation, errors are logged and
+    /// otherwise ignored.
+    ///
+    /// Since queries are written in batches, some of the queries added may
+    /// not yet have been sent when this method returns, and
+    /// [`flushed`][ExtendReport::flushed] may be less than
+    /// [`queued`][ExtendReport::queued]. It may also be greater, if queries
+    /// added earlier were written along with them. Use
+    /// [`flush_now()`][Self::flush_now] to send any that remain.
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # use irrc::{IrrClient, Query, Error};
+    /// # fn main() -> Result<(), Error> {
+    /// # let mut conn = IrrClient::new("whois.radb.net:43").connect()?;
+    /// let mut pipeline = conn.pipeline();
+    /// let report = pipeline.extend_with_report([Query::Version, Query::GetSources]);
+    /// if report.flushed < report.queued {
+    ///     pipeline.flush_now()?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[tracing::instrument(skip(self, iter), level = "debug")]
+    pub fn extend_with_report<I>(&mut self, iter: I) -> ExtendReport
+    where
+        I: IntoIterator<Item = Query>,
+    {
+        let (pushed, sent) = (self.queue.pushed(), self.queue.sent());
+        iter.into_iter().for_each(|q| {
+            if let Err(err) = self.push(q) {
+                tracing::error!("error enqueuing query: {}", err);
+            }
+        });
+        ExtendReport {
+            queued: self.queue.pushed() - pushed,
+            flushed: self.queue.sent() - sent,
+        }
+    }
+
     #[tracing::instrument(level = "trace")]
     fn flush(&mut self) -> Result<(), Error> {
-        self.queue.flush(|query| self.conn.send(&query.cmd()))
+        self.flush_batch(false)
+    }
+
+    /// Write queued queries, reading any responses into the read buffer
+    /// whenever a write stalls.
+    ///
+    /// The server stops reading queries while it is unable to write its
+    /// responses, so a write may block for as long as the responses already
+    /// sent remain unread. If the queries may not fit in the socket buffers
+    /// (see [`may_stall()`][Self::may_stall]), writes are therefore made with
+    /// a short timeout, and when one expires, pending response data is read
+    /// before the write is retried.
+    fn flush_batch(&mut self, now: bool) -> Result<(), Error> {
+        if !self.queue.has_unsent() {
+            return Ok(());
+        }
+        let limit = self.conn.query_budget();
+        let max_len = self.max_response_len;
+        let may_stall = self.may_stall(limit);
+        if may_stall {
+            self.conn.set_write_timeout(Some(WRITE_STALL_TIMEOUT))?;
+        }
+        let mut stalled = false;
+        let buf = &mut self.buf;
+        let conn = &mut *self.conn;
+        let send = |query: &Query| {
+            let cmd = query.cmd();
+            conn.send(&cmd, |conn| {
+                if !stalled {
+                    conn.set_read_timeout(Some(WRITE_STALL_TIMEOUT))?;
+                    stalled = true;
+                }
+                read_stalled(conn, buf, max_len)
+            })
+            .map(|()| cmd.len())
+        };
+        let result = if now {
+            self.queue.flush_now(limit, send)
+        } else {
+            self.queue.flush(limit, send)
+        };
+        let mut restored = if stalled {
+            self.conn.set_read_timeout(None)
+        } else {
+            Ok(())
+        };
+        if may_stall {
+            let write_restored = self.conn.set_write_timeout(None);
+            restored = restored.and(write_restored);
+        }
+        // a failure to flush takes precedence over a failure to restore
+        result.and(restored)
+    }
+
+    /// Whether writing up to `limit` queued queries could stall on full socket
+    /// buffers.
+    ///
+    /// This is only possible if the queries that have been sent but not
+    /// answered, together with those about to be sent, exceed
+    /// [`DEFAULT_MAX_IN_FLIGHT_BYTES`][Self::DEFAULT_MAX_IN_FLIGHT_BYTES],
+    /// which fits within typical socket buffers.
+    fn may_stall(&self, limit: usize) -> bool {
+        let safe = Self::DEFAULT_MAX_IN_FLIGHT_BYTES;
+        self.queue.max_in_flight_bytes() > safe
+            && self
+                .queue
+                .sendable(limit)
+                .try_fold(self.queue.in_flight_bytes(), |total, query| {
+                    let total = total.saturating_add(query.cmd().len());
+                    (total <= safe).then_some(total)
+                })
+                .is_none()
+    }
+
+    /// Re-establish the underlying [`Connection`] if its query limit has been
+    /// reached, and the responses to all queries sent over it have been read.
+    ///
+    /// This must only be called when no [`Response`] is being read.
+    fn rotate_if_exhausted(&mut self) -> Result<(), Error> {
+        if self.conn.query_budget() == 0 && self.queue.in_flight() == 0 && self.queue.has_unsent() {
+            let unread = self.buf.available_data();
+            if unread > 0 {
+                tracing::warn!("discarding {unread} unexpected bytes before reconnecting");
+                _ = self.buf.consume(unread);
+            }
+            self.conn.rotate()?;
+        }
+        Ok(())
+    }
+
+    /// Immediately write any queued queries that have not yet been sent to
+    /// the server.
+    ///
+    /// Queries added using [`push()`][Self::push] are normally written to the
+    /// underlying TCP socket in batches, so recently pushed queries may not
+    /// yet have been sent. They will be sent once enough further queries are
+    /// pushed, or when responses are next read.
+    ///
+    /// This method bypasses the batching behaviour, and should only be used
+    /// when the caller knows that no further queries will be pushed soon,
+    /// and is not about to read any responses.
+    ///
+    /// The limit on the total number of queries in-flight is still respected.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error`] is returned if the queries cannot be written to the
+    /// underlying TCP socket.
+    #[tracing::instrument(skip(self), level = "debug")]
+    pub fn flush_now(&mut self) -> Result<&mut Self, Error> {
+        self.flush_batch(true)?;
+        Ok(self)
     }
 
     /// Get the next query response from this [`Pipeline`].
@@ -119,6 +715,9 @@ impl<'a> Pipeline<'a> {
     /// The compiler may need to be told which `T` to parse into in some cases,
     /// as in the following example.
     ///
+    /// If queued queries cannot be written to the underlying connection, the
+    /// write error is returned and any queries not yet sent are discarded.
+    ///
     /// # Example
     ///
     /// ``` no_run
@@ -143,6 +742,71 @@ impl<'a> Pipeline<'a> {
             .map(|wrapped| wrapped.map_err(error::Wrapper::take_inner))
     }
 
+    /// Get the next query response from this [`Pipeline`], retaining the
+    /// bytes of each item as received.
+    ///
+    /// **Unstable:** intended for reading the response to a command added
+    /// using [`push_raw()`][Self::push_raw], and may change or be removed in
+    /// any release.
+    ///
+    /// This is identical to [`pop()`][Self::pop], except that the bytes of
+    /// each item are available from [`ResponseItem::raw()`], as if by
+    /// [`Response::keep_raw()`].
+    pub fn pop_raw<'b, T>(&'b mut self) -> Option<Result<Response<'a, 'b, T>, Error>>
+    where
+        T: FromStr + fmt::Debug,
+        T::Err: std::error::Error + Send + Sync + 'static,
+    {
+        self.pop().map(|result| result.map(Response::keep_raw))
+    }
+
+    /// Get the status of the next query response from this [`Pipeline`],
+    /// without parsing any data returned.
+    ///
+    /// This is a lightweight alternative to [`pop()`][Self::pop] for cases
+    /// where only the outcome of a query is of interest, such as checking
+    /// whether an object exists using [`Query::RpslObject`].
+    ///
+    /// Any response data is read from the connection and discarded, so that
+    /// the next call to [`pop()`][Self::pop] or
+    /// [`pop_status()`][Self::pop_status] is correctly aligned with the
+    /// following response.
+    ///
+    /// Error responses from the server are returned as the corresponding
+    /// [`ResponseOutcome`], except for rate limiting, which is returned as an
+    /// [`Error::RateLimited`]. Other errors, such as failures on the
+    /// underlying connection, are returned as for [`pop()`][Self::pop].
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # use irrc::{IrrClient, Query, Error, ResponseOutcome, RpslObjectClass};
+    /// # fn main() -> Result<(), Error> {
+    /// # let mut conn = IrrClient::new("whois.radb.net:43").connect()?;
+    /// let mut pipeline = conn.pipeline();
+    /// pipeline.push(Query::RpslObject(RpslObjectClass::AutNum, "AS65000".to_string()))?;
+    /// let exists = matches!(pipeline.pop_status(), Some(Ok(ResponseOutcome::Found(_))));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn pop_status(&mut self) -> Option<Result<ResponseOutcome, Error>> {
+        let outcome = match self.pop::<String>()? {
+            Ok(response) => {
+                let len = response.expected_len();
+                response.discard().map(|()| ResponseOutcome::Found(len))
+            }
+            Err(Error::ResponseErr(_, error::Response::KeyNotFound)) => {
+                Ok(ResponseOutcome::NotFound)
+            }
+            Err(Error::ResponseErr(_, error::Response::KeyNotUnique)) => {
+                Ok(ResponseOutcome::NotUnique)
+            }
+            Err(Error::ResponseErr(_, err)) => Ok(ResponseOutcome::Error(err)),
+            Err(err) => Err(err),
+        };
+        Some(outcome)
+    }
+
     #[tracing::instrument(level = "trace")]
     fn pop_wrapped<'b, T>(
         &'b mut self,
@@ -152,9 +816,13 @@ impl<'a> Pipeline<'a> {
         T: FromStr + fmt::Debug,
         T::Err: std::error::Error + Send + Sync + 'static,
     {
-        match self.flush() {
+        match self.rotate_if_exhausted().and_then(|()| self.flush()) {
             Ok(()) => {}
-            Err(err) => return Some(Err(error::Wrapper::new(Some(self), err))),
+            Err(err) => {
+                let discarded = self.queue.discard_unsent();
+                tracing::warn!("failed to send queries, discarded {discarded} unsent: {err}");
+                return Some(Err(error::Wrapper::new(Some(self), err)));
+            }
         };
         #[allow(clippy::cognitive_complexity)]
         self.queue.pop().map(move |query| {
@@ -168,10 +836,11 @@ impl<'a> Pipeline<'a> {
                             Ok(Some(len)) => break len,
                             Ok(None) => break 0,
                             Err(err) => {
+                                self.conn.track_state(&query, false);
                                 return Err(error::Wrapper::new(
                                     Some(self),
-                                    Error::ResponseErr(query, err),
-                                ))
+                                    Error::from_response(query, err),
+                                ));
                             }
                         }
                     }
@@ -187,9 +856,27 @@ impl<'a> Pipeline<'a> {
                     }
                 }
             };
+            self.conn.track_state(&query, true);
+            if expect > self.max_response_len {
+                tracing::error!("response length {expect} exceeds maximum");
+                return Err(error::Wrapper::new(
+                    Some(self),
+                    Error::ResponseTooLarge(expect),
+                ));
+            }
             if query.expect_data() {
                 if expect == 0 {
-                    tracing::warn!("unexpected zero length response for query {query:?}");
+                    let kind = QueryKind::from(&query);
+                    let allowed = self
+                        .allow_empty
+                        .get(&kind)
+                        .copied()
+                        .unwrap_or_else(|| kind.allows_empty_response());
+                    if !allowed {
+                        tracing::warn!("unexpected zero length response for {query}");
+                        return Err(error::Wrapper::new(Some(self), Error::EmptyResponse(query)));
+                    }
+                    tracing::debug!("found empty response for {query}");
                 }
                 tracing::debug!("expecting response length {} bytes", expect);
                 Ok(Response::new(query, self, expect))
@@ -214,8 +901,12 @@ impl<'a> Pipeline<'a> {
     /// If the compiler cannot determine the appropriate type, the turbo-fish
     /// (`::<T>`) syntax may be necessary.
     ///
-    /// Error responses received from the server will be logged at the
-    /// `WARNING` level and then skipped.
+    /// Error responses received from the server, and errors encountered
+    /// while parsing response items, are logged at the `WARNING` level and
+    /// returned in place of the affected items. Use
+    /// [`collect_errors()`][Responses::collect_errors] to iterate over only
+    /// the successfully parsed items, while retaining the errors for
+    /// inspection afterwards.
     ///
     /// If some other error handling is required, use
     /// [`pop()`][Self::pop] instead.
@@ -247,26 +938,325 @@ impl<'a> Pipeline<'a> {
         Responses {
             pipeline: Some(self),
             current_reponse: None,
+            until: None,
+            keep_raw: false,
         }
     }
 
-    #[tracing::instrument(skip(self), level = "trace")]
-    fn fetch(&mut self) -> Result<usize, Error> {
-        self.buf.shift();
-        let space = self.buf.space();
-        tracing::trace!("trying to fetch up to {} bytes", space.len());
-        let fetched = self.conn.read(space)?;
-        tracing::trace!("fetched {} bytes", fetched);
-        let filled = self.buf.fill(fetched);
-        Ok(filled)
-    }
-
-    /// Clear an existing [`Pipeline`] by consuming and discarding
-    /// any unread responses from the server.
+    /// Get an iterator over the [`ResponseItem`]s returned by the server for
+    /// each outstanding query issued, in order, retaining the bytes of each
+    /// item as received.
     ///
-    /// Because query responses are transmitted by the server serially, the
-    /// client relies on the known ordering of queries in order to match
-    /// query to response. Therefore any un consumed responses must be read
+    /// This is identical to [`responses()`][Self::responses], except that
+    /// the bytes of each item are available from [`ResponseItem::raw()`].
+    /// This can be used to detect differences between the data sent by the
+    /// server and its parsed representation, such as leading zeros removed
+    /// when parsing a prefix.
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # use irrc::{IrrClient, Query, Error};
+    /// # fn main() -> Result<(), Error> {
+    /// IrrClient::new("whois.radb.net:43")
+    ///     .connect()?
+    ///     .pipeline()
+    ///     .push(Query::Ipv4Routes("AS65000".parse().unwrap()))?
+    ///     .responses_with_raw::<String>()
+    ///     .filter_map(Result::ok)
+    ///     .for_each(|item| println!("{:?}", item.raw()));
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[tracing::instrument(skip(self), level = "trace")]
+    pub fn responses_with_raw<'b, T>(&'b mut self) -> Responses<'a, 'b, T>
+    where
+        'a: 'b,
+        T: FromStr + fmt::Debug,
+        T::Err: std::error::Error + Send + Sync + 'static,
+    {
+        Responses {
+            keep_raw: true,
+            ..self.responses()
+        }
+    }
+
+    /// Get an iterator over the [`ResponseItem`]s returned by the server for
+    /// each outstanding query issued, in order, paired with the [`Query`]
+    /// that each item or error was returned in response to.
+    ///
+    /// This is identical to [`responses()`][Self::responses], except that
+    /// errors also carry the originating [`Query`]. This allows a batch of
+    /// queries to report exactly which inputs failed, including failures that
+    /// occur before any item of the response is read, such as an error
+    /// response from the server.
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # use irrc::{IrrClient, Query, Error};
+    /// # fn main() -> Result<(), Error> {
+    /// IrrClient::new("whois.radb.net:43")
+    ///     .connect()?
+    ///     .pipeline()
+    ///     .push(Query::Ipv4Routes("AS65000".parse().unwrap()))?
+    ///     .push(Query::Ipv4Routes("AS65001".parse().unwrap()))?
+    ///     .responses_with_query::<String>()
+    ///     .for_each(|(query, result)| match result {
+    ///         Ok(item) => println!("{query}: {}", item.content()),
+    ///         Err(err) => eprintln!("{query} failed: {err}"),
+    ///     });
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[tracing::instrument(skip(self), level = "trace")]
+    pub fn responses_with_query<'b, T>(&'b mut self) -> ResponsesWithQuery<'a, 'b, T>
+    where
+        'a: 'b,
+        T: FromStr + fmt::Debug,
+        T::Err: std::error::Error + Send + Sync + 'static,
+    {
+        ResponsesWithQuery(self.responses())
+    }
+
+    /// Get an iterator over the [`ResponseItem`]s returned by the server for
+    /// each outstanding query issued, parsing the items on the [`rayon`]
+    /// global thread pool.
+    ///
+    /// Items are read from the connection on the calling thread, and parsed
+    /// as `T` concurrently, while the next items are being read. This is
+    /// useful when parsing each item is expensive relative to reading it,
+    /// for example when parsing whole RPSL objects as
+    /// [`RpslObject`][crate::RpslObject].
+    ///
+    /// Items are yielded in the order in which they were received, exactly
+    /// as for [`responses()`][Self::responses]. To bound memory use, reading
+    /// pauses once a small number of items per thread in the pool have been
+    /// read but not yet yielded.
+    ///
+    /// A panic while parsing an item is propagated to the calling thread
+    /// when that item would have been yielded.
+    ///
+    /// Requires the `parallel` feature.
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # use irrc::{IrrClient, Query, Error, RpslObjectClass};
+    /// # fn main() -> Result<(), Error> {
+    /// use irrc::RpslObject;
+    ///
+    /// IrrClient::new("whois.radb.net:43")
+    ///     .connect()?
+    ///     .pipeline()
+    ///     .push(Query::RpslObject(RpslObjectClass::AutNum, "AS65000".to_string()))?
+    ///     .push(Query::RpslObject(RpslObjectClass::AutNum, "AS65001".to_string()))?
+    ///     .responses_parallel::<RpslObject>()
+    ///     .filter_map(Result::ok)
+    ///     .for_each(|item| println!("{:?}", item.content().source()));
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`rayon`]: https://docs.rs/rayon
+    #[cfg(feature = "parallel")]
+    #[tracing::instrument(skip(self), level = "trace")]
+    pub fn responses_parallel<'b, T>(&'b mut self) -> ParallelResponses<'a, 'b, T>
+    where
+        'a: 'b,
+        T: FromStr + fmt::Debug + Send + 'static,
+        T::Err: std::error::Error + Send + Sync + 'static,
+    {
+        ParallelResponses::new(self.responses())
+    }
+
+    /// Get a lending iterator over the responses returned by the server for
+    /// each outstanding query issued, in order, with the [`ResponseItem`]s of
+    /// each response grouped together with the [`Query`] that produced them.
+    ///
+    /// Unlike [`responses()`][Self::responses], error responses are not
+    /// skipped, but are returned in place of the corresponding group.
+    ///
+    /// Each group is a [`Response`], which reads its items from the
+    /// connection as they are consumed, and so borrows the
+    /// [`ResponseChunks`]. It must be consumed or dropped before the next
+    /// group is requested, and for this reason [`ResponseChunks`] does not
+    /// implement [`Iterator`].
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # use irrc::{IrrClient, Query, Error};
+    /// # fn main() -> Result<(), Error> {
+    /// let autnum = "AS65000".parse().unwrap();
+    /// let mut conn = IrrClient::new("whois.radb.net:43").connect()?;
+    /// let mut pipeline = conn.pipeline();
+    /// pipeline
+    ///     .push(Query::Ipv4Routes(autnum))?
+    ///     .push(Query::Ipv6Routes(autnum))?;
+    /// let mut chunks = pipeline.responses_chunked::<String>();
+    /// while let Some((query, items)) = chunks.next().transpose()? {
+    ///     println!("{query:?}: {}", items.count());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[tracing::instrument(skip(self), level = "trace")]
+    pub fn responses_chunked<'b, T>(&'b mut self) -> ResponseChunks<'a, 'b, T>
+    where
+        'a: 'b,
+        T: FromStr + fmt::Debug,
+        T::Err: std::error::Error + Send + Sync + 'static,
+    {
+        ResponseChunks {
+            pipeline: self,
+            content_type: PhantomData,
+        }
+    }
+
+    /// Mark the boundary between queries already added to this [`Pipeline`]
+    /// and those added subsequently.
+    ///
+    /// The returned [`Checkpoint`] can be passed to
+    /// [`responses_until()`][Self::responses_until] or
+    /// [`reached()`][Self::reached] to distinguish the responses to one batch
+    /// of queries from the next, without the need to
+    /// [`clear()`][Self::clear] the [`Pipeline`] between batches.
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # use irrc::{IrrClient, Query, Error};
+    /// # fn main() -> Result<(), Error> {
+    /// # let mut conn = IrrClient::new("whois.radb.net:43").connect()?;
+    /// let mut pipeline = conn.pipeline();
+    /// pipeline.push(Query::Ipv4Routes("AS65000".parse().unwrap()))?;
+    /// let first_batch = pipeline.checkpoint();
+    /// pipeline.push(Query::Ipv4Routes("AS65001".parse().unwrap()))?;
+    /// // only the routes originated by AS65000 are returned
+    /// pipeline
+    ///     .responses_until::<String>(first_batch)
+    ///     .filter_map(Result::ok)
+    ///     .for_each(|route| println!("{:?}", route));
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub const fn checkpoint(&self) -> Checkpoint {
+        Checkpoint(self.queue.pushed())
+    }
+
+    /// Whether all responses to queries added before `checkpoint` have been
+    /// popped from this [`Pipeline`].
+    #[must_use]
+    pub const fn reached(&self, checkpoint: Checkpoint) -> bool {
+        self.queue.popped() >= checkpoint.0
+    }
+
+    /// Get an iterator over the [`ResponseItem`]s returned by the server for
+    /// each outstanding query issued before `checkpoint`.
+    ///
+    /// The iterator ends once all of the responses to those queries have
+    /// been consumed. Responses to queries issued after `checkpoint` are left
+    /// unread.
+    ///
+    /// See [`responses()`][Self::responses] and
+    /// [`checkpoint()`][Self::checkpoint] for details.
+    #[tracing::instrument(skip(self), level = "trace")]
+    pub fn responses_until<'b, T>(&'b mut self, checkpoint: Checkpoint) -> Responses<'a, 'b, T>
+    where
+        'a: 'b,
+        T: FromStr + fmt::Debug,
+        T::Err: std::error::Error + Send + Sync + 'static,
+    {
+        Responses {
+            pipeline: Some(self),
+            current_reponse: None,
+            until: Some(checkpoint),
+            keep_raw: false,
+        }
+    }
+
+    /// Fetch more data from the [`Connection`].
+    ///
+    /// While the [`Pipeline`] is being cleared with a
+    /// [`drain_timeout()`][Self::drain_timeout] set, this gives up once the
+    /// deadline has passed, returning an error that ends the drain.
+    fn fetch(&mut self) -> Result<usize, Error> {
+        match self.drain_deadline {
+            None => self.fill(),
+            Some(deadline) => self.fetch_until(Some(deadline)).map_err(|err| match err {
+                Error::ItemTimeout => {
+                    self.drain_expired = true;
+                    Error::Io(io::ErrorKind::TimedOut.into())
+                }
+                err => err,
+            }),
+        }
+    }
+
+    #[tracing::instrument(skip(self), level = "trace")]
+    fn fill(&mut self) -> Result<usize, Error> {
+        self.buf.shift();
+        if self.buf.available_space() == 0 {
+            self.grow()?;
+        }
+        let space = self.buf.space();
+        let requested = space.len();
+        tracing::trace!("trying to fetch up to {requested} bytes");
+        let fetched = self.conn.read(space)?;
+        tracing::trace!("fetched {} bytes", fetched);
+        if fetched == 0 && requested > 0 {
+            tracing::warn!("connection closed by server");
+            return Err(Error::ConnectionClosed);
+        }
+        let filled = self.buf.fill(fetched);
+        Ok(filled)
+    }
+
+    /// Fetch more data from the [`Connection`], giving up with
+    /// [`Error::ItemTimeout`] if none is received before `deadline`.
+    fn fetch_until(&mut self, deadline: Option<Instant>) -> Result<usize, Error> {
+        let Some(deadline) = deadline else {
+            return self.fetch();
+        };
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(Error::ItemTimeout);
+        }
+        self.conn.set_read_timeout(Some(remaining))?;
+        let result = self.fill().map_err(|err| match err {
+            Error::Io(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                tracing::warn!("no response data received before deadline");
+                Error::ItemTimeout
+            }
+            err => err,
+        });
+        // restore the timeout even if the fetch failed, preferring to report
+        // the failure of the fetch itself
+        let restored = self.conn.set_read_timeout(None);
+        result.and_then(|filled| restored.map(|()| filled))
+    }
+
+    /// Grow a full read buffer, so that an item larger than the buffer can
+    /// be read.
+    ///
+    /// The capacity is doubled each time, up to the maximum response length.
+    fn grow(&mut self) -> Result<(), Error> {
+        grow_buffer(&mut self.buf, self.max_response_len)
+    }
+
+    /// Clear an existing [`Pipeline`] by consuming and discarding
+    /// any unread responses from the server.
+    ///
+    /// Because query responses are transmitted by the server serially, the
+    /// client relies on the known ordering of queries in order to match
+    /// query to response. Therefore any un consumed responses must be read
     /// and dropped before the [`Pipeline`] can be reused for a new sequence
     /// of queries.
     ///
@@ -303,31 +1293,144 @@ impl<'a> Pipeline<'a> {
     /// ```
     #[tracing::instrument(level = "trace")]
     pub fn clear(&mut self) -> &mut Self {
+        self.drain_deadline = self.drain_timeout.map(|timeout| Instant::now() + timeout);
         self.responses::<String>().consume();
+        self.drain_deadline = None;
+        if std::mem::take(&mut self.drain_expired) {
+            tracing::warn!("outstanding responses not drained before timeout");
+            _ = self.abort_remaining();
+        }
+        self
+    }
+
+    /// Discard all outstanding queries without reading their responses, and
+    /// close the underlying [`Connection`].
+    ///
+    /// Unlike [`clear()`][Self::clear], this method returns immediately,
+    /// regardless of how many responses are still to be received from the
+    /// server.
+    ///
+    /// Because any unread response data remains in flight, the
+    /// [`Connection`] cannot be re-synchronized with the server and is closed.
+    /// Any further attempt to issue queries using it, either via this
+    /// [`Pipeline`] or another, will return [`Error::ConnectionClosed`]. The
+    /// caller should discard the [`Connection`] and establish a new one. Use
+    /// [`Connection::is_closed()`] to check whether this has happened.
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # use irrc::{IrrClient, Query, Error};
+    /// # fn main() -> Result<(), Error> {
+    /// let client = IrrClient::new("whois.radb.net:43");
+    /// let mut conn = client.connect()?;
+    /// let mut pipeline = conn.pipeline();
+    /// pipeline.push(Query::AsSetMembersRecursive("AS-HUGE".parse().unwrap()))?;
+    /// // decide that the results are no longer needed
+    /// pipeline.abort_remaining();
+    /// drop(pipeline);
+    /// assert!(conn.is_closed());
+    /// let conn = client.connect()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[tracing::instrument(level = "debug")]
+    pub fn abort_remaining(&mut self) -> &mut Self {
+        let discarded = self.queue.discard_all();
+        tracing::info!("aborting {discarded} outstanding queries");
+        let buffered = self.buf.available_data();
+        _ = self.buf.consume(buffered);
+        self.conn.abort();
         self
     }
 }
 
+/// Time allowed for a write to make progress before pending responses are
+/// read instead.
+const WRITE_STALL_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// Double the capacity of a full read buffer, up to `max_len`.
+fn grow_buffer(buf: &mut Buffer, max_len: usize) -> Result<(), Error> {
+    let capacity = buf.capacity();
+    if capacity >= max_len {
+        tracing::error!("read buffer is full at maximum capacity of {capacity} bytes");
+        return Err(Error::ItemTooLarge(capacity));
+    }
+    let new_capacity = capacity.saturating_mul(2).clamp(1, max_len);
+    tracing::debug!("growing read buffer from {capacity} to {new_capacity} bytes");
+    _ = buf.grow(new_capacity);
+    Ok(())
+}
+
+/// Read whatever response data is available from `conn` into `buf`, while a
+/// write is stalled.
+///
+/// The read timeout of `conn` must already be set to
+/// [`WRITE_STALL_TIMEOUT`], so that this waits at most that long for data.
+///
+/// The buffer is grown as necessary, up to `max_len`, since responses to
+/// several queries may be read before any of them is popped. Data already in
+/// the buffer is only moved, never consumed, so offsets relative to the
+/// start of [`Buffer::data()`] remain valid.
+fn read_stalled(conn: &mut Connection, buf: &mut Buffer, max_len: usize) -> Result<(), Error> {
+    buf.shift();
+    if buf.available_space() == 0 {
+        grow_buffer(buf, max_len)?;
+    }
+    match conn.read(buf.space()) {
+        Ok(0) => {
+            tracing::warn!("connection closed by server");
+            Err(Error::ConnectionClosed)
+        }
+        Ok(read) => {
+            tracing::trace!("read {read} bytes while write stalled");
+            _ = buf.fill(read);
+            Ok(())
+        }
+        Err(Error::Io(err))
+            if matches!(
+                err.kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+            ) =>
+        {
+            Ok(())
+        }
+        Err(err) => Err(err),
+    }
+}
+
 impl Drop for Pipeline<'_> {
     fn drop(&mut self) {
         _ = self.clear();
+        let buf = std::mem::replace(&mut self.buf, Buffer::with_capacity(0));
+        self.conn.return_buffer(buf);
+        self.conn.exit_pipeline();
     }
 }
 
+/// Errors encountered while adding queries are logged and otherwise ignored.
+/// Use [`Pipeline::try_extend()`] to stop at the first error instead, or
+/// [`Pipeline::extend_with_report()`] to find out how many queries were
+/// sent.
 impl Extend<Query> for Pipeline<'_> {
-    #[tracing::instrument(skip(self, iter), level = "debug")]
     fn extend<I>(&mut self, iter: I)
     where
         I: IntoIterator<Item = Query>,
     {
-        iter.into_iter().for_each(|q| {
-            if let Err(err) = self.push(q) {
-                tracing::error!("error enqueuing query: {}", err);
-            }
-        });
+        _ = self.extend_with_report(iter);
     }
 }
 
+/// The outcome of [`Pipeline::extend_with_report()`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ExtendReport {
+    /// The number of queries added to the [`Pipeline`].
+    pub queued: usize,
+    /// The number of queries written to the server, including any added
+    /// before the call.
+    pub flushed: usize,
+}
+
 impl fmt::Debug for Pipeline<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let max_length = 100;
@@ -344,10 +1447,40 @@ impl fmt::Debug for Pipeline<'_> {
                 &format_args!("'{}{}'", buf_decoded.escape_debug(), truncated),
             )
             .field("queue", &self.queue)
+            .field("max_response_len", &self.max_response_len)
+            .field("max_items_per_response", &self.max_items_per_response)
             .finish()
     }
 }
 
+/// The status of a query response, returned by [`Pipeline::pop_status()`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum ResponseOutcome {
+    /// The query succeeded, and the server returned the given number of bytes
+    /// of response data, which were discarded.
+    ///
+    /// The length is zero for queries that do not return data, and for empty
+    /// responses.
+    Found(usize),
+    /// The query was valid, but the primary key queried for did not exist.
+    NotFound,
+    /// The query was valid, but there are multiple copies of the key in one
+    /// database.
+    NotUnique,
+    /// The server returned some other error response.
+    Error(error::Response),
+}
+
+/// A boundary between batches of queries added to a [`Pipeline`].
+///
+/// Returned by [`Pipeline::checkpoint()`]. See the method documentation for
+/// details.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Checkpoint(usize);
+
+/// A response item, and the query of an item that is an error.
+type Tagged<T> = (Option<Query>, Result<ResponseItem<T>, Error>);
+
 /// Iterator returned by [`responses()`][Pipeline::responses] method.
 ///
 /// See [`Pipeline::responses`] for details.
@@ -359,6 +1492,8 @@ where
 {
     pipeline: Option<&'b mut Pipeline<'a>>,
     current_reponse: Option<Response<'a, 'b, T>>,
+    until: Option<Checkpoint>,
+    keep_raw: bool,
 }
 
 impl<T> Responses<'_, '_, T>
@@ -383,20 +1518,36 @@ where
 
     #[tracing::instrument(level = "trace")]
     fn next(&mut self) -> Option<Self::Item> {
+        self.next_tagged(false).map(|(_, item)| item)
+    }
+}
+
+impl<T> Responses<'_, '_, T>
+where
+    T: FromStr + fmt::Debug,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    /// Get the next item, and, if `tag` is set and the item is an error, the
+    /// query in response to which the error occurred.
+    fn next_tagged(&mut self, tag: bool) -> Option<Tagged<T>> {
         loop {
             if let Some(ref mut current) = self.current_reponse {
                 match current.next_or_yield() {
-                    Ok(ItemOrYield::Item(item)) => return Some(item),
+                    Ok(ItemOrYield::Item(item)) => {
+                        let query = (tag && item.is_err()).then(|| current.query().clone());
+                        return Some((query, item));
+                    }
                     Ok(ItemOrYield::Yield(pipeline)) => {
                         self.pipeline = Some(pipeline);
                         self.current_reponse = None;
                     }
                     Err(err) => {
+                        let query = tag.then(|| current.query().clone());
                         let (pipeline, inner_err) = err.split();
                         tracing::warn!("error while extracting response item: {inner_err}");
                         self.pipeline = pipeline;
                         self.current_reponse = None;
-                        return Some(Err(inner_err));
+                        return Some((query, Err(inner_err)));
                     }
                     Ok(ItemOrYield::Finished) => {
                         unreachable!("current_reponse has already finished")
@@ -404,15 +1555,30 @@ where
                 }
             }
             if let Some(pipeline) = self.pipeline.take() {
+                if self
+                    .until
+                    .is_some_and(|checkpoint| pipeline.reached(checkpoint))
+                {
+                    tracing::debug!("checkpoint reached");
+                    self.pipeline = Some(pipeline);
+                    return None;
+                }
+                // the query is popped, and may be consumed, before a
+                // response error is returned
+                let query = tag.then(|| pipeline.queue.front().cloned()).flatten();
                 if let Some(next_response) = pipeline.pop_wrapped() {
                     match next_response {
                         Ok(response) => {
-                            self.current_reponse = Some(response);
+                            self.current_reponse = Some(if self.keep_raw {
+                                response.keep_raw()
+                            } else {
+                                response
+                            });
                         }
                         Err(err) => {
                             let (pipeline, inner_err) = err.split();
                             self.pipeline = pipeline;
-                            return Some(Err(inner_err));
+                            return Some((query, Err(inner_err)));
                         }
                     }
                 }
@@ -431,140 +1597,270 @@ where
 {
 }
 
-/// A successful query response.
-///
-/// If the query returned data, this can be accessed by iteration over
-/// [`Response<T>`].
-///
-/// Constructed by [`Pipeline::pop()`]. See the method documentation for
-/// details.
-#[derive(Debug)]
-pub struct Response<'a, 'b, T>
+impl<'a, 'b, T> Responses<'a, 'b, T>
 where
     T: FromStr + fmt::Debug,
     T::Err: std::error::Error + Send + Sync + 'static,
 {
-    query: Query,
-    pipeline: Option<&'b mut Pipeline<'a>>,
-    expect: usize,
-    seen: usize,
-    finished: bool,
-    content_type: PhantomData<T>,
-}
+    /// Iterate over the successfully parsed [`ResponseItem`]s only, setting
+    /// aside any errors encountered.
+    ///
+    /// The errors are available from [`CollectErrors::errors()`] once the
+    /// items have been consumed. No allocation is made unless an error
+    /// occurs.
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # use irrc::{IrrClient, Query, Error};
+    /// # fn main() -> Result<(), Error> {
+    /// let autnum = "AS65000".parse().unwrap();
+    /// let mut conn = IrrClient::new("whois.radb.net:43").connect()?;
+    /// let mut pipeline = conn.pipeline();
+    /// let mut items = pipeline
+    ///     .push(Query::Ipv4Routes(autnum))?
+    ///     .push(Query::Ipv6Routes(autnum))?
+    ///     .responses::<String>()
+    ///     .collect_errors();
+    /// items.by_ref().for_each(|route| println!("{}", route.content()));
+    /// println!("{} errors", items.errors().len());
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub const fn collect_errors(self) -> CollectErrors<'a, 'b, T> {
+        CollectErrors {
+            responses: self,
+            errors: Vec::new(),
+        }
+    }
 
-impl<'a, 'b, T> Response<'a, 'b, T>
-where
-    T: FromStr + fmt::Debug,
-    T::Err: std::error::Error + Send + Sync + 'static,
-{
-    pub(crate) fn new(query: Query, pipeline: &'b mut Pipeline<'a>, expect: usize) -> Self {
-        Self {
-            query,
-            pipeline: Some(pipeline),
-            expect,
-            seen: 0,
-            finished: false,
-            content_type: PhantomData,
+    /// Skip RPSL objects that have already been returned.
+    ///
+    /// When more than one source is selected, a query that returns whole RPSL
+    /// objects (such as [`Query::RpslObject`]) may return the same object more
+    /// than once, for example when a mirror of one source is included in
+    /// another. Objects are considered duplicates if they have the same class,
+    /// primary key and `source:`. Only the first occurrence of each object is
+    /// returned, and no attempt is made to merge the contents of duplicates.
+    ///
+    /// Items that are not whole RPSL objects, and errors, are passed through
+    /// unchanged.
+    ///
+    /// The primary key of `route` and `route6` objects is the combination of
+    /// the prefix and the `origin:`. For all other classes it is the value of
+    /// the first (class) attribute.
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # use irrc::{IrrClient, Query, Error, RpslObjectClass};
+    /// # fn main() -> Result<(), Error> {
+    /// let mut conn = IrrClient::new("whois.radb.net:43").connect()?;
+    /// let mut pipeline = conn.pipeline();
+    /// for item in pipeline
+    ///     .push(Query::RpslObject(RpslObjectClass::AutNum, "AS65000".to_string()))?
+    ///     .push(Query::RpslObject(RpslObjectClass::AutNum, "AS65000".to_string()))?
+    ///     .responses::<String>()
+    ///     .dedup_objects()
+    /// {
+    ///     println!("{}", item?.content());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn dedup_objects(self) -> DedupObjects<'a, 'b, T> {
+        DedupObjects {
+            responses: self,
+            seen: HashSet::new(),
         }
     }
+}
 
-    /// The [`Query`] which this was a response to.
+#[cfg(feature = "ip")]
+impl Responses<'_, '_, Prefix<Any>> {
+    /// Consume the remaining items, aggregating the prefixes into a
+    /// [`PrefixSet`].
+    ///
+    /// Any errors encountered are logged and the corresponding items are
+    /// skipped.
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # use irrc::{IrrClient, Query, Error};
+    /// # fn main() -> Result<(), Error> {
+    /// use ip::{Any, Prefix};
+    ///
+    /// let autnum = "AS65000".parse().unwrap();
+    /// let mut conn = IrrClient::new("whois.radb.net:43").connect()?;
+    /// let prefixes = conn
+    ///     .pipeline()
+    ///     .push(Query::Ipv4Routes(autnum))?
+    ///     .push(Query::Ipv6Routes(autnum))?
+    ///     .responses::<Prefix<Any>>()
+    ///     .into_prefix_set();
+    /// # Ok(())
+    /// # }
+    /// ```
     #[must_use]
-    pub const fn query(&self) -> &Query {
-        &self.query
+    pub fn into_prefix_set(self) -> PrefixSet<Any> {
+        collect_prefixes::<Any, _>(self)
     }
+}
 
-    fn fuse(&mut self) {
-        self.finished = true;
+#[cfg(feature = "ip")]
+impl Responses<'_, '_, Prefix<Ipv4>> {
+    /// Consume the remaining items, aggregating the IPv4 prefixes into a
+    /// [`PrefixSet`].
+    ///
+    /// See [`into_prefix_set()`][Responses::into_prefix_set] for details.
+    #[must_use]
+    pub fn into_prefix_set_v4(self) -> PrefixSet<Ipv4> {
+        collect_prefixes::<Ipv4, _>(self)
     }
+}
 
-    #[tracing::instrument(level = "trace")]
-    fn next_or_yield(&mut self) -> Result<ItemOrYield<'a, 'b, T>, error::Wrapper<'a, 'b>> {
-        if self.finished {
-            tracing::trace!("response fully consumed");
-            return Ok(ItemOrYield::Finished);
-        }
-        if let Some(pipeline) = self.pipeline.take() {
-            if self.query.expect_data() {
-                if self.expect == 0 {
-                    self.fuse();
-                    Ok(ItemOrYield::Yield(pipeline))
-                } else {
-                    loop {
-                        if let Ok((_, consumed)) = parse::end_of_response(pipeline.buf.data()) {
-                            _ = pipeline.buf.consume(consumed);
-                            self.fuse();
-                            break if self.expect == self.seen + 1 {
-                                Ok(ItemOrYield::Yield(pipeline))
-                            } else {
-                                let err = Error::ResponseDataUnderrun(self.seen, self.expect);
-                                tracing::error!(%err);
-                                Err(error::Wrapper::new(Some(pipeline), err))
-                            };
-                        }
-                        if self.seen > self.expect {
-                            self.fuse();
-                            let err = Error::ResponseDataOverrun(self.seen, self.expect);
-                            tracing::error!(%err);
-                            break Err(error::Wrapper::new(Some(pipeline), err));
-                        }
-                        match self.query.parse_item(pipeline.buf.data()) {
-                            Ok((consumed, item)) => {
-                                let item_result = Ok(ResponseItem(item, self.query.clone()));
-                                _ = pipeline.buf.consume(consumed);
-                                self.seen += consumed;
-                                self.pipeline = Some(pipeline);
-                                break Ok(ItemOrYield::Item(item_result));
-                            }
-                            Err(Error::Incomplete | Error::ParseErr) => {
-                                if let Err(err) = pipeline.fetch() {
-                                    break Ok(ItemOrYield::Item(Err(err)));
-                                }
-                            }
-                            Err(err @ Error::ParseItem(_, _)) => {
-                                tracing::error!("error parsing content from response item: {err}");
-                                if let Error::ParseItem(_, consumed) = err {
-                                    _ = pipeline.buf.consume(consumed);
-                                    self.seen += consumed;
-                                }
-                                self.pipeline = Some(pipeline);
-                                break Ok(ItemOrYield::Item(Err(err)));
-                            }
-                            Err(err) => {
-                                tracing::error!("error parsing word from buffer: {err}");
-                                break Ok(ItemOrYield::Item(Err(err)));
-                            }
-                        }
-                    }
-                }
-            } else {
-                self.fuse();
-                Ok(ItemOrYield::Yield(pipeline))
+#[cfg(feature = "ip")]
+impl Responses<'_, '_, Prefix<Ipv6>> {
+    /// Consume the remaining items, aggregating the IPv6 prefixes into a
+    /// [`PrefixSet`].
+    ///
+    /// See [`into_prefix_set()`][Responses::into_prefix_set] for details.
+    #[must_use]
+    pub fn into_prefix_set_v6(self) -> PrefixSet<Ipv6> {
+        collect_prefixes::<Ipv6, _>(self)
+    }
+}
+
+#[cfg(feature = "ip")]
+fn collect_prefixes<A, I>(items: I) -> PrefixSet<A>
+where
+    A: AfiClass,
+    I: Iterator<Item = Result<ResponseItem<Prefix<A>>, Error>>,
+    Prefix<A>: FromStr + fmt::Debug,
+    <Prefix<A> as FromStr>::Err: std::error::Error + Send + Sync + 'static,
+{
+    items
+        .filter_map(|result| match result {
+            Ok(item) => Some(item.into_content()),
+            Err(err) => {
+                tracing::warn!("skipping prefix: {err}");
+                None
             }
-        } else {
-            self.fuse();
-            Err(error::Wrapper::new(None, Error::ConsumedResponse))
-        }
+        })
+        .collect()
+}
+
+/// Iterator returned by
+/// [`responses_with_query()`][Pipeline::responses_with_query] method.
+///
+/// See [`Pipeline::responses_with_query`] for details.
+#[derive(Debug)]
+pub struct ResponsesWithQuery<'a, 'b, T>(Responses<'a, 'b, T>)
+where
+    T: FromStr + fmt::Debug,
+    T::Err: std::error::Error + Send + Sync + 'static;
+
+impl<T> Iterator for ResponsesWithQuery<'_, '_, T>
+where
+    T: FromStr + fmt::Debug,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    type Item = (Query, Result<ResponseItem<T>, Error>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (query, result) = self.0.next_tagged(true)?;
+        let query = match (&result, query) {
+            (Ok(item), _) => item.query().clone(),
+            (Err(_), Some(query)) => query,
+            // every error is returned while a query is outstanding
+            (Err(_), None) => unreachable!("error returned without an outstanding query"),
+        };
+        Some((query, result))
+    }
+}
+
+impl<T> FusedIterator for ResponsesWithQuery<'_, '_, T>
+where
+    T: FromStr + fmt::Debug,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+}
+
+/// Iterator returned by [`collect_errors()`][Responses::collect_errors]
+/// method.
+///
+/// See [`Responses::collect_errors`] for details.
+#[derive(Debug)]
+pub struct CollectErrors<'a, 'b, T>
+where
+    T: FromStr + fmt::Debug,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    responses: Responses<'a, 'b, T>,
+    errors: Vec<Error>,
+}
+
+impl<T> CollectErrors<'_, '_, T>
+where
+    T: FromStr + fmt::Debug,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    /// The errors encountered so far, in the order in which they occurred.
+    #[must_use]
+    pub fn errors(&self) -> &[Error] {
+        &self.errors
     }
 
-    fn consume(&mut self) {
-        for item in self {
-            tracing::debug!(?item, "consuming unused response item");
-        }
+    /// Consume the iterator, returning the errors encountered so far.
+    #[must_use]
+    pub fn into_errors(self) -> Vec<Error> {
+        self.errors
     }
 }
 
-impl<T> Drop for Response<'_, '_, T>
+impl<T> Iterator for CollectErrors<'_, '_, T>
 where
     T: FromStr + fmt::Debug,
     T::Err: std::error::Error + Send + Sync + 'static,
 {
-    fn drop(&mut self) {
-        self.consume();
+    type Item = ResponseItem<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for result in self.responses.by_ref() {
+            match result {
+                Ok(item) => return Some(item),
+                Err(err) => self.errors.push(err),
+            }
+        }
+        None
     }
 }
 
-impl<T> Iterator for Response<'_, '_, T>
+impl<T> FusedIterator for CollectErrors<'_, '_, T>
+where
+    T: FromStr + fmt::Debug,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+}
+
+/// Iterator returned by [`dedup_objects()`][Responses::dedup_objects]
+/// method.
+///
+/// See [`Responses::dedup_objects`] for details.
+#[derive(Debug)]
+pub struct DedupObjects<'a, 'b, T>
+where
+    T: FromStr + fmt::Debug,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    responses: Responses<'a, 'b, T>,
+    seen: HashSet<String>,
+}
+
+impl<T> Iterator for DedupObjects<'_, '_, T>
 where
     T: FromStr + fmt::Debug,
     T::Err: std::error::Error + Send + Sync + 'static,
@@ -572,91 +1868,2782 @@ where
     type Item = Result<ResponseItem<T>, Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        match self.next_or_yield() {
-            Ok(ItemOrYield::Item(item)) => Some(item),
-            Ok(ItemOrYield::Yield(_) | ItemOrYield::Finished) => None,
-            Err(err) => Some(Err(err.into())),
+        for result in self.responses.by_ref() {
+            if let Some(key) = result.as_ref().ok().and_then(ResponseItem::primary_key) {
+                if !self.seen.insert(key.to_owned()) {
+                    tracing::debug!("skipping duplicate object {key}");
+                    continue;
+                }
+            }
+            return Some(result);
         }
+        None
     }
 }
 
-impl<T> FusedIterator for Response<'_, '_, T>
+impl<T> FusedIterator for DedupObjects<'_, '_, T>
 where
     T: FromStr + fmt::Debug,
     T::Err: std::error::Error + Send + Sync + 'static,
 {
 }
 
-enum ItemOrYield<'a, 'b, T>
+/// Lending iterator returned by
+/// [`responses_chunked()`][Pipeline::responses_chunked] method.
+///
+/// See [`Pipeline::responses_chunked`] for details.
+#[derive(Debug)]
+pub struct ResponseChunks<'a, 'b, T> {
+    pipeline: &'b mut Pipeline<'a>,
+    content_type: PhantomData<T>,
+}
+
+impl<'a, T> ResponseChunks<'a, '_, T>
 where
     T: FromStr + fmt::Debug,
     T::Err: std::error::Error + Send + Sync + 'static,
 {
-    Item(Result<ResponseItem<T>, Error>),
-    Yield(&'b mut Pipeline<'a>),
-    Finished,
+    /// Get the [`Query`] and [`Response`] for the next outstanding query,
+    /// or `None` once all responses have been read.
+    ///
+    /// Any previously returned [`Response`] that was not fully consumed is
+    /// read to the end first.
+    #[allow(clippy::should_implement_trait)]
+    #[tracing::instrument(level = "trace")]
+    pub fn next(&mut self) -> Option<Result<(Query, Response<'a, '_, T>), Error>> {
+        self.pipeline
+            .pop::<T>()
+            .map(|result| result.map(|response| (response.query().clone(), response)))
+    }
 }
 
-/// An individual data element contained within the query response.
+/// Iterator returned by [`single()`][Response::single] method.
 ///
-/// The nature of each element is dependent on the corresponding [`Query`]
-/// variant.
+/// Yields exactly one result, and so implements [`ExactSizeIterator`].
+///
+/// See [`Response::single`] for details.
 #[derive(Debug)]
-pub struct ResponseItem<T>(ResponseContent<T>, Query)
+pub struct SingleResponse<'a, 'b, T>
 where
     T: FromStr + fmt::Debug,
-    T::Err: std::error::Error + Send + Sync + 'static;
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    response: Option<Response<'a, 'b, T>>,
+}
 
-impl<T> ResponseItem<T>
+impl<T> Iterator for SingleResponse<'_, '_, T>
 where
     T: FromStr + fmt::Debug,
     T::Err: std::error::Error + Send + Sync + 'static,
 {
-    /// Borrow the content of [`ResponseItem`].
-    pub const fn content(&self) -> &T {
-        self.0.content()
-    }
+    type Item = Result<ResponseItem<T>, Error>;
 
-    /// Take ownership of the content of [`ResponseItem`].
-    pub fn into_content(self) -> T {
-        self.0.into_content()
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut response = self.response.take()?;
+        let first = match response.next() {
+            Some(Ok(item)) => Some(item),
+            Some(Err(err)) => return Some(Err(err)),
+            None => None,
+        };
+        let mut extra = 0;
+        for result in response.by_ref() {
+            match result {
+                Err(err) if err.is_fatal() => return Some(Err(err)),
+                _ => extra += 1,
+            }
+        }
+        Some(match (first, extra) {
+            (Some(item), 0) => Ok(item),
+            (first, extra) => Err(Error::ExpectedSingle(
+                response.query().clone(),
+                usize::from(first.is_some()) + extra,
+            )),
+        })
     }
 
-    /// The [`Query`] which this element was provided in response to.
-    pub const fn query(&self) -> &Query {
-        &self.1
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = usize::from(self.response.is_some());
+        (len, Some(len))
     }
 }
 
-#[derive(Debug)]
-pub(crate) struct ResponseContent<T>(T)
+impl<T> ExactSizeIterator for SingleResponse<'_, '_, T>
 where
     T: FromStr + fmt::Debug,
-    T::Err: std::error::Error + Send + Sync + 'static;
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+}
 
-impl<T> ResponseContent<T>
+impl<T> FusedIterator for SingleResponse<'_, '_, T>
 where
     T: FromStr + fmt::Debug,
     T::Err: std::error::Error + Send + Sync + 'static,
 {
-    const fn content(&self) -> &T {
-        &self.0
-    }
-
-    #[allow(clippy::missing_const_for_fn)]
-    fn into_content(self) -> T {
-        self.0
-    }
 }
 
-impl<T> TryFrom<&[u8]> for ResponseContent<T>
+/// A successful query response.
+///
+/// If the query returned data, this can be accessed by iteration over
+/// [`Response<T>`].
+///
+/// Constructed by [`Pipeline::pop()`]. See the method documentation for
+/// details.
+#[derive(Debug)]
+pub struct Response<'a, 'b, T>
 where
     T: FromStr + fmt::Debug,
     T::Err: std::error::Error + Send + Sync + 'static,
 {
-    type Error = Box<dyn std::error::Error + Send + Sync>;
+    query: Query,
+    pipeline: Option<&'b mut Pipeline<'a>>,
+    expect: usize,
+    seen: usize,
+    finished: bool,
+    item_timeout: Option<Duration>,
+    keep_raw: bool,
+    pending: usize,
+    max_items: Option<usize>,
+    items: usize,
+    truncated: bool,
+    index: usize,
+    lines: usize,
+    content_type: PhantomData<T>,
+}
 
-    fn try_from(buf: &[u8]) -> Result<Self, Self::Error> {
-        Ok(Self(from_utf8(buf)?.parse()?))
+impl<'a, 'b, T> Response<'a, 'b, T>
+where
+    T: FromStr + fmt::Debug,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    pub(crate) fn new(query: Query, pipeline: &'b mut Pipeline<'a>, expect: usize) -> Self {
+        Self {
+            query,
+            pipeline: Some(pipeline),
+            expect,
+            seen: 0,
+            finished: false,
+            item_timeout: None,
+            keep_raw: false,
+            pending: 0,
+            max_items: None,
+            items: 0,
+            truncated: false,
+            index: 0,
+            lines: 0,
+            content_type: PhantomData,
+        }
+    }
+
+    /// The [`Query`] which this was a response to.
+    #[must_use]
+    pub const fn query(&self) -> &Query {
+        &self.query
+    }
+
+    /// The length in bytes of the response data, as indicated by the server.
+    ///
+    /// This is zero for queries that do not return data, and for queries that
+    /// returned an empty response.
+    ///
+    /// Together with [`consumed_len()`][Self::consumed_len], this can be used
+    /// to report progress while reading a large response.
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # use irrc::{IrrClient, Query, Error};
+    /// # fn main() -> Result<(), Error> {
+    /// # let mut conn = IrrClient::new("whois.radb.net:43").connect()?;
+    /// let mut pipeline = conn.pipeline();
+    /// pipeline.push(Query::Ipv4Routes("AS65000".parse().unwrap()))?;
+    /// let mut response = pipeline.pop::<String>().unwrap()?;
+    /// while let Some(item) = response.next() {
+    ///     let progress = 100 * response.consumed_len() / response.expected_len();
+    ///     println!("{progress}%: {}", item?.content());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub const fn expected_len(&self) -> usize {
+        self.expect
+    }
+
+    /// The number of bytes of response data read so far.
+    ///
+    /// This is updated as each item is yielded, and is equal to
+    /// [`expected_len()`][Self::expected_len] once the response has been
+    /// completely read.
+    #[must_use]
+    pub const fn consumed_len(&self) -> usize {
+        self.seen + self.pending
+    }
+
+    /// Estimate the number of items remaining in this [`Response`].
+    ///
+    /// The estimate is based on the number of bytes of response data not yet
+    /// read, and the typical size of the items returned by the [`Query`]: for
+    /// example, an IPv4 prefix is assumed to occupy about 16 bytes, and an
+    /// RPSL object about 256 bytes.
+    ///
+    /// Unlike the bounds returned by
+    /// [`size_hint()`][Iterator::size_hint], this may be either more or less
+    /// than the number of items actually returned, but is usually a better
+    /// guide to the capacity to pre-allocate. It is used by
+    /// [`into_vec()`][Self::into_vec] and
+    /// [`into_vec_skipping_errors()`][Self::into_vec_skipping_errors].
+    #[must_use]
+    pub const fn estimated_len(&self) -> usize {
+        if self.finished || self.pipeline.is_none() || !self.query.expect_data() {
+            0
+        } else {
+            self.expect
+                .saturating_sub(self.consumed_len())
+                .div_ceil(self.query.typical_item_len())
+        }
+    }
+
+    /// Give up waiting for each item of this [`Response`] after `timeout`.
+    ///
+    /// If the next item has not been received from the server within
+    /// `timeout` of a call to [`next()`][Iterator::next], that call returns
+    /// [`Error::ItemTimeout`]. Unlike a read timeout on the underlying
+    /// socket, this leaves the [`Connection`] usable: no data is discarded,
+    /// and calling [`next()`][Iterator::next] again resumes reading where
+    /// the previous call left off, waiting for up to `timeout` once more.
+    ///
+    /// # Re-synchronization
+    ///
+    /// The rest of the response may still be arriving from the server after
+    /// an item timeout, and must be read before the response to the next
+    /// query can be. Dropping the [`Response`] does this, waiting without a
+    /// timeout until the remaining data has been received and discarded. If
+    /// the server may never complete the response, use
+    /// [`Pipeline::abort_remaining()`] instead, and establish a new
+    /// [`Connection`].
+    ///
+    /// For connections constructed using [`Connection::from_stream()`],
+    /// reads cannot be interrupted, and the timeout is only checked between
+    /// reads.
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # use irrc::{IrrClient, Query, Error};
+    /// # fn main() -> Result<(), Error> {
+    /// # let mut conn = IrrClient::new("whois.radb.net:43").connect()?;
+    /// use std::time::Duration;
+    ///
+    /// let mut pipeline = conn.pipeline();
+    /// pipeline.push(Query::AsSetMembersRecursive("AS-HUGE".parse().unwrap()))?;
+    /// let mut response = pipeline
+    ///     .pop::<String>()
+    ///     .unwrap()?
+    ///     .item_timeout(Duration::from_millis(500));
+    /// while let Some(item) = response.next() {
+    ///     match item {
+    ///         Ok(item) => println!("{}", item.content()),
+    ///         Err(Error::ItemTimeout) => {
+    ///             println!("giving up on this response");
+    ///             break;
+    ///         }
+    ///         Err(err) => return Err(err),
+    ///     }
+    /// }
+    /// // dropping `response` waits for the remaining data to be discarded
+    /// drop(response);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub const fn item_timeout(mut self, timeout: Duration) -> Self {
+        self.item_timeout = Some(timeout);
+        self
+    }
+
+    /// Retain the bytes of each item, exactly as received from the server,
+    /// alongside the parsed content.
+    ///
+    /// The bytes are available from [`ResponseItem::raw()`]. This is useful
+    /// for auditing normalization performed while parsing, or for debugging
+    /// parse mismatches.
+    ///
+    /// This incurs an additional allocation for each item, and so is
+    /// disabled by default. See also
+    /// [`Pipeline::responses_with_raw()`].
+    #[must_use]
+    pub const fn keep_raw(mut self) -> Self {
+        self.keep_raw = true;
+        self
+    }
+
+    /// Stop after `limit` items have been successfully parsed.
+    ///
+    /// Once the limit is reached, the rest of the response data is read and
+    /// discarded without being parsed, leaving the [`Connection`] ready to
+    /// read the response to the next query, and
+    /// [`is_truncated()`][Self::is_truncated] returns `true` if any data was
+    /// discarded.
+    ///
+    /// This is useful for queries, such as [`Query::RoutesMore`] for a short
+    /// prefix, that may return a very large number of items when only a
+    /// sample is required. Note that the discarded data must still be
+    /// received from the server.
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # use irrc::{IrrClient, Query, Error};
+    /// # fn main() -> Result<(), Error> {
+    /// # let mut conn = IrrClient::new("whois.radb.net:43").connect()?;
+    /// let mut pipeline = conn.pipeline();
+    /// pipeline.push(Query::routes_more("10.0.0.0/8")?)?;
+    /// let mut response = pipeline.pop::<String>().unwrap()?.max_items(100);
+    /// let sample = response.by_ref().collect::<Result<Vec<_>, _>>()?;
+    /// if response.is_truncated() {
+    ///     println!("showing the first {} routes only", sample.len());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub const fn max_items(mut self, limit: usize) -> Self {
+        self.max_items = Some(limit);
+        self
+    }
+
+    /// Whether response data was discarded after reaching the limit set
+    /// using [`max_items()`][Self::max_items], or the limit set using
+    /// [`Pipeline::max_items_per_response()`].
+    #[must_use]
+    pub const fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Convert this [`Response`] into one whose items are parsed as `U`
+    /// rather than `T`.
+    ///
+    /// This allows the item type to be chosen after inspecting the
+    /// [`query()`][Self::query] that this is a response to.
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # use irrc::{IrrClient, Query, Error};
+    /// # fn main() -> Result<(), Error> {
+    /// # let mut conn = IrrClient::new("whois.radb.net:43").connect()?;
+    /// use rpsl::expr::AsSetMember;
+    ///
+    /// let mut pipeline = conn.pipeline();
+    /// pipeline
+    ///     .push(Query::as_set_members("AS-FOO")?)?
+    ///     .push(Query::ipv4_routes(65000)?)?;
+    /// while let Some(response) = pipeline.pop::<String>() {
+    ///     let response = response?;
+    ///     if let Query::AsSetMembers(_) = response.query() {
+    ///         for item in response.parse_as::<AsSetMember>() {
+    ///             println!("member: {}", item?.content());
+    ///         }
+    ///     } else {
+    ///         for item in response {
+    ///             println!("route: {}", item?.content());
+    ///         }
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn parse_as<U>(mut self) -> Response<'a, 'b, U>
+    where
+        U: FromStr + fmt::Debug,
+        U::Err: std::error::Error + Send + Sync + 'static,
+    {
+        let converted = Response {
+            query: self.query.clone(),
+            pipeline: self.pipeline.take(),
+            expect: self.expect,
+            seen: self.seen,
+            finished: self.finished,
+            item_timeout: self.item_timeout,
+            keep_raw: self.keep_raw,
+            pending: self.pending,
+            max_items: self.max_items,
+            items: self.items,
+            truncated: self.truncated,
+            index: self.index,
+            lines: self.lines,
+            content_type: PhantomData,
+        };
+        Self::fuse(&mut self);
+        converted
+    }
+
+    /// Expect this [`Response`] to contain exactly one item.
+    ///
+    /// This is intended for queries that should return a single data
+    /// element, such as [`Query::Version`], or a [`Query::RpslObject`] lookup
+    /// of a unique primary key. The returned iterator yields exactly one
+    /// result, so implements [`ExactSizeIterator`], unlike [`Response`],
+    /// whose number of items cannot be known until the response data has
+    /// been parsed.
+    ///
+    /// The result is the single item, or an [`Error::ExpectedSingle`] if the
+    /// response contains no items or more than one. Any further items are
+    /// read and discarded, so that the [`Pipeline`] remains usable. An error
+    /// reading or parsing the first item is returned as is.
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # use irrc::{IrrClient, Query, Error};
+    /// # fn main() -> Result<(), Error> {
+    /// # let mut conn = IrrClient::new("whois.radb.net:43").connect()?;
+    /// let mut pipeline = conn.pipeline();
+    /// let version = pipeline
+    ///     .push(Query::Version)?
+    ///     .pop::<String>()
+    ///     .unwrap()?
+    ///     .single();
+    /// assert_eq!(version.len(), 1);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub const fn single(self) -> SingleResponse<'a, 'b, T> {
+        SingleResponse {
+            response: Some(self),
+        }
+    }
+
+    /// Read all of the remaining items in this [`Response`] into a [`Vec`].
+    ///
+    /// # Errors
+    ///
+    /// The first error encountered while reading or parsing an item is
+    /// returned. Any items remaining after the error are consumed and
+    /// discarded. Use
+    /// [`into_vec_skipping_errors()`][Self::into_vec_skipping_errors] to
+    /// continue past errors instead.
+    pub fn into_vec(self) -> Result<Vec<ResponseItem<T>>, Error> {
+        let mut items = Vec::with_capacity(self.estimated_len());
+        for result in self {
+            items.push(result?);
+        }
+        Ok(items)
+    }
+
+    /// Read all of the remaining items in this [`Response`] into a [`Vec`],
+    /// collecting any errors encountered separately.
+    #[must_use]
+    pub fn into_vec_skipping_errors(self) -> (Vec<ResponseItem<T>>, Vec<Error>) {
+        let mut items = Vec::with_capacity(self.estimated_len());
+        let mut errors = Vec::new();
+        for result in self {
+            match result {
+                Ok(item) => items.push(item),
+                Err(err) => errors.push(err),
+            }
+        }
+        (items, errors)
+    }
+
+    /// Parse the remaining items as IP prefixes of any address family,
+    /// aggregating them into a [`PrefixSet`].
+    ///
+    /// Any errors encountered are logged and the corresponding items are
+    /// skipped.
+    #[cfg(feature = "ip")]
+    #[must_use]
+    pub fn into_prefix_set(self) -> PrefixSet<Any> {
+        collect_prefixes::<Any, _>(self.parse_as())
+    }
+
+    /// Parse the remaining items as IPv4 prefixes, aggregating them into a
+    /// [`PrefixSet`].
+    ///
+    /// See [`into_prefix_set()`][Self::into_prefix_set] for details.
+    #[cfg(feature = "ip")]
+    #[must_use]
+    pub fn into_prefix_set_v4(self) -> PrefixSet<Ipv4> {
+        collect_prefixes::<Ipv4, _>(self.parse_as())
+    }
+
+    /// Parse the remaining items as IPv6 prefixes, aggregating them into a
+    /// [`PrefixSet`].
+    ///
+    /// See [`into_prefix_set()`][Self::into_prefix_set] for details.
+    #[cfg(feature = "ip")]
+    #[must_use]
+    pub fn into_prefix_set_v6(self) -> PrefixSet<Ipv6> {
+        collect_prefixes::<Ipv6, _>(self.parse_as())
+    }
+
+    /// Read the remaining data elements of this [`Response`] into temporary
+    /// storage, returning an iterator over the items that reads them back.
+    ///
+    /// Responses with more remaining data than the
+    /// [`threshold()`][SpillOptions::threshold] in `options` are written to
+    /// an anonymous temporary file, which is removed when the returned
+    /// iterator is dropped. Smaller responses are held in memory. This allows
+    /// very large responses, such as a full table of route objects, to be
+    /// processed with bounded memory usage, after the [`Pipeline`] has moved
+    /// on to later responses or been dropped.
+    ///
+    /// Items are parsed as they are read back, so any errors in their
+    /// content are returned from the iterator, rather than by this method.
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if the response data cannot be read from the
+    /// underlying connection, or is not correctly framed, or if the temporary
+    /// file cannot be created or written.
+    ///
+    /// If the temporary file cannot be created or written, the remainder of
+    /// the response is still read and discarded, so that the [`Pipeline`] is
+    /// left ready to read the next response. If reading the response data
+    /// fails, reading stops at the point of failure, as for
+    /// [`for_each_raw()`][Self::for_each_raw], and later responses in the
+    /// [`Pipeline`] may not be readable.
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # use irrc::{IrrClient, Query, Error};
+    /// # fn main() -> Result<(), Error> {
+    /// # let mut conn = IrrClient::new("whois.radb.net:43").connect()?;
+    /// use irrc::SpillOptions;
+    ///
+    /// let mut options = SpillOptions::new();
+    /// options.threshold(16 << 20);
+    /// let routes = conn
+    ///     .pipeline()
+    ///     .push(Query::RoutesMore("0.0.0.0/0".to_string()))?
+    ///     .pop::<String>()
+    ///     .unwrap()?
+    ///     .spill(&options)?;
+    /// for route in routes {
+    ///     println!("{}", route?.content());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "spill")]
+    pub fn spill(self, options: &SpillOptions) -> Result<SpilledResponse<T>, Error> {
+        SpilledResponse::new(self, options)
+    }
+
+    /// Call `f` with the raw bytes of each of the remaining data elements in
+    /// this [`Response`].
+    ///
+    /// The bytes passed to `f` are borrowed directly from the read buffer of
+    /// the [`Pipeline`], so no allocation is performed per item. This is
+    /// useful when the data elements are to be hashed, written to another
+    /// stream, or otherwise processed without parsing into an owned `T`.
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if the response data cannot be read from the
+    /// underlying connection, or is not correctly framed. Unlike iteration
+    /// over the [`Response`], no checks are performed on the content of the
+    /// data elements themselves.
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # use irrc::{IrrClient, Query, Error};
+    /// # fn main() -> Result<(), Error> {
+    /// # let mut conn = IrrClient::new("whois.radb.net:43").connect()?;
+    /// let mut total = 0;
+    /// conn.pipeline()
+    ///     .push(Query::Ipv4Routes("AS65000".parse().unwrap()))?
+    ///     .pop::<String>()
+    ///     .unwrap()?
+    ///     .for_each_raw(|prefix| total += prefix.len())?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn for_each_raw<F>(mut self, mut f: F) -> Result<(), Error>
+    where
+        F: FnMut(&[u8]),
+    {
+        loop {
+            let step = self.step(|query, buf| {
+                query.parse_raw(buf).map(|(consumed, raw)| {
+                    f(raw);
+                    (consumed, ())
+                })
+            });
+            match step {
+                Ok(ItemOrYield::Item(Ok(()))) => {}
+                Ok(ItemOrYield::Item(Err(err))) => return Err(err),
+                Ok(ItemOrYield::Yield(_) | ItemOrYield::Finished) => return Ok(()),
+                Err(err) => return Err(err.take_inner()),
+            }
+        }
+    }
+
+    /// Feed the raw bytes of each of the remaining data elements in this
+    /// [`Response`] into `hasher`, as they are read.
+    ///
+    /// This computes a digest of the response without storing it, for
+    /// example to detect whether the membership of an `as-set` has changed
+    /// since it was last queried. Each element is hashed as a byte slice,
+    /// including its length, so that the digest depends on how the data is
+    /// divided into elements, but not on the whitespace separating them.
+    ///
+    /// The digest depends on the order in which elements are returned by the
+    /// server. If the digests are to be stored and compared across program
+    /// runs, use a [`Hasher`] with a stable, specified algorithm, rather than
+    /// [`DefaultHasher`][std::collections::hash_map::DefaultHasher].
+    ///
+    /// See [`for_each_raw()`][Self::for_each_raw] for details of the checks
+    /// performed.
+    ///
+    /// # Errors
+    ///
+    /// As for [`for_each_raw()`][Self::for_each_raw].
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # use irrc::{IrrClient, Query, Error};
+    /// # fn main() -> Result<(), Error> {
+    /// # let mut conn = IrrClient::new("whois.radb.net:43").connect()?;
+    /// use std::collections::hash_map::DefaultHasher;
+    /// use std::hash::Hasher;
+    ///
+    /// let mut hasher = DefaultHasher::new();
+    /// conn.pipeline()
+    ///     .push(Query::AsSetMembers("AS-FOO".parse().unwrap()))?
+    ///     .pop::<String>()
+    ///     .unwrap()?
+    ///     .digest(&mut hasher)?;
+    /// println!("{:x}", hasher.finish());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn digest<H: Hasher>(self, hasher: &mut H) -> Result<(), Error> {
+        self.for_each_raw(|item| item.hash(hasher))
+    }
+
+    /// Get the next data element of this [`Response`] as a string slice
+    /// borrowed from the read buffer of the [`Pipeline`].
+    ///
+    /// This avoids the allocation of an owned `T` for each item, when the
+    /// content of each item is only needed transiently. The same checks are
+    /// applied to each item as when iterating over the [`Response`], and an
+    /// [`Error::ParseItem`] is returned in place of an item that is not
+    /// valid UTF-8.
+    ///
+    /// Because the returned slice borrows from the read buffer, it must be
+    /// dropped before the [`Response`] (or the [`Pipeline`] it was obtained
+    /// from) can be advanced. For this reason, this method cannot be provided
+    /// as an [`Iterator`], and should be used in a `while let` loop instead.
+    /// Use [`into_content()`][ResponseItem::into_content] on the items of the
+    /// [`Response`] iterator if owned values are required.
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # use irrc::{IrrClient, Query, Error};
+    /// # fn main() -> Result<(), Error> {
+    /// # let mut conn = IrrClient::new("whois.radb.net:43").connect()?;
+    /// let mut pipeline = conn.pipeline();
+    /// pipeline.push(Query::Ipv4Routes("AS65000".parse().unwrap()))?;
+    /// let mut response = pipeline.pop::<String>().unwrap()?;
+    /// while let Some(prefix) = response.next_str() {
+    ///     println!("{}", prefix?);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn next_str(&mut self) -> Option<Result<&str, Error>> {
+        let step = self.step(|query, buf| {
+            let (consumed, item) = query.parse_raw(buf)?;
+            query.check_item(item, consumed)?;
+            // `item` is a sub-slice of `buf`
+            let start = item.as_ptr() as usize - buf.as_ptr() as usize;
+            // consumption of the item is deferred until the next step, so
+            // that it can be borrowed from the buffer in the meantime
+            Ok((0, (consumed, start..start + item.len())))
+        });
+        match step {
+            Ok(ItemOrYield::Item(Ok((consumed, range)))) => {
+                self.pending = consumed;
+                let pipeline = self.pipeline.as_deref()?;
+                let position = self.item_position(&pipeline.buf.data()[..consumed]);
+                Some(
+                    from_utf8(&pipeline.buf.data()[range])
+                        .map_err(|err| Error::ParseItem(Box::new(err), consumed, Some(position))),
+                )
+            }
+            Ok(ItemOrYield::Item(Err(err))) => Some(Err(err)),
+            Ok(ItemOrYield::Yield(_) | ItemOrYield::Finished) => None,
+            Err(err) => Some(Err(err.into())),
+        }
+    }
+
+    /// Discard the remaining items in this [`Response`], without parsing
+    /// them.
+    ///
+    /// Dropping a [`Response`] reads and parses each of its remaining items
+    /// in order to discard them. When a large response is no longer
+    /// required, this method is much cheaper: the response data is skipped
+    /// over using the length indicated by the server, and only the
+    /// terminating bytes are checked. Afterwards, the [`Pipeline`] is ready
+    /// to read the response to the next query.
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if the response data cannot be read from the
+    /// underlying connection, or is not correctly terminated.
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # use irrc::{IrrClient, Query, Error};
+    /// # fn main() -> Result<(), Error> {
+    /// # let mut conn = IrrClient::new("whois.radb.net:43").connect()?;
+    /// let mut pipeline = conn.pipeline();
+    /// pipeline
+    ///     .push(Query::AsSetMembersRecursive("AS-HUGE".parse().unwrap()))?
+    ///     .push(Query::Version)?;
+    /// let mut members = pipeline.pop::<String>().unwrap()?;
+    /// if let Some(first) = members.next() {
+    ///     println!("first member is {}", first?.content());
+    /// }
+    /// members.discard()?;
+    /// let version = pipeline.pop::<String>().unwrap()?.into_vec()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn discard(mut self) -> Result<(), Error> {
+        self.consume_pending();
+        if !self.finished && self.query.expect_data() {
+            if let Some(pipeline) = self.pipeline.as_mut() {
+                // stop short of the newline terminating the data, which is
+                // checked by `step()` along with the end of response marker.
+                let target = self.expect.saturating_sub(1);
+                tracing::debug!(
+                    "skipping {} bytes of response data",
+                    target.saturating_sub(self.seen)
+                );
+                while self.seen < target {
+                    if pipeline.buf.available_data() == 0 {
+                        if let Err(err) = pipeline.fetch() {
+                            Self::fuse(&mut self);
+                            return Err(match err {
+                                Error::ConnectionClosed => self.truncate(&Buffer::with_capacity(0)),
+                                err => err,
+                            });
+                        }
+                    }
+                    let skipped = pipeline
+                        .buf
+                        .consume(pipeline.buf.available_data().min(target - self.seen));
+                    self.seen += skipped;
+                }
+            }
+        }
+        self.for_each_raw(|_| {})
+    }
+
+    fn fuse(&mut self) {
+        self.finished = true;
+    }
+
+    /// The error to report when the length of the response data read does
+    /// not match the length indicated by the server.
+    fn length_mismatch(&self) -> Error {
+        if self.seen > self.expect {
+            Error::ResponseDataOverrun(self.query.clone(), self.seen, self.expect)
+        } else {
+            Error::ResponseDataUnderrun(self.query.clone(), self.seen, self.expect)
+        }
+    }
+
+    /// Finish reading this response after the connection is closed before
+    /// the length of response data indicated by the server has been
+    /// received, returning the error to report.
+    ///
+    /// `buf` holds any data received but not yet consumed.
+    fn truncate(&mut self, buf: &Buffer) -> Error {
+        self.fuse();
+        let err = Error::ResponseTruncated(
+            self.query.clone(),
+            self.seen + buf.available_data(),
+            self.expect,
+        );
+        tracing::error!(%err);
+        err
+    }
+
+    /// Discard response data following the last item permitted by
+    /// [`max_items()`][Self::max_items], returning the number of bytes
+    /// discarded.
+    ///
+    /// The newline terminating the data is left in place, to be checked
+    /// along with the end of response marker.
+    fn discard_excess(&mut self, buf: &mut Buffer) -> usize {
+        let remaining = self.expect.saturating_sub(self.seen + 1);
+        self.truncated |= remaining > 0;
+        let discarded = buf.consume(buf.available_data().min(remaining));
+        self.seen += discarded;
+        discarded
+    }
+
+    /// Consume the item last returned by [`next_str()`][Self::next_str]
+    /// from the read buffer.
+    fn consume_pending(&mut self) {
+        if self.pending > 0 {
+            let pending = std::mem::take(&mut self.pending);
+            if let Some(pipeline) = self.pipeline.take() {
+                self.advance(&mut pipeline.buf, pending);
+                self.pipeline = Some(pipeline);
+            } else {
+                self.seen += pending;
+            }
+        }
+    }
+
+    /// The position of the next data element in the response, which
+    /// occupies `data`, ignoring any leading whitespace.
+    fn item_position(&self, data: &[u8]) -> ItemPosition {
+        let lead = &data[..data.iter().take_while(|b| b.is_ascii_whitespace()).count()];
+        ItemPosition {
+            index: self.index,
+            offset: self.seen + lead.len(),
+            line: self.lines + count_lines(lead) + 1,
+        }
+    }
+
+    /// Consume a data element occupying `consumed` bytes from `buf`.
+    ///
+    /// Nothing is consumed if `consumed` is zero, which is the case when
+    /// consumption is deferred by [`next_str()`][Self::next_str].
+    fn advance(&mut self, buf: &mut Buffer, consumed: usize) {
+        if consumed > 0 {
+            self.lines += count_lines(&buf.data()[..consumed]);
+            self.index += 1;
+            self.seen += buf.consume(consumed);
+        }
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn next_or_yield(
+        &mut self,
+    ) -> Result<ItemOrYield<'a, 'b, ResponseItem<T>>, error::Wrapper<'a, 'b>> {
+        let keep_raw = self.keep_raw;
+        let normalize_sources = self
+            .pipeline
+            .as_ref()
+            .is_some_and(|pipeline| pipeline.conn.normalizes_sources());
+        self.step(|query, buf| {
+            query
+                .parse_item(buf, keep_raw)
+                .map(|(consumed, mut content)| {
+                    if normalize_sources {
+                        content.uppercase_source();
+                    }
+                    (consumed, ResponseItem(content, query.clone()))
+                })
+        })
+    }
+
+    /// Advance through the response data by a single item, using `parse` to
+    /// extract the item from the read buffer.
+    #[allow(clippy::too_many_lines)]
+    fn step<I, P>(&mut self, mut parse: P) -> Result<ItemOrYield<'a, 'b, I>, error::Wrapper<'a, 'b>>
+    where
+        P: FnMut(&Query, &[u8]) -> Result<(usize, I), Error>,
+    {
+        if self.finished {
+            tracing::trace!("response fully consumed");
+            return Ok(ItemOrYield::Finished);
+        }
+        self.consume_pending();
+        let deadline = self.item_timeout.map(|timeout| Instant::now() + timeout);
+        if let Some(pipeline) = self.pipeline.take() {
+            if self.query.expect_data() {
+                if self.expect == 0 {
+                    self.fuse();
+                    Ok(ItemOrYield::Yield(pipeline))
+                } else {
+                    loop {
+                        if let Ok((_, consumed)) = parse::end_of_response(pipeline.buf.data()) {
+                            _ = pipeline.buf.consume(consumed);
+                            self.fuse();
+                            break if self.expect == self.seen + 1
+                                || (pipeline.relaxed_terminator && self.expect == self.seen)
+                            {
+                                // account for the newline terminating the data
+                                self.seen = self.expect;
+                                Ok(ItemOrYield::Yield(pipeline))
+                            } else {
+                                let err = self.length_mismatch();
+                                tracing::error!(%err);
+                                Err(error::Wrapper::new(Some(pipeline), err))
+                            };
+                        }
+                        if pipeline.relaxed_terminator && self.seen == self.expect {
+                            if let Ok((_, consumed)) =
+                                parse::bare_end_of_response(pipeline.buf.data())
+                            {
+                                _ = pipeline.buf.consume(consumed);
+                                self.fuse();
+                                break Ok(ItemOrYield::Yield(pipeline));
+                            }
+                        }
+                        if self.seen > self.expect {
+                            self.fuse();
+                            let err = self.length_mismatch();
+                            tracing::error!(%err);
+                            break Err(error::Wrapper::new(Some(pipeline), err));
+                        }
+                        let at_max_items = self.max_items.is_some_and(|max| self.items >= max);
+                        let parsed = if at_max_items
+                            || self.items >= pipeline.max_items_per_response
+                        {
+                            // `truncated` is set by the first discard, so
+                            // that the limit is reported only once
+                            let reported = self.truncated;
+                            if self.discard_excess(&mut pipeline.buf) > 0 {
+                                if !at_max_items && !reported {
+                                    let err = Error::TooManyItems(
+                                        self.query.clone(),
+                                        pipeline.max_items_per_response,
+                                    );
+                                    tracing::warn!(%err);
+                                    self.pipeline = Some(pipeline);
+                                    break Ok(ItemOrYield::Item(Err(err)));
+                                }
+                                continue;
+                            }
+                            Err(Error::Incomplete)
+                        } else if let Some(consumed) = self.query.out_of_bounds(pipeline.buf.data())
+                        {
+                            tracing::trace!("skipping item outside prefix length bound");
+                            self.advance(&mut pipeline.buf, consumed);
+                            continue;
+                        } else {
+                            parse(&self.query, pipeline.buf.data())
+                        };
+                        match parsed {
+                            Ok((consumed, item)) => {
+                                self.advance(&mut pipeline.buf, consumed);
+                                self.items += 1;
+                                self.pipeline = Some(pipeline);
+                                break Ok(ItemOrYield::Item(Ok(item)));
+                            }
+                            Err(Error::Incomplete | Error::ParseErr) => {
+                                match pipeline.fetch_until(deadline) {
+                                    Ok(_) => {}
+                                    Err(err @ Error::ItemTimeout) => {
+                                        // leave the response intact, so that
+                                        // reading can be resumed
+                                        self.pipeline = Some(pipeline);
+                                        break Ok(ItemOrYield::Item(Err(err)));
+                                    }
+                                    Err(Error::ConnectionClosed) => {
+                                        break Ok(ItemOrYield::Item(Err(
+                                            self.truncate(&pipeline.buf)
+                                        )));
+                                    }
+                                    Err(err) => break Ok(ItemOrYield::Item(Err(err))),
+                                }
+                            }
+                            Err(Error::ParseItem(err, consumed, _)) => {
+                                let err = Error::ParseItem(
+                                    err,
+                                    consumed,
+                                    Some(self.item_position(&pipeline.buf.data()[..consumed])),
+                                );
+                                tracing::error!("error parsing content from response item: {err}");
+                                self.advance(&mut pipeline.buf, consumed);
+                                self.pipeline = Some(pipeline);
+                                break Ok(ItemOrYield::Item(Err(err)));
+                            }
+                            Err(err) => {
+                                tracing::error!("error parsing word from buffer: {err}");
+                                break Ok(ItemOrYield::Item(Err(err)));
+                            }
+                        }
+                    }
+                }
+            } else {
+                self.fuse();
+                Ok(ItemOrYield::Yield(pipeline))
+            }
+        } else {
+            self.fuse();
+            Err(error::Wrapper::new(None, Error::ConsumedResponse))
+        }
+    }
+
+    fn consume(&mut self) {
+        // wait for the rest of the response, however long it takes to arrive
+        self.item_timeout = None;
+        for item in self {
+            tracing::debug!(?item, "consuming unused response item");
+        }
+    }
+}
+
+impl<T> Drop for Response<'_, '_, T>
+where
+    T: FromStr + fmt::Debug,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    fn drop(&mut self) {
+        self.consume();
+    }
+}
+
+impl<T> Iterator for Response<'_, '_, T>
+where
+    T: FromStr + fmt::Debug,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    type Item = Result<ResponseItem<T>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_or_yield() {
+            Ok(ItemOrYield::Item(item)) => Some(item),
+            Ok(ItemOrYield::Yield(_) | ItemOrYield::Finished) => None,
+            Err(err) => Some(Err(err.into())),
+        }
+    }
+
+    /// Estimate the number of items remaining, based on the number of bytes
+    /// of response data not yet read.
+    ///
+    /// Each data element of a well-formed response occupies at least two
+    /// bytes, including its delimiter, so the upper bound is half of the
+    /// remaining bytes, plus allowance for errors that may be returned when
+    /// the response is malformed.
+    ///
+    /// The lower bound is always zero, since the number of items cannot be
+    /// known before they are parsed, so [`Iterator::collect()`] will not
+    /// pre-allocate based on it. The upper bound is usually a large
+    /// over-estimate: to pre-allocate, use
+    /// [`Response::estimated_len()`], or [`Response::into_vec()`], which
+    /// does so. For responses expected to contain exactly one item, use
+    /// [`Response::single()`] to obtain an [`ExactSizeIterator`].
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.finished || !self.query.expect_data() {
+            (0, Some(0))
+        } else if self.pipeline.is_none() {
+            (0, Some(1))
+        } else {
+            (0, Some(self.expect.saturating_sub(self.seen) / 2 + 2))
+        }
+    }
+}
+
+impl<T> FusedIterator for Response<'_, '_, T>
+where
+    T: FromStr + fmt::Debug,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+}
+
+enum ItemOrYield<'a, 'b, I> {
+    Item(Result<I, Error>),
+    Yield(&'b mut Pipeline<'a>),
+    Finished,
+}
+
+/// An individual data element contained within the query response.
+///
+/// The nature of each element is dependent on the corresponding [`Query`]
+/// variant.
+#[derive(Clone, Debug)]
+pub struct ResponseItem<T>(ResponseContent<T>, Query)
+where
+    T: FromStr + fmt::Debug,
+    T::Err: std::error::Error + Send + Sync + 'static;
+
+impl<T> ResponseItem<T>
+where
+    T: FromStr + fmt::Debug,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    /// Borrow the content of [`ResponseItem`].
+    pub const fn content(&self) -> &T {
+        self.0.content()
+    }
+
+    /// Take ownership of the content of [`ResponseItem`].
+    pub fn into_content(self) -> T {
+        self.0.into_content()
+    }
+
+    /// The [`Query`] which this element was provided in response to.
+    pub const fn query(&self) -> &Query {
+        &self.1
+    }
+
+    /// The name of the source from which this element was returned, if
+    /// known.
+    ///
+    /// This is extracted from the `source:` attribute for queries that
+    /// return whole RPSL objects.
+    ///
+    /// IRRd does not provide source attribution for queries returning
+    /// whitespace separated words (such as [`Query::Ipv4Routes`] or
+    /// [`Query::AsSetMembers`]), in which case [`None`] is returned.
+    pub fn source(&self) -> Option<&str> {
+        self.0.source()
+    }
+
+    /// The bytes of this element exactly as received from the server, if
+    /// they were retained.
+    ///
+    /// The bytes are only retained for items read using
+    /// [`Pipeline::responses_with_raw()`] or [`Response::keep_raw()`].
+    /// Otherwise, [`None`] is returned.
+    pub fn raw(&self) -> Option<&[u8]> {
+        self.0.raw.as_deref()
+    }
+
+    /// The primary key of the RPSL object contained in this element,
+    /// qualified by its `source:`, for queries that return whole RPSL
+    /// objects.
+    fn primary_key(&self) -> Option<&str> {
+        self.0.primary_key.as_deref()
+    }
+}
+
+impl ResponseItem<String> {
+    /// Parse the content of an item read as a [`String`] as `T`, retaining
+    /// the associated metadata.
+    pub(crate) fn parse_as<T>(self) -> Result<ResponseItem<T>, Error>
+    where
+        T: FromStr + fmt::Debug,
+        T::Err: std::error::Error + Send + Sync + 'static,
+    {
+        let Self(content, query) = self;
+        let content = ResponseContent {
+            content: content
+                .content
+                .parse()
+                .map_err(|err| Error::ParseItem(Box::new(err), content.content.len(), None))?,
+            source: content.source,
+            primary_key: content.primary_key,
+            raw: content.raw,
+        };
+        Ok(ResponseItem(content, query))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct ResponseContent<T>
+where
+    T: FromStr + fmt::Debug,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    content: T,
+    source: Option<String>,
+    primary_key: Option<String>,
+    raw: Option<Vec<u8>>,
+}
+
+impl<T> ResponseContent<T>
+where
+    T: FromStr + fmt::Debug,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    const fn content(&self) -> &T {
+        &self.content
+    }
+
+    #[allow(clippy::missing_const_for_fn)]
+    fn into_content(self) -> T {
+        self.content
+    }
+
+    fn source(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+
+    pub(crate) fn set_source(&mut self, source: Option<&str>) {
+        self.source = source.map(ToOwned::to_owned);
+    }
+
+    fn uppercase_source(&mut self) {
+        if let Some(source) = self.source.as_mut() {
+            source.make_ascii_uppercase();
+        }
+    }
+
+    pub(crate) fn set_primary_key(&mut self, primary_key: Option<String>) {
+        self.primary_key = primary_key;
+    }
+
+    pub(crate) fn set_raw(&mut self, raw: &[u8]) {
+        self.raw = Some(raw.to_vec());
+    }
+}
+
+impl<T> TryFrom<&[u8]> for ResponseContent<T>
+where
+    T: FromStr + fmt::Debug,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    fn try_from(buf: &[u8]) -> Result<Self, Self::Error> {
+        Ok(Self {
+            content: from_utf8(buf)?.parse()?,
+            source: None,
+            primary_key: None,
+            raw: None,
+        })
+    }
+}
+
+/// The number of newlines in `data`.
+fn count_lines(data: &[u8]) -> usize {
+    data.iter().map(|b| usize::from(*b == b'\n')).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::hash_map::DefaultHasher;
+    use std::io;
+    #[cfg(feature = "std-net")]
+    use std::io::Write as _;
+    #[cfg(feature = "std-net")]
+    use std::net::{TcpListener, TcpStream};
+    #[cfg(feature = "std-net")]
+    use std::sync::mpsc;
+    #[cfg(feature = "std-net")]
+    use std::thread;
+
+    use std::sync::{Arc, Mutex};
+
+    use rpsl::{expr::AsSetMember, names::AutNum};
+
+    use crate::transport::{mock::Mock, Transport};
+
+    use super::*;
+
+    #[test]
+    fn query_is_written_to_stream() {
+        let stream = Mock::new(b"A5\nAS-FOO\nC\n");
+        let output = stream.output();
+        let mut conn = Connection::from_stream(stream);
+        _ = conn.pipeline().push(Query::Version).unwrap();
+        assert!(output.lock().unwrap().starts_with(b"!v\n"));
+    }
+
+    #[test]
+    fn word_response_items_are_parsed() {
+        let stream = Mock::new(b"A29\n192.0.2.0/24 198.51.100.0/24\nC\n");
+        let mut conn = Connection::from_stream(stream);
+        let items = conn
+            .pipeline()
+            .push(Query::Ipv4Routes("AS65000".parse().unwrap()))
+            .unwrap()
+            .responses::<String>()
+            .map(|item| item.unwrap().into_content())
+            .collect::<Vec<_>>();
+        assert_eq!(items, vec!["192.0.2.0/24", "198.51.100.0/24"]);
+    }
+
+    #[test]
+    fn error_response_is_returned() {
+        let stream = Mock::new(b"D\n");
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        let err = pipeline
+            .push(Query::MntBy("FOO-MNT".parse().unwrap()))
+            .unwrap()
+            .pop::<String>()
+            .unwrap()
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::ResponseErr(_, error::Response::KeyNotFound)
+        ));
+    }
+
+    #[test]
+    fn object_source_is_extracted() {
+        let stream = Mock::new(
+            b"A67\nroute: 192.0.2.0/24\nsource: RADB\n\nroute: 192.0.2.0/24\nsource: RIPE\nC\n\
+              A8\nAS65000\nC\n",
+        );
+        let mut conn = Connection::from_stream(stream);
+        let sources = conn
+            .pipeline()
+            .push(Query::RoutesExact("192.0.2.0/24".to_string()))
+            .unwrap()
+            .push(Query::AsSetMembers("AS-FOO".parse().unwrap()))
+            .unwrap()
+            .responses::<String>()
+            .map(|item| item.unwrap().source().map(ToOwned::to_owned))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sources,
+            vec![Some("RADB".to_string()), Some("RIPE".to_string()), None]
+        );
+    }
+
+    #[test]
+    fn rate_limiting_is_reported() {
+        let stream = Mock::new(b"F Rate limit exceeded, retry after 10 seconds\n");
+        let mut conn = Connection::from_stream(stream);
+        assert!(matches!(
+            conn.pipeline()
+                .push(Query::Version)
+                .unwrap()
+                .pop::<String>()
+                .unwrap(),
+            Err(Error::RateLimited {
+                retry_after: Some(delay),
+                source: None,
+            }) if delay == Duration::from_secs(10)
+        ));
+        let mut conn = Connection::from_stream(Mock::resetting(b""));
+        assert!(matches!(
+            conn.pipeline()
+                .push(Query::Version)
+                .unwrap()
+                .pop::<String>()
+                .unwrap(),
+            Err(Error::RateLimited {
+                retry_after: None,
+                source: Some(_),
+            })
+        ));
+        let mut conn = Connection::from_stream(Mock::resetting(b"A9\nIRRd 4.4\nC\n"));
+        let mut pipeline = conn.pipeline();
+        _ = pipeline
+            .push(Query::Version)
+            .unwrap()
+            .push(Query::Version)
+            .unwrap();
+        assert!(pipeline.pop::<String>().unwrap().is_ok());
+        assert!(matches!(
+            pipeline.pop::<String>().unwrap(),
+            Err(Error::Io(err)) if err.kind() == io::ErrorKind::ConnectionReset
+        ));
+    }
+
+    #[test]
+    fn object_source_case_is_normalized() {
+        let data = b"A33\nroute: 192.0.2.0/24\nsource: radb\nC\n";
+        for (mut conn, expected) in [
+            (Connection::from_stream(Mock::new(data)), "RADB"),
+            (
+                Connection::from_stream(Mock::new(data)).with_case_sensitive_sources(),
+                "radb",
+            ),
+        ] {
+            let item = conn
+                .pipeline()
+                .push(Query::RoutesExact("192.0.2.0/24".to_string()))
+                .unwrap()
+                .responses::<String>()
+                .next()
+                .unwrap()
+                .unwrap();
+            assert_eq!(item.source(), Some(expected));
+        }
+    }
+
+    #[test]
+    fn duplicate_objects_are_skipped() {
+        let stream = Mock::new(
+            b"A61\naut-num: AS65000\nsource: RADB\n\naut-num: AS65000\nsource: RIPE\nC\n\
+              A30\naut-num: AS65000\nsource: RADB\nC\n\
+              D\nA8\nAS1 AS1\nC\n",
+        );
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        _ = pipeline
+            .push(Query::RpslObject(
+                RpslObjectClass::AutNum,
+                "AS65000".to_string(),
+            ))
+            .unwrap()
+            .push(Query::RpslObject(
+                RpslObjectClass::AutNum,
+                "as65000".to_string(),
+            ))
+            .unwrap()
+            .push(Query::RpslObject(
+                RpslObjectClass::AutNum,
+                "AS65001".to_string(),
+            ))
+            .unwrap()
+            .push(Query::AsSetMembers("AS-FOO".parse().unwrap()))
+            .unwrap();
+        let mut items = pipeline.responses::<String>().dedup_objects();
+        let sources = items
+            .by_ref()
+            .take(2)
+            .map(|item| item.unwrap().source().map(ToOwned::to_owned))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sources,
+            vec![Some("RADB".to_string()), Some("RIPE".to_string())]
+        );
+        assert!(matches!(
+            items.next(),
+            Some(Err(Error::ResponseErr(_, error::Response::KeyNotFound)))
+        ));
+        let words = items
+            .map(|item| item.unwrap().into_content())
+            .collect::<Vec<_>>();
+        assert_eq!(words, vec!["AS1", "AS1"]);
+    }
+
+    #[test]
+    fn skipped_response_is_discarded() {
+        let stream = Mock::new(b"A16\nAS1 AS2 AS3 AS4\nC\nA4\nAS5\nC\n");
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        _ = pipeline
+            .push(Query::AsSetMembers("AS-FOO".parse().unwrap()))
+            .unwrap()
+            .push(Query::AsSetMembers("AS-BAR".parse().unwrap()))
+            .unwrap();
+        let mut response = pipeline.pop::<String>().unwrap().unwrap();
+        assert_eq!(response.next().unwrap().unwrap().content(), "AS1");
+        response.discard().unwrap();
+        let items = pipeline
+            .pop::<String>()
+            .unwrap()
+            .unwrap()
+            .into_vec()
+            .unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].content(), "AS5");
+    }
+
+    #[test]
+    fn response_status_is_popped_without_parsing() {
+        let stream = Mock::new(b"A16\nAS1 AS2 AS3 AS4\nC\nD\nE\nF invalid\nA4\nAS5\nC\n");
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        for set in ["AS-FOO", "AS-BAR", "AS-BAZ", "AS-QUX", "AS-QUUX"] {
+            _ = pipeline
+                .push(Query::AsSetMembers(set.parse().unwrap()))
+                .unwrap();
+        }
+        assert_eq!(
+            pipeline.pop_status().unwrap().unwrap(),
+            ResponseOutcome::Found(16)
+        );
+        assert_eq!(
+            pipeline.pop_status().unwrap().unwrap(),
+            ResponseOutcome::NotFound
+        );
+        assert_eq!(
+            pipeline.pop_status().unwrap().unwrap(),
+            ResponseOutcome::NotUnique
+        );
+        assert!(matches!(
+            pipeline.pop_status().unwrap().unwrap(),
+            ResponseOutcome::Error(_)
+        ));
+        let items = pipeline
+            .pop::<String>()
+            .unwrap()
+            .unwrap()
+            .into_vec()
+            .unwrap();
+        assert_eq!(items[0].content(), "AS5");
+        assert!(pipeline.pop_status().is_none());
+    }
+
+    #[test]
+    fn skipped_response_is_checked_for_termination() {
+        let stream = Mock::new(b"A8\nAS1 AS2 AS3\nC\n");
+        let mut conn = Connection::from_stream(stream);
+        let result = conn
+            .pipeline()
+            .push(Query::AsSetMembers("AS-FOO".parse().unwrap()))
+            .unwrap()
+            .pop::<String>()
+            .unwrap()
+            .unwrap()
+            .discard();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn absurd_response_length_is_rejected() {
+        let stream = Mock::new(b"A99999999999\nfoo\nC\n");
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        let err = pipeline
+            .max_response_len(1024)
+            .push(Query::Version)
+            .unwrap()
+            .pop::<String>()
+            .unwrap()
+            .unwrap_err();
+        assert!(matches!(err, Error::ResponseTooLarge(99_999_999_999)));
+    }
+
+    #[test]
+    fn closed_connection_is_detected() {
+        let stream = Mock::new(b"A10\nfoo");
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        let mut response = pipeline
+            .push(Query::Version)
+            .unwrap()
+            .pop::<String>()
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            response.next(),
+            Some(Err(Error::ResponseTruncated(Query::Version, 3, 10)))
+        ));
+    }
+
+    #[test]
+    fn invalid_utf8_in_version_is_replaced() {
+        let stream = Mock::new(b"A8\nIRRd \xff4\nC\n");
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        let items = pipeline
+            .push(Query::Version)
+            .unwrap()
+            .pop_raw::<String>()
+            .unwrap()
+            .unwrap()
+            .into_vec()
+            .unwrap();
+        assert_eq!(items[0].content(), "IRRd \u{fffd}4");
+        assert_eq!(items[0].raw(), Some(&b"IRRd \xff4"[..]));
+    }
+
+    #[test]
+    fn truncated_response_reports_lengths() {
+        let data = format!("A100\n{}AS65", "AS65000 ".repeat(7));
+        let stream = Mock::new(data.as_bytes());
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        let response = pipeline
+            .push(Query::AsSetMembers("AS-FOO".parse().unwrap()))
+            .unwrap()
+            .pop::<String>()
+            .unwrap()
+            .unwrap();
+        let (items, errors) = response.into_vec_skipping_errors();
+        assert_eq!(items.len(), 7);
+        assert!(matches!(
+            errors.as_slice(),
+            [Error::ResponseTruncated(_, 60, 100)]
+        ));
+    }
+
+    #[test]
+    fn closed_connection_is_detected_before_status() {
+        let stream = Mock::new(b"");
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        let err = pipeline
+            .push(Query::Version)
+            .unwrap()
+            .pop::<String>()
+            .unwrap()
+            .unwrap_err();
+        assert!(matches!(err, Error::ConnectionClosed));
+    }
+
+    #[test]
+    fn response_into_vec_stops_at_error() {
+        let stream = Mock::new(b"A12\nAS1 foo AS2\nC\n");
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        let result = pipeline
+            .push(Query::AsSetMembers("AS-FOO".parse().unwrap()))
+            .unwrap()
+            .pop::<AutNum>()
+            .unwrap()
+            .unwrap()
+            .into_vec();
+        assert!(matches!(result, Err(Error::ParseItem(..))));
+        assert!(pipeline.pop::<AutNum>().is_none());
+    }
+
+    #[test]
+    fn digest_depends_on_items() {
+        let stream = Mock::new(b"A8\nAS1 AS2\nC\nA9\nAS1  AS2\nC\nA7\nAS1AS2\nC\n");
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        _ = pipeline
+            .try_extend(
+                ["AS-A", "AS-B", "AS-C"].map(|set| Query::AsSetMembers(set.parse().unwrap())),
+            )
+            .unwrap();
+        let mut digests = Vec::new();
+        while let Some(response) = pipeline.pop::<String>() {
+            let mut hasher = DefaultHasher::new();
+            response.unwrap().digest(&mut hasher).unwrap();
+            digests.push(hasher.finish());
+        }
+        assert_eq!(digests.len(), 3);
+        assert_eq!(digests[0], digests[1]);
+        assert_ne!(digests[0], digests[2]);
+    }
+
+    #[test]
+    fn raw_commands_are_sent_and_read() {
+        let stream = Mock::new(b"A17\nRADB:Y:1-2351234\nC\n");
+        let output = stream.output();
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        for cmd in ["!jRADB", "jRADB\n", "!jRADB\n!v\n", "!jRADB\r\n"] {
+            assert!(
+                matches!(pipeline.push_raw(cmd), Err(Error::InvalidQueryInput(_))),
+                "{cmd:?}"
+            );
+        }
+        let items = pipeline
+            .push_raw("!jRADB\n")
+            .unwrap()
+            .pop_raw::<String>()
+            .unwrap()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].content(), "RADB:Y:1-2351234");
+        assert_eq!(items[0].raw(), Some(&b"RADB:Y:1-2351234"[..]));
+        assert_eq!(items[0].query(), &Query::Raw("!jRADB".to_string()));
+        assert_eq!(output.lock().unwrap().as_slice(), b"!jRADB\n");
+    }
+
+    #[test]
+    fn responses_with_query_tags_errors() {
+        let stream = Mock::new(b"A8\nAS65000\nC\nD\nA4\nfoo\nC\n");
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        let queries = ["AS-A", "AS-B", "AS-C"].map(|set| Query::AsSetMembers(set.parse().unwrap()));
+        _ = pipeline.try_extend(queries.clone()).unwrap();
+        let results = pipeline
+            .responses_with_query::<AutNum>()
+            .collect::<Vec<_>>();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].0, queries[0]);
+        assert!(results[0].1.is_ok());
+        assert_eq!(results[1].0, queries[1]);
+        assert!(matches!(
+            results[1].1,
+            Err(Error::ResponseErr(_, error::Response::KeyNotFound))
+        ));
+        assert_eq!(results[2].0, queries[2]);
+        assert!(matches!(results[2].1, Err(Error::ParseItem(..))));
+    }
+
+    #[test]
+    fn response_into_vec_skipping_errors() {
+        let stream = Mock::new(b"A12\nAS1 foo AS2\nC\n");
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        let (items, errors) = pipeline
+            .push(Query::AsSetMembers("AS-FOO".parse().unwrap()))
+            .unwrap()
+            .pop::<AutNum>()
+            .unwrap()
+            .unwrap()
+            .into_vec_skipping_errors();
+        assert_eq!(
+            items
+                .into_iter()
+                .map(ResponseItem::into_content)
+                .collect::<Vec<_>>(),
+            vec!["AS1".parse().unwrap(), "AS2".parse().unwrap()]
+        );
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn responses_until_checkpoint() {
+        let stream = Mock::new(b"A4\nAS1\nC\nA4\nAS2\nC\nA4\nAS3\nC\n");
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        _ = pipeline
+            .push(Query::AsSetMembers("AS-FOO".parse().unwrap()))
+            .unwrap()
+            .push(Query::AsSetMembers("AS-BAR".parse().unwrap()))
+            .unwrap();
+        let checkpoint = pipeline.checkpoint();
+        _ = pipeline
+            .push(Query::AsSetMembers("AS-BAZ".parse().unwrap()))
+            .unwrap();
+        assert!(!pipeline.reached(checkpoint));
+        let first = pipeline
+            .responses_until::<String>(checkpoint)
+            .map(|item| item.unwrap().into_content())
+            .collect::<Vec<_>>();
+        assert_eq!(first, vec!["AS1", "AS2"]);
+        assert!(pipeline.reached(checkpoint));
+        assert_eq!(pipeline.responses_until::<String>(checkpoint).count(), 0);
+        let second = pipeline
+            .responses::<String>()
+            .map(|item| item.unwrap().into_content())
+            .collect::<Vec<_>>();
+        assert_eq!(second, vec!["AS3"]);
+    }
+
+    #[test]
+    fn mixed_as_set_members_are_distinguished() {
+        let stream = Mock::new(b"A19\nAS1 AS-FOO AS65000\nC\n");
+        let mut conn = Connection::from_stream(stream);
+        let members = conn
+            .pipeline()
+            .push(Query::AsSetMembers("AS-BAR".parse().unwrap()))
+            .unwrap()
+            .pop::<AsSetMember>()
+            .unwrap()
+            .unwrap()
+            .into_vec()
+            .unwrap();
+        let members: Vec<_> = members
+            .into_iter()
+            .map(ResponseItem::into_content)
+            .collect();
+        assert!(matches!(
+            members.as_slice(),
+            [
+                AsSetMember::AutNum(_),
+                AsSetMember::AsSet(_),
+                AsSetMember::AutNum(_)
+            ]
+        ));
+        assert_eq!(members[1].to_string(), "AS-FOO");
+    }
+
+    #[test]
+    fn version_is_framed_mid_pipeline() {
+        let stream = Mock::new(
+            b"A13\n192.0.2.0/24\nC\n\
+              A22\nIRRd -- version 4.4.0\nC\n\
+              A14\n2001:db8::/32\nC\n",
+        );
+        let mut conn = Connection::from_stream(stream);
+        let autnum: AutNum = "AS65000".parse().unwrap();
+        let items: Vec<_> = conn
+            .pipeline_from_iter([
+                Query::Ipv4Routes(autnum),
+                Query::Version,
+                Query::Ipv6Routes(autnum),
+            ])
+            .responses::<String>()
+            .map(|item| {
+                let item = item.unwrap();
+                (item.query().clone(), item.into_content())
+            })
+            .collect();
+        assert_eq!(
+            items,
+            [
+                (Query::Ipv4Routes(autnum), "192.0.2.0/24".to_string()),
+                (Query::Version, "IRRd -- version 4.4.0".to_string()),
+                (Query::Ipv6Routes(autnum), "2001:db8::/32".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn response_parse_as() {
+        let stream = Mock::new(b"A8\nAS65000\nC\nA4\nfoo\nC\n");
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        _ = pipeline
+            .push(Query::AsSetMembers("AS-FOO".parse().unwrap()))
+            .unwrap()
+            .push(Query::GetSources)
+            .unwrap();
+        let autnums = pipeline
+            .pop::<String>()
+            .unwrap()
+            .unwrap()
+            .parse_as::<AutNum>()
+            .into_vec()
+            .unwrap();
+        assert_eq!(autnums[0].content(), &"AS65000".parse().unwrap());
+        let sources = pipeline
+            .pop::<String>()
+            .unwrap()
+            .unwrap()
+            .into_vec()
+            .unwrap();
+        assert_eq!(sources[0].content(), "foo");
+    }
+
+    #[derive(Debug)]
+    struct Broken;
+
+    impl io::Read for Broken {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Ok(0)
+        }
+    }
+
+    impl io::Write for Broken {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn invalid_query_is_not_pushed() {
+        let stream = Mock::new(b"");
+        let output = stream.output();
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        let result = pipeline.push(Query::SetClientId("foo\n!v".to_string()));
+        assert!(matches!(result, Err(Error::InvalidQueryInput(_))));
+        assert_eq!(pipeline.queue.pushed(), 0);
+        drop(pipeline);
+        assert!(output.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn abort_remaining_closes_connection() {
+        let stream = Mock::new(b"A8\nAS65000\nC\nA4\nfoo\nC\nD\n");
+        let output = stream.output();
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        _ = pipeline
+            .push(Query::AsSetMembers("AS-FOO".parse().unwrap()))
+            .unwrap()
+            .push(Query::GetSources)
+            .unwrap()
+            .push(Query::AsSetMembers("AS-BAR".parse().unwrap()))
+            .unwrap();
+        let checkpoint = pipeline.checkpoint();
+        assert!(pipeline.pop::<String>().unwrap().is_ok());
+        _ = pipeline.abort_remaining();
+        assert!(pipeline.reached(checkpoint));
+        assert!(pipeline.pop::<String>().is_none());
+        assert!(matches!(
+            pipeline.push(Query::Version),
+            Err(Error::ConnectionClosed)
+        ));
+        drop(pipeline);
+        assert!(conn.is_closed());
+        drop(conn);
+        assert!(!output.lock().unwrap().ends_with(b"!q\n"));
+    }
+
+    #[test]
+    fn queries_are_held_at_in_flight_bytes_limit() {
+        let stream = Mock::new(b"A4\nfoo\nC\nA4\nbar\nC\n");
+        let output = stream.output();
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        _ = pipeline
+            .max_in_flight_bytes(1)
+            .push(Query::GetSources)
+            .unwrap()
+            .push(Query::GetSources)
+            .unwrap()
+            .flush_now()
+            .unwrap();
+        assert_eq!(output.lock().unwrap().as_slice(), b"!s-lc\n");
+        assert!(pipeline.pop::<String>().unwrap().is_ok());
+        assert_eq!(output.lock().unwrap().as_slice(), b"!s-lc\n");
+        assert!(pipeline.pop::<String>().unwrap().is_ok());
+        assert_eq!(output.lock().unwrap().as_slice(), b"!s-lc\n!s-lc\n");
+    }
+
+    #[test]
+    fn stalled_writes_read_pending_responses() {
+        let stream = Mock::windowed(b"A8\nAS65000\nC\n", 64);
+        let output = stream.output();
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline_with_capacity(16);
+        _ = pipeline.max_in_flight_bytes(usize::MAX);
+        let query = Query::AsSetMembers("AS-FOO".parse().unwrap());
+        for _ in 0..500 {
+            _ = pipeline.push(query.clone()).unwrap();
+        }
+        _ = pipeline.flush_now().unwrap();
+        assert_eq!(output.lock().unwrap().len(), 500 * query.cmd().len());
+        let items = pipeline
+            .responses::<AutNum>()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(items.len(), 500);
+    }
+
+    #[test]
+    fn stalled_writes_during_initial_response_read_pending_responses() {
+        let stream = Mock::windowed(b"A16\nAS65000 AS65001\nC\n", 64);
+        let output = stream.output();
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn
+            .pipeline_from_initial(
+                Query::AsSetMembers("AS-FOO".parse().unwrap()),
+                |item: Result<ResponseItem<AutNum>, Error>| {
+                    let autnum = item.unwrap().into_content();
+                    Some(vec![Query::Ipv4Routes(autnum); 250])
+                },
+            )
+            .unwrap();
+        _ = pipeline.flush_now().unwrap();
+        let sent = output.lock().unwrap().len();
+        assert_eq!(
+            sent,
+            Query::AsSetMembers("AS-FOO".parse().unwrap()).cmd().len()
+                + 250 * Query::Ipv4Routes("AS65000".parse().unwrap()).cmd().len()
+                + 250 * Query::Ipv4Routes("AS65001".parse().unwrap()).cmd().len()
+        );
+        let items = pipeline
+            .responses::<AutNum>()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(items.len(), 1000);
+    }
+
+    #[test]
+    fn writes_that_cannot_stall_leave_timeouts_unset() {
+        let transport = Timeouts::default();
+        let calls = Arc::clone(&transport.0);
+        let mut conn = Connection::from_transport(Box::new(transport));
+        let mut pipeline = conn.pipeline();
+        for _ in 0..200 {
+            _ = pipeline.push(Query::Version).unwrap();
+        }
+        _ = pipeline.flush_now().unwrap();
+        assert_eq!(*calls.lock().unwrap(), 0);
+        _ = pipeline.max_in_flight_bytes(usize::MAX);
+        let query = Query::SetClientId("x".repeat(1 << 10));
+        let fits = (Pipeline::DEFAULT_MAX_IN_FLIGHT_BYTES - 200 * Query::Version.cmd().len())
+            / query.cmd().len();
+        for _ in 0..fits {
+            _ = pipeline.push(query.clone()).unwrap();
+        }
+        assert_eq!(*calls.lock().unwrap(), 0);
+        _ = pipeline.push(query).unwrap();
+        // the write timeout is set and restored, but no write stalls
+        assert_eq!(*calls.lock().unwrap(), 2);
+    }
+
+    /// A transport that accepts every write, and counts the calls made to
+    /// set its timeouts.
+    #[derive(Debug, Default)]
+    struct Timeouts(Arc<Mutex<usize>>);
+
+    impl io::Read for Timeouts {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Ok(0)
+        }
+    }
+
+    impl io::Write for Timeouts {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Transport for Timeouts {
+        fn set_read_timeout(&mut self, _: Option<Duration>) -> io::Result<()> {
+            *self.0.lock().unwrap() += 1;
+            Ok(())
+        }
+
+        fn set_write_timeout(&mut self, _: Option<Duration>) -> io::Result<()> {
+            *self.0.lock().unwrap() += 1;
+            Ok(())
+        }
+
+        fn shutdown(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn try_extend_stops_at_first_error() {
+        let mut conn = Connection::from_stream(Broken);
+        let mut pipeline = conn.pipeline();
+        let result = pipeline.try_extend([Query::Version, Query::GetSources, Query::Version]);
+        assert!(matches!(result, Err(Error::Io(_))));
+        assert_eq!(pipeline.queue.pushed(), 1);
+    }
+
+    #[test]
+    fn extend_reports_flushed_queries() {
+        let stream = Mock::new(b"");
+        let output = stream.output();
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        let report = pipeline.max_in_flight_bytes(9).extend_with_report([
+            Query::Version,
+            Query::Version,
+            Query::SetSources(vec!["RADB\n".to_string()]),
+            Query::Version,
+            Query::Version,
+        ]);
+        assert_eq!(
+            report,
+            ExtendReport {
+                queued: 4,
+                flushed: 3
+            }
+        );
+        assert_eq!(output.lock().unwrap().as_slice(), b"!v\n!v\n!v\n");
+    }
+
+    #[test]
+    fn response_for_each_raw() {
+        let stream = Mock::new(b"A12\nAS65000 AS1\nC\nA4\nfoo\nC\n");
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        _ = pipeline
+            .push(Query::AsSetMembers("AS-FOO".parse().unwrap()))
+            .unwrap()
+            .push(Query::GetSources)
+            .unwrap();
+        let mut items = Vec::new();
+        pipeline
+            .pop::<String>()
+            .unwrap()
+            .unwrap()
+            .for_each_raw(|raw| items.push(raw.to_vec()))
+            .unwrap();
+        assert_eq!(items, [b"AS65000".to_vec(), b"AS1".to_vec()]);
+        let sources = pipeline
+            .pop::<String>()
+            .unwrap()
+            .unwrap()
+            .into_vec()
+            .unwrap();
+        assert_eq!(sources[0].content(), "foo");
+    }
+
+    #[test]
+    fn response_for_each_raw_detects_underrun() {
+        let stream = Mock::new(b"A13\nAS65000 AS1\nC\n");
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        _ = pipeline
+            .push(Query::AsSetMembers("AS-FOO".parse().unwrap()))
+            .unwrap();
+        let result = pipeline
+            .pop::<String>()
+            .unwrap()
+            .unwrap()
+            .for_each_raw(|_| ());
+        assert!(matches!(
+            result,
+            Err(Error::ResponseDataUnderrun(_, 11, 13))
+        ));
+    }
+
+    #[test]
+    fn framing_errors_identify_query() {
+        let query = Query::AsSetMembers("AS-FOO".parse().unwrap());
+        let stream = Mock::new(b"A8\nAS1 AS2 AS3\nC\n");
+        let mut conn = Connection::from_stream(stream);
+        let result = conn
+            .pipeline()
+            .push(query.clone())
+            .unwrap()
+            .pop::<String>()
+            .unwrap()
+            .unwrap()
+            .into_vec();
+        match result {
+            Err(err @ Error::ResponseDataOverrun(..)) => {
+                assert!(err.to_string().contains("AS-FOO"), "{err}");
+                assert!(matches!(err, Error::ResponseDataOverrun(q, 11, 8) if q == query));
+            }
+            result => panic!("expected overrun, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn strict_terminator_rejects_uncounted_newline() {
+        let stream = Mock::new(b"A11\nAS65000 AS1\nC\n");
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        _ = pipeline
+            .push(Query::AsSetMembers("AS-FOO".parse().unwrap()))
+            .unwrap();
+        let result = pipeline
+            .pop::<String>()
+            .unwrap()
+            .unwrap()
+            .for_each_raw(|_| ());
+        assert!(matches!(
+            result,
+            Err(Error::ResponseDataUnderrun(_, 11, 11))
+        ));
+    }
+
+    #[test]
+    fn duplicate_queries_are_dropped_within_batch() {
+        let stream = Mock::new(b"A15\nas-set: AS-FOO\nC\nA15\nas-set: AS-FOO\nC\n");
+        let output = stream.output();
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        let query = |key: &str| Query::RpslObject(RpslObjectClass::AsSet, key.to_owned());
+        _ = pipeline
+            .push_deduplicated(query("AS-FOO"))
+            .unwrap()
+            .push_deduplicated(query("as-foo"))
+            .unwrap();
+        assert_eq!(pipeline.responses::<String>().count(), 1);
+        _ = pipeline.push_deduplicated(query("as-foo")).unwrap();
+        assert_eq!(pipeline.responses::<String>().count(), 1);
+        assert_eq!(
+            output.lock().unwrap().as_slice(),
+            b"!mas-set,AS-FOO\n!mas-set,as-foo\n"
+        );
+    }
+
+    #[test]
+    fn state_changing_queries_are_not_deduplicated() {
+        let stream = Mock::new(b"C\nC\nA13\n192.0.2.0/24\nC\nC\nA13\n192.0.2.0/24\nC\n");
+        let output = stream.output();
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        let sources = |source: &str| Query::SetSources(vec![source.to_owned()]);
+        let routes = || Query::Ipv4Routes("AS65000".parse().unwrap());
+        _ = pipeline
+            .push_deduplicated(sources("RADB"))
+            .unwrap()
+            .push_deduplicated(sources("RIPE"))
+            .unwrap()
+            .push_deduplicated(routes())
+            .unwrap()
+            .push_deduplicated(sources("RADB"))
+            .unwrap()
+            .push_deduplicated(routes())
+            .unwrap();
+        assert_eq!(pipeline.responses::<String>().count(), 2);
+        assert_eq!(
+            output.lock().unwrap().as_slice(),
+            b"!sRADB\n!sRIPE\n!gAS65000\n!sRADB\n!gAS65000\n"
+        );
+    }
+
+    #[test]
+    fn raw_bytes_are_retained_on_request() {
+        let stream = Mock::new(b"A22\n192.0.2.0/024 AS65000\nC\nA8\nAS65000\nC\n");
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        _ = pipeline
+            .push(Query::AsSetMembers("AS-FOO".parse().unwrap()))
+            .unwrap()
+            .push(Query::AsSetMembers("AS-BAR".parse().unwrap()))
+            .unwrap();
+        let raw = pipeline
+            .pop::<String>()
+            .unwrap()
+            .unwrap()
+            .keep_raw()
+            .map(|item| item.unwrap().raw().unwrap().to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(raw, vec![b"192.0.2.0/024".to_vec(), b"AS65000".to_vec()]);
+        let item = pipeline.responses::<String>().next().unwrap().unwrap();
+        assert_eq!(item.raw(), None);
+    }
+
+    #[test]
+    fn responses_with_raw_retains_raw_bytes() {
+        let stream = Mock::new(b"A8\nAS65000\nC\nA8\nAS65001\nC\n");
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        _ = pipeline
+            .push(Query::AsSetMembers("AS-FOO".parse().unwrap()))
+            .unwrap()
+            .push(Query::AsSetMembers("AS-BAR".parse().unwrap()))
+            .unwrap();
+        let raw = pipeline
+            .responses_with_raw::<AutNum>()
+            .map(|item| item.unwrap().raw().unwrap().to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(raw, vec![b"AS65000".to_vec(), b"AS65001".to_vec()]);
+    }
+
+    #[cfg(feature = "ip")]
+    #[test]
+    fn prefixes_are_aggregated_into_prefix_set() {
+        use ip::traits::PrefixSet as _;
+
+        let data = b"A17\n192.0.2.0/24 foo\nC\nA14\n2001:db8::/32\nC\nA11\n10.0.0.0/8\nC\n";
+        let stream = Mock::new(data);
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        let autnum = "AS65000".parse().unwrap();
+        _ = pipeline
+            .push(Query::Ipv4Routes(autnum))
+            .unwrap()
+            .push(Query::Ipv6Routes(autnum))
+            .unwrap()
+            .push(Query::Ipv4Routes(autnum))
+            .unwrap();
+        let v4 = pipeline
+            .pop::<String>()
+            .unwrap()
+            .unwrap()
+            .into_prefix_set_v4();
+        assert_eq!(
+            v4.prefixes().map(|p| p.to_string()).collect::<Vec<_>>(),
+            vec!["192.0.2.0/24"]
+        );
+        let any = pipeline.responses::<Prefix<Any>>().into_prefix_set();
+        assert_eq!(any.prefixes().count(), 2);
+    }
+
+    #[test]
+    fn responses_are_truncated_at_max_items() {
+        let data = b"A35\n10.0.0.0/8 10.1.0.0/16 10.2.0.0/16\nC\nA5\nIRRd\nC\n";
+        for (capacity, limit, expected, truncated) in [
+            (Connection::DEFAULT_CAPACITY, 1, 1, true),
+            (16, 2, 2, true),
+            (16, 3, 3, false),
+            (16, 0, 0, true),
+        ] {
+            let mut conn = Connection::from_stream(Mock::new(data));
+            let mut pipeline = conn.pipeline_with_capacity(capacity);
+            _ = pipeline
+                .push(Query::Ipv4Routes("AS65000".parse().unwrap()))
+                .unwrap()
+                .push(Query::Version)
+                .unwrap();
+            let mut response = pipeline.pop::<String>().unwrap().unwrap().max_items(limit);
+            let items = response.by_ref().map(Result::unwrap).count();
+            assert_eq!(items, expected);
+            assert_eq!(response.is_truncated(), truncated);
+            assert_eq!(response.consumed_len(), response.expected_len());
+            drop(response);
+            let version = pipeline.pop::<String>().unwrap().unwrap().next();
+            assert_eq!(version.unwrap().unwrap().content(), "IRRd");
+        }
+    }
+
+    #[test]
+    fn items_beyond_pipeline_limit_are_rejected() {
+        let data = b"A35\n10.0.0.0/8 10.1.0.0/16 10.2.0.0/16\nC\nA11\n10.0.0.0/8\nC\n";
+        for (capacity, limit, expected, exceeded) in [
+            (Connection::DEFAULT_CAPACITY, 1, 1, true),
+            (16, 2, 2, true),
+            (16, 3, 3, false),
+        ] {
+            let mut conn = Connection::from_stream(Mock::new(data));
+            let mut pipeline = conn.pipeline_with_capacity(capacity);
+            _ = pipeline
+                .max_items_per_response(limit)
+                .push(Query::Ipv4Routes("AS65000".parse().unwrap()))
+                .unwrap()
+                .push(Query::Ipv4Routes("AS65001".parse().unwrap()))
+                .unwrap();
+            let (items, errors) = pipeline
+                .pop::<String>()
+                .unwrap()
+                .unwrap()
+                .into_vec_skipping_errors();
+            assert_eq!(items.len(), expected);
+            assert_eq!(
+                matches!(errors.as_slice(), [Error::TooManyItems(_, max)] if *max == limit),
+                exceeded
+            );
+            let next = pipeline.pop::<String>().unwrap().unwrap().into_vec();
+            assert_eq!(next.unwrap()[0].content(), "10.0.0.0/8");
+        }
+    }
+
+    #[test]
+    fn str_items_are_borrowed_from_buffer() {
+        let stream = Mock::new(b"A16\nAS65000 AS65001\nC\nA8\nAS65002\nC\n");
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        _ = pipeline
+            .push(Query::AsSetMembers("AS-FOO".parse().unwrap()))
+            .unwrap()
+            .push(Query::AsSetMembers("AS-BAR".parse().unwrap()))
+            .unwrap();
+        let mut response = pipeline.pop::<String>().unwrap().unwrap();
+        let mut items = Vec::new();
+        while let Some(item) = response.next_str() {
+            let item = item.unwrap().to_owned();
+            items.push((item, response.consumed_len()));
+        }
+        assert_eq!(
+            items,
+            vec![("AS65000".to_owned(), 8), ("AS65001".to_owned(), 15)]
+        );
+        assert_eq!(response.consumed_len(), 16);
+        drop(response);
+        let mut response = pipeline.pop::<String>().unwrap().unwrap();
+        assert_eq!(response.next_str().unwrap().unwrap(), "AS65002");
+        response.discard().unwrap();
+        assert!(pipeline.pop::<String>().is_none());
+    }
+
+    #[test]
+    fn invalid_utf8_str_item_is_skipped() {
+        let stream = Mock::new(b"A11\n\xff\xfe AS65000\nC\n");
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        _ = pipeline
+            .push(Query::AsSetMembers("AS-FOO".parse().unwrap()))
+            .unwrap();
+        let mut response = pipeline.pop::<String>().unwrap().unwrap();
+        assert!(matches!(
+            response.next_str(),
+            Some(Err(Error::ParseItem(_, 3, _)))
+        ));
+        assert_eq!(response.next_str().unwrap().unwrap(), "AS65000");
+        assert!(response.next_str().is_none());
+    }
+
+    #[test]
+    fn single_response_has_exact_size() {
+        let stream = Mock::new(b"A22\nIRRd -- version 4.4.0\nC\nA8\nAS1 AS2\nC\nA4\nAS3\nC\n");
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        _ = pipeline
+            .push(Query::Version)
+            .unwrap()
+            .push(Query::AsSetMembers("AS-FOO".parse().unwrap()))
+            .unwrap()
+            .push(Query::AsSetMembers("AS-BAR".parse().unwrap()))
+            .unwrap();
+        let mut version = pipeline.pop::<String>().unwrap().unwrap().single();
+        assert_eq!(version.len(), 1);
+        assert_eq!(
+            version.next().unwrap().unwrap().content(),
+            "IRRd -- version 4.4.0"
+        );
+        assert_eq!(version.len(), 0);
+        assert!(version.next().is_none());
+        drop(version);
+        let mut members = pipeline.pop::<AutNum>().unwrap().unwrap().single();
+        assert!(matches!(
+            members.next(),
+            Some(Err(Error::ExpectedSingle(Query::AsSetMembers(_), 2)))
+        ));
+        drop(members);
+        let member = pipeline.pop::<AutNum>().unwrap().unwrap().single().next();
+        assert_eq!(
+            member.unwrap().unwrap().into_content(),
+            "AS3".parse().unwrap()
+        );
+    }
+
+    #[test]
+    fn parse_errors_report_item_position() {
+        let stream = Mock::new(
+            b"A60\naut-num: AS1\nsource: RADB\n\nbogus\n\n\
+              aut-num: AS3\nsource: RADB\nC\n",
+        );
+        let mut conn = Connection::from_stream(stream);
+        let results = conn
+            .pipeline()
+            .push(Query::MntBy("FOO-MNT".parse().unwrap()))
+            .unwrap()
+            .responses::<crate::RpslObject>()
+            .collect::<Vec<_>>();
+        assert_eq!(results.len(), 3);
+        let err = results[1].as_ref().unwrap_err();
+        let pos = err.item_position().unwrap();
+        assert_eq!((pos.index(), pos.line(), pos.offset()), (1, 4, 27));
+        assert!(err.to_string().contains("item 2 (line 4, byte offset 27)"));
+        assert!(results[2].is_ok());
+    }
+
+    #[test]
+    fn objects_outside_length_bound_are_skipped() {
+        let stream = Mock::new(
+            b"A94\nroute: 192.0.2.0/25\nsource: A\n\n\
+              route: 192.0.2.0/24\nsource: A\n\n\
+              route: 192.0.2.128/25\nsource: A\nC\n",
+        );
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        _ = pipeline
+            .push(Query::routes_more_bounded("192.0.0.0/16", 24).unwrap())
+            .unwrap();
+        let items = pipeline
+            .responses::<String>()
+            .map(|item| item.unwrap().into_content())
+            .collect::<Vec<_>>();
+        assert_eq!(items, vec!["route: 192.0.2.0/24\nsource: A"]);
+    }
+
+    #[test]
+    fn empty_responses_are_handled_per_query_kind() {
+        let stream = Mock::new(b"C\nC\nC\nC\n");
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        _ = pipeline
+            .push(Query::Ipv4Routes("AS65000".parse().unwrap()))
+            .unwrap()
+            .push(Query::Version)
+            .unwrap()
+            .push(Query::GetSources)
+            .unwrap()
+            .push(Query::Version)
+            .unwrap();
+        assert_eq!(pipeline.pop::<String>().unwrap().unwrap().count(), 0);
+        assert!(matches!(
+            pipeline.pop::<String>(),
+            Some(Err(Error::EmptyResponse(Query::Version)))
+        ));
+        _ = pipeline
+            .allow_empty_response(QueryKind::GetSources, false)
+            .allow_empty_response(QueryKind::Version, true);
+        assert!(matches!(
+            pipeline.pop::<String>(),
+            Some(Err(Error::EmptyResponse(Query::GetSources)))
+        ));
+        assert_eq!(pipeline.pop::<String>().unwrap().unwrap().count(), 0);
+        assert!(pipeline.pop::<String>().is_none());
+    }
+
+    #[test]
+    fn relaxed_terminator_accepts_uncounted_newline() {
+        let stream = Mock::new(b"A11\nAS65000 AS1\nC\nA4\nfoo\nC\n");
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        _ = pipeline
+            .relaxed_terminator(true)
+            .push(Query::AsSetMembers("AS-FOO".parse().unwrap()))
+            .unwrap()
+            .push(Query::GetSources)
+            .unwrap();
+        let items = pipeline
+            .pop::<String>()
+            .unwrap()
+            .unwrap()
+            .map(|item| item.unwrap().into_content())
+            .collect::<Vec<_>>();
+        assert_eq!(items, vec!["AS65000", "AS1"]);
+        let sources = pipeline.pop::<String>().unwrap().unwrap();
+        assert_eq!(sources.count(), 1);
+    }
+
+    #[test]
+    fn relaxed_terminator_accepts_missing_newline() {
+        let stream = Mock::new(b"A12\nAS65000 AS1 C\nA4\nfoo\nC\n");
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        _ = pipeline
+            .relaxed_terminator(true)
+            .push(Query::AsSetMembers("AS-FOO".parse().unwrap()))
+            .unwrap()
+            .push(Query::GetSources)
+            .unwrap();
+        let items = pipeline
+            .pop::<String>()
+            .unwrap()
+            .unwrap()
+            .map(|item| item.unwrap().into_content())
+            .collect::<Vec<_>>();
+        assert_eq!(items, vec!["AS65000", "AS1"]);
+        let sources = pipeline.pop::<String>().unwrap().unwrap();
+        assert_eq!(sources.count(), 1);
+    }
+
+    #[test]
+    fn relaxed_terminator_still_detects_underrun() {
+        let stream = Mock::new(b"A14\nAS65000 AS1\nC\n");
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        _ = pipeline
+            .relaxed_terminator(true)
+            .push(Query::AsSetMembers("AS-FOO".parse().unwrap()))
+            .unwrap();
+        let result = pipeline
+            .pop::<String>()
+            .unwrap()
+            .unwrap()
+            .for_each_raw(|_| ());
+        assert!(matches!(
+            result,
+            Err(Error::ResponseDataUnderrun(_, 11, 14))
+        ));
+    }
+
+    #[test]
+    fn response_size_hint_bounds_items() {
+        let stream = Mock::new(b"A12\nAS65000 AS1\nC\n");
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        _ = pipeline
+            .push(Query::AsSetMembers("AS-FOO".parse().unwrap()))
+            .unwrap();
+        let mut response = pipeline.pop::<String>().unwrap().unwrap();
+        assert_eq!(response.size_hint(), (0, Some(8)));
+        assert!(response.next().unwrap().is_ok());
+        assert_eq!(response.size_hint(), (0, Some(4)));
+        assert!(response.next().unwrap().is_ok());
+        assert!(response.next().is_none());
+        assert_eq!(response.size_hint(), (0, Some(0)));
+    }
+
+    #[test]
+    fn response_len_is_estimated_by_query() {
+        let stream = Mock::new(b"A16\nAS65000 AS65001\nC\nA29\n192.0.2.0/24 198.51.100.0/24\nC\n");
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        _ = pipeline
+            .push(Query::AsSetMembers("AS-FOO".parse().unwrap()))
+            .unwrap()
+            .push(Query::Ipv4Routes("AS65000".parse().unwrap()))
+            .unwrap();
+        let mut response = pipeline.pop::<String>().unwrap().unwrap();
+        assert_eq!(response.estimated_len(), 2);
+        assert!(response.next().unwrap().is_ok());
+        assert_eq!(response.estimated_len(), 1);
+        assert_eq!(response.into_vec().unwrap().len(), 1);
+        let response = pipeline.pop::<String>().unwrap().unwrap();
+        assert_eq!(response.estimated_len(), 2);
+        let items = response.into_vec().unwrap();
+        assert_eq!(items.len(), 2);
+        assert!(items.capacity() >= 2);
+    }
+
+    #[test]
+    fn response_progress_is_reported() {
+        let stream = Mock::new(b"A12\nAS65000 AS1\nC\nC\n");
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        _ = pipeline
+            .push(Query::AsSetMembers("AS-FOO".parse().unwrap()))
+            .unwrap()
+            .push(Query::UnsetSources)
+            .unwrap();
+        let mut response = pipeline.pop::<String>().unwrap().unwrap();
+        assert_eq!((response.consumed_len(), response.expected_len()), (0, 12));
+        assert!(response.next().unwrap().is_ok());
+        assert_eq!(response.consumed_len(), 8);
+        assert!(response.next().unwrap().is_ok());
+        assert_eq!(response.consumed_len(), 11);
+        assert!(response.next().is_none());
+        assert_eq!(response.consumed_len(), 12);
+        drop(response);
+        let response = pipeline.pop::<String>().unwrap().unwrap();
+        assert_eq!((response.consumed_len(), response.expected_len()), (0, 0));
+    }
+
+    #[test]
+    fn responses_are_chunked_by_query() {
+        let stream = Mock::new(b"A12\nAS65000 AS1\nC\nD\nA4\nfoo\nC\n");
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        _ = pipeline
+            .push(Query::AsSetMembers("AS-FOO".parse().unwrap()))
+            .unwrap()
+            .push(Query::AsSetMembers("AS-BAR".parse().unwrap()))
+            .unwrap()
+            .push(Query::GetSources)
+            .unwrap();
+        let mut chunks = pipeline.responses_chunked::<String>();
+        let (query, mut items) = chunks.next().unwrap().unwrap();
+        assert_eq!(query, Query::AsSetMembers("AS-FOO".parse().unwrap()));
+        assert_eq!(items.next().unwrap().unwrap().into_content(), "AS65000");
+        // dropping the partially consumed response reads it to the end
+        drop(items);
+        assert!(matches!(
+            chunks.next(),
+            Some(Err(Error::ResponseErr(_, error::Response::KeyNotFound)))
+        ));
+        let (query, mut items) = chunks.next().unwrap().unwrap();
+        assert_eq!(query, Query::GetSources);
+        assert_eq!(items.next().unwrap().unwrap().content(), "foo");
+        assert!(items.next().is_none());
+        drop(items);
+        assert!(chunks.next().is_none());
+    }
+
+    #[test]
+    fn read_buffer_grows_to_fit_large_items() {
+        let stream = Mock::new(b"A16\nABCDEFGHIJKLMNO\nC\n");
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline_with_capacity(4);
+        let items = pipeline
+            .push(Query::GetSources)
+            .unwrap()
+            .pop::<String>()
+            .unwrap()
+            .unwrap()
+            .map(|item| item.unwrap().into_content())
+            .collect::<Vec<_>>();
+        assert_eq!(items, vec!["ABCDEFGHIJKLMNO"]);
+    }
+
+    #[test]
+    fn item_larger_than_max_response_len_is_rejected() {
+        let stream = Mock::new(b"A16\nABCDEFGHIJKLMNOP\nC\n");
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline_with_capacity(4);
+        let result = pipeline
+            .max_response_len(16)
+            .push(Query::GetSources)
+            .unwrap()
+            .pop::<String>()
+            .unwrap()
+            .unwrap()
+            .next()
+            .unwrap();
+        assert!(matches!(result, Err(Error::ItemTooLarge(16))));
+    }
+
+    #[test]
+    fn collect_errors_sets_aside_errors() {
+        let stream = Mock::new(b"D\nA12\nAS65000 AS1\nC\nA7\nAS-FOO\nC\n");
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        let mut items = pipeline
+            .push(Query::AsSetMembers("AS-FOO".parse().unwrap()))
+            .unwrap()
+            .push(Query::AsSetMembers("AS-BAR".parse().unwrap()))
+            .unwrap()
+            .push(Query::AsSetMembers("AS-BAZ".parse().unwrap()))
+            .unwrap()
+            .responses::<AutNum>()
+            .collect_errors();
+        assert_eq!(items.by_ref().count(), 2);
+        let errors = items.into_errors();
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(
+            errors[0],
+            Error::ResponseErr(_, error::Response::KeyNotFound)
+        ));
+        assert!(matches!(errors[1], Error::ParseItem(..)));
+    }
+
+    #[test]
+    fn responses_are_read_in_order() {
+        let stream = Mock::new(b"C\nA8\nAS65000\nC\nA8\nfoo bar\nC\n");
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        _ = pipeline
+            .push(Query::UnsetSources)
+            .unwrap()
+            .push(Query::AsSetMembers("AS-FOO".parse().unwrap()))
+            .unwrap()
+            .push(Query::GetSources)
+            .unwrap();
+        assert_eq!(pipeline.pop::<String>().unwrap().unwrap().count(), 0);
+        assert_eq!(pipeline.pop::<String>().unwrap().unwrap().count(), 1);
+        assert_eq!(pipeline.pop::<String>().unwrap().unwrap().count(), 2);
+        assert!(pipeline.pop::<String>().is_none());
+    }
+
+    /// Start a server that writes `first` to the connected client, and then
+    /// `rest` once signalled to do so.
+    #[cfg(feature = "std-net")]
+    fn stalling_server(first: &'static [u8], rest: &'static [u8]) -> (TcpStream, mpsc::Sender<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut server, _) = listener.accept().unwrap();
+        let (tx, rx) = mpsc::channel();
+        _ = thread::spawn(move || {
+            server.write_all(first).unwrap();
+            // don't wait forever, so that a failing test cannot hang
+            _ = rx.recv_timeout(Duration::from_secs(5));
+            server.write_all(rest).unwrap();
+        });
+        (client, tx)
+    }
+
+    #[cfg(feature = "std-net")]
+    #[test]
+    fn item_timeout_does_not_abandon_response() {
+        let (stream, resume) = stalling_server(b"A12\nAS1 A", b"S2 AS3\nC\n");
+        let mut conn = Connection::from_transport(Box::new(stream));
+        let mut pipeline = conn.pipeline();
+        let mut response = pipeline
+            .push(Query::AsSetMembers("AS-FOO".parse().unwrap()))
+            .unwrap()
+            .pop::<String>()
+            .unwrap()
+            .unwrap()
+            .item_timeout(Duration::from_millis(50));
+        assert_eq!(response.next().unwrap().unwrap().content(), "AS1");
+        assert!(matches!(response.next(), Some(Err(Error::ItemTimeout))));
+        resume.send(()).unwrap();
+        let rest = response
+            .map(|item| item.unwrap().into_content())
+            .collect::<Vec<_>>();
+        assert_eq!(rest, vec!["AS2", "AS3"]);
+    }
+
+    #[cfg(feature = "std-net")]
+    #[test]
+    fn drain_timeout_closes_connection() {
+        let (stream, _resume) = stalling_server(b"A12\nAS1 A", b"S2 AS3\nC\n");
+        let mut conn = Connection::from_transport(Box::new(stream));
+        let mut pipeline = conn.pipeline();
+        _ = pipeline
+            .drain_timeout(Duration::from_millis(50))
+            .push(Query::AsSetMembers("AS-FOO".parse().unwrap()))
+            .unwrap()
+            .push(Query::Version)
+            .unwrap();
+        let started = Instant::now();
+        drop(pipeline);
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(conn.is_closed());
+    }
+
+    #[test]
+    fn drain_within_timeout_leaves_connection_open() {
+        let stream = Mock::new(b"A12\nAS1 AS2 AS3\nC\nA5\nIRRd\nC\n");
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        _ = pipeline
+            .drain_timeout(Duration::from_secs(5))
+            .push(Query::AsSetMembers("AS-FOO".parse().unwrap()))
+            .unwrap()
+            .push(Query::Version)
+            .unwrap()
+            .clear();
+        drop(pipeline);
+        assert!(!conn.is_closed());
+    }
+
+    #[cfg(feature = "std-net")]
+    #[test]
+    fn dropping_response_after_item_timeout_resyncs() {
+        let (stream, resume) = stalling_server(b"A12\nAS1 A", b"S2 AS3\nC\nA4\nAS4\nC\n");
+        let mut conn = Connection::from_transport(Box::new(stream));
+        let mut pipeline = conn.pipeline();
+        _ = pipeline
+            .push(Query::AsSetMembers("AS-FOO".parse().unwrap()))
+            .unwrap()
+            .push(Query::AsSetMembers("AS-BAR".parse().unwrap()))
+            .unwrap();
+        let mut response = pipeline
+            .pop::<String>()
+            .unwrap()
+            .unwrap()
+            .item_timeout(Duration::from_millis(50));
+        assert!(response.next().unwrap().is_ok());
+        assert!(matches!(response.next(), Some(Err(Error::ItemTimeout))));
+        resume.send(()).unwrap();
+        drop(response);
+        let items = pipeline
+            .pop::<String>()
+            .unwrap()
+            .unwrap()
+            .into_vec()
+            .unwrap();
+        assert_eq!(items[0].content(), "AS4");
     }
 }
@@ -0,0 +1,171 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::iter::FusedIterator;
+use std::panic::{self, AssertUnwindSafe};
+use std::str::FromStr;
+use std::sync::mpsc;
+use std::thread;
+
+use crate::error::Error;
+
+use super::{ResponseItem, Responses};
+
+type Parsed<T> = (usize, thread::Result<Result<ResponseItem<T>, Error>>);
+
+/// Iterator returned by
+/// [`responses_parallel()`][super::Pipeline::responses_parallel] method.
+///
+/// See [`Pipeline::responses_parallel`][super::Pipeline::responses_parallel]
+/// for details.
+pub struct ParallelResponses<'a, 'b, T>
+where
+    T: FromStr + fmt::Debug + Send + 'static,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    responses: Responses<'a, 'b, String>,
+    sender: mpsc::Sender<Parsed<T>>,
+    receiver: mpsc::Receiver<Parsed<T>>,
+    window: usize,
+    dispatched: usize,
+    yielded: usize,
+    ready: BTreeMap<usize, Result<ResponseItem<T>, Error>>,
+    exhausted: bool,
+}
+
+impl<'a, 'b, T> ParallelResponses<'a, 'b, T>
+where
+    T: FromStr + fmt::Debug + Send + 'static,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    /// Number of items that may be parsed ahead of the consumer, per thread
+    /// in the `rayon` thread pool.
+    const ITEMS_PER_THREAD: usize = 4;
+
+    pub(super) fn new(responses: Responses<'a, 'b, String>) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            responses,
+            sender,
+            receiver,
+            window: rayon::current_num_threads() * Self::ITEMS_PER_THREAD,
+            dispatched: 0,
+            yielded: 0,
+            ready: BTreeMap::new(),
+            exhausted: false,
+        }
+    }
+
+    /// Read items from the connection until the window of items in flight is
+    /// full, handing each to the thread pool to be parsed.
+    fn dispatch(&mut self) {
+        while !self.exhausted && self.dispatched - self.yielded < self.window {
+            let seq = self.dispatched;
+            match self.responses.next() {
+                Some(Ok(item)) => {
+                    let sender = self.sender.clone();
+                    rayon::spawn(move || {
+                        let result = panic::catch_unwind(AssertUnwindSafe(|| item.parse_as()));
+                        // the receiver is gone if the iterator has been dropped
+                        _ = sender.send((seq, result));
+                    });
+                }
+                Some(Err(err)) => _ = self.ready.insert(seq, Err(err)),
+                None => {
+                    self.exhausted = true;
+                    break;
+                }
+            }
+            self.dispatched += 1;
+        }
+    }
+}
+
+impl<T> Iterator for ParallelResponses<'_, '_, T>
+where
+    T: FromStr + fmt::Debug + Send + 'static,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    type Item = Result<ResponseItem<T>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.dispatch();
+        loop {
+            if let Some(result) = self.ready.remove(&self.yielded) {
+                self.yielded += 1;
+                return Some(result);
+            }
+            if self.yielded == self.dispatched {
+                return None;
+            }
+            match self.receiver.recv() {
+                Ok((seq, Ok(result))) => _ = self.ready.insert(seq, result),
+                Ok((_, Err(payload))) => panic::resume_unwind(payload),
+                Err(_) => unreachable!("a sender is held by the iterator"),
+            }
+        }
+    }
+}
+
+impl<T> FusedIterator for ParallelResponses<'_, '_, T>
+where
+    T: FromStr + fmt::Debug + Send + 'static,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+}
+
+impl<T> fmt::Debug for ParallelResponses<'_, '_, T>
+where
+    T: FromStr + fmt::Debug + Send + 'static,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParallelResponses")
+            .field("responses", &self.responses)
+            .field("window", &self.window)
+            .field("dispatched", &self.dispatched)
+            .field("yielded", &self.yielded)
+            .field("exhausted", &self.exhausted)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rpsl::names::AutNum;
+
+    use crate::{client::Connection, pipeline::ResponseItem, query::Query, transport::mock::Mock};
+
+    #[test]
+    fn items_are_yielded_in_order() {
+        let words = (0..500)
+            .map(|i| {
+                if i == 123 {
+                    "foo".to_string()
+                } else {
+                    format!("AS{i}")
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        let data = format!("A{}\n{words}\nC\nA8\nAS65000\nC\n", words.len() + 1);
+        let mut conn = Connection::from_stream(Mock::new(data.as_bytes()));
+        let mut pipeline = conn.pipeline();
+        _ = pipeline
+            .push(Query::AsSetMembers("AS-FOO".parse().unwrap()))
+            .unwrap()
+            .push(Query::AsSetMembers("AS-BAR".parse().unwrap()))
+            .unwrap();
+        let items = pipeline
+            .responses_parallel::<AutNum>()
+            .map(|result| result.map(ResponseItem::into_content).ok())
+            .collect::<Vec<_>>();
+        assert_eq!(items.len(), 501);
+        for (i, item) in items.iter().enumerate() {
+            match i {
+                123 => assert!(item.is_none()),
+                500 => assert_eq!(*item, Some("AS65000".parse().unwrap())),
+                _ => assert_eq!(*item, Some(format!("AS{i}").parse().unwrap())),
+            }
+        }
+    }
+}
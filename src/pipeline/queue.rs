@@ -1,40 +1,136 @@
 use std::{cmp::min, collections::VecDeque};
 
-use crate::{error::Error, query::Query};
+use crate::{error::Error, pipeline::Pipeline, query::Query};
 
 #[derive(Debug)]
 pub(crate) struct Queue {
     q: VecDeque<Query>,
+    pushed: usize,
+    popped: usize,
+    sent: usize,
     in_flight: usize,
     max_in_flight: usize,
     min_batch: usize,
+    in_flight_lens: VecDeque<usize>,
+    in_flight_bytes: usize,
+    max_in_flight_bytes: usize,
 }
 
 impl Default for Queue {
     fn default() -> Self {
         Self {
             q: VecDeque::default(),
+            pushed: 0,
+            popped: 0,
+            sent: 0,
             in_flight: 0,
             max_in_flight: 1000,
             min_batch: 100,
+            in_flight_lens: VecDeque::default(),
+            in_flight_bytes: 0,
+            max_in_flight_bytes: Pipeline::DEFAULT_MAX_IN_FLIGHT_BYTES,
         }
     }
 }
 
 impl Queue {
+    /// Create a [`Queue`] that keeps at most one query in-flight, for use
+    /// with servers that do not support multiple command mode.
+    pub(crate) fn single_query() -> Self {
+        Self {
+            max_in_flight: 1,
+            min_batch: 1,
+            ..Self::default()
+        }
+    }
+
     fn len(&self) -> usize {
         self.q.len()
     }
 
+    /// Set the limit on the total length of queries sent but not yet
+    /// answered.
+    pub(crate) const fn set_max_in_flight_bytes(&mut self, bytes: usize) {
+        self.max_in_flight_bytes = bytes;
+    }
+
+    /// The limit on the total length of queries sent but not yet answered.
+    pub(crate) const fn max_in_flight_bytes(&self) -> usize {
+        self.max_in_flight_bytes
+    }
+
+    /// Total length of queries sent but not yet answered.
+    pub(crate) const fn in_flight_bytes(&self) -> usize {
+        self.in_flight_bytes
+    }
+
+    /// Total number of queries ever pushed.
+    pub(crate) const fn pushed(&self) -> usize {
+        self.pushed
+    }
+
+    /// Total number of queries ever popped.
+    pub(crate) const fn popped(&self) -> usize {
+        self.popped
+    }
+
+    /// Total number of queries ever sent.
+    pub(crate) const fn sent(&self) -> usize {
+        self.sent
+    }
+
     #[tracing::instrument(level = "trace")]
     pub(crate) fn push(&mut self, query: Query) {
         self.q.push_back(query);
+        self.pushed += 1;
+    }
+
+    /// The query that will next be popped, if any.
+    pub(crate) fn front(&self) -> Option<&Query> {
+        self.q.front()
+    }
+
+    /// Number of queries sent but not yet popped.
+    pub(crate) const fn in_flight(&self) -> usize {
+        self.in_flight
+    }
+
+    /// Whether any queries are waiting to be sent.
+    pub(crate) fn has_unsent(&self) -> bool {
+        self.in_flight < self.len()
+    }
+
+    /// The queries waiting to be sent that the next flush could send, sending
+    /// at most `limit` queries.
+    pub(crate) fn sendable(&self, limit: usize) -> impl Iterator<Item = &Query> {
+        let capacity = self.max_in_flight - self.in_flight;
+        self.q
+            .iter()
+            .skip(self.in_flight)
+            .take(min(capacity, limit))
+    }
+
+    /// Send queued queries, provided a minimum batch can be sent, and
+    /// sending at most `limit` queries.
+    pub(crate) fn flush<F>(&mut self, limit: usize, f: F) -> Result<(), Error>
+    where
+        F: FnMut(&Query) -> Result<usize, Error>,
+    {
+        self.flush_batch(self.min_batch, limit, f)
+    }
+
+    /// Send queued queries, sending at most `limit` queries.
+    pub(crate) fn flush_now<F>(&mut self, limit: usize, f: F) -> Result<(), Error>
+    where
+        F: FnMut(&Query) -> Result<usize, Error>,
+    {
+        self.flush_batch(1, limit, f)
     }
 
     #[tracing::instrument(skip(f), level = "trace")]
-    pub(crate) fn flush<F>(&mut self, mut f: F) -> Result<(), Error>
+    fn flush_batch<F>(&mut self, min_batch: usize, limit: usize, mut f: F) -> Result<(), Error>
     where
-        F: FnMut(&Query) -> Result<(), Error>,
+        F: FnMut(&Query) -> Result<usize, Error>,
     {
         tracing::trace!("{} of {} queries in-flight", self.in_flight, self.len());
         if self.in_flight == self.len() {
@@ -42,18 +138,48 @@ impl Queue {
         }
         let capacity = self.max_in_flight - self.in_flight;
         tracing::trace!("available capacity to flush {capacity} queries");
-        if capacity >= self.min_batch {
-            let upto = min(self.in_flight + capacity, self.len());
+        if capacity >= min_batch {
+            let upto = min(self.in_flight + min(capacity, limit), self.len());
             tracing::debug!("trying to flush {} queries", upto - self.in_flight);
-            self.q.range(self.in_flight..upto).try_for_each(|item| {
-                f(item)?;
+            while self.in_flight < upto {
+                if self.in_flight_bytes >= self.max_in_flight_bytes {
+                    tracing::debug!("in-flight bytes limit reached, waiting for responses");
+                    break;
+                }
+                let len = f(&self.q[self.in_flight])?;
                 self.in_flight += 1;
-                Ok(())
-            })
+                self.sent += 1;
+                self.in_flight_bytes += len;
+                self.in_flight_lens.push_back(len);
+            }
         } else {
             tracing::trace!("waiting for enough capacity to flush minimum query batch");
-            Ok(())
         }
+        Ok(())
+    }
+
+    /// Drop any queries that have not yet been sent, returning the number of
+    /// queries dropped.
+    ///
+    /// Dropped queries are counted as popped, so that checkpoints taken
+    /// after they were pushed can still be reached.
+    pub(crate) fn discard_unsent(&mut self) -> usize {
+        let unsent = self.len() - self.in_flight;
+        self.q.truncate(self.in_flight);
+        self.popped += unsent;
+        unsent
+    }
+
+    /// Drop all queries, whether or not they have been sent, returning the
+    /// number of queries dropped.
+    pub(crate) fn discard_all(&mut self) -> usize {
+        let dropped = self.len();
+        self.q.clear();
+        self.in_flight = 0;
+        self.in_flight_lens.clear();
+        self.in_flight_bytes = 0;
+        self.popped += dropped;
+        dropped
     }
 
     #[tracing::instrument(level = "trace")]
@@ -62,9 +188,80 @@ impl Queue {
             // OK to unwrap here, as self.in_flight <= self.len()
             let item = self.q.pop_front().unwrap();
             self.in_flight -= 1;
+            self.in_flight_bytes -= self.in_flight_lens.pop_front().unwrap_or_default();
+            self.popped += 1;
             Some(item)
         } else {
             None
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flush_count(queue: &mut Queue, now: bool) -> usize {
+        let mut sent = 0;
+        let f = |query: &Query| {
+            sent += 1;
+            Ok(query.cmd().len())
+        };
+        if now {
+            queue.flush_now(usize::MAX, f).unwrap();
+        } else {
+            queue.flush(usize::MAX, f).unwrap();
+        }
+        sent
+    }
+
+    #[test]
+    fn flush_waits_for_minimum_batch() {
+        let mut queue = Queue::default();
+        (0..950).for_each(|_| queue.push(Query::Version));
+        assert_eq!(flush_count(&mut queue, false), 950);
+        (0..10).for_each(|_| queue.push(Query::Version));
+        assert_eq!(flush_count(&mut queue, false), 0);
+    }
+
+    #[test]
+    fn flush_now_ignores_minimum_batch() {
+        let mut queue = Queue::default();
+        (0..950).for_each(|_| queue.push(Query::Version));
+        assert_eq!(flush_count(&mut queue, false), 950);
+        (0..100).for_each(|_| queue.push(Query::Version));
+        assert_eq!(flush_count(&mut queue, true), 50);
+        assert_eq!(flush_count(&mut queue, true), 0);
+        _ = queue.pop();
+        assert_eq!(flush_count(&mut queue, true), 1);
+    }
+
+    #[test]
+    fn flush_limits_in_flight_bytes() {
+        let mut queue = Queue::default();
+        queue.set_max_in_flight_bytes(9);
+        (0..10).for_each(|_| queue.push(Query::Version));
+        assert_eq!(flush_count(&mut queue, true), 3);
+        assert_eq!(flush_count(&mut queue, true), 0);
+        _ = queue.pop();
+        assert_eq!(flush_count(&mut queue, true), 1);
+        assert_eq!(queue.discard_all(), 9);
+        assert_eq!(flush_count(&mut queue, true), 0);
+        queue.push(Query::Version);
+        assert_eq!(flush_count(&mut queue, true), 1);
+    }
+
+    #[test]
+    fn flush_respects_limit() {
+        let mut queue = Queue::default();
+        (0..10).for_each(|_| queue.push(Query::Version));
+        queue.flush_now(4, |_| Ok(3)).unwrap();
+        assert_eq!(queue.in_flight(), 4);
+        assert!(queue.has_unsent());
+        queue.flush_now(0, |_| Ok(3)).unwrap();
+        assert_eq!(queue.in_flight(), 4);
+        queue.flush_now(usize::MAX, |_| Ok(3)).unwrap();
+        assert_eq!(queue.in_flight(), 10);
+        assert!(!queue.has_unsent());
+    }
+}
@@ -0,0 +1,291 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Cursor, Read, Seek, Write};
+use std::iter::FusedIterator;
+use std::marker::PhantomData;
+use std::mem::size_of;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::{error::Error, query::Query};
+
+use super::{Response, ResponseItem};
+
+/// Options controlling whether [`Response::spill()`] holds the response
+/// data in memory or writes it to a temporary file.
+///
+/// # Example
+///
+/// ```
+/// use irrc::SpillOptions;
+///
+/// let mut options = SpillOptions::new();
+/// options.threshold(16 << 20).temp_dir("/var/tmp");
+/// ```
+#[derive(Clone, Debug)]
+pub struct SpillOptions {
+    threshold: usize,
+    temp_dir: Option<PathBuf>,
+}
+
+impl SpillOptions {
+    /// The default [`threshold()`][Self::threshold], of 64 MiB.
+    pub const DEFAULT_THRESHOLD: usize = 64 << 20;
+
+    /// Construct [`SpillOptions`] with the default threshold, using the
+    /// system temporary directory.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            threshold: Self::DEFAULT_THRESHOLD,
+            temp_dir: None,
+        }
+    }
+
+    /// Write responses with more than `bytes` of remaining data to a
+    /// temporary file. Smaller responses are held in memory.
+    pub const fn threshold(&mut self, bytes: usize) -> &mut Self {
+        self.threshold = bytes;
+        self
+    }
+
+    /// Create temporary files in `dir`, rather than in the directory given by
+    /// [`std::env::temp_dir()`].
+    pub fn temp_dir<P: Into<PathBuf>>(&mut self, dir: P) -> &mut Self {
+        self.temp_dir = Some(dir.into());
+        self
+    }
+}
+
+impl Default for SpillOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug)]
+enum Storage {
+    Memory(Cursor<Vec<u8>>),
+    File(BufReader<File>),
+}
+
+impl Read for Storage {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Memory(cursor) => cursor.read(buf),
+            Self::File(file) => file.read(buf),
+        }
+    }
+}
+
+/// Iterator returned by [`Response::spill()`].
+///
+/// See [`Response::spill`] for details.
+#[derive(Debug)]
+pub struct SpilledResponse<T> {
+    query: Query,
+    keep_raw: bool,
+    normalize_sources: bool,
+    storage: Storage,
+    remaining: usize,
+    content_type: PhantomData<T>,
+}
+
+impl<T> SpilledResponse<T>
+where
+    T: FromStr + fmt::Debug,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    pub(super) fn new(
+        response: Response<'_, '_, T>,
+        options: &SpillOptions,
+    ) -> Result<Self, Error> {
+        let query = response.query.clone();
+        let keep_raw = response.keep_raw;
+        let normalize_sources = response
+            .pipeline
+            .as_ref()
+            .is_some_and(|pipeline| pipeline.conn.normalizes_sources());
+        let remaining_len = response
+            .expected_len()
+            .saturating_sub(response.consumed_len());
+        let (storage, len) = if remaining_len > options.threshold {
+            tracing::debug!("spilling {remaining_len} bytes of response data to a temporary file");
+            let file = match &options.temp_dir {
+                Some(dir) => tempfile::tempfile_in(dir)?,
+                None => tempfile::tempfile()?,
+            };
+            let mut writer = BufWriter::new(file);
+            let len = write_items(response, &mut writer)?;
+            let mut file = writer
+                .into_inner()
+                .map_err(io::IntoInnerError::into_error)?;
+            file.rewind()?;
+            (Storage::File(BufReader::new(file)), len)
+        } else {
+            let mut buf = Vec::with_capacity(remaining_len);
+            let len = write_items(response, &mut buf)?;
+            (Storage::Memory(Cursor::new(buf)), len)
+        };
+        Ok(Self {
+            query,
+            keep_raw,
+            normalize_sources,
+            storage,
+            remaining: len,
+            content_type: PhantomData,
+        })
+    }
+
+    /// Whether the response data was written to a temporary file, rather
+    /// than held in memory.
+    #[must_use]
+    pub const fn is_spilled(&self) -> bool {
+        matches!(self.storage, Storage::File(_))
+    }
+
+    /// The [`Query`] which this was a response to.
+    #[must_use]
+    pub const fn query(&self) -> &Query {
+        &self.query
+    }
+
+    fn read_item(&mut self) -> Result<ResponseItem<T>, Error> {
+        let mut len = [0; size_of::<usize>()];
+        self.storage.read_exact(&mut len)?;
+        let mut item = vec![0; usize::from_ne_bytes(len)];
+        self.storage.read_exact(&mut item)?;
+        let mut content = self.query.item_content(&item, item.len(), self.keep_raw)?;
+        if self.normalize_sources {
+            content.uppercase_source();
+        }
+        Ok(ResponseItem(content, self.query.clone()))
+    }
+}
+
+/// Write each of the remaining data elements of `response` to `writer`,
+/// prefixed by its length, returning the number of elements written.
+fn write_items<T, W>(response: Response<'_, '_, T>, writer: &mut W) -> Result<usize, Error>
+where
+    T: FromStr + fmt::Debug,
+    T::Err: std::error::Error + Send + Sync + 'static,
+    W: Write,
+{
+    let mut count = 0;
+    let mut result = Ok(());
+    // the response is read to the end, even after a write error, so that the
+    // pipeline is left ready to read the next response.
+    response.for_each_raw(|item| {
+        if result.is_ok() {
+            result = writer
+                .write_all(&item.len().to_ne_bytes())
+                .and_then(|()| writer.write_all(item));
+            count += 1;
+        }
+    })?;
+    result?;
+    Ok(count)
+}
+
+impl<T> Iterator for SpilledResponse<T>
+where
+    T: FromStr + fmt::Debug,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    type Item = Result<ResponseItem<T>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let result = self.read_item();
+        if matches!(result, Err(Error::Io(_))) {
+            // the position of the following items is unknown
+            self.remaining = 0;
+        }
+        Some(result)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T> ExactSizeIterator for SpilledResponse<T>
+where
+    T: FromStr + fmt::Debug,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+}
+
+impl<T> FusedIterator for SpilledResponse<T>
+where
+    T: FromStr + fmt::Debug,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{client::Connection, query::Query, transport::mock::Mock};
+
+    use super::*;
+
+    #[test]
+    fn large_responses_are_spilled() {
+        let stream = Mock::new(b"A29\n192.0.2.0/24 198.51.100.0/24\nC\nA8\nAS65000\nC\n");
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        _ = pipeline
+            .push(Query::Ipv4Routes("AS65000".parse().unwrap()))
+            .unwrap()
+            .push(Query::AsSetMembers("AS-FOO".parse().unwrap()))
+            .unwrap();
+        let mut options = SpillOptions::new();
+        _ = options.threshold(16).temp_dir(std::env::temp_dir());
+        let spilled = pipeline
+            .pop::<String>()
+            .unwrap()
+            .unwrap()
+            .spill(&options)
+            .unwrap();
+        assert!(spilled.is_spilled());
+        assert_eq!(spilled.len(), 2);
+        let members = pipeline
+            .pop::<String>()
+            .unwrap()
+            .unwrap()
+            .into_vec()
+            .unwrap();
+        assert_eq!(members[0].content(), "AS65000");
+        let items = spilled
+            .map(|item| item.unwrap().into_content())
+            .collect::<Vec<_>>();
+        assert_eq!(items, vec!["192.0.2.0/24", "198.51.100.0/24"]);
+    }
+
+    #[test]
+    fn small_responses_are_held_in_memory() {
+        let stream = Mock::new(
+            b"A67\nroute: 192.0.2.0/24\nsource: radb\n\nroute: 192.0.2.0/24\nsource: RIPE\nC\n",
+        );
+        let mut conn = Connection::from_stream(stream);
+        let mut spilled = conn
+            .pipeline()
+            .push(Query::RoutesExact("192.0.2.0/24".to_string()))
+            .unwrap()
+            .pop::<String>()
+            .unwrap()
+            .unwrap()
+            .keep_raw()
+            .spill(&SpillOptions::default())
+            .unwrap();
+        assert!(!spilled.is_spilled());
+        let first = spilled.next().unwrap().unwrap();
+        assert_eq!(first.source(), Some("RADB"));
+        assert_eq!(first.raw(), Some(&b"route: 192.0.2.0/24\nsource: radb"[..]));
+        assert_eq!(spilled.next().unwrap().unwrap().source(), Some("RIPE"));
+        assert!(spilled.next().is_none());
+    }
+}
@@ -0,0 +1,302 @@
+use std::fmt;
+use std::str::FromStr;
+
+#[cfg(feature = "ip")]
+use ip::{Any, Prefix};
+
+use crate::error::Error;
+
+/// An RPSL prefix range operator, as defined in [RFC 2622].
+///
+/// Range operators may follow a prefix in the members of a `route-set`, to
+/// select prefixes more specific than the given prefix.
+///
+/// # Example
+///
+/// ```
+/// use irrc::RangeOperator;
+///
+/// assert_eq!("^16-24".parse::<RangeOperator>()?, RangeOperator::Range(16, 24));
+/// assert_eq!(RangeOperator::InclusiveMoreSpecifics.to_string(), "^+");
+/// # Ok::<(), irrc::Error>(())
+/// ```
+///
+/// [RFC 2622]: https://www.rfc-editor.org/rfc/rfc2622#section-2
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum RangeOperator {
+    /// `^-`: the more specifics of the prefix, excluding the prefix itself.
+    ExclusiveMoreSpecifics,
+    /// `^+`: the more specifics of the prefix, including the prefix itself.
+    InclusiveMoreSpecifics,
+    /// `^n`: the more specifics of the prefix of length `n`.
+    Length(u8),
+    /// `^n-m`: the more specifics of the prefix with lengths from `n` to `m`
+    /// inclusive.
+    Range(u8, u8),
+}
+
+impl RangeOperator {
+    /// The maximum prefix length of any address family.
+    const MAX_LENGTH: u8 = 128;
+
+    /// Check that the prefix lengths selected by this operator are valid for
+    /// a prefix of length `len`, in an address family with maximum prefix
+    /// length `max_len`.
+    #[cfg(feature = "ip")]
+    const fn fits(self, len: u8, max_len: u8) -> bool {
+        match self {
+            Self::ExclusiveMoreSpecifics | Self::InclusiveMoreSpecifics => true,
+            Self::Length(n) => len <= n && n <= max_len,
+            Self::Range(n, m) => len <= n && m <= max_len,
+        }
+    }
+}
+
+impl FromStr for RangeOperator {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidRangeOperator(s.to_owned());
+        let length = |n: &str| {
+            if !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()) {
+                n.parse::<u8>()
+                    .ok()
+                    .filter(|n| *n <= Self::MAX_LENGTH)
+                    .ok_or_else(invalid)
+            } else {
+                Err(invalid())
+            }
+        };
+        match s.strip_prefix('^').ok_or_else(invalid)? {
+            "-" => Ok(Self::ExclusiveMoreSpecifics),
+            "+" => Ok(Self::InclusiveMoreSpecifics),
+            op => match op.split_once('-') {
+                Some((n, m)) => {
+                    let (n, m) = (length(n)?, length(m)?);
+                    if n <= m {
+                        Ok(Self::Range(n, m))
+                    } else {
+                        Err(invalid())
+                    }
+                }
+                None => length(op).map(Self::Length),
+            },
+        }
+    }
+}
+
+impl fmt::Display for RangeOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ExclusiveMoreSpecifics => f.write_str("^-"),
+            Self::InclusiveMoreSpecifics => f.write_str("^+"),
+            Self::Length(n) => write!(f, "^{n}"),
+            Self::Range(n, m) => write!(f, "^{n}-{m}"),
+        }
+    }
+}
+
+/// An IP prefix, optionally followed by a [`RangeOperator`].
+///
+/// The members of a `route-set` may carry range operators, which are lost
+/// if the response to a [`Query::RouteSetMembersRecursive`] is parsed as
+/// [`Prefix<Any>`]. Parse the response items as [`PrefixRange`] instead to
+/// retain them.
+///
+/// # Example
+///
+/// ``` no_run
+/// # use irrc::{IrrClient, Query, Error};
+/// # fn main() -> Result<(), Error> {
+/// use irrc::PrefixRange;
+///
+/// IrrClient::new("whois.radb.net:43")
+///     .connect()?
+///     .pipeline()
+///     .push(Query::route_set_members_recursive("RS-FOO")?)?
+///     .responses::<PrefixRange>()
+///     .filter_map(Result::ok)
+///     .for_each(|item| match item.content().op {
+///         Some(op) => println!("{} with range {op}", item.content().prefix),
+///         None => println!("{}", item.content().prefix),
+///     });
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`Query::RouteSetMembersRecursive`]: crate::Query::RouteSetMembersRecursive
+#[cfg(feature = "ip")]
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct PrefixRange {
+    /// The IP prefix.
+    pub prefix: Prefix<Any>,
+    /// The range operator following the prefix, if any.
+    pub op: Option<RangeOperator>,
+}
+
+#[cfg(feature = "ip")]
+impl FromStr for PrefixRange {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (prefix, op) = s.find('^').map_or((s, None), |i| (&s[..i], Some(&s[i..])));
+        let invalid_prefix = || Error::InvalidPrefix(prefix.to_owned());
+        let (addr, len) = prefix.split_once('/').ok_or_else(invalid_prefix)?;
+        let len = len.parse::<u8>().map_err(|_| invalid_prefix())?;
+        let max_len = if addr.contains(':') { 128 } else { 32 };
+        let prefix = prefix.parse().map_err(|_| invalid_prefix())?;
+        let op = op.map(str::parse::<RangeOperator>).transpose()?;
+        match op {
+            Some(op) if !op.fits(len, max_len) => Err(Error::InvalidRangeOperator(s.to_owned())),
+            _ => Ok(Self { prefix, op }),
+        }
+    }
+}
+
+#[cfg(feature = "ip")]
+impl fmt::Display for PrefixRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.prefix)?;
+        if let Some(op) = self.op {
+            write!(f, "{op}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn range_operators_are_parsed() {
+        for (input, op) in [
+            ("^-", RangeOperator::ExclusiveMoreSpecifics),
+            ("^+", RangeOperator::InclusiveMoreSpecifics),
+            ("^24", RangeOperator::Length(24)),
+            ("^16-24", RangeOperator::Range(16, 24)),
+            ("^0-128", RangeOperator::Range(0, 128)),
+        ] {
+            assert_eq!(input.parse::<RangeOperator>().unwrap(), op);
+        }
+    }
+
+    #[test]
+    fn invalid_range_operators_are_rejected() {
+        for input in [
+            "", "^", "-", "24", "^*", "^129", "^24-16", "^-24", "^+24", "^16-",
+        ] {
+            assert!(
+                matches!(
+                    input.parse::<RangeOperator>(),
+                    Err(Error::InvalidRangeOperator(_))
+                ),
+                "{input}"
+            );
+        }
+    }
+
+    #[cfg(feature = "ip")]
+    #[test]
+    fn prefix_without_operator_has_no_op() {
+        let range: PrefixRange = "10.0.0.0/8".parse().unwrap();
+        assert_eq!(range.op, None);
+        assert_eq!(range.prefix, "10.0.0.0/8".parse().unwrap());
+    }
+
+    #[cfg(feature = "ip")]
+    #[test]
+    fn prefix_with_operator_is_parsed() {
+        let range: PrefixRange = "10.0.0.0/8^16-24".parse().unwrap();
+        assert_eq!(range.op, Some(RangeOperator::Range(16, 24)));
+        assert_eq!(range.to_string(), "10.0.0.0/8^16-24");
+    }
+
+    #[cfg(feature = "ip")]
+    #[test]
+    fn invalid_prefix_ranges_are_rejected() {
+        for input in ["10.0.0.0", "10.0.0.0/33", "10.0.0.0/8^", "foo^+"] {
+            assert!(input.parse::<PrefixRange>().is_err(), "{input}");
+        }
+        for input in [
+            "10.0.0.0/16^8",
+            "10.0.0.0/16^8-24",
+            "10.0.0.0/8^33",
+            "2001:db8::/32^129",
+        ] {
+            assert!(
+                matches!(
+                    input.parse::<PrefixRange>(),
+                    Err(Error::InvalidRangeOperator(_))
+                ),
+                "{input}"
+            );
+        }
+    }
+
+    impl Arbitrary for RangeOperator {
+        type Parameters = ();
+        type Strategy = BoxedStrategy<Self>;
+
+        fn arbitrary_with((): Self::Parameters) -> Self::Strategy {
+            prop_oneof![
+                Just(Self::ExclusiveMoreSpecifics),
+                Just(Self::InclusiveMoreSpecifics),
+                (0..=Self::MAX_LENGTH).prop_map(Self::Length),
+                (0..=Self::MAX_LENGTH, 0..=Self::MAX_LENGTH)
+                    .prop_map(|(n, m)| Self::Range(n.min(m), n.max(m))),
+            ]
+            .boxed()
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn range_operator_round_trips(op in any::<RangeOperator>()) {
+            assert_eq!(op.to_string().parse::<RangeOperator>().unwrap(), op);
+        }
+
+        #[test]
+        fn range_operator_parse_never_panics(input in any::<String>()) {
+            _ = input.parse::<RangeOperator>();
+        }
+    }
+
+    #[cfg(feature = "ip")]
+    proptest! {
+        #[test]
+        fn ipv4_prefix_range_round_trips(
+            addr in any::<u32>(),
+            len in 0..=32u8,
+            op in proptest::option::of(any::<RangeOperator>()),
+        ) {
+            let addr = std::net::Ipv4Addr::from(addr & !u32::MAX.checked_shr(u32::from(len)).unwrap_or(0));
+            let op = op.filter(|op| op.fits(len, 32));
+            let input = format!("{addr}/{len}{}", op.map(|op| op.to_string()).unwrap_or_default());
+            let range = input.parse::<PrefixRange>().unwrap();
+            assert_eq!(range.op, op);
+            assert_eq!(range.to_string().parse::<PrefixRange>().unwrap(), range);
+        }
+
+        #[test]
+        fn ipv6_prefix_range_round_trips(
+            addr in any::<u128>(),
+            len in 0..=128u8,
+            op in proptest::option::of(any::<RangeOperator>()),
+        ) {
+            let addr = std::net::Ipv6Addr::from(addr & !u128::MAX.checked_shr(u32::from(len)).unwrap_or(0));
+            let op = op.filter(|op| op.fits(len, 128));
+            let input = format!("{addr}/{len}{}", op.map(|op| op.to_string()).unwrap_or_default());
+            let range = input.parse::<PrefixRange>().unwrap();
+            assert_eq!(range.op, op);
+            assert_eq!(range.to_string().parse::<PrefixRange>().unwrap(), range);
+        }
+
+        #[test]
+        fn prefix_range_parse_never_panics(input in any::<String>()) {
+            _ = input.parse::<PrefixRange>();
+        }
+    }
+}
@@ -1,16 +1,36 @@
+use std::borrow::Cow;
 use std::convert::TryInto;
 use std::fmt;
 use std::iter::{once, Once};
-use std::str::FromStr;
+use std::net::IpAddr;
+use std::str::{from_utf8, FromStr};
 use std::time::Duration;
 
 use rpsl::names::{AsSet, AutNum, Mntner, RouteSet};
 
-use crate::{error::Error, parse, pipeline::ResponseContent};
+use crate::{
+    as_block::AsBlock,
+    error::{AddressFamilyMismatch, Error},
+    parse,
+    pipeline::ResponseContent,
+    prefix::RangeOperator,
+};
 
 /// IRRd query variants.
-// TODO: !a, !j, maybe !J
-#[derive(Clone, Debug, PartialEq, Eq)]
+///
+/// The [`Display`](fmt::Display) implementation describes the intent of the
+/// query in human-readable form, for use in log messages, rather than the
+/// command sent to the server.
+///
+/// ```
+/// use irrc::Query;
+///
+/// let q = Query::AsSetMembersRecursive("AS-FOO".parse().unwrap());
+/// assert_eq!(q.to_string(), "recursive members of AS-FOO");
+/// ```
+// TODO: maybe !J
+#[derive(Clone, Debug, PartialEq, Eq, strum::EnumDiscriminants)]
+#[strum_discriminants(name(QueryKind), derive(Hash))]
 pub enum Query {
     /// Returns the current version of the server.
     Version,
@@ -32,7 +52,49 @@ pub enum Query {
     SetSources(Vec<String>),
     /// Re-sets the list of sources to all those available on the server.
     UnsetSources,
+    /// Returns the mirroring status and available journal serial range of
+    /// the named source, or of all sources if `"-*"` is given.
+    ///
+    /// Each data element is of the form `SOURCE:MIRRORABLE:SERIALS[:EXPORT]`,
+    /// for example `RADB:Y:1-2351234:2351000`.
+    ///
+    /// This query can be used to determine whether a local copy of a source
+    /// is up-to-date, and which serials are available. The journal entries
+    /// themselves are not returned by a framed query, and are read using
+    /// [`Connection::journal_since()`][crate::Connection::journal_since].
+    JournalStatus(String),
     /// Returns all (direct) members of an `as-set`.
+    ///
+    /// The members returned may be either `aut-num`s or the names of other
+    /// `as-set`s. Parse the response items as [`rpsl::expr::AsSetMember`] to
+    /// distinguish between the two: parsing as [`AutNum`] will fail with an
+    /// [`Error::ParseItem`] for each nested `as-set`, and those items will be
+    /// lost if errors are skipped (for example, by
+    /// [`Pipeline::responses()`][crate::Pipeline::responses]).
+    ///
+    /// Use [`Query::AsSetMembersRecursive`] to have the server expand nested
+    /// `as-set`s, returning only `aut-num`s.
+    ///
+    /// # Example
+    ///
+    /// ``` no_run
+    /// # use irrc::{IrrClient, Query, Error};
+    /// # fn main() -> Result<(), Error> {
+    /// use rpsl::expr::AsSetMember;
+    ///
+    /// IrrClient::new("whois.radb.net:43")
+    ///     .connect()?
+    ///     .pipeline()
+    ///     .push(Query::AsSetMembers("AS-FOO".parse().unwrap()))?
+    ///     .responses::<AsSetMember>()
+    ///     .filter_map(Result::ok)
+    ///     .for_each(|item| match item.content() {
+    ///         AsSetMember::AutNum(autnum) => println!("aut-num: {autnum}"),
+    ///         AsSetMember::AsSet(set) => println!("as-set: {set}"),
+    ///     });
+    /// # Ok(())
+    /// # }
+    /// ```
     AsSetMembers(AsSet),
     /// Returns all members of an `as-set`, recursively expanding `as-set`
     /// members as necessary.
@@ -41,7 +103,34 @@ pub enum Query {
     RouteSetMembers(RouteSet),
     /// Returns all members of an `route-set`, recursively expanding members
     /// as necessary.
+    ///
+    /// Members may be followed by a [`RangeOperator`](crate::RangeOperator).
+    /// With the `ip` feature enabled, parse response items as `PrefixRange`
+    /// to retain it.
     RouteSetMembersRecursive(RouteSet),
+    /// Returns the prefixes of all `route` and `route6` objects originated by
+    /// the members of an `as-set`, recursively expanding `as-set` members as
+    /// necessary.
+    ///
+    /// If an [`AddressFamily`] is given, only prefixes of that family are
+    /// returned (issued as `!a4` or `!a6`). Otherwise, both IPv4 and IPv6
+    /// prefixes are returned in a single response.
+    ///
+    /// This is equivalent to a [`Query::AsSetMembersRecursive`] followed by a
+    /// [`Query::Ipv4Routes`] and [`Query::Ipv6Routes`] for each member, but is
+    /// resolved in a single round-trip.
+    ///
+    /// The prefixes returned are de-duplicated, but are not aggregated: each
+    /// is the prefix of at least one `route` or `route6` object. IRRd provides
+    /// no option to summarize the response server-side, so a caller that
+    /// needs a compact prefix list (for example, to generate a filter) must
+    /// aggregate the prefixes itself. Conversely, adjacent prefixes must not
+    /// be assumed to have been merged, since the response is an exact list of
+    /// registered routes.
+    ///
+    /// Construct using [`Query::as_set_prefixes()`] to parse the `as-set`
+    /// name.
+    AsSetPrefixes(AsSet, Option<AddressFamily>),
     /// Returns all IPv4 prefixes corresponding to a `route` object having
     /// `origin:` set to the provided AS.
     Ipv4Routes(AutNum),
@@ -50,15 +139,41 @@ pub enum Query {
     Ipv6Routes(AutNum),
     /// Returns an RPSL object exactly matching the provided key, of the
     /// specified RPSL object class.
+    ///
+    /// Parse the response items as [`RpslObject`][crate::RpslObject] to
+    /// access the object attributes.
     RpslObject(RpslObjectClass, String),
+    /// Returns the `as-block` object for the provided range of AS numbers.
+    ///
+    /// This is equivalent to a [`Query::RpslObject`] for the
+    /// [`RpslObjectClass::AsBlock`] class, with the key validated as an AS
+    /// number range.
+    AsBlock(AsBlock),
     /// Returns all RPSL objects with the specified maintainer in their
     /// `mnt-by:` attribute.
     MntBy(Mntner),
     /// Returns the unique `origin:`s of `route` or `route6` objects exactly
     /// matching the provided prefix.
+    ///
+    /// Only the origin AS numbers are returned, as whitespace separated
+    /// words. Use [`Query::RoutesExact`] to retrieve the `route` or `route6`
+    /// objects themselves.
     Origins(String),
     /// Returns all RPSL `route` or `route6` objects exactly matching the
     /// provided prefix.
+    ///
+    /// The server selects the object class according to the address family
+    /// of the prefix, so a single query variant serves both IPv4 and IPv6.
+    /// Each item of the response is a whole RPSL object, from which the
+    /// `origin:`, `source:` and other attributes can be read. Use
+    /// [`Query::Origins`] if only the origin AS numbers are required.
+    ///
+    /// An object of the wrong class for the address family of the prefix is
+    /// returned as an [`Error::ParseItem`], caused by an
+    /// [`AddressFamilyMismatch`].
+    ///
+    /// See [`RouteQuery`][crate::RouteQuery] for a builder covering this and the other `!r`
+    /// route search variants.
     RoutesExact(String),
     /// Returns all RPSL `route` or `route6` objects one level less-specific
     /// (excluding exeact matches) than the provided prefix.
@@ -69,9 +184,491 @@ pub enum Query {
     /// Returns all RPSL `route` or `route6` objects one level more-specific
     /// (excluding exeact matches) than the provided prefix.
     RoutesMore(String),
+    /// Returns all RPSL `route` or `route6` objects less-specific (including
+    /// exact matches) than the provided prefix, with a prefix length of at
+    /// least the given minimum.
+    ///
+    /// IRRd provides no way to bound the prefix length of a `!r` query, so
+    /// this is issued as for [`Query::RoutesLessEqual`], and objects outside
+    /// the bound are skipped as the response is read.
+    ///
+    /// Construct using [`Query::routes_less_bounded()`] to ensure that the
+    /// bound is valid.
+    RoutesLessBounded(String, u8),
+    /// Returns all RPSL `route` or `route6` objects more-specific (excluding
+    /// exact matches) than the provided prefix, with a prefix length of at
+    /// most the given maximum.
+    ///
+    /// IRRd provides no way to bound the prefix length of a `!r` query, so
+    /// this is issued as for [`Query::RoutesMore`], and objects outside the
+    /// bound are skipped as the response is read.
+    ///
+    /// Construct using [`Query::routes_more_bounded()`] or
+    /// [`Query::routes_more_range()`] to ensure that the bound is valid.
+    RoutesMoreBounded(String, u8),
+    /// An arbitrary `!` command, not otherwise modelled by a [`Query`]
+    /// variant, given without its trailing newline.
+    ///
+    /// **Unstable:** this is an escape hatch for experimenting with server
+    /// features that this crate does not yet support, and may change or be
+    /// removed in any release. Beyond its framing, the response is neither
+    /// checked nor interpreted: it may be empty or contain data, which is
+    /// split into whitespace separated items.
+    ///
+    /// Construct using [`Pipeline::push_raw()`][crate::Pipeline::push_raw].
+    Raw(String),
 }
 
 impl Query {
+    /// Construct a [`Query::Origins`], checking that `prefix` is a valid IP
+    /// prefix.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::InvalidPrefix`] is returned if `prefix` is not a valid IPv4
+    /// or IPv6 prefix.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use irrc::Query;
+    ///
+    /// assert!(Query::origins("192.0.2.0/24").is_ok());
+    /// assert!(Query::origins("192.0.2.0/33").is_err());
+    /// ```
+    pub fn origins<P: AsRef<str>>(prefix: P) -> Result<Self, Error> {
+        validate_prefix(prefix.as_ref()).map(Self::Origins)
+    }
+
+    /// Construct a [`Query::RoutesExact`], checking that `prefix` is a valid
+    /// IP prefix.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::InvalidPrefix`] is returned if `prefix` is not a valid IPv4
+    /// or IPv6 prefix.
+    pub fn routes_exact<P: AsRef<str>>(prefix: P) -> Result<Self, Error> {
+        validate_prefix(prefix.as_ref()).map(Self::RoutesExact)
+    }
+
+    /// Construct a [`Query::RoutesLess`], checking that `prefix` is a valid
+    /// IP prefix.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::InvalidPrefix`] is returned if `prefix` is not a valid IPv4
+    /// or IPv6 prefix.
+    pub fn routes_less<P: AsRef<str>>(prefix: P) -> Result<Self, Error> {
+        validate_prefix(prefix.as_ref()).map(Self::RoutesLess)
+    }
+
+    /// Construct a [`Query::RoutesLessEqual`], checking that `prefix` is a
+    /// valid IP prefix.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::InvalidPrefix`] is returned if `prefix` is not a valid IPv4
+    /// or IPv6 prefix.
+    pub fn routes_less_equal<P: AsRef<str>>(prefix: P) -> Result<Self, Error> {
+        validate_prefix(prefix.as_ref()).map(Self::RoutesLessEqual)
+    }
+
+    /// Construct a [`Query::RoutesMore`], checking that `prefix` is a valid
+    /// IP prefix.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::InvalidPrefix`] is returned if `prefix` is not a valid IPv4
+    /// or IPv6 prefix.
+    pub fn routes_more<P: AsRef<str>>(prefix: P) -> Result<Self, Error> {
+        validate_prefix(prefix.as_ref()).map(Self::RoutesMore)
+    }
+
+    /// Construct a [`Query::RoutesLessBounded`], checking that `prefix` is a
+    /// valid IP prefix, and that `min_len` is no longer than its prefix
+    /// length.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::InvalidPrefix`] is returned if `prefix` is not a valid IPv4
+    /// or IPv6 prefix, and an [`Error::InvalidLengthBound`] if `min_len` is
+    /// longer than the prefix length of `prefix`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use irrc::Query;
+    ///
+    /// assert!(Query::routes_less_bounded("192.0.2.0/24", 16).is_ok());
+    /// assert!(Query::routes_less_bounded("192.0.2.0/24", 25).is_err());
+    /// ```
+    pub fn routes_less_bounded<P: AsRef<str>>(prefix: P, min_len: u8) -> Result<Self, Error> {
+        let prefix = validate_prefix(prefix.as_ref())?;
+        match prefix_len(&prefix) {
+            Some((len, _)) if min_len <= len => Ok(Self::RoutesLessBounded(prefix, min_len)),
+            _ => Err(Error::InvalidLengthBound(prefix, min_len)),
+        }
+    }
+
+    /// Construct a [`Query::RoutesMoreBounded`], checking that `prefix` is a
+    /// valid IP prefix, and that `max_len` is longer than its prefix length
+    /// and within the maximum for its address family.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::InvalidPrefix`] is returned if `prefix` is not a valid IPv4
+    /// or IPv6 prefix, and an [`Error::InvalidLengthBound`] if `max_len` is
+    /// not valid for `prefix`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use irrc::Query;
+    ///
+    /// assert!(Query::routes_more_bounded("192.0.2.0/22", 24).is_ok());
+    /// assert!(Query::routes_more_bounded("192.0.2.0/22", 33).is_err());
+    /// ```
+    pub fn routes_more_bounded<P: AsRef<str>>(prefix: P, max_len: u8) -> Result<Self, Error> {
+        let prefix = validate_prefix(prefix.as_ref())?;
+        match prefix_len(&prefix) {
+            Some((len, max)) if len < max_len && max_len <= max => {
+                Ok(Self::RoutesMoreBounded(prefix, max_len))
+            }
+            _ => Err(Error::InvalidLengthBound(prefix, max_len)),
+        }
+    }
+
+    /// Construct a [`Query::RoutesMoreBounded`] from the upper bound of the
+    /// prefix lengths selected by the [`RangeOperator`] `op`.
+    ///
+    /// Since [`Query::RoutesMoreBounded`] excludes exact matches, and has no
+    /// lower bound, this is only possible for operators that select exactly
+    /// the more-specifics of `prefix` up to some length: `^-`, or a range
+    /// beginning immediately below the prefix length of `prefix`.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::InvalidPrefix`] is returned if `prefix` is not a valid IPv4
+    /// or IPv6 prefix, and an [`Error::InvalidRangeOperator`] if `op` cannot
+    /// be expressed as an upper bound for `prefix`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use irrc::{Query, RangeOperator};
+    ///
+    /// assert_eq!(
+    ///     Query::routes_more_range("192.0.2.0/22", RangeOperator::Range(23, 24))?,
+    ///     Query::routes_more_bounded("192.0.2.0/22", 24)?,
+    /// );
+    /// # Ok::<(), irrc::Error>(())
+    /// ```
+    pub fn routes_more_range<P: AsRef<str>>(prefix: P, op: RangeOperator) -> Result<Self, Error> {
+        let prefix = validate_prefix(prefix.as_ref())?;
+        let invalid = || Error::InvalidRangeOperator(format!("{prefix}{op}"));
+        let (len, max) = prefix_len(&prefix).ok_or_else(invalid)?;
+        match op {
+            RangeOperator::ExclusiveMoreSpecifics if len < max => {
+                Ok(Self::RoutesMoreBounded(prefix, max))
+            }
+            RangeOperator::Length(n) if n == len + 1 && n <= max => {
+                Ok(Self::RoutesMoreBounded(prefix, n))
+            }
+            RangeOperator::Range(n, m) if n == len + 1 && m <= max => {
+                Ok(Self::RoutesMoreBounded(prefix, m))
+            }
+            _ => Err(invalid()),
+        }
+    }
+
+    /// Construct a [`Query::Ipv4Routes`] for `autnum`, which may be an
+    /// [`AutNum`], a bare AS number, or a name to be parsed.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::InvalidQueryInput`] is returned if `autnum` is not a valid
+    /// `aut-num` name.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use irrc::Query;
+    ///
+    /// assert_eq!(Query::ipv4_routes(65000)?, Query::ipv4_routes("AS65000")?);
+    /// assert!(Query::ipv4_routes("AS-FOO").is_err());
+    /// # Ok::<(), irrc::Error>(())
+    /// ```
+    pub fn ipv4_routes<A: IntoName<AutNum>>(autnum: A) -> Result<Self, Error> {
+        autnum.into_name().map(Self::Ipv4Routes)
+    }
+
+    /// Construct a [`Query::Ipv6Routes`] for `autnum`, which may be an
+    /// [`AutNum`], a bare AS number, or a name to be parsed.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::InvalidQueryInput`] is returned if `autnum` is not a valid
+    /// `aut-num` name.
+    pub fn ipv6_routes<A: IntoName<AutNum>>(autnum: A) -> Result<Self, Error> {
+        autnum.into_name().map(Self::Ipv6Routes)
+    }
+
+    /// Construct a [`Query::Ipv4Routes`] or [`Query::Ipv6Routes`] for
+    /// `autnum`, according to `family`.
+    ///
+    /// The [`address_family()`][Self::address_family] of the query returned
+    /// is `family`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use irrc::{AddressFamily, Query};
+    /// use rpsl::names::AutNum;
+    ///
+    /// let autnum: AutNum = "AS65000".parse().unwrap();
+    /// let queries = AddressFamily::ALL.map(|family| Query::routes(family, autnum));
+    /// assert_eq!(
+    ///     queries,
+    ///     [Query::Ipv4Routes(autnum), Query::Ipv6Routes(autnum)]
+    /// );
+    /// ```
+    pub fn routes<A: Into<AutNum>>(family: AddressFamily, autnum: A) -> Self {
+        match family {
+            AddressFamily::Ipv4 => Self::Ipv4Routes(autnum.into()),
+            AddressFamily::Ipv6 => Self::Ipv6Routes(autnum.into()),
+        }
+    }
+
+    /// Construct a [`Query::AsSetMembers`], parsing `as_set` as the name of
+    /// an `as-set`.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::InvalidQueryInput`] is returned if `as_set` is not a valid
+    /// `as-set` name.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use irrc::Query;
+    ///
+    /// assert!(Query::as_set_members("AS65000:AS-CUSTOMERS").is_ok());
+    /// assert!(Query::as_set_members("AS65000").is_err());
+    /// ```
+    pub fn as_set_members<S: IntoName<AsSet>>(as_set: S) -> Result<Self, Error> {
+        as_set.into_name().map(Self::AsSetMembers)
+    }
+
+    /// Construct a [`Query::AsSetMembersRecursive`], parsing `as_set` as the
+    /// name of an `as-set`.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::InvalidQueryInput`] is returned if `as_set` is not a valid
+    /// `as-set` name.
+    pub fn as_set_members_recursive<S: IntoName<AsSet>>(as_set: S) -> Result<Self, Error> {
+        as_set.into_name().map(Self::AsSetMembersRecursive)
+    }
+
+    /// Construct a [`Query::AsSetPrefixes`], parsing `as_set` as the name of
+    /// an `as-set`.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::InvalidQueryInput`] is returned if `as_set` is not a valid
+    /// `as-set` name.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use irrc::{AddressFamily, Query};
+    ///
+    /// let q = Query::as_set_prefixes("AS-FOO", Some(AddressFamily::Ipv6))?;
+    /// assert_eq!(q.address_family(), Some(AddressFamily::Ipv6));
+    /// assert!(q.returns_prefixes());
+    /// # Ok::<(), irrc::Error>(())
+    /// ```
+    pub fn as_set_prefixes<S: IntoName<AsSet>>(
+        as_set: S,
+        family: Option<AddressFamily>,
+    ) -> Result<Self, Error> {
+        as_set
+            .into_name()
+            .map(|as_set| Self::AsSetPrefixes(as_set, family))
+    }
+
+    /// Construct a [`Query::RouteSetMembers`], parsing `route_set` as the
+    /// name of a `route-set`.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::InvalidQueryInput`] is returned if `route_set` is not a
+    /// valid `route-set` name.
+    pub fn route_set_members<S: IntoName<RouteSet>>(route_set: S) -> Result<Self, Error> {
+        route_set.into_name().map(Self::RouteSetMembers)
+    }
+
+    /// Construct a [`Query::RouteSetMembersRecursive`], parsing `route_set`
+    /// as the name of a `route-set`.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::InvalidQueryInput`] is returned if `route_set` is not a
+    /// valid `route-set` name.
+    pub fn route_set_members_recursive<S: IntoName<RouteSet>>(route_set: S) -> Result<Self, Error> {
+        route_set.into_name().map(Self::RouteSetMembersRecursive)
+    }
+
+    /// Construct a [`Query::MntBy`], parsing `mntner` as the name of a
+    /// `mntner`.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::InvalidQueryInput`] is returned if `mntner` is not a valid
+    /// `mntner` name.
+    ///
+    /// As well as being parsed as a [`Mntner`], the name is checked against
+    /// the RPSL syntax for object names ([RFC 2622 section 2]): it must begin
+    /// with a letter, end with a letter or digit, contain only letters,
+    /// digits, `_` and `-`, and be neither a reserved word nor begin with a
+    /// prefix reserved for set names (such as `AS-`). A server would reject
+    /// such a name, so the check saves a round-trip.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use irrc::Query;
+    ///
+    /// assert!(Query::mnt_by("MNT-AS65000").is_ok());
+    /// assert!(Query::mnt_by("1-MNT").is_err());
+    /// assert!(Query::mnt_by("FOO-MNT-").is_err());
+    /// ```
+    ///
+    /// [RFC 2622 section 2]: https://www.rfc-editor.org/rfc/rfc2622#section-2
+    pub fn mnt_by<S: IntoName<Mntner>>(mntner: S) -> Result<Self, Error> {
+        let mntner = mntner.into_name()?;
+        validate_object_name(&mntner.to_string())?;
+        Ok(Self::MntBy(mntner))
+    }
+
+    /// Construct a query for the RPSL objects of `class` whose key matches
+    /// `key` according to `mode`, checking that `mode` is supported for
+    /// `class`.
+    ///
+    /// [`MatchMode::Exact`] gives a [`Query::RpslObject`]. The other modes
+    /// give the `!r` route search variant for the mode (see [`MatchMode`]),
+    /// and `key` must be an IP prefix of the address family corresponding
+    /// to `class`, since `!r` selects the object class by address family.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::UnsupportedMatchMode`] is returned if `mode` is not
+    /// supported for `class`, or an [`Error::InvalidPrefix`] if `key` is not a
+    /// valid prefix of the appropriate address family.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use irrc::{MatchMode, Query, RpslObjectClass};
+    ///
+    /// let less = MatchMode::LessSpecificOneLevel;
+    /// assert_eq!(
+    ///     Query::rpsl_object_by_key(RpslObjectClass::Route, "192.0.2.0/24", less)?,
+    ///     Query::RoutesLess("192.0.2.0/24".to_string()),
+    /// );
+    /// assert!(Query::rpsl_object_by_key(RpslObjectClass::Route6, "192.0.2.0/24", less).is_err());
+    /// assert!(Query::rpsl_object_by_key(RpslObjectClass::AsSet, "AS-FOO", less).is_err());
+    /// # Ok::<(), irrc::Error>(())
+    /// ```
+    pub fn rpsl_object_by_key<K: AsRef<str>>(
+        class: RpslObjectClass,
+        key: K,
+        mode: MatchMode,
+    ) -> Result<Self, Error> {
+        let key = key.as_ref();
+        let af = match (class, mode) {
+            (_, MatchMode::Exact) => return Ok(Self::RpslObject(class, key.to_owned())),
+            (RpslObjectClass::Route, _) => AddressFamily::Ipv4,
+            (RpslObjectClass::Route6, _) => AddressFamily::Ipv6,
+            _ => return Err(Error::UnsupportedMatchMode(class, mode)),
+        };
+        let prefix = validate_prefix(key)?;
+        if af.check_prefix(prefix.as_bytes()).is_err() {
+            return Err(Error::InvalidPrefix(prefix));
+        }
+        Ok(match mode {
+            MatchMode::Exact => unreachable!("exact matches are handled above"),
+            MatchMode::LessSpecificOneLevel => Self::RoutesLess(prefix),
+            MatchMode::LessSpecific => Self::RoutesLessEqual(prefix),
+            MatchMode::MoreSpecific => Self::RoutesMore(prefix),
+        })
+    }
+
+    /// Get the canonical form of this query.
+    ///
+    /// Queries that are semantically equivalent, but differ structurally,
+    /// have equal canonical forms. This makes the canonical form suitable
+    /// for use as the key of a query cache, or for de-duplicating queries.
+    ///
+    /// The canonical form is obtained by:
+    ///
+    /// - upper-casing source names, and removing repeated names from the
+    ///   source list of a [`Query::SetSources`];
+    /// - upper-casing RPSL object keys, which are case-insensitive; and
+    /// - writing IP prefixes with lower-case, compressed addresses and
+    ///   without leading zeros in the prefix length.
+    ///
+    /// The order of the sources in a [`Query::SetSources`] is preserved,
+    /// since the server treats it as an order of preference: queries
+    /// selecting the same sources in a different order are not equivalent,
+    /// and their canonical forms differ.
+    ///
+    /// Queries are sent to the server as given, rather than in canonical
+    /// form. The command written for a query is therefore its own, and the
+    /// command of its canonical form is only used to compare queries, for
+    /// example by [`Pipeline::push_deduplicated()`][crate::Pipeline::push_deduplicated]
+    /// and [`CachedConnection`][crate::CachedConnection].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use irrc::Query;
+    ///
+    /// let q = Query::SetSources(vec!["ripe".to_string(), "RADB".to_string(), "Ripe".to_string()]);
+    /// assert_eq!(
+    ///     q.canonical(),
+    ///     Query::SetSources(vec!["RIPE".to_string(), "RADB".to_string()]),
+    /// );
+    /// ```
+    #[must_use]
+    pub fn canonical(&self) -> Self {
+        match self {
+            Self::SetSources(sources) => {
+                let mut canonical: Vec<String> = Vec::with_capacity(sources.len());
+                for source in sources.iter().map(|source| source.to_uppercase()) {
+                    if !canonical.contains(&source) {
+                        canonical.push(source);
+                    }
+                }
+                Self::SetSources(canonical)
+            }
+            Self::JournalStatus(source) => Self::JournalStatus(source.to_uppercase()),
+            Self::RpslObject(class, key) => Self::RpslObject(*class, key.to_uppercase()),
+            Self::Origins(prefix) => Self::Origins(canonical_prefix(prefix)),
+            Self::RoutesExact(prefix) => Self::RoutesExact(canonical_prefix(prefix)),
+            Self::RoutesLess(prefix) => Self::RoutesLess(canonical_prefix(prefix)),
+            Self::RoutesLessEqual(prefix) => Self::RoutesLessEqual(canonical_prefix(prefix)),
+            Self::RoutesMore(prefix) => Self::RoutesMore(canonical_prefix(prefix)),
+            Self::RoutesLessBounded(prefix, min_len) => {
+                Self::RoutesLessBounded(canonical_prefix(prefix), *min_len)
+            }
+            Self::RoutesMoreBounded(prefix, max_len) => {
+                Self::RoutesMoreBounded(canonical_prefix(prefix), *max_len)
+            }
+            _ => self.clone(),
+        }
+    }
+
     pub(crate) fn cmd(&self) -> String {
         match self {
             Self::Version => "!v\n".to_owned(),
@@ -80,64 +677,652 @@ impl Query {
             Self::GetSources => "!s-lc\n".to_owned(),
             Self::SetSources(sources) => format!("!s{}\n", sources.join(",")),
             Self::UnsetSources => "!s-*\n".to_owned(),
+            Self::JournalStatus(source) => format!("!j{source}\n"),
             Self::AsSetMembers(q) => format!("!i{q}\n"),
             Self::AsSetMembersRecursive(q) => format!("!i{q},1\n"),
             Self::RouteSetMembers(q) => format!("!i{q}\n"),
             Self::RouteSetMembersRecursive(q) => format!("!i{q},1\n"),
+            Self::AsSetPrefixes(q, family) => match family {
+                None => format!("!a{q}\n"),
+                Some(AddressFamily::Ipv4) => format!("!a4{q}\n"),
+                Some(AddressFamily::Ipv6) => format!("!a6{q}\n"),
+            },
             Self::Ipv4Routes(q) => format!("!g{q}\n"),
             Self::Ipv6Routes(q) => format!("!6{q}\n"),
             Self::RpslObject(class, q) => format!("!m{class},{q}\n"),
+            Self::AsBlock(block) => format!("!m{},{block}\n", RpslObjectClass::AsBlock),
             Self::MntBy(q) => format!("!o{q}\n"),
             Self::Origins(q) => format!("!r{q},o\n"),
             Self::RoutesExact(q) => format!("!r{q}\n"),
             Self::RoutesLess(q) => format!("!r{q},l\n"),
-            Self::RoutesLessEqual(q) => format!("!r{q},L\n"),
-            Self::RoutesMore(q) => format!("!r{q},M\n"),
+            Self::RoutesLessEqual(q) | Self::RoutesLessBounded(q, _) => format!("!r{q},L\n"),
+            Self::RoutesMore(q) | Self::RoutesMoreBounded(q, _) => format!("!r{q},M\n"),
+            Self::Raw(cmd) => format!("{cmd}\n"),
+        }
+    }
+
+    /// Check that any user provided values in this query cannot alter the
+    /// framing of the command sent to the server.
+    ///
+    /// Control characters (including newlines) are rejected in all values.
+    /// Commas are additionally rejected where they would delimit options or
+    /// list elements.
+    ///
+    /// `mntner` names are also checked against the RPSL object name syntax,
+    /// since the server would reject the query otherwise.
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        let check = |value: &str, reject_comma: bool| {
+            if value
+                .chars()
+                .any(|c| c.is_control() || (reject_comma && c == ','))
+            {
+                Err(Error::InvalidQueryInput(value.to_owned()))
+            } else {
+                Ok(())
+            }
+        };
+        match self {
+            Self::SetClientId(value) | Self::JournalStatus(value) => check(value, false),
+            Self::RpslObject(_, key) => check(key, false),
+            Self::SetSources(sources) => sources.iter().try_for_each(|source| check(source, true)),
+            Self::Raw(cmd) if cmd.starts_with('!') => check(cmd, false),
+            Self::Raw(cmd) => Err(Error::InvalidQueryInput(cmd.clone())),
+            Self::MntBy(mntner) => validate_object_name(&mntner.to_string()),
+            Self::Origins(prefix)
+            | Self::RoutesExact(prefix)
+            | Self::RoutesLess(prefix)
+            | Self::RoutesLessEqual(prefix)
+            | Self::RoutesMore(prefix)
+            | Self::RoutesLessBounded(prefix, _)
+            | Self::RoutesMoreBounded(prefix, _) => check(prefix, true),
+            Self::Version
+            | Self::SetTimeout(_)
+            | Self::GetSources
+            | Self::UnsetSources
+            | Self::AsSetMembers(_)
+            | Self::AsSetMembersRecursive(_)
+            | Self::RouteSetMembers(_)
+            | Self::RouteSetMembersRecursive(_)
+            | Self::AsSetPrefixes(..)
+            | Self::Ipv4Routes(_)
+            | Self::Ipv6Routes(_)
+            | Self::AsBlock(_) => Ok(()),
         }
     }
 
+    /// Whether this query changes server-side connection state, and so may
+    /// change the responses to the queries that follow it.
+    ///
+    /// [`Query::Raw`] commands are assumed to do so.
+    pub(crate) const fn changes_state(&self) -> bool {
+        matches!(
+            self,
+            Self::SetClientId(_)
+                | Self::SetTimeout(_)
+                | Self::SetSources(_)
+                | Self::UnsetSources
+                | Self::Raw(_)
+        )
+    }
+
     pub(crate) const fn expect_data(&self) -> bool {
         matches!(
             self,
             Self::Version
                 | Self::GetSources
+                | Self::JournalStatus(_)
                 | Self::AsSetMembers(_)
                 | Self::RouteSetMembers(_)
                 | Self::AsSetMembersRecursive(_)
                 | Self::RouteSetMembersRecursive(_)
+                | Self::AsSetPrefixes(..)
                 | Self::Ipv4Routes(_)
                 | Self::Ipv6Routes(_)
                 | Self::RpslObject(..)
+                | Self::AsBlock(_)
                 | Self::MntBy(_)
                 | Self::Origins(_)
                 | Self::RoutesExact(_)
                 | Self::RoutesLess(_)
                 | Self::RoutesLessEqual(_)
                 | Self::RoutesMore(_)
+                | Self::RoutesLessBounded(..)
+                | Self::RoutesMoreBounded(..)
+                | Self::Raw(_)
         )
     }
 
-    pub(crate) fn parse_item<T>(&self, input: &[u8]) -> Result<(usize, ResponseContent<T>), Error>
-    where
-        T: FromStr + fmt::Debug,
-        T::Err: std::error::Error + Send + Sync + 'static,
-    {
-        let (_, (consumed, item)) = match self {
+    /// The address family of the prefixes returned by this query, for queries
+    /// that are specific to a single address family.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use irrc::{AddressFamily, Query};
+    ///
+    /// let q = Query::Ipv6Routes("AS65000".parse().unwrap());
+    /// assert_eq!(q.address_family(), Some(AddressFamily::Ipv6));
+    /// ```
+    #[must_use]
+    pub const fn address_family(&self) -> Option<AddressFamily> {
+        match self {
+            Self::Ipv4Routes(_) => Some(AddressFamily::Ipv4),
+            Self::Ipv6Routes(_) => Some(AddressFamily::Ipv6),
+            Self::AsSetPrefixes(_, family) => *family,
+            _ => None,
+        }
+    }
+
+    /// The address family of the `route` or `route6` objects returned by
+    /// this query, for queries that look up objects by IP prefix.
+    ///
+    /// The server selects the object class according to the address family
+    /// of the prefix, so only `route` objects are expected for an IPv4 prefix,
+    /// and only `route6` objects for an IPv6 prefix.
+    fn object_address_family(&self) -> Option<AddressFamily> {
+        match self {
+            Self::RoutesExact(prefix)
+            | Self::RoutesLess(prefix)
+            | Self::RoutesLessEqual(prefix)
+            | Self::RoutesMore(prefix)
+            | Self::RoutesLessBounded(prefix, _)
+            | Self::RoutesMoreBounded(prefix, _) => AddressFamily::of_prefix(prefix),
+            _ => None,
+        }
+    }
+
+    /// Whether every data element returned by this query is a bare IP
+    /// prefix.
+    ///
+    /// Note that this is not the case for [`Query::RouteSetMembers`], since
+    /// direct members of a `route-set` may also be the names of other sets,
+    /// nor for [`Query::RouteSetMembersRecursive`], since members may be
+    /// followed by a range operator.
+    #[must_use]
+    pub const fn returns_prefixes(&self) -> bool {
+        matches!(
+            self,
+            Self::Ipv4Routes(_) | Self::Ipv6Routes(_) | Self::AsSetPrefixes(..)
+        )
+    }
+
+    /// Whether the response data consists of whole RPSL objects, rather than
+    /// whitespace separated words.
+    pub(crate) const fn returns_objects(&self) -> bool {
+        matches!(
+            self,
+            Self::RpslObject(..)
+                | Self::AsBlock(_)
+                | Self::MntBy(_)
+                | Self::RoutesExact(_)
+                | Self::RoutesLess(_)
+                | Self::RoutesLessEqual(_)
+                | Self::RoutesMore(_)
+                | Self::RoutesLessBounded(..)
+                | Self::RoutesMoreBounded(..)
+        )
+    }
+
+    /// The typical number of bytes occupied by a data element returned by
+    /// this query, including its delimiter.
+    ///
+    /// This is used to estimate the number of items in a response from its
+    /// length, and is neither a lower nor an upper bound.
+    pub(crate) const fn typical_item_len(&self) -> usize {
+        match self {
+            // e.g. "AS64496 "
+            Self::AsSetMembers(_) | Self::AsSetMembersRecursive(_) | Self::Origins(_) => 8,
+            // e.g. "198.51.100.0/24 "
+            Self::Ipv4Routes(_) | Self::AsSetPrefixes(_, Some(AddressFamily::Ipv4)) => 16,
+            // e.g. "2001:db8:1000::/36 "
+            Self::Ipv6Routes(_) | Self::AsSetPrefixes(_, Some(AddressFamily::Ipv6)) => 20,
+            Self::AsSetPrefixes(_, None)
+            | Self::RouteSetMembers(_)
+            | Self::RouteSetMembersRecursive(_) => 18,
+            _ if self.returns_objects() => 256,
+            _ => 16,
+        }
+    }
+
+    /// The number of bytes occupied by the next data element in `input`, if
+    /// it is a `route` or `route6` object with a prefix length outside the
+    /// bound of this query, and should be skipped.
+    pub(crate) fn out_of_bounds(&self, input: &[u8]) -> Option<usize> {
+        let (min_len, max_len) = match self {
+            Self::RoutesLessBounded(_, min_len) => (*min_len, u8::MAX),
+            Self::RoutesMoreBounded(_, max_len) => (0, *max_len),
+            _ => return None,
+        };
+        let (consumed, item) = self.parse_raw(input).ok()?;
+        let len = route_object_prefix_len(item)?;
+        (len < min_len || len > max_len).then_some(consumed)
+    }
+
+    /// This query, with any source names converted to upper case.
+    pub(crate) fn with_uppercase_sources(self) -> Self {
+        match self {
+            Self::SetSources(sources) => Self::SetSources(
+                sources
+                    .into_iter()
+                    .map(|source| source.to_ascii_uppercase())
+                    .collect(),
+            ),
+            Self::JournalStatus(source) => Self::JournalStatus(source.to_ascii_uppercase()),
+            query => query,
+        }
+    }
+
+    /// Extract the bytes of the next data element from `input`, without
+    /// any further checks on its content.
+    pub(crate) fn parse_raw<'i>(&self, input: &'i [u8]) -> Result<(usize, &'i [u8]), Error> {
+        let (_, parsed) = match self {
             _ if !self.expect_data() => parse::noop(input)?,
             Self::Version => parse::all(input)?,
-            Self::RpslObject(..)
-            | Self::MntBy(_)
-            | Self::RoutesExact(_)
-            | Self::RoutesLess(_)
-            | Self::RoutesLessEqual(_)
-            | Self::RoutesMore(_) => parse::paragraph(input)?,
+            _ if self.returns_objects() => parse::paragraph(input)?,
             _ => parse::word(input)?,
         };
-        let content = item
-            .try_into()
-            .map_err(|err| Error::ParseItem(err, consumed))?;
+        Ok(parsed)
+    }
+
+    /// Check that the raw response `item`, occupying `consumed` bytes of the
+    /// response data, is consistent with this query.
+    pub(crate) fn check_item(&self, item: &[u8], consumed: usize) -> Result<(), Error> {
+        if let Some(af) = self.address_family() {
+            af.check_prefix(item)
+                .map_err(|err| Error::ParseItem(Box::new(err), consumed, None))?;
+        } else if let Some(af) = self.object_address_family() {
+            af.check_route_object(item)
+                .map_err(|err| Error::ParseItem(Box::new(err), consumed, None))?;
+        }
+        Ok(())
+    }
+
+    /// Replace any invalid UTF-8 in the free-form text returned by a
+    /// [`Query::Version`], rather than rejecting the whole response.
+    ///
+    /// The data of other queries is returned unchanged, and checked when it
+    /// is parsed.
+    fn decode_lossily<'i>(&self, item: &'i [u8]) -> Cow<'i, [u8]> {
+        if !matches!(self, Self::Version) {
+            return Cow::Borrowed(item);
+        }
+        match from_utf8(item) {
+            Ok(_) => Cow::Borrowed(item),
+            Err(err) => {
+                tracing::warn!(
+                    "replacing invalid UTF-8 in response to {self}, after {} valid bytes",
+                    err.valid_up_to()
+                );
+                Cow::Owned(String::from_utf8_lossy(item).into_owned().into_bytes())
+            }
+        }
+    }
+
+    pub(crate) fn parse_item<T>(
+        &self,
+        input: &[u8],
+        keep_raw: bool,
+    ) -> Result<(usize, ResponseContent<T>), Error>
+    where
+        T: FromStr + fmt::Debug,
+        T::Err: std::error::Error + Send + Sync + 'static,
+    {
+        let (consumed, item) = self.parse_raw(input)?;
+        let content = self.item_content(item, consumed, keep_raw)?;
         Ok((consumed, content))
     }
+
+    /// Parse the raw response `item`, previously extracted by
+    /// [`parse_raw()`][Self::parse_raw] from `consumed` bytes of the response
+    /// data.
+    pub(crate) fn item_content<T>(
+        &self,
+        item: &[u8],
+        consumed: usize,
+        keep_raw: bool,
+    ) -> Result<ResponseContent<T>, Error>
+    where
+        T: FromStr + fmt::Debug,
+        T::Err: std::error::Error + Send + Sync + 'static,
+    {
+        self.check_item(item, consumed)?;
+        let decoded = self.decode_lossily(item);
+        let mut content: ResponseContent<T> = decoded
+            .as_ref()
+            .try_into()
+            .map_err(|err| Error::ParseItem(err, consumed, None))?;
+        if self.returns_objects() {
+            let object = from_utf8(item).ok();
+            content.set_source(object.and_then(parse::source));
+            content.set_primary_key(object.and_then(parse::primary_key));
+        }
+        if keep_raw {
+            content.set_raw(item);
+        }
+        Ok(content)
+    }
+}
+
+/// Parse `name` as an RPSL object name of type `N`.
+fn parse_name<N: FromStr>(name: &str) -> Result<N, Error> {
+    name.parse()
+        .map_err(|_| Error::InvalidQueryInput(name.to_owned()))
+}
+
+/// Conversion into an RPSL object name of type `N`, as accepted by the
+/// named constructors of [`Query`].
+///
+/// Implemented for each name type itself, for string names to be parsed, and for bare `u32` AS numbers as an [`AutNum`].
+pub trait IntoName<N> {
+    /// Convert `self` into a name of type `N`.
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::InvalidQueryInput`] is returned if `self` is not a valid
+    /// name of type `N`.
+    fn into_name(self) -> Result<N, Error>;
+}
+
+impl<N: FromStr> IntoName<N> for &str {
+    fn into_name(self) -> Result<N, Error> {
+        parse_name(self)
+    }
+}
+
+impl<N: FromStr> IntoName<N> for String {
+    fn into_name(self) -> Result<N, Error> {
+        parse_name(&self)
+    }
+}
+
+impl<N: FromStr> IntoName<N> for &String {
+    fn into_name(self) -> Result<N, Error> {
+        parse_name(self)
+    }
+}
+
+impl IntoName<AutNum> for u32 {
+    fn into_name(self) -> Result<AutNum, Error> {
+        parse_name(&format!("AS{self}"))
+    }
+}
+
+impl IntoName<Self> for AutNum {
+    fn into_name(self) -> Result<Self, Error> {
+        Ok(self)
+    }
+}
+
+impl IntoName<Self> for AsSet {
+    fn into_name(self) -> Result<Self, Error> {
+        Ok(self)
+    }
+}
+
+impl IntoName<Self> for RouteSet {
+    fn into_name(self) -> Result<Self, Error> {
+        Ok(self)
+    }
+}
+
+impl IntoName<Self> for Mntner {
+    fn into_name(self) -> Result<Self, Error> {
+        Ok(self)
+    }
+}
+
+/// Words that may not be used as RPSL object names.
+///
+/// See [RFC 2622 section 2](https://www.rfc-editor.org/rfc/rfc2622#section-2).
+const RESERVED_WORDS: [&str; 20] = [
+    "any", "as-any", "rs-any", "peeras", "and", "or", "not", "atomic", "from", "to", "at",
+    "action", "accept", "announce", "except", "refine", "networks", "into", "inbound", "outbound",
+];
+
+/// Prefixes reserved for the names of set objects.
+const RESERVED_PREFIXES: [&str; 5] = ["as-", "rs-", "rtrs-", "fltr-", "prng-"];
+
+/// Check that `name` is a valid RPSL object name, such as that of a
+/// `mntner`.
+///
+/// Names of the form `AS<digits>` are rejected, as they would be read as AS
+/// numbers.
+fn validate_object_name(name: &str) -> Result<(), Error> {
+    let invalid = || Error::InvalidQueryInput(name.to_owned());
+    let (Some(first), Some(last)) = (name.chars().next(), name.chars().last()) else {
+        return Err(invalid());
+    };
+    let reserved_prefix = RESERVED_PREFIXES.iter().any(|prefix| {
+        name.get(..prefix.len())
+            .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+    });
+    let as_number = name
+        .get(..2)
+        .is_some_and(|start| start.eq_ignore_ascii_case("as"))
+        && name.len() > 2
+        && name[2..].chars().all(|c| c.is_ascii_digit());
+    if !first.is_ascii_alphabetic()
+        || !last.is_ascii_alphanumeric()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        || RESERVED_WORDS
+            .iter()
+            .any(|word| word.eq_ignore_ascii_case(name))
+        || reserved_prefix
+        || as_number
+    {
+        return Err(invalid());
+    }
+    Ok(())
+}
+
+/// Replace any control characters in `s` with spaces, so that it cannot
+/// span multiple lines of a query.
+#[cfg_attr(not(feature = "std-net"), allow(dead_code))]
+pub(crate) fn single_line(s: &str) -> String {
+    s.replace(char::is_control, " ")
+}
+
+/// Check that `prefix` is an IP address and prefix length, with the length
+/// not exceeding the maximum for the address family.
+fn validate_prefix(prefix: &str) -> Result<String, Error> {
+    let invalid = || Error::InvalidPrefix(prefix.to_owned());
+    let (addr, len) = prefix.split_once('/').ok_or_else(invalid)?;
+    let max_len = match addr.parse().map_err(|_| invalid())? {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    };
+    if !len.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    match len.parse::<u8>() {
+        Ok(len) if len <= max_len => Ok(prefix.to_owned()),
+        _ => Err(invalid()),
+    }
+}
+
+/// The prefix length of a valid `prefix`, and the maximum prefix length of
+/// its address family.
+fn prefix_len(prefix: &str) -> Option<(u8, u8)> {
+    let (addr, len) = prefix.split_once('/')?;
+    let max_len = match addr.parse().ok()? {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    };
+    Some((len.parse().ok()?, max_len))
+}
+
+/// The prefix length of the `route` or `route6` object `object`, taken from
+/// the key on its first line.
+fn route_object_prefix_len(object: &[u8]) -> Option<u8> {
+    let first = from_utf8(object).ok()?.lines().next()?;
+    let (_, key) = first.split_once(':')?;
+    prefix_len(key.trim()).map(|(len, _)| len)
+}
+
+/// Re-write a prefix in canonical form, leaving it unchanged if it cannot be
+/// parsed.
+fn canonical_prefix(prefix: &str) -> String {
+    prefix
+        .split_once('/')
+        .and_then(|(addr, len)| {
+            let addr = addr.parse::<IpAddr>().ok()?;
+            let len = len.parse::<u8>().ok()?;
+            Some(format!("{addr}/{len}"))
+        })
+        .unwrap_or_else(|| prefix.to_owned())
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Version => write!(f, "server version"),
+            Self::SetClientId(id) => write!(f, "set client id to {id:?}"),
+            Self::SetTimeout(dur) => write!(f, "set server timeout to {}s", dur.as_secs()),
+            Self::GetSources => write!(f, "selected sources"),
+            Self::SetSources(sources) => write!(f, "select sources {}", sources.join(",")),
+            Self::UnsetSources => write!(f, "select all sources"),
+            Self::JournalStatus(source) => write!(f, "journal status of {source}"),
+            Self::AsSetMembers(q) => write!(f, "members of {q}"),
+            Self::AsSetMembersRecursive(q) => write!(f, "recursive members of {q}"),
+            Self::RouteSetMembers(q) => write!(f, "members of {q}"),
+            Self::RouteSetMembersRecursive(q) => write!(f, "recursive members of {q}"),
+            Self::AsSetPrefixes(q, None) => write!(f, "prefixes of members of {q}"),
+            Self::AsSetPrefixes(q, Some(family)) => {
+                write!(f, "{family} prefixes of members of {q}")
+            }
+            Self::Ipv4Routes(q) => write!(f, "IPv4 routes originated by {q}"),
+            Self::Ipv6Routes(q) => write!(f, "IPv6 routes originated by {q}"),
+            Self::RpslObject(class, q) => write!(f, "{class} object {q}"),
+            Self::AsBlock(block) => write!(f, "as-block object {block}"),
+            Self::MntBy(q) => write!(f, "objects maintained by {q}"),
+            Self::Origins(q) => write!(f, "origins of {q}"),
+            Self::RoutesExact(q) => write!(f, "routes exactly matching {q}"),
+            Self::RoutesLess(q) => write!(f, "routes less-specific than {q}"),
+            Self::RoutesLessEqual(q) => write!(f, "routes less-specific than or equal to {q}"),
+            Self::RoutesMore(q) => write!(f, "routes more-specific than {q}"),
+            Self::RoutesLessBounded(q, min_len) => write!(
+                f,
+                "routes less-specific than or equal to {q}, with length at least {min_len}"
+            ),
+            Self::RoutesMoreBounded(q, max_len) => write!(
+                f,
+                "routes more-specific than {q}, with length at most {max_len}"
+            ),
+            Self::Raw(cmd) => write!(f, "raw command {cmd}"),
+        }
+    }
+}
+
+/// Parse a query command, as sent to the server, back into a [`Query`].
+///
+/// This is the inverse of the command written for each query, with or
+/// without the trailing newline. It is useful for replaying commands
+/// captured from logs or from other clients.
+///
+/// Some commands are shared by more than one variant, and are resolved as
+/// follows:
+///
+/// - `!i` is parsed as an `as-set` query, unless the last component of the
+///   set name begins with `RS-`, in which case it is parsed as a `route-set`
+///   query.
+/// - `!m` is parsed as a [`Query::RpslObject`], or as a [`Query::AsBlock`]
+///   for the `as-block` class if the key is a valid AS number range.
+/// - `!r` is parsed as one of the `Routes*` variants, or as a
+///   [`Query::Origins`].
+///
+/// The length bounds of [`Query::RoutesLessBounded`] and
+/// [`Query::RoutesMoreBounded`] are applied client-side, and are not
+/// recovered.
+///
+/// # Errors
+///
+/// An [`Error::UnrecognizedCommand`] is returned if `cmd` is not a command
+/// that can be issued as a [`Query`], and an [`Error::InvalidQueryInput`] if
+/// an RPSL object name in the command cannot be parsed.
+///
+/// # Example
+///
+/// ```
+/// use irrc::Query;
+///
+/// assert_eq!(
+///     Query::try_from("!iAS-FOO,1")?,
+///     Query::AsSetMembersRecursive("AS-FOO".parse().unwrap()),
+/// );
+/// assert!(Query::try_from("!xyz").is_err());
+/// # Ok::<(), irrc::Error>(())
+/// ```
+impl TryFrom<&str> for Query {
+    type Error = Error;
+
+    fn try_from(cmd: &str) -> Result<Self, Self::Error> {
+        let unrecognized = || Error::UnrecognizedCommand(cmd.to_owned());
+        let line = cmd.strip_suffix('\n').unwrap_or(cmd);
+        let mut chars = line.strip_prefix('!').ok_or_else(unrecognized)?.chars();
+        let op = chars.next().ok_or_else(unrecognized)?;
+        let arg = chars.as_str();
+        let query = match op {
+            'v' if arg.is_empty() => Self::Version,
+            'n' => Self::SetClientId(arg.to_owned()),
+            't' => Self::SetTimeout(Duration::from_secs(
+                arg.parse().map_err(|_| unrecognized())?,
+            )),
+            's' => match arg {
+                "-lc" => Self::GetSources,
+                "-*" => Self::UnsetSources,
+                "" => Self::SetSources(Vec::new()),
+                _ => Self::SetSources(arg.split(',').map(str::to_owned).collect()),
+            },
+            'j' => Self::JournalStatus(arg.to_owned()),
+            'i' => {
+                let (set, recursive) = arg
+                    .strip_suffix(",1")
+                    .map_or((arg, false), |set| (set, true));
+                let is_route_set = set
+                    .rsplit(':')
+                    .next()
+                    .and_then(|component| component.get(..3))
+                    .is_some_and(|prefix| prefix.eq_ignore_ascii_case("rs-"));
+                match (is_route_set, recursive) {
+                    (false, false) => Self::AsSetMembers(parse_name(set)?),
+                    (false, true) => Self::AsSetMembersRecursive(parse_name(set)?),
+                    (true, false) => Self::RouteSetMembers(parse_name(set)?),
+                    (true, true) => Self::RouteSetMembersRecursive(parse_name(set)?),
+                }
+            }
+            'a' => {
+                let (set, family) = match arg.as_bytes().first() {
+                    Some(b'4') => (&arg[1..], Some(AddressFamily::Ipv4)),
+                    Some(b'6') => (&arg[1..], Some(AddressFamily::Ipv6)),
+                    _ => (arg, None),
+                };
+                Self::AsSetPrefixes(parse_name(set)?, family)
+            }
+            'g' => Self::Ipv4Routes(parse_name(arg)?),
+            '6' => Self::Ipv6Routes(parse_name(arg)?),
+            'm' => {
+                let (class, key) = arg.split_once(',').ok_or_else(unrecognized)?;
+                let class = class.parse().map_err(|_| unrecognized())?;
+                match (class, key.parse()) {
+                    (RpslObjectClass::AsBlock, Ok(block)) => Self::AsBlock(block),
+                    _ => Self::RpslObject(class, key.to_owned()),
+                }
+            }
+            'o' => Self::MntBy(parse_name(arg)?),
+            'r' => match arg.rsplit_once(',') {
+                None => Self::RoutesExact(arg.to_owned()),
+                Some((prefix, "o")) => Self::Origins(prefix.to_owned()),
+                Some((prefix, "l")) => Self::RoutesLess(prefix.to_owned()),
+                Some((prefix, "L")) => Self::RoutesLessEqual(prefix.to_owned()),
+                Some((prefix, "M")) => Self::RoutesMore(prefix.to_owned()),
+                Some(_) => return Err(unrecognized()),
+            },
+            _ => return Err(unrecognized()),
+        };
+        Ok(query)
+    }
 }
 
 impl IntoIterator for Query {
@@ -148,8 +1333,125 @@ impl IntoIterator for Query {
     }
 }
 
+impl QueryKind {
+    /// Whether a zero-length response to a query of this kind is accepted
+    /// by default.
+    pub(crate) const fn allows_empty_response(self) -> bool {
+        !matches!(self, Self::Version)
+    }
+}
+
+/// IP address families.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, strum::Display)]
+pub enum AddressFamily {
+    /// IPv4 address family.
+    #[strum(to_string = "IPv4")]
+    Ipv4,
+    /// IPv6 address family.
+    #[strum(to_string = "IPv6")]
+    Ipv6,
+}
+
+impl AddressFamily {
+    /// Both address families, for issuing a query once per family.
+    pub const ALL: [Self; 2] = [Self::Ipv4, Self::Ipv6];
+
+    /// The address family of `prefix`, if it is an IP prefix.
+    fn of_prefix(prefix: &str) -> Option<Self> {
+        match prefix.split_once('/')?.0.parse().ok()? {
+            IpAddr::V4(_) => Some(Self::Ipv4),
+            IpAddr::V6(_) => Some(Self::Ipv6),
+        }
+    }
+
+    /// The class of the RPSL objects describing routes in this address
+    /// family.
+    const fn route_class(self) -> &'static str {
+        match self {
+            Self::Ipv4 => "route",
+            Self::Ipv6 => "route6",
+        }
+    }
+
+    /// Check that `object` is a `route` or `route6` object, as appropriate
+    /// for this address family, and that its prefix belongs to this address
+    /// family.
+    fn check_route_object(self, object: &[u8]) -> Result<(), AddressFamilyMismatch> {
+        let first = from_utf8(object)
+            .ok()
+            .and_then(|object| object.lines().next())
+            .unwrap_or_default();
+        let matches = |(class, key): (&str, &str)| {
+            class.trim().eq_ignore_ascii_case(self.route_class())
+                && Self::of_prefix(key.trim()) == Some(self)
+        };
+        if first.split_once(':').is_some_and(matches) {
+            Ok(())
+        } else {
+            Err(AddressFamilyMismatch {
+                expected: self,
+                item: first.to_owned(),
+            })
+        }
+    }
+
+    /// Check that `item`, if it is an IP prefix, belongs to this address
+    /// family.
+    fn check_prefix(self, item: &[u8]) -> Result<(), AddressFamilyMismatch> {
+        let addr = from_utf8(item)
+            .ok()
+            .and_then(|item| item.split_once('/'))
+            .and_then(|(addr, _)| addr.parse().ok());
+        match (self, addr) {
+            (Self::Ipv4, Some(IpAddr::V6(_))) | (Self::Ipv6, Some(IpAddr::V4(_))) => {
+                Err(AddressFamilyMismatch {
+                    expected: self,
+                    item: String::from_utf8_lossy(item).into_owned(),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Modes of matching the key of an RPSL object, used with
+/// [`Query::rpsl_object_by_key()`].
+///
+/// The modes supported for each RPSL object class are:
+///
+/// | Class                | `Exact` | `LessSpecificOneLevel` | `LessSpecific` | `MoreSpecific` |
+/// |----------------------|---------|------------------------|----------------|----------------|
+/// | `route`, `route6`    | yes     | yes                    | yes            | yes            |
+/// | all others           | yes     | no                     | no             | no             |
+///
+/// [`MatchMode::Exact`] gives a [`Query::RpslObject`], issued as an `!m`
+/// query with the key in the usual form for the object class (for `route`
+/// and `route6` objects, the prefix followed by the origin AS, e.g.
+/// `192.0.2.0/24AS65000`). The other modes give [`Query::RoutesLess`],
+/// [`Query::RoutesLessEqual`] and [`Query::RoutesMore`] respectively, issued
+/// as `!r` queries with the key given as an IP prefix.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, strum::Display)]
+#[cfg_attr(test, derive(strum::EnumIter))]
+pub enum MatchMode {
+    /// Match only objects with exactly the provided key.
+    #[strum(to_string = "exact")]
+    Exact,
+    /// Match objects one level less-specific than the provided prefix,
+    /// excluding exact matches.
+    #[strum(to_string = "one-level less-specific")]
+    LessSpecificOneLevel,
+    /// Match all objects less-specific than the provided prefix, including
+    /// exact matches.
+    #[strum(to_string = "less-specific")]
+    LessSpecific,
+    /// Match all objects more-specific than the provided prefix, excluding
+    /// exact matches.
+    #[strum(to_string = "more-specific")]
+    MoreSpecific,
+}
+
 /// RPSL object classes.
-#[derive(Clone, Copy, Debug, PartialEq, Eq, strum::Display)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, strum::Display, strum::EnumString)]
 #[cfg_attr(test, derive(strum::EnumIter))]
 pub enum RpslObjectClass {
     /// `mntner` object class.
@@ -188,6 +1490,12 @@ pub enum RpslObjectClass {
     /// `peering-set` object class.
     #[strum(to_string = "peering-set")]
     PeeringSet,
+    /// `as-block` object class.
+    #[strum(to_string = "as-block")]
+    AsBlock,
+    /// `domain` object class.
+    #[strum(to_string = "domain")]
+    Domain,
 }
 
 #[cfg(test)]
@@ -198,6 +1506,491 @@ pub enum RpslObjectClass {
 mod tests {
     use super::*;
 
+    #[test]
+    fn valid_prefixes_are_accepted() {
+        for prefix in ["192.0.2.0/24", "0.0.0.0/0", "2001:db8::/32", "::/128"] {
+            assert_eq!(
+                Query::routes_exact(prefix).unwrap(),
+                Query::RoutesExact(prefix.to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_prefixes_are_rejected() {
+        for prefix in [
+            "",
+            "192.0.2.0",
+            "192.0.2.0/33",
+            "192.0.2.0/+24",
+            "192.0.2/24",
+            "2001:db8::/129",
+            "AS65000/24",
+            "192.0.2.0/24\n!v",
+        ] {
+            assert!(
+                matches!(Query::origins(prefix), Err(Error::InvalidPrefix(_))),
+                "{prefix}"
+            );
+        }
+    }
+
+    #[test]
+    fn routes_exact_returns_objects_unlike_origins() {
+        for prefix in ["192.0.2.0/24", "2001:db8::/32"] {
+            let routes = Query::routes_exact(prefix).unwrap();
+            assert_eq!(routes.cmd(), format!("!r{prefix}\n"));
+            assert!(routes.returns_objects());
+            assert_eq!(routes.address_family(), None);
+            let origins = Query::origins(prefix).unwrap();
+            assert_eq!(origins.cmd(), format!("!r{prefix},o\n"));
+            assert!(!origins.returns_objects());
+        }
+    }
+
+    #[test]
+    fn named_constructors() {
+        let autnum: AutNum = "AS65000".parse().unwrap();
+        assert_eq!(Query::ipv4_routes(autnum).unwrap().cmd(), "!gAS65000\n");
+        assert_eq!(Query::ipv4_routes(65000).unwrap().cmd(), "!gAS65000\n");
+        assert_eq!(Query::ipv6_routes("AS65000").unwrap().cmd(), "!6AS65000\n");
+        let as_set: AsSet = "AS-FOO".parse().unwrap();
+        assert_eq!(
+            Query::as_set_members(as_set.clone()).unwrap(),
+            Query::as_set_members("AS-FOO".to_string()).unwrap()
+        );
+        assert_eq!(
+            Query::as_set_prefixes(as_set, None).unwrap().cmd(),
+            "!aAS-FOO\n"
+        );
+        assert_eq!(
+            Query::as_set_members_recursive("AS-FOO").unwrap().cmd(),
+            "!iAS-FOO,1\n"
+        );
+        assert_eq!(
+            Query::route_set_members("RS-FOO").unwrap().cmd(),
+            "!iRS-FOO\n"
+        );
+        assert_eq!(Query::mnt_by("FOO-MNT").unwrap().cmd(), "!oFOO-MNT\n");
+        for result in [
+            Query::as_set_members("RS-FOO"),
+            Query::route_set_members_recursive("AS-FOO"),
+            Query::mnt_by("AS65000"),
+            Query::ipv4_routes("AS-FOO"),
+        ] {
+            assert!(matches!(result, Err(Error::InvalidQueryInput(_))));
+        }
+    }
+
+    #[test]
+    fn canonical_form_normalizes_equivalent_queries() {
+        let sources =
+            |sources: &[&str]| Query::SetSources(sources.iter().map(ToString::to_string).collect());
+        assert_eq!(
+            sources(&["RIPE", "radb", "RADB"]).canonical(),
+            sources(&["RIPE", "RADB"])
+        );
+        assert_ne!(
+            sources(&["B", "A"]).canonical(),
+            sources(&["A", "B"]).canonical()
+        );
+        assert_eq!(
+            Query::RoutesMore("2001:DB8:0::/032".to_string()).canonical(),
+            Query::RoutesMore("2001:db8::/32".to_string())
+        );
+        assert_eq!(
+            Query::RpslObject(RpslObjectClass::Mntner, "foo-mnt".to_string()).canonical(),
+            Query::RpslObject(RpslObjectClass::Mntner, "FOO-MNT".to_string())
+        );
+        assert_eq!(
+            Query::SetClientId("Foo".to_string()).canonical(),
+            Query::SetClientId("Foo".to_string())
+        );
+    }
+
+    #[test]
+    fn framing_characters_are_rejected() {
+        for q in [
+            Query::SetClientId("foo\n!v".to_string()),
+            Query::SetSources(vec!["RADB".to_string(), "foo\n!v".to_string()]),
+            Query::SetSources(vec!["RADB,RIPE".to_string()]),
+            Query::JournalStatus("foo\r\n!v".to_string()),
+            Query::RpslObject(RpslObjectClass::AutNum, "foo\n!v".to_string()),
+            Query::Origins("192.0.2.0/24,l".to_string()),
+            Query::RoutesMore("foo\n!v".to_string()),
+        ] {
+            assert!(
+                matches!(q.validate(), Err(Error::InvalidQueryInput(_))),
+                "{q:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn mntner_names_are_validated() {
+        for name in [
+            "FOO-MNT",
+            "MAINT-AS65000",
+            "foo_bar-mnt",
+            "M1",
+            "ab",
+            "M",
+            "AS",
+            "ASN-MNT",
+            "AS65000-MNT",
+        ] {
+            assert!(validate_object_name(name).is_ok(), "{name:?}");
+        }
+        for name in [
+            "", "1", "_", "1-MNT", "-MNT", "FOO-MNT-", "FOO_", "FOO MNT", "FOO.MNT", "AS-FOO",
+            "rs-foo", "ANY", "peeras", "AS65000", "as1",
+        ] {
+            assert!(
+                matches!(validate_object_name(name), Err(Error::InvalidQueryInput(_))),
+                "{name:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn plain_values_are_accepted() {
+        for q in [
+            Query::SetClientId("irrc-0.1.0 ticket 1234".to_string()),
+            Query::SetSources(vec!["RADB".to_string(), "RIPE".to_string()]),
+            Query::RpslObject(RpslObjectClass::Route, "192.0.2.0/24AS65000".to_string()),
+            Query::Origins("192.0.2.0/24".to_string()),
+        ] {
+            assert!(q.validate().is_ok(), "{q:?}");
+        }
+    }
+
+    #[test]
+    fn as_set_prefixes_query_selects_address_family() {
+        for (family, cmd) in [
+            (None, "!aAS-FOO\n"),
+            (Some(AddressFamily::Ipv4), "!a4AS-FOO\n"),
+            (Some(AddressFamily::Ipv6), "!a6AS-FOO\n"),
+        ] {
+            let q = Query::as_set_prefixes("AS-FOO", family).unwrap();
+            assert_eq!(q.cmd(), cmd);
+            assert_eq!(q.address_family(), family);
+            assert!(q.returns_prefixes());
+        }
+        assert!(Query::as_set_prefixes("AS65000", None).is_err());
+    }
+
+    #[test]
+    fn prefix_returning_queries() {
+        let as_set = || "AS-FOO".parse().unwrap();
+        let route_set = || "RS-FOO".parse().unwrap();
+        let prefix = || "192.0.2.0/24".to_string();
+        for (q, expected) in [
+            (Query::Version, false),
+            (Query::SetClientId("irrc".to_string()), false),
+            (Query::SetTimeout(Duration::from_secs(30)), false),
+            (Query::GetSources, false),
+            (Query::SetSources(vec!["RADB".to_string()]), false),
+            (Query::UnsetSources, false),
+            (Query::JournalStatus("RADB".to_string()), false),
+            (Query::AsSetMembers(as_set()), false),
+            (Query::AsSetMembersRecursive(as_set()), false),
+            (Query::RouteSetMembers(route_set()), false),
+            (Query::RouteSetMembersRecursive(route_set()), false),
+            (Query::AsSetPrefixes(as_set(), None), true),
+            (Query::Ipv4Routes("AS65000".parse().unwrap()), true),
+            (Query::Ipv6Routes("AS65000".parse().unwrap()), true),
+            (
+                Query::RpslObject(RpslObjectClass::AutNum, "AS65000".to_string()),
+                false,
+            ),
+            (Query::AsBlock("AS1000 - AS2000".parse().unwrap()), false),
+            (Query::MntBy("FOO-MNT".parse().unwrap()), false),
+            (Query::Origins(prefix()), false),
+            (Query::RoutesExact(prefix()), false),
+            (Query::RoutesLess(prefix()), false),
+            (Query::RoutesLessEqual(prefix()), false),
+            (Query::RoutesMore(prefix()), false),
+            (Query::RoutesLessBounded(prefix(), 24), false),
+            (Query::RoutesMoreBounded(prefix(), 24), false),
+            (Query::Raw("!v".to_string()), false),
+        ] {
+            assert_eq!(q.returns_prefixes(), expected, "{q:?}");
+        }
+    }
+
+    #[test]
+    fn as_block_query_uses_object_class() {
+        let q = Query::AsBlock("as1000 - as2000".parse().unwrap());
+        assert_eq!(q.cmd(), "!mas-block,AS1000-AS2000\n");
+        assert!(q.returns_objects());
+    }
+
+    #[test]
+    fn match_mode_compatibility() {
+        use strum::IntoEnumIterator;
+        for class in RpslObjectClass::iter() {
+            for mode in MatchMode::iter() {
+                let key = match (class, mode) {
+                    (_, MatchMode::Exact) => "foo",
+                    (RpslObjectClass::Route6, _) => "2001:db8::/32",
+                    _ => "192.0.2.0/24",
+                };
+                let result = Query::rpsl_object_by_key(class, key, mode);
+                match (class, mode) {
+                    (_, MatchMode::Exact) => {
+                        assert_eq!(result.unwrap().cmd(), format!("!m{class},foo\n"));
+                    }
+                    (RpslObjectClass::Route | RpslObjectClass::Route6, _) => {
+                        let flag = match mode {
+                            MatchMode::LessSpecificOneLevel => 'l',
+                            MatchMode::LessSpecific => 'L',
+                            MatchMode::MoreSpecific => 'M',
+                            MatchMode::Exact => unreachable!(),
+                        };
+                        assert_eq!(result.unwrap().cmd(), format!("!r{key},{flag}\n"));
+                    }
+                    _ => assert!(
+                        matches!(result, Err(Error::UnsupportedMatchMode(c, m)) if c == class && m == mode)
+                    ),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn match_mode_checks_prefix_family() {
+        for (class, key) in [
+            (RpslObjectClass::Route, "2001:db8::/32"),
+            (RpslObjectClass::Route6, "192.0.2.0/24"),
+            (RpslObjectClass::Route, "192.0.2.0/24AS65000"),
+        ] {
+            assert!(matches!(
+                Query::rpsl_object_by_key(class, key, MatchMode::MoreSpecific),
+                Err(Error::InvalidPrefix(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn address_family_mismatch_is_rejected() {
+        let q = Query::Ipv4Routes("AS65000".parse().unwrap());
+        assert!(q.parse_item::<String>(b"192.0.2.0/24 foo", false).is_ok());
+        assert!(q.parse_item::<String>(b"foo 192.0.2.0/24", false).is_ok());
+        match q.parse_item::<String>(b"2001:db8::/32 foo", false) {
+            Err(Error::ParseItem(err, 14, None)) => {
+                assert!(err.downcast_ref::<AddressFamilyMismatch>().is_some());
+            }
+            result => panic!("expected address family mismatch, got {result:?}"),
+        }
+        let q = Query::Ipv6Routes("AS65000".parse().unwrap());
+        assert!(q.parse_item::<String>(b"2001:db8::/32\nC", false).is_ok());
+        assert!(q.parse_item::<String>(b"192.0.2.0/24\nC", false).is_err());
+    }
+
+    #[test]
+    fn length_bounds_are_validated() {
+        assert_eq!(
+            Query::routes_more_bounded("2001:db8::/32", 48)
+                .unwrap()
+                .cmd(),
+            "!r2001:db8::/32,M\n"
+        );
+        assert_eq!(
+            Query::routes_less_bounded("192.0.2.0/24", 16)
+                .unwrap()
+                .cmd(),
+            "!r192.0.2.0/24,L\n"
+        );
+        assert!(Query::routes_less_bounded("192.0.2.0/24", 24).is_ok());
+        for (prefix, len) in [
+            ("192.0.2.0/24", 24),
+            ("192.0.2.0/24", 33),
+            ("2001:db8::/32", 129),
+        ] {
+            assert!(matches!(
+                Query::routes_more_bounded(prefix, len),
+                Err(Error::InvalidLengthBound(..))
+            ));
+        }
+        assert!(matches!(
+            Query::routes_less_bounded("192.0.2.0/24", 25),
+            Err(Error::InvalidLengthBound(..))
+        ));
+        assert!(matches!(
+            Query::routes_more_bounded("192.0.2.0/33", 24),
+            Err(Error::InvalidPrefix(_))
+        ));
+    }
+
+    #[test]
+    fn range_operators_are_converted_to_length_bounds() {
+        for (op, max_len) in [
+            (RangeOperator::ExclusiveMoreSpecifics, 32),
+            (RangeOperator::Length(23), 23),
+            (RangeOperator::Range(23, 24), 24),
+        ] {
+            assert_eq!(
+                Query::routes_more_range("192.0.2.0/22", op).unwrap(),
+                Query::RoutesMoreBounded("192.0.2.0/22".to_string(), max_len)
+            );
+        }
+        for op in [
+            RangeOperator::InclusiveMoreSpecifics,
+            RangeOperator::Length(24),
+            RangeOperator::Range(22, 24),
+            RangeOperator::Range(24, 32),
+        ] {
+            assert!(matches!(
+                Query::routes_more_range("192.0.2.0/22", op),
+                Err(Error::InvalidRangeOperator(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn objects_outside_length_bound_are_identified() {
+        let q = Query::routes_more_bounded("192.0.0.0/16", 24).unwrap();
+        assert_eq!(
+            q.out_of_bounds(b"route: 192.0.2.0/24\norigin: AS65000\n\n"),
+            None
+        );
+        assert_eq!(
+            q.out_of_bounds(b"route: 192.0.2.0/25\norigin: AS65000\n\n"),
+            Some(36)
+        );
+        let q = Query::routes_exact("192.0.2.0/25").unwrap();
+        assert_eq!(
+            q.out_of_bounds(b"route: 192.0.2.0/25\norigin: AS65000\n\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn route_object_address_family_mismatch_is_rejected() {
+        let route = b"route: 192.0.2.0/24\norigin: AS65000\n\n";
+        let route6 = b"route6: 2001:db8::/32\norigin: AS65000\n\n";
+        let q = Query::routes_exact("192.0.2.0/24").unwrap();
+        assert!(q.parse_item::<String>(route, false).is_ok());
+        match q.parse_item::<String>(route6, false) {
+            Err(Error::ParseItem(err, 38, None)) => {
+                let err = err.downcast_ref::<AddressFamilyMismatch>().unwrap();
+                assert_eq!(err.expected(), AddressFamily::Ipv4);
+                assert_eq!(err.item(), "route6: 2001:db8::/32");
+            }
+            result => panic!("expected address family mismatch, got {result:?}"),
+        }
+        let q = Query::rpsl_object_by_key(
+            RpslObjectClass::Route6,
+            "2001:db8::/32",
+            MatchMode::MoreSpecific,
+        )
+        .unwrap();
+        assert!(q.parse_item::<String>(route6, false).is_ok());
+        assert!(q.parse_item::<String>(route, false).is_err());
+        assert!(q
+            .parse_item::<String>(b"route6: 192.0.2.0/24\norigin: AS65000\n\n", false)
+            .is_err());
+        let q = Query::RpslObject(RpslObjectClass::Route6, "2001:db8::/32AS65000".to_string());
+        assert!(q.parse_item::<String>(route, false).is_ok());
+    }
+
+    #[test]
+    fn display_describes_intent() {
+        for (q, display) in [
+            (
+                Query::AsSetMembersRecursive("AS-FOO".parse().unwrap()),
+                "recursive members of AS-FOO",
+            ),
+            (
+                Query::Ipv4Routes("AS65000".parse().unwrap()),
+                "IPv4 routes originated by AS65000",
+            ),
+            (
+                Query::RpslObject(RpslObjectClass::AutNum, "AS65000".to_string()),
+                "aut-num object AS65000",
+            ),
+            (
+                Query::routes_more_bounded("192.0.2.0/24", 28).unwrap(),
+                "routes more-specific than 192.0.2.0/24, with length at most 28",
+            ),
+            (
+                Query::SetSources(vec!["RADB".to_string(), "RIPE".to_string()]),
+                "select sources RADB,RIPE",
+            ),
+        ] {
+            assert_eq!(q.to_string(), display);
+            assert_ne!(q.to_string(), q.cmd().trim_end());
+        }
+    }
+
+    #[test]
+    fn commands_are_parsed() {
+        for (cmd, query) in [
+            ("!v\n", Query::Version),
+            ("!s-lc", Query::GetSources),
+            ("!s-*", Query::UnsetSources),
+            (
+                "!sRADB,RIPE",
+                Query::SetSources(vec!["RADB".to_string(), "RIPE".to_string()]),
+            ),
+            ("!t90", Query::SetTimeout(Duration::from_secs(90))),
+            (
+                "!iAS-FOO,1",
+                Query::AsSetMembersRecursive("AS-FOO".parse().unwrap()),
+            ),
+            (
+                "!iRS-FOO",
+                Query::RouteSetMembers("RS-FOO".parse().unwrap()),
+            ),
+            (
+                "!a6AS-FOO",
+                Query::AsSetPrefixes("AS-FOO".parse().unwrap(), Some(AddressFamily::Ipv6)),
+            ),
+            ("!gAS65000", Query::Ipv4Routes("AS65000".parse().unwrap())),
+            (
+                "!maut-num,AS65000",
+                Query::RpslObject(RpslObjectClass::AutNum, "AS65000".to_string()),
+            ),
+            (
+                "!mas-block,AS1-AS2",
+                Query::AsBlock("AS1-AS2".parse().unwrap()),
+            ),
+            (
+                "!r192.0.2.0/24,L",
+                Query::RoutesLessEqual("192.0.2.0/24".to_string()),
+            ),
+            (
+                "!r192.0.2.0/24,o",
+                Query::Origins("192.0.2.0/24".to_string()),
+            ),
+        ] {
+            assert_eq!(Query::try_from(cmd).unwrap(), query, "{cmd:?}");
+        }
+    }
+
+    #[test]
+    fn unrecognized_commands_are_rejected() {
+        for cmd in [
+            "",
+            "!",
+            "v",
+            "!x",
+            "!vfoo",
+            "!tfoo",
+            "!mfoo,bar",
+            "!r192.0.2.0/24,x",
+        ] {
+            assert!(
+                matches!(Query::try_from(cmd), Err(Error::UnrecognizedCommand(_))),
+                "{cmd:?}"
+            );
+        }
+        assert!(matches!(
+            Query::try_from("!gfoo"),
+            Err(Error::InvalidQueryInput(_))
+        ));
+    }
+
     #[test]
     fn query_is_singleton_iterator() {
         let q = Query::Version;
@@ -232,26 +2025,127 @@ mod tests {
                     Just(Self::GetSources),
                     any::<Vec<String>>().prop_map(Self::SetSources),
                     Just(Self::UnsetSources),
+                    any::<String>().prop_map(Self::JournalStatus),
                     any::<AsSet>().prop_map(Self::AsSetMembers),
                     any::<AsSet>().prop_map(Self::AsSetMembersRecursive),
                     any::<RouteSet>().prop_map(Self::RouteSetMembers),
                     any::<RouteSet>().prop_map(Self::RouteSetMembersRecursive),
+                    (
+                        any::<AsSet>(),
+                        prop_oneof![
+                            Just(None),
+                            Just(Some(AddressFamily::Ipv4)),
+                            Just(Some(AddressFamily::Ipv6)),
+                        ]
+                    )
+                        .prop_map(|(as_set, family)| Self::AsSetPrefixes(as_set, family)),
                     any::<AutNum>().prop_map(Self::Ipv4Routes),
                     any::<AutNum>().prop_map(Self::Ipv6Routes),
                     any::<(RpslObjectClass, String)>()
                         .prop_map(|(class, object)| Self::RpslObject(class, object)),
+                    (any::<u32>(), any::<u32>()).prop_map(|(start, end)| Self::AsBlock(
+                        format!("AS{}-AS{}", start.min(end), start.max(end))
+                            .parse()
+                            .unwrap()
+                    )),
                     any::<Mntner>().prop_map(Self::MntBy),
                     any::<String>().prop_map(Self::Origins),
                     any::<String>().prop_map(Self::RoutesExact),
                     any::<String>().prop_map(Self::RoutesLess),
                     any::<String>().prop_map(Self::RoutesLessEqual),
                     any::<String>().prop_map(Self::RoutesMore),
+                    any::<(String, u8)>()
+                        .prop_map(|(prefix, len)| Self::RoutesLessBounded(prefix, len)),
+                    any::<(String, u8)>()
+                        .prop_map(|(prefix, len)| Self::RoutesMoreBounded(prefix, len)),
+                    // `!g` is excluded, as it would be mistaken for an IPv4 query
+                    "![a-fh-z][ -~]{0,15}".prop_map(Self::Raw),
                 ]
                 .boxed()
             }
         }
 
+        /// Queries whose command is not shared with another variant, and
+        /// whose values survive being written to and parsed from a command.
+        fn round_trippable() -> impl Strategy<Value = Query> {
+            let word = "[A-Z0-9][A-Z0-9-]{0,15}";
+            let as_set =
+                "(AS[0-9]{1,5}:)?AS-[A-Z0-9]{1,10}".prop_map(|name| name.parse::<AsSet>().unwrap());
+            let route_set = "(AS[0-9]{1,5}:)?RS-[A-Z0-9]{1,10}"
+                .prop_map(|name| name.parse::<RouteSet>().unwrap());
+            let prefix = (any::<std::net::Ipv4Addr>(), 0..=32u8)
+                .prop_map(|(addr, len)| format!("{addr}/{len}"));
+            let class = any::<RpslObjectClass>()
+                .prop_filter("as-block keys may parse as Query::AsBlock", |class| {
+                    *class != RpslObjectClass::AsBlock
+                });
+            prop_oneof![
+                Just(Query::Version),
+                word.prop_map(Query::SetClientId),
+                any::<u32>().prop_map(|secs| Query::SetTimeout(Duration::from_secs(secs.into()))),
+                Just(Query::GetSources),
+                proptest::collection::vec("[A-Z][A-Z0-9]{0,7}", 0..4).prop_map(Query::SetSources),
+                Just(Query::UnsetSources),
+                word.prop_map(Query::JournalStatus),
+                as_set.clone().prop_map(Query::AsSetMembers),
+                as_set.clone().prop_map(Query::AsSetMembersRecursive),
+                route_set.clone().prop_map(Query::RouteSetMembers),
+                route_set.prop_map(Query::RouteSetMembersRecursive),
+                (
+                    as_set,
+                    prop_oneof![
+                        Just(None),
+                        Just(Some(AddressFamily::Ipv4)),
+                        Just(Some(AddressFamily::Ipv6)),
+                    ]
+                )
+                    .prop_map(|(as_set, family)| Query::AsSetPrefixes(as_set, family)),
+                any::<AutNum>().prop_map(Query::Ipv4Routes),
+                any::<AutNum>().prop_map(Query::Ipv6Routes),
+                (class, word).prop_map(|(class, key)| Query::RpslObject(class, key)),
+                (any::<u32>(), any::<u32>()).prop_map(|(start, end)| Query::AsBlock(
+                    format!("AS{}-AS{}", start.min(end), start.max(end))
+                        .parse()
+                        .unwrap()
+                )),
+                any::<Mntner>().prop_map(Query::MntBy),
+                prefix.clone().prop_map(Query::Origins),
+                prefix.clone().prop_map(Query::RoutesExact),
+                prefix.clone().prop_map(Query::RoutesLess),
+                prefix.clone().prop_map(Query::RoutesLessEqual),
+                prefix.prop_map(Query::RoutesMore),
+            ]
+        }
+
         proptest! {
+            #[test]
+            fn cmd_round_trips(q in round_trippable()) {
+                assert_eq!(Query::try_from(q.cmd().trim()).unwrap(), q);
+            }
+
+            #[test]
+            fn try_from_never_panics(cmd in any::<String>()) {
+                _ = Query::try_from(cmd.as_str());
+            }
+
+            #[test]
+            fn canonical_form_is_idempotent(q in any::<Query>()) {
+                let canonical = q.canonical();
+                assert_eq!(canonical.canonical(), canonical);
+            }
+
+            #[test]
+            fn valid_ipv4_prefix_is_accepted(addr in any::<std::net::Ipv4Addr>(), len in 0..=32u8) {
+                let prefix = format!("{addr}/{len}");
+                assert_eq!(Query::routes_more(&prefix).unwrap().cmd(), format!("!r{prefix},M\n"));
+            }
+
+            #[test]
+            fn valid_ipv6_prefix_is_accepted(addr in any::<std::net::Ipv6Addr>(), len in 0..=128u8) {
+                let prefix = format!("{addr}/{len}");
+                assert_eq!(Query::routes_less(&prefix).unwrap().cmd(), format!("!r{prefix},l\n"));
+            }
+
             #[test]
             fn cmd_begins_with_bang(q in any::<Query>()) {
                 assert!(q.cmd().starts_with('!'));
@@ -262,10 +2156,42 @@ mod tests {
                 assert!(q.cmd().ends_with('\n'));
             }
 
+            #[test]
+            fn address_family_matches_cmd(q in any::<Query>()) {
+                let cmd = q.cmd();
+                match q.address_family() {
+                    Some(AddressFamily::Ipv4) => assert!(cmd.starts_with("!g") || cmd.starts_with("!a4")),
+                    Some(AddressFamily::Ipv6) => assert!(cmd.starts_with("!6") || cmd.starts_with("!a6")),
+                    None => assert!(!cmd.starts_with("!g") && !cmd.starts_with("!6")),
+                }
+            }
+
+            #[test]
+            fn routes_query_has_requested_family(autnum in any::<AutNum>()) {
+                for family in AddressFamily::ALL {
+                    assert_eq!(Query::routes(family, autnum).address_family(), Some(family));
+                }
+            }
+
+            #[test]
+            fn family_specific_queries_return_prefixes(q in any::<Query>()) {
+                if q.address_family().is_some() {
+                    assert!(q.returns_prefixes());
+                }
+            }
+
+            #[test]
+            fn prefix_queries_do_not_return_objects(q in any::<Query>()) {
+                if q.returns_prefixes() {
+                    assert!(q.expect_data());
+                    assert!(!q.returns_objects());
+                }
+            }
+
             #[test]
             #[allow(unused_must_use)]
             fn parse_item_never_panics(q in any::<Query>(), input in any::<Vec<u8>>()) {
-                q.parse_item::<String>(&input);
+                q.parse_item::<String>(&input, false);
             }
         }
     }
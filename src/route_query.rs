@@ -0,0 +1,259 @@
+use crate::{error::Error, prefix::RangeOperator, query::Query};
+
+/// Builder for `!r` route searches by IP prefix.
+///
+/// Each combination of options corresponds to one of the `Routes*` variants
+/// of [`Query`], which can still be constructed directly. The builder checks
+/// that the options are compatible, and selects the variant, when
+/// [`build()`][Self::build] is called.
+///
+/// | Options                                                | Query                           |
+/// |--------------------------------------------------------|---------------------------------|
+/// | (none), or [`exact()`][Self::exact]                    | [`Query::RoutesExact`]          |
+/// | [`less_specifics()`][Self::less_specifics]             | [`Query::RoutesLess`]           |
+/// | [`less_specifics_or_exact()`][Self::less_specifics_or_exact] | [`Query::RoutesLessEqual`] |
+/// | ... and [`min_length()`][Self::min_length]             | [`Query::RoutesLessBounded`]    |
+/// | [`more_specifics()`][Self::more_specifics]             | [`Query::RoutesMore`]           |
+/// | ... and [`max_length()`][Self::max_length] or [`range()`][Self::range] | [`Query::RoutesMoreBounded`] |
+///
+/// # Example
+///
+/// ```
+/// use irrc::{Query, RouteQuery};
+///
+/// let query = RouteQuery::new("192.0.2.0/22").more_specifics().max_length(24).build()?;
+/// assert_eq!(query, Query::routes_more_bounded("192.0.2.0/22", 24)?);
+///
+/// assert!(RouteQuery::new("192.0.2.0/22")
+///     .less_specifics()
+///     .more_specifics()
+///     .build()
+///     .is_err());
+/// # Ok::<(), irrc::Error>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct RouteQuery {
+    prefix: String,
+    direction: Option<Direction>,
+    conflicting: bool,
+    min_len: Option<u8>,
+    max_len: Option<u8>,
+    range: Option<RangeOperator>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Direction {
+    Exact,
+    Less,
+    LessEqual,
+    More,
+}
+
+impl RouteQuery {
+    /// Begin a route search for `prefix`.
+    ///
+    /// The prefix is validated by [`build()`][Self::build].
+    #[must_use]
+    pub fn new<P: AsRef<str>>(prefix: P) -> Self {
+        Self {
+            prefix: prefix.as_ref().to_string(),
+            direction: None,
+            conflicting: false,
+            min_len: None,
+            max_len: None,
+            range: None,
+        }
+    }
+
+    /// Search for exact matches of the prefix only.
+    ///
+    /// This is the default.
+    pub fn exact(&mut self) -> &mut Self {
+        self.direction(Direction::Exact)
+    }
+
+    /// Search for the first level of less-specifics of the prefix,
+    /// excluding exact matches.
+    pub fn less_specifics(&mut self) -> &mut Self {
+        self.direction(Direction::Less)
+    }
+
+    /// Search for all less-specifics of the prefix, including exact matches.
+    pub fn less_specifics_or_exact(&mut self) -> &mut Self {
+        self.direction(Direction::LessEqual)
+    }
+
+    /// Search for the first level of more-specifics of the prefix, excluding
+    /// exact matches.
+    pub fn more_specifics(&mut self) -> &mut Self {
+        self.direction(Direction::More)
+    }
+
+    /// Skip less-specifics with a prefix length shorter than `min_len`.
+    ///
+    /// Only valid with [`less_specifics_or_exact()`][Self::less_specifics_or_exact].
+    pub const fn min_length(&mut self, min_len: u8) -> &mut Self {
+        self.min_len = Some(min_len);
+        self
+    }
+
+    /// Skip more-specifics with a prefix length longer than `max_len`.
+    ///
+    /// Only valid with [`more_specifics()`][Self::more_specifics], and not
+    /// together with [`range()`][Self::range].
+    pub const fn max_length(&mut self, max_len: u8) -> &mut Self {
+        self.max_len = Some(max_len);
+        self
+    }
+
+    /// Select the more-specifics given by the range operator `op`.
+    ///
+    /// Only valid with [`more_specifics()`][Self::more_specifics], and not
+    /// together with [`max_length()`][Self::max_length]. See
+    /// [`Query::routes_more_range()`] for the operators that can be used.
+    pub const fn range(&mut self, op: RangeOperator) -> &mut Self {
+        self.range = Some(op);
+        self
+    }
+
+    fn direction(&mut self, direction: Direction) -> &mut Self {
+        self.conflicting |= self.direction.is_some_and(|prev| prev != direction);
+        self.direction = Some(direction);
+        self
+    }
+
+    /// Construct the [`Query`].
+    ///
+    /// # Errors
+    ///
+    /// An [`Error::InvalidRouteQuery`] is returned if more than one search
+    /// direction was selected, or if a length bound or range operator was set
+    /// that cannot be used with the selected direction.
+    ///
+    /// Otherwise, any error returned by the corresponding [`Query`]
+    /// constructor, such as [`Error::InvalidPrefix`] or
+    /// [`Error::InvalidLengthBound`], is returned.
+    pub fn build(&self) -> Result<Query, Error> {
+        let invalid = |reason: &str| {
+            Err(Error::InvalidRouteQuery(format!(
+                "{}: {reason}",
+                self.prefix
+            )))
+        };
+        if self.conflicting {
+            return invalid("more than one search direction selected");
+        }
+        let prefix = &self.prefix;
+        match (
+            self.direction.unwrap_or(Direction::Exact),
+            self.min_len,
+            self.max_len,
+            self.range,
+        ) {
+            (Direction::LessEqual, Some(min_len), None, None) => {
+                Query::routes_less_bounded(prefix, min_len)
+            }
+            (Direction::More, None, Some(max_len), None) => {
+                Query::routes_more_bounded(prefix, max_len)
+            }
+            (Direction::More, None, None, Some(op)) => Query::routes_more_range(prefix, op),
+            (Direction::More, None, Some(_), Some(_)) => {
+                invalid("maximum length and range operator are mutually exclusive")
+            }
+            (_, Some(_), _, _) => {
+                invalid("minimum length requires less-specifics including exact matches")
+            }
+            (_, _, Some(_), _) | (_, _, _, Some(_)) => {
+                invalid("maximum length or range operator requires more-specifics")
+            }
+            (Direction::Exact, None, None, None) => Query::routes_exact(prefix),
+            (Direction::Less, None, None, None) => Query::routes_less(prefix),
+            (Direction::LessEqual, None, None, None) => Query::routes_less_equal(prefix),
+            (Direction::More, None, None, None) => Query::routes_more(prefix),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn options_select_query_variant() {
+        let prefix = "192.0.2.0/22";
+        for (query, expected) in [
+            (RouteQuery::new(prefix).build(), Query::routes_exact(prefix)),
+            (
+                RouteQuery::new(prefix).less_specifics().build(),
+                Query::routes_less(prefix),
+            ),
+            (
+                RouteQuery::new(prefix).less_specifics_or_exact().build(),
+                Query::routes_less_equal(prefix),
+            ),
+            (
+                RouteQuery::new(prefix)
+                    .less_specifics_or_exact()
+                    .min_length(16)
+                    .build(),
+                Query::routes_less_bounded(prefix, 16),
+            ),
+            (
+                RouteQuery::new(prefix).more_specifics().build(),
+                Query::routes_more(prefix),
+            ),
+            (
+                RouteQuery::new(prefix)
+                    .more_specifics()
+                    .max_length(24)
+                    .build(),
+                Query::routes_more_bounded(prefix, 24),
+            ),
+            (
+                RouteQuery::new(prefix)
+                    .more_specifics()
+                    .range(RangeOperator::ExclusiveMoreSpecifics)
+                    .build(),
+                Query::routes_more_bounded(prefix, 32),
+            ),
+        ] {
+            assert_eq!(query.unwrap(), expected.unwrap());
+        }
+    }
+
+    #[test]
+    fn incompatible_options_are_rejected() {
+        let prefix = "192.0.2.0/22";
+        for builder in [
+            RouteQuery::new(prefix).less_specifics().more_specifics(),
+            RouteQuery::new(prefix).exact().less_specifics_or_exact(),
+            RouteQuery::new(prefix).min_length(16),
+            RouteQuery::new(prefix).less_specifics().min_length(16),
+            RouteQuery::new(prefix).more_specifics().min_length(16),
+            RouteQuery::new(prefix).max_length(24),
+            RouteQuery::new(prefix)
+                .less_specifics_or_exact()
+                .max_length(24),
+            RouteQuery::new(prefix)
+                .more_specifics()
+                .max_length(24)
+                .range(RangeOperator::Length(23)),
+        ] {
+            assert!(
+                matches!(builder.build(), Err(Error::InvalidRouteQuery(_))),
+                "{builder:?}"
+            );
+        }
+        assert!(matches!(
+            RouteQuery::new(prefix)
+                .more_specifics()
+                .max_length(33)
+                .build(),
+            Err(Error::InvalidLengthBound(..))
+        ));
+        assert!(matches!(
+            RouteQuery::new("foo").build(),
+            Err(Error::InvalidPrefix(_))
+        ));
+    }
+}
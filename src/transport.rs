@@ -0,0 +1,582 @@
+use std::fmt;
+use std::io::{self, Read, Write};
+#[cfg(feature = "std-net")]
+use std::net::{Shutdown, SocketAddr, TcpStream, ToSocketAddrs};
+#[cfg(feature = "std-net")]
+use std::sync::mpsc;
+#[cfg(feature = "std-net")]
+use std::thread;
+use std::time::Duration;
+
+#[cfg(feature = "std-net")]
+use crate::error::Error;
+
+/// Delay between starting successive connection attempts.
+///
+/// See [RFC 8305 section 5](https://www.rfc-editor.org/rfc/rfc8305#section-5).
+#[cfg(feature = "std-net")]
+const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Time after which an individual connection attempt is abandoned.
+#[cfg(feature = "std-net")]
+const CONNECTION_ATTEMPT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A bi-directional byte stream over which queries are sent and responses
+/// received.
+pub(crate) trait Transport: Read + Write + fmt::Debug + Send {
+    /// Shut down both halves of the underlying stream.
+    fn shutdown(&mut self) -> io::Result<()>;
+
+    /// Set the timeout for blocking reads from the underlying stream, where
+    /// supported.
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()>;
+
+    /// Set the timeout for blocking writes to the underlying stream, where
+    /// supported.
+    fn set_write_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+#[cfg(feature = "std-net")]
+impl Transport for TcpStream {
+    fn shutdown(&mut self) -> io::Result<()> {
+        Self::shutdown(self, Shutdown::Both)
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        Self::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        Self::set_write_timeout(self, timeout)
+    }
+}
+
+/// Adaptor allowing an arbitrary [`Read`] + [`Write`] stream to be used as a
+/// [`Transport`].
+#[derive(Debug)]
+pub(crate) struct Stream<S>(pub(crate) S);
+
+impl<S: Read> Read for Stream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl<S: Write> Write for Stream<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl<S> Transport for Stream<S>
+where
+    S: Read + Write + fmt::Debug + Send,
+{
+    fn shutdown(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+
+    fn set_read_timeout(&mut self, _: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn set_write_timeout(&mut self, _: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Transport replaying previously recorded server output, and discarding
+/// anything written to it.
+#[derive(Debug)]
+pub(crate) struct Replay<R>(pub(crate) R);
+
+impl<R: Read> Read for Replay<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl<R> Write for Replay<R> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<R> Transport for Replay<R>
+where
+    R: Read + fmt::Debug + Send,
+{
+    fn shutdown(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn set_read_timeout(&mut self, _: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn set_write_timeout(&mut self, _: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Transport wrapper that copies everything read from the inner transport to
+/// `sink`.
+///
+/// Failures writing to `sink` are logged, and stop the recording, but do not
+/// affect the inner transport: the bytes read from it must still be returned,
+/// so that the framing of the responses is not lost.
+#[derive(Debug)]
+pub(crate) struct Record<W> {
+    inner: Box<dyn Transport>,
+    sink: W,
+    failed: bool,
+}
+
+impl<W> Record<W> {
+    pub(crate) const fn new(inner: Box<dyn Transport>, sink: W) -> Self {
+        Self {
+            inner,
+            sink,
+            failed: false,
+        }
+    }
+}
+
+impl<W: Write> Read for Record<W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if !self.failed {
+            if let Err(err) = self.sink.write_all(&buf[..n]) {
+                tracing::error!("failed to write recording, stopping recording: {err}");
+                self.failed = true;
+            }
+        }
+        Ok(n)
+    }
+}
+
+impl<W> Write for Record<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W> Transport for Record<W>
+where
+    W: Write + fmt::Debug + Send,
+{
+    fn shutdown(&mut self) -> io::Result<()> {
+        if !self.failed {
+            if let Err(err) = self.sink.flush() {
+                tracing::error!("failed to flush recording: {err}");
+            }
+        }
+        self.inner.shutdown()
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.inner.set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.inner.set_write_timeout(timeout)
+    }
+}
+
+/// Establish a TCP connection to `addr`, racing connection attempts to each
+/// resolved address in the manner of "Happy Eyeballs" ([RFC 8305]).
+///
+/// Resolved addresses are ordered alternating between IPv6 and IPv4,
+/// beginning with IPv6. A connection attempt to each address is started
+/// either once the previous attempt fails, or after
+/// [`CONNECTION_ATTEMPT_DELAY`], whichever is sooner. The first connection
+/// to succeed is used, and any others are closed as they complete.
+///
+/// Each attempt is abandoned after [`CONNECTION_ATTEMPT_TIMEOUT`], so that
+/// the threads making attempts that are still outstanding once a connection
+/// has been established do not linger.
+///
+/// IPv6 link-local addresses are only usable with a zone index, such as
+/// `[fe80::1%eth0]:43`. Any link-local address resolved without one is
+/// reported as a failed attempt, rather than being tried.
+///
+/// [RFC 8305]: https://www.rfc-editor.org/rfc/rfc8305
+#[cfg(feature = "std-net")]
+#[tracing::instrument(skip(addr), level = "debug")]
+pub(crate) fn connect_tcp<A>(addr: &A) -> Result<TcpStream, Error>
+where
+    A: ToSocketAddrs + fmt::Display,
+{
+    let mut addrs = interleave(resolve(addr)?).into_iter();
+    let (tx, rx) = mpsc::channel();
+    let mut pending = 0usize;
+    let mut errors = Vec::new();
+    loop {
+        if let Some(addr) = addrs.next() {
+            if is_unscoped_link_local(&addr) {
+                tracing::debug!("skipping link-local address {addr} without a zone index");
+                let err = io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "link-local address requires a zone index",
+                );
+                errors.push((addr, err));
+                continue;
+            }
+            tracing::debug!("trying to connect to {addr}");
+            let tx = tx.clone();
+            _ = thread::spawn(move || {
+                // the receiver will have gone away if another attempt has
+                // already succeeded, in which case the stream is dropped.
+                _ = tx.send((
+                    addr,
+                    TcpStream::connect_timeout(&addr, CONNECTION_ATTEMPT_TIMEOUT),
+                ));
+            });
+            pending += 1;
+        }
+        if pending == 0 {
+            break;
+        }
+        let attempt = if addrs.as_slice().is_empty() {
+            rx.recv().ok()
+        } else {
+            rx.recv_timeout(CONNECTION_ATTEMPT_DELAY).ok()
+        };
+        if let Some((addr, result)) = attempt {
+            pending -= 1;
+            match result {
+                Ok(stream) => {
+                    tracing::debug!("connected to {addr}");
+                    return Ok(stream);
+                }
+                Err(err) => {
+                    tracing::debug!("failed to connect to {addr}: {err}");
+                    errors.push((addr, err));
+                }
+            }
+        }
+    }
+    Err(Error::Connect(errors))
+}
+
+/// Resolve `addr` to the socket addresses to which connections are
+/// attempted.
+///
+/// The standard library cannot resolve a bracketed IPv6 address whose zone
+/// index is an interface name, such as `[fe80::1%eth0]:43`, so such an
+/// address is resolved again with the brackets removed.
+#[cfg(feature = "std-net")]
+fn resolve<A>(addr: &A) -> Result<Vec<SocketAddr>, Error>
+where
+    A: ToSocketAddrs + fmt::Display,
+{
+    let addrs: Vec<_> = match addr.to_socket_addrs() {
+        Ok(addrs) => addrs.collect(),
+        Err(err) => {
+            let addr = addr.to_string();
+            match split_zoned(&addr) {
+                Some(host_port) => host_port.to_socket_addrs()?.collect(),
+                None => return Err(err.into()),
+            }
+        }
+    };
+    if addrs.is_empty() {
+        return Err(Error::NoAddresses(addr.to_string()));
+    }
+    Ok(addrs)
+}
+
+/// Split an address of the form `[host%zone]:port` into the host, including
+/// the zone index, and the port.
+#[cfg(feature = "std-net")]
+fn split_zoned(addr: &str) -> Option<(&str, u16)> {
+    let (host, port) = addr.strip_prefix('[')?.rsplit_once("]:")?;
+    if !host.contains('%') {
+        return None;
+    }
+    Some((host, port.parse().ok()?))
+}
+
+/// Whether `addr` is an IPv6 link-local address without a zone index.
+#[cfg(feature = "std-net")]
+const fn is_unscoped_link_local(addr: &SocketAddr) -> bool {
+    match addr {
+        SocketAddr::V6(addr) => addr.ip().is_unicast_link_local() && addr.scope_id() == 0,
+        SocketAddr::V4(_) => false,
+    }
+}
+
+/// Order `addrs` alternating between address families, beginning with IPv6.
+#[cfg(feature = "std-net")]
+fn interleave<I>(addrs: I) -> Vec<SocketAddr>
+where
+    I: IntoIterator<Item = SocketAddr>,
+{
+    let (v6, v4): (Vec<_>, Vec<_>) = addrs.into_iter().partition(SocketAddr::is_ipv6);
+    let (mut v6, mut v4) = (v6.into_iter(), v4.into_iter());
+    let mut ordered = Vec::new();
+    loop {
+        match (v6.next(), v4.next()) {
+            (None, None) => break ordered,
+            (first, second) => ordered.extend(first.into_iter().chain(second)),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std-net"))]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn addresses_are_interleaved() {
+        let addrs = [
+            "192.0.2.1:43",
+            "192.0.2.2:43",
+            "[2001:db8::1]:43",
+            "192.0.2.3:43",
+        ]
+        .map(|addr| addr.parse().unwrap());
+        let expected = [
+            "[2001:db8::1]:43",
+            "192.0.2.1:43",
+            "192.0.2.2:43",
+            "192.0.2.3:43",
+        ]
+        .map(|addr| addr.parse().unwrap());
+        assert_eq!(interleave(addrs), expected);
+    }
+
+    #[test]
+    fn connects_to_listening_address() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let stream = connect_tcp(&addr).unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), addr);
+    }
+
+    #[test]
+    fn failures_are_collected() {
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        match connect_tcp(&addr) {
+            Err(Error::Connect(errors)) => {
+                assert_eq!(errors.len(), 1);
+                assert_eq!(errors[0].0, addr);
+            }
+            result => panic!("expected connection failure, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn zoned_addresses_are_split() {
+        assert_eq!(split_zoned("[fe80::1%eth0]:43"), Some(("fe80::1%eth0", 43)));
+        assert_eq!(split_zoned("[2001:db8::1]:43"), None);
+        assert_eq!(split_zoned("whois.radb.net:43"), None);
+        assert_eq!(split_zoned("[fe80::1%eth0]"), None);
+    }
+
+    #[test]
+    fn unscoped_link_local_addresses_are_not_tried() {
+        let addr = "[fe80::1]:43".parse().unwrap();
+        match connect_tcp(&addr) {
+            Err(Error::Connect(errors)) => {
+                assert_eq!(errors.len(), 1);
+                assert_eq!(errors[0].0, addr);
+                assert_eq!(errors[0].1.kind(), io::ErrorKind::InvalidInput);
+            }
+            result => panic!("expected connection failure, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn empty_resolution_is_reported() {
+        assert!(matches!(resolve(&Unresolvable), Err(Error::NoAddresses(_))));
+    }
+
+    /// An address that resolves to no socket addresses.
+    struct Unresolvable;
+
+    impl ToSocketAddrs for Unresolvable {
+        type Iter = std::option::IntoIter<SocketAddr>;
+
+        fn to_socket_addrs(&self) -> io::Result<Self::Iter> {
+            Ok(None.into_iter())
+        }
+    }
+
+    impl fmt::Display for Unresolvable {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "unresolvable.example:43")
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod mock {
+    use std::collections::VecDeque;
+    use std::io::{self, Cursor, Read, Write};
+    use std::sync::{Arc, Mutex};
+
+    /// In-memory stream that replays canned server output and captures
+    /// everything written to it.
+    #[derive(Debug)]
+    pub(crate) struct Mock {
+        input: Cursor<Vec<u8>>,
+        output: Arc<Mutex<Vec<u8>>>,
+        responses: VecDeque<Vec<u8>>,
+        reply: Option<Vec<u8>>,
+        received: Vec<u8>,
+        window: usize,
+        reset: bool,
+    }
+
+    impl Mock {
+        pub(crate) fn new(input: &[u8]) -> Self {
+            Self {
+                input: Cursor::new(input.to_vec()),
+                output: Arc::default(),
+                responses: VecDeque::new(),
+                reply: None,
+                received: Vec::new(),
+                window: usize::MAX,
+                reset: false,
+            }
+        }
+
+        /// Construct a stream that makes each of `responses` available to
+        /// read only once a further query has been written, as a server
+        /// would.
+        ///
+        /// Unlike [`new()`][Self::new], this allows responses to be read by
+        /// successive [`Pipeline`][crate::Pipeline]s, each of which discards
+        /// any data left in its read buffer when dropped.
+        pub(crate) fn responding(responses: &[&[u8]]) -> Self {
+            Self {
+                input: Cursor::default(),
+                output: Arc::default(),
+                responses: responses.iter().map(|response| response.to_vec()).collect(),
+                reply: None,
+                received: Vec::new(),
+                window: usize::MAX,
+                reset: false,
+            }
+        }
+
+        /// Construct a stream that answers every query with `reply`, as a
+        /// server whose receive and send buffers each hold at most `window`
+        /// bytes.
+        ///
+        /// The server only reads a further query while its unread responses
+        /// fit within `window`. A write that would overfill the receive
+        /// buffer, or a read with no response available, fails with
+        /// [`WouldBlock`][io::ErrorKind::WouldBlock], as a blocking operation
+        /// with a timeout would.
+        pub(crate) fn windowed(reply: &[u8], window: usize) -> Self {
+            Self {
+                input: Cursor::default(),
+                output: Arc::default(),
+                responses: VecDeque::new(),
+                reply: Some(reply.to_vec()),
+                received: Vec::new(),
+                window,
+                reset: false,
+            }
+        }
+
+        /// Construct a stream that replays `input`, and then fails reads as
+        /// if the connection had been reset by the server.
+        pub(crate) fn resetting(input: &[u8]) -> Self {
+            Self {
+                reset: true,
+                ..Self::new(input)
+            }
+        }
+
+        /// Number of response bytes not yet read by the client.
+        fn unread(&self) -> usize {
+            let read = usize::try_from(self.input.position()).unwrap();
+            self.input.get_ref().len() - read
+        }
+
+        /// Answer received queries while there is room for the responses.
+        fn serve(&mut self) {
+            let Some(reply) = &self.reply else {
+                return;
+            };
+            while self.unread() < self.window {
+                let Some(end) = self.received.iter().position(|&b| b == b'\n') else {
+                    break;
+                };
+                _ = self.received.drain(..=end);
+                self.input.get_mut().extend(reply);
+            }
+        }
+
+        /// Get a handle to the bytes written to this stream.
+        pub(crate) fn output(&self) -> Arc<Mutex<Vec<u8>>> {
+            Arc::clone(&self.output)
+        }
+    }
+
+    impl Read for Mock {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.reply.is_none() {
+                return match self.input.read(buf)? {
+                    0 if self.reset && !buf.is_empty() => {
+                        Err(io::ErrorKind::ConnectionReset.into())
+                    }
+                    read => Ok(read),
+                };
+            }
+            self.serve();
+            match self.input.read(buf)? {
+                0 if !buf.is_empty() => Err(io::ErrorKind::WouldBlock.into()),
+                read => {
+                    self.serve();
+                    Ok(read)
+                }
+            }
+        }
+    }
+
+    impl Write for Mock {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.reply.is_some() {
+                let room = self.window.saturating_sub(self.received.len());
+                if room == 0 {
+                    return Err(io::ErrorKind::WouldBlock.into());
+                }
+                let accepted = &buf[..buf.len().min(room)];
+                self.received.extend(accepted);
+                self.serve();
+                return self.output.lock().unwrap().write(accepted);
+            }
+            for _ in buf.iter().filter(|&&b| b == b'\n') {
+                if let Some(response) = self.responses.pop_front() {
+                    self.input.get_mut().extend(response);
+                }
+            }
+            self.output.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+}
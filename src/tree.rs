@@ -0,0 +1,200 @@
+use std::collections::{HashMap, HashSet};
+
+use rpsl::{
+    expr::AsSetMember,
+    names::{AsSet, AutNum},
+};
+
+use crate::{
+    error::{self, Error},
+    pipeline::Pipeline,
+    query::Query,
+};
+
+/// The hierarchy of `as-set`s referenced by an `as-set`.
+///
+/// Constructed by [`Pipeline::as_set_tree()`] or
+/// [`Connection::as_set_tree()`][crate::Connection::as_set_tree]. See the
+/// method documentation for details.
+#[derive(Clone, Debug)]
+pub struct AsSetTree {
+    root: AsSet,
+    nodes: Vec<(AsSet, AsSetMembership)>,
+    index: HashMap<String, usize>,
+    unresolved: Vec<AsSet>,
+}
+
+impl AsSetTree {
+    fn new(root: AsSet) -> Self {
+        Self {
+            root,
+            nodes: Vec::new(),
+            index: HashMap::new(),
+            unresolved: Vec::new(),
+        }
+    }
+
+    fn key(set: &AsSet) -> String {
+        set.to_string().to_ascii_uppercase()
+    }
+
+    fn insert(&mut self, set: AsSet, members: AsSetMembership) {
+        _ = self.index.insert(Self::key(&set), self.nodes.len());
+        self.nodes.push((set, members));
+    }
+
+    /// The `as-set` from which the tree was expanded.
+    #[must_use]
+    pub const fn root(&self) -> &AsSet {
+        &self.root
+    }
+
+    /// The direct members of `set`, if it was encountered during expansion.
+    #[must_use]
+    pub fn members(&self, set: &AsSet) -> Option<&AsSetMembership> {
+        self.index.get(&Self::key(set)).map(|&i| &self.nodes[i].1)
+    }
+
+    /// Iterate over each `as-set` encountered during expansion, together with
+    /// its direct members.
+    ///
+    /// Sets are visited in breadth-first order, beginning with the
+    /// [`root()`][Self::root].
+    pub fn sets(&self) -> impl Iterator<Item = (&AsSet, &AsSetMembership)> {
+        self.nodes.iter().map(|(set, members)| (set, members))
+    }
+
+    /// The `as-set`s referenced during expansion that do not exist on the
+    /// server.
+    #[must_use]
+    pub fn unresolved(&self) -> &[AsSet] {
+        &self.unresolved
+    }
+
+    /// All of the `aut-num`s that are members of any `as-set` in the tree,
+    /// without duplicates.
+    #[must_use]
+    pub fn autnums(&self) -> Vec<AutNum> {
+        let mut seen = HashSet::new();
+        self.nodes
+            .iter()
+            .flat_map(|(_, members)| members.autnums())
+            .filter(|autnum| seen.insert(**autnum))
+            .copied()
+            .collect()
+    }
+}
+
+/// The direct members of an `as-set`.
+#[derive(Clone, Debug, Default)]
+pub struct AsSetMembership {
+    autnums: Vec<AutNum>,
+    sets: Vec<AsSet>,
+}
+
+impl AsSetMembership {
+    /// The `aut-num` members of the `as-set`.
+    #[must_use]
+    pub fn autnums(&self) -> &[AutNum] {
+        &self.autnums
+    }
+
+    /// The `as-set` members of the `as-set`.
+    #[must_use]
+    pub fn sets(&self) -> &[AsSet] {
+        &self.sets
+    }
+}
+
+#[tracing::instrument(skip(pipeline), fields(%root), level = "debug")]
+pub(crate) fn expand(pipeline: &mut Pipeline<'_>, root: AsSet) -> Result<AsSetTree, Error> {
+    let mut tree = AsSetTree::new(root.clone());
+    let mut seen = HashSet::from([AsSetTree::key(&root)]);
+    let mut frontier = vec![root];
+    while !frontier.is_empty() {
+        tracing::debug!("expanding {} as-sets", frontier.len());
+        for set in &frontier {
+            _ = pipeline.push(Query::AsSetMembers(set.clone()))?;
+        }
+        let mut next = Vec::new();
+        for set in frontier {
+            let response = match pipeline
+                .pop::<String>()
+                .unwrap_or_else(|| Err(Error::Dequeue))
+            {
+                Ok(response) => response,
+                Err(Error::ResponseErr(_, error::Response::KeyNotFound)) => {
+                    tracing::warn!("as-set {set} not found");
+                    tree.unresolved.push(set);
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            let mut members = AsSetMembership::default();
+            for item in response.parse_as::<AsSetMember>() {
+                match item {
+                    Ok(item) => match item.into_content() {
+                        AsSetMember::AutNum(autnum) => members.autnums.push(autnum),
+                        AsSetMember::AsSet(nested) => {
+                            if seen.insert(AsSetTree::key(&nested)) {
+                                next.push(nested.clone());
+                            }
+                            members.sets.push(nested);
+                        }
+                    },
+                    Err(err @ Error::ParseItem(..)) => {
+                        tracing::warn!("ignoring invalid member of {set}: {err}");
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+            tree.insert(set, members);
+        }
+        frontier = next;
+    }
+    Ok(tree)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{client::Connection, transport::mock::Mock};
+
+    use super::*;
+
+    #[test]
+    fn cyclic_tree_is_expanded() {
+        let stream = Mock::new(
+            b"A14\nAS1 AS-B AS-C\nC\n\
+              A13\nAS2 AS-A AS1\nC\n\
+              D\n",
+        );
+        let mut conn = Connection::from_stream(stream);
+        let tree = conn.as_set_tree("AS-A".parse().unwrap()).unwrap();
+        let set = |s: &str| s.parse::<AsSet>().unwrap();
+        let autnum = |s: &str| s.parse::<AutNum>().unwrap();
+        assert_eq!(tree.root(), &set("AS-A"));
+        assert_eq!(
+            tree.sets().map(|(s, _)| s.clone()).collect::<Vec<_>>(),
+            vec![set("AS-A"), set("AS-B")]
+        );
+        let members = tree.members(&set("AS-B")).unwrap();
+        assert_eq!(members.autnums(), [autnum("AS2"), autnum("AS1")]);
+        assert_eq!(members.sets(), [set("AS-A")]);
+        assert_eq!(tree.unresolved(), [set("AS-C")]);
+        assert_eq!(tree.autnums(), vec![autnum("AS1"), autnum("AS2")]);
+    }
+
+    #[test]
+    fn pipeline_outstanding_responses_are_discarded() {
+        let stream = Mock::new(b"A8\nAS65000\nC\nA8\nAS1 AS2\nC\n");
+        let mut conn = Connection::from_stream(stream);
+        let mut pipeline = conn.pipeline();
+        _ = pipeline
+            .push(Query::AsSetMembers("AS-X".parse().unwrap()))
+            .unwrap();
+        let tree = pipeline.as_set_tree("AS-A".parse().unwrap()).unwrap();
+        let members = tree.members(&"AS-A".parse().unwrap()).unwrap();
+        assert_eq!(members.autnums().len(), 2);
+        assert!(members.sets().is_empty());
+    }
+}
@@ -0,0 +1,200 @@
+use std::convert::Infallible;
+use std::fmt;
+use std::str::FromStr;
+
+/// Server software implementations, as identified from the version string
+/// returned in response to a [`Query::Version`][crate::Query::Version].
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum Implementation {
+    /// [IRRd](https://irrd.readthedocs.io/en/stable/).
+    Irrd,
+    /// An implementation that could not be identified.
+    Unknown,
+}
+
+/// Optional server features, whose availability can be inferred from a
+/// [`ServerVersion`].
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+#[cfg_attr(test, derive(strum::EnumIter))]
+pub enum Capability {
+    /// Expansion of an `as-set` directly into prefixes, using
+    /// [`Query::AsSetPrefixes`][crate::Query::AsSetPrefixes] (`!a`).
+    ///
+    /// Available from IRRd version 4.
+    AsSetPrefixes,
+    /// Reporting of the mirroring status and journal serials of sources,
+    /// using [`Query::JournalStatus`][crate::Query::JournalStatus] (`!j`).
+    ///
+    /// Available from all identified versions of IRRd.
+    JournalStatus,
+    /// Retrieval of journal entries using NRTM version 3, with
+    /// [`Connection::journal_since()`][crate::Connection::journal_since].
+    ///
+    /// Available from IRRd version 4. The server may nonetheless refuse NRTM
+    /// requests from clients that it has not been configured to permit.
+    Nrtm,
+}
+
+/// The version of the software run by a server.
+///
+/// This is parsed from the version string returned in response to a
+/// [`Query::Version`][crate::Query::Version], and used to infer whether
+/// optional features are available, so that queries that would be rejected
+/// by the server need not be issued.
+///
+/// Parsing never fails. Capabilities are inferred conservatively: if the
+/// implementation or version cannot be identified, no optional features are
+/// assumed to be available.
+///
+/// # Example
+///
+/// ```
+/// use irrc::{Capability, Implementation, ServerVersion};
+///
+/// let version: ServerVersion = "IRRd -- version 4.4.2".parse().unwrap();
+/// assert_eq!(version.implementation(), Implementation::Irrd);
+/// assert_eq!(version.version(), Some((4, 4, 2)));
+/// assert!(version.supports(Capability::AsSetPrefixes));
+///
+/// let unknown: ServerVersion = "whois server".parse().unwrap();
+/// assert!(!unknown.supports(Capability::JournalStatus));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServerVersion {
+    banner: String,
+    implementation: Implementation,
+    version: Option<(u32, u32, u32)>,
+}
+
+impl ServerVersion {
+    /// The version string, as returned by the server.
+    #[must_use]
+    pub fn banner(&self) -> &str {
+        &self.banner
+    }
+
+    /// The server software implementation.
+    #[must_use]
+    pub const fn implementation(&self) -> Implementation {
+        self.implementation
+    }
+
+    /// The `(major, minor, patch)` version number of the server software, if
+    /// found in the version string.
+    ///
+    /// Missing minor or patch components are taken to be zero.
+    #[must_use]
+    pub const fn version(&self) -> Option<(u32, u32, u32)> {
+        self.version
+    }
+
+    /// Whether the server is expected to support `capability`.
+    ///
+    /// This is `false` whenever the version number could not be found.
+    #[must_use]
+    pub fn supports(&self, capability: Capability) -> bool {
+        let Some(version) = self.version else {
+            return false;
+        };
+        match (self.implementation, capability) {
+            (Implementation::Irrd, Capability::AsSetPrefixes | Capability::Nrtm) => {
+                version >= (4, 0, 0)
+            }
+            (Implementation::Irrd, Capability::JournalStatus) => true,
+            (Implementation::Unknown, _) => false,
+        }
+    }
+}
+
+impl FromStr for ServerVersion {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let banner = s.trim();
+        let implementation = if banner
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .any(|word| word.eq_ignore_ascii_case("irrd"))
+        {
+            Implementation::Irrd
+        } else {
+            Implementation::Unknown
+        };
+        let version = banner
+            .split(|c: char| c.is_whitespace() || c == '-')
+            .map(|word| word.trim_start_matches(['v', 'V']))
+            .find_map(parse_version);
+        Ok(Self {
+            banner: banner.to_owned(),
+            implementation,
+            version,
+        })
+    }
+}
+
+impl fmt::Display for ServerVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.banner)
+    }
+}
+
+/// Parse a dotted version number of up to three components.
+fn parse_version(word: &str) -> Option<(u32, u32, u32)> {
+    let mut parts = word.split('.');
+    let mut next = || parts.next().map(str::parse::<u32>).transpose().ok();
+    let major = next()??;
+    let minor = next()?.unwrap_or_default();
+    let patch = next()?.unwrap_or_default();
+    Some((major, minor, patch))
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+
+    use super::*;
+
+    #[test]
+    fn irrd_versions_are_parsed() {
+        for (banner, version) in [
+            ("IRRd -- version 4.4.2", (4, 4, 2)),
+            ("IRRd 4.4", (4, 4, 0)),
+            ("IRRd -- version 2.3.10\n", (2, 3, 10)),
+        ] {
+            let parsed: ServerVersion = banner.parse().unwrap();
+            assert_eq!(parsed.implementation(), Implementation::Irrd, "{banner}");
+            assert_eq!(parsed.version(), Some(version), "{banner}");
+        }
+    }
+
+    #[test]
+    fn capabilities_depend_on_irrd_version() {
+        let legacy: ServerVersion = "IRRd -- version 3.0.8".parse().unwrap();
+        assert!(!legacy.supports(Capability::AsSetPrefixes));
+        assert!(legacy.supports(Capability::JournalStatus));
+        assert!(!legacy.supports(Capability::Nrtm));
+        let current: ServerVersion = "IRRd -- version 4.2.0".parse().unwrap();
+        for capability in Capability::iter() {
+            assert!(current.supports(capability), "{capability:?}");
+        }
+    }
+
+    #[test]
+    fn unversioned_servers_support_nothing() {
+        let unversioned: ServerVersion = "IRRd".parse().unwrap();
+        assert_eq!(unversioned.implementation(), Implementation::Irrd);
+        assert_eq!(unversioned.version(), None);
+        for capability in Capability::iter() {
+            assert!(!unversioned.supports(capability), "{capability:?}");
+        }
+    }
+
+    #[test]
+    fn unknown_servers_support_nothing() {
+        let version: ServerVersion = "ExampleWhois 9.9.9".parse().unwrap();
+        assert_eq!(version.implementation(), Implementation::Unknown);
+        assert_eq!(version.version(), Some((9, 9, 9)));
+        for capability in Capability::iter() {
+            assert!(!version.supports(capability), "{capability:?}");
+        }
+    }
+}